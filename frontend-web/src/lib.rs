@@ -19,6 +19,7 @@ use crate::utils::set_panic_hook;
 use notecalc_lib::borrow_checker_fighter::{to_box_ptr, BorrowCheckerFighter};
 use notecalc_lib::editor::editor::{EditorInputEvent, InputModifiers};
 use notecalc_lib::helper::*;
+use notecalc_lib::lint::WarningKind;
 use notecalc_lib::{
     Layer, OutputMessage, OutputMessageCommandId, RenderAsciiTextMsg, RenderBuckets,
     RenderStringMsg, RenderUtf8TextMsg,
@@ -109,6 +110,34 @@ pub fn set_theme(app_ptr: usize, theme_index: usize) {
     );
 }
 
+#[wasm_bindgen]
+pub fn set_user_snippets(app_ptr: usize, text: String) {
+    let bcf = BorrowCheckerFighter::from_ptr(app_ptr);
+    bcf.mut_app().set_user_snippets(&text);
+}
+
+#[wasm_bindgen]
+pub fn set_lint_enabled(app_ptr: usize, category: String, enabled: bool) {
+    let kind = match WarningKind::from_name(&category) {
+        Some(kind) => kind,
+        None => return,
+    };
+    let bcf = BorrowCheckerFighter::from_ptr(app_ptr);
+    let app = bcf.mut_app();
+    app.set_lint_enabled(
+        kind,
+        enabled,
+        bcf.mut_editor_objects(),
+        bcf.units(),
+        bcf.allocator(),
+        bcf.mut_tokens(),
+        bcf.mut_results(),
+        bcf.mut_vars(),
+        bcf.mut_func_defs(),
+        bcf.mut_render_bucket(),
+    );
+}
+
 #[wasm_bindgen]
 pub fn get_compressed_encoded_content(app_ptr: usize) -> String {
     let bcf = BorrowCheckerFighter::from_ptr(app_ptr);
@@ -235,6 +264,23 @@ pub fn handle_click(app_ptr: usize, x: usize, y: usize) {
     );
 }
 
+#[wasm_bindgen]
+pub fn handle_ctrl_click(app_ptr: usize, x: usize, y: usize) {
+    let bcf = BorrowCheckerFighter::from_ptr(app_ptr);
+    bcf.mut_app().handle_ctrl_click(
+        x,
+        CanvasY::new(y as isize),
+        bcf.mut_editor_objects(),
+        bcf.units(),
+        bcf.allocator(),
+        bcf.mut_tokens(),
+        bcf.mut_results(),
+        bcf.mut_vars(),
+        bcf.mut_func_defs(),
+        bcf.mut_render_bucket(),
+    );
+}
+
 #[wasm_bindgen]
 pub fn handle_wheel(app_ptr: usize, dir: usize) -> bool {
     let bcf = BorrowCheckerFighter::from_ptr(app_ptr);
@@ -380,6 +426,7 @@ pub fn handle_input(app_ptr: usize, input: u32, modifiers: u8) -> bool {
         11 => EditorInputEvent::PageUp,
         12 => EditorInputEvent::PageDown,
         13 => EditorInputEvent::Tab,
+        14 => EditorInputEvent::F2,
         _ => {
             let ch = std::char::from_u32(input);
             if let Some(ch) = ch {
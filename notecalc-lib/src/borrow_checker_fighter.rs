@@ -1,8 +1,8 @@
 use crate::helper::{AppTokens, EditorObjects, Results};
 use crate::units::units::Units;
 use crate::{
-    FunctionDef, NoteCalcApp, RenderBuckets, Variable, MAX_LINE_COUNT, SUM_VARIABLE_INDEX,
-    VARIABLE_ARR_SIZE,
+    FunctionDef, NoteCalcApp, RenderBuckets, Variable, MAX_LINE_COUNT,
+    SECTION_TOTAL_VARIABLE_INDEX, SECTION_TOTAL_VAR_NAME, SUM_VARIABLE_INDEX, VARIABLE_ARR_SIZE,
 };
 use bumpalo::Bump;
 
@@ -29,6 +29,12 @@ pub fn create_vars() -> [Option<Variable>; VARIABLE_ARR_SIZE] {
     vars[SUM_VARIABLE_INDEX] = Some(Variable {
         name: Box::from(&['s', 'u', 'm'][..]),
         value: Err(()),
+        unknowns: None,
+    });
+    vars[SECTION_TOTAL_VARIABLE_INDEX] = Some(Variable {
+        name: Box::from(SECTION_TOTAL_VAR_NAME),
+        value: Err(()),
+        unknowns: None,
     });
     return vars;
 }
@@ -1,20 +1,23 @@
 use std::ops::Not;
 use std::ops::{BitXor, Shl};
-use std::ops::{Neg, Shr};
+use std::ops::{Neg, Range, Shr};
 
 use crate::borrow_checker_fighter::create_vars;
 use crate::editor::editor_content::EditorContent;
-use crate::functions::FnType;
+use crate::equation::unknown_value;
+use crate::functions::{array_param, wrong_arg_count_msg, FnType};
 use crate::helper::{content_y, AppTokens, BitFlag256};
 use crate::matrix::MatrixData;
-use crate::token_parser::{debug_print, OperatorTokenType, TokenType, UnitTokenType};
-use crate::units::consts::EMPTY_UNIT_DIMENSIONS;
+use crate::random::{self, RandomSeed};
+use crate::token_parser::{debug_print, OperatorTokenType, Token, TokenType, UnitTokenType};
+use crate::units::consts::{unit_type_name, UnitType, EMPTY_UNIT_DIMENSIONS};
 use crate::units::units::{UnitOutput, Units, MAX_UNIT_COUNT};
 use crate::{
-    tracy_span, FunctionDefinitions, LineData, Variable, Variables, FIRST_FUNC_PARAM_VAR_INDEX,
-    MAX_TOKEN_COUNT_PER_LINE, SUM_VARIABLE_INDEX, VARIABLE_ARR_SIZE,
+    scoped_var_name, tracy_span, FunctionDefinitions, LineData, Tokens, Variable, Variables,
+    FIRST_FUNC_PARAM_VAR_INDEX, MAX_TOKEN_COUNT_PER_LINE, SUM_VARIABLE_INDEX, VARIABLE_ARR_SIZE,
 };
 use rust_decimal::prelude::*;
+use std::cell::Cell;
 use tinyvec::ArrayVec;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -30,7 +33,13 @@ pub enum CalcResultType {
     Percentage(Decimal),
     Unit(UnitOutput),
     Quantity(Decimal, UnitOutput),
+    // value, absolute error and unit (unitless for plain numbers), e.g. 5.00 m ± 0.02 m
+    Uncertain(Decimal, Decimal, UnitOutput),
+    // lower bound, upper bound and unit (unitless for plain numbers), e.g. 3..5 days
+    Interval(Decimal, Decimal, UnitOutput),
     Matrix(MatrixData),
+    // index of a user function passed as an argument, e.g. deriv(f, 2)
+    Function(usize),
 }
 
 impl CalcResult {
@@ -61,6 +70,7 @@ impl CalcResult {
                 cells: Vec::new(),
                 row_count: 0,
                 col_count: 0,
+                col_labels: None,
             }),
             index_into_tokens: 0,
             index2_into_tokens: None,
@@ -95,7 +105,7 @@ impl ShuntingYardResult {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct EvalErr {
     pub token_index: usize,
     pub token_index_lhs_1: Option<usize>,
@@ -149,6 +159,7 @@ pub fn evaluate_tokens<'text_ptr>(
     editor_content: &EditorContent<LineData>,
     call_depth: usize,
     fn_context_index: Option<usize>,
+    random_seed: RandomSeed,
 ) -> (BitFlag256, Result<Option<EvaluationResult>, EvalErr>) {
     let _span = tracy_span("calc", file!(), line!());
     let mut wrong_type_token_indices: BitFlag256 = BitFlag256::empty();
@@ -156,7 +167,7 @@ pub fn evaluate_tokens<'text_ptr>(
     if call_depth >= 5 {
         return (
             wrong_type_token_indices,
-            Err(EvalErr::new("too much recursion TODO index".to_string(), 0)),
+            Err(EvalErr::new("Too many nested function calls".to_owned(), 0)),
         );
     }
 
@@ -203,7 +214,7 @@ pub fn evaluate_tokens<'text_ptr>(
                 return (
                     wrong_type_token_indices,
                     Err(EvalErr::new(
-                        "Number parsin/format".to_string(),
+                        "Invalid number".to_owned(),
                         token.index_into_tokens,
                     )),
                 );
@@ -294,64 +305,29 @@ pub fn evaluate_tokens<'text_ptr>(
                     return (
                         wrong_type_token_indices,
                         Err(EvalErr::new(
-                            format!(
-                                "Expected {} arguments, provided {}",
-                                expected_arg_count, *arg_count
-                            ),
+                            wrong_arg_count_msg(expected_arg_count, *arg_count),
                             index_into_tokens,
                         )),
                     );
                 }
-                // fill variables from the stack
-                // TODO avoid copy
-                let mut local_vars = create_vars();
-
-                // don't clone sum
-                local_vars[0..SUM_VARIABLE_INDEX].clone_from_slice(&vars[0..SUM_VARIABLE_INDEX]);
-                local_vars[SUM_VARIABLE_INDEX + 1..VARIABLE_ARR_SIZE]
-                    .clone_from_slice(&vars[SUM_VARIABLE_INDEX + 1..VARIABLE_ARR_SIZE]);
-                for i in (0..expected_arg_count).rev() {
-                    local_vars[FIRST_FUNC_PARAM_VAR_INDEX + i] = Some(Variable {
-                        // TODO: absolutely not, it would mean an alloc in hot path
-                        name: Box::from(fd.param_names[i]),
-                        value: stack.pop().ok_or(()),
-                    });
-                }
-                let mut result: Result<Option<EvaluationResult>, EvalErr> =
-                    Err(EvalErr::new(String::new(), 0));
-                let mut sum_is_null = true;
-                debug_print("calc> evaluate function");
-                for i in (fd.first_row_index.as_usize() + 1)..=fd.last_row_index.as_usize() {
-                    result = evaluate_tokens(
-                        i,
-                        apptokens,
-                        &local_vars,
-                        &func_defs,
-                        units,
-                        editor_content,
-                        if fn_context_index.map(|it| it == *fn_index).unwrap_or(false) {
-                            call_depth + 1
-                        } else {
-                            call_depth
-                        },
-                        Some(*fn_index),
-                    )
-                    .1;
-                    process_variable_assignment_or_line_ref(
-                        &result,
-                        &mut local_vars,
-                        i,
-                        editor_content,
-                    );
-                    if let Ok(Some(result)) = result.as_ref() {
-                        if sum_is_null {
-                            sum_is_null = false;
-                            local_vars[SUM_VARIABLE_INDEX].as_mut().unwrap().value =
-                                Ok(result.result.clone());
-                        }
-                    }
-                }
-                debug_print("calc> evaluate end");
+                let args = stack.split_off(stack.len() - expected_arg_count);
+                let fn_token_index = get_token_index_into_tokens(apptokens, editor_y, i);
+                let result = call_user_fn(
+                    *fn_index,
+                    args,
+                    apptokens,
+                    vars,
+                    func_defs,
+                    units,
+                    editor_content,
+                    if fn_context_index.map(|it| it == *fn_index).unwrap_or(false) {
+                        call_depth + 1
+                    } else {
+                        call_depth
+                    },
+                    // every call has its own random numbers
+                    random_seed.derive(editor_content.get_data(editor_y).line_id, fn_token_index),
+                );
                 if let Ok(Some(result)) = result {
                     stack.push(result.result);
                     last_success_operation_result_index = Some(stack.len() - 1);
@@ -360,11 +336,189 @@ pub fn evaluate_tokens<'text_ptr>(
                     let tokens = apptokens[content_y(editor_y)].as_ref().unwrap();
                     let shunting_tokens = &tokens.shunting_output_stack;
                     let index_into_tokens = shunting_tokens[i].index_into_tokens;
+                    let fn_name: String = fd.func_name.iter().collect();
+                    let reason = match result {
+                        Err(err) => format!("{}: {}", fn_name, err.reason),
+                        _ => format!("'{}' has no result", fn_name),
+                    };
+                    return (
+                        wrong_type_token_indices,
+                        Err(EvalErr::new(reason, index_into_tokens)),
+                    );
+                }
+            }
+            TokenType::Operator(OperatorTokenType::Fn {
+                typ: FnType::GoalSeek,
+                arg_count,
+            }) => {
+                let tokens = apptokens[content_y(editor_y)].as_ref().unwrap();
+                let fn_token_index = tokens.shunting_output_stack[i].index_into_tokens;
+                if *arg_count != 3 || stack.len() < 3 {
+                    return (
+                        wrong_type_token_indices,
+                        Err(EvalErr::new(
+                            "Usage: goalseek(expr, var, target)".to_owned(),
+                            fn_token_index,
+                        )),
+                    );
+                }
+                match goal_seek(
+                    &stack[stack.len() - 3..],
+                    &tokens.tokens,
+                    apptokens,
+                    vars,
+                    func_defs,
+                    units,
+                    editor_content,
+                    call_depth,
+                    fn_token_index,
+                    random_seed,
+                ) {
+                    Ok(result) => {
+                        stack.truncate(stack.len() - 3);
+                        stack.push(result);
+                        last_success_operation_result_index = Some(stack.len() - 1);
+                        op_is_fn_call = true;
+                    }
+                    Err(err) => return (wrong_type_token_indices, Err(err)),
+                }
+            }
+            TokenType::Operator(OperatorTokenType::ColumnLabels) => {
+                let tokens = apptokens[content_y(editor_y)].as_ref().unwrap();
+                let bracket_index = tokens.shunting_output_stack[i].index_into_tokens;
+                // the labels are the non-operator tokens until the ';' of the header
+                let labels = tokens.tokens[bracket_index + 1..]
+                    .iter()
+                    .take_while(|it| {
+                        !matches!(it.typ, TokenType::Operator(OperatorTokenType::Semicolon))
+                    })
+                    .filter(|it| it.is_string() && !it.ptr[0].is_ascii_whitespace())
+                    .map(|it| it.ptr.iter().collect::<String>())
+                    .collect::<Vec<_>>();
+                if let Some(CalcResult {
+                    typ: CalcResultType::Matrix(mat),
+                    ..
+                }) = stack.pop()
+                {
+                    stack.push(CalcResult::new(
+                        CalcResultType::Matrix(mat.with_col_labels(labels)),
+                        bracket_index,
+                    ));
+                }
+            }
+            TokenType::Operator(OperatorTokenType::Column) => {
+                let tokens = apptokens[content_y(editor_y)].as_ref().unwrap();
+                let token_index = tokens.shunting_output_stack[i].index_into_tokens;
+                // skip the '.'
+                let label = &tokens.tokens[token_index].ptr[1..];
+                let column = match stack.pop().map(|it| it.typ) {
+                    Some(CalcResultType::Matrix(mat)) => mat
+                        .col_index_of(label)
+                        .map(|col| mat.sub_matrix(0..mat.row_count, col..col + 1))
+                        .ok_or_else(|| {
+                            format!(
+                                "The table has no column named '{}'",
+                                label.iter().collect::<String>()
+                            )
+                        }),
+                    _ => Err("Only tables have named columns".to_owned()),
+                };
+                match column {
+                    Ok(column) => {
+                        stack.push(CalcResult::new(CalcResultType::Matrix(column), token_index));
+                        last_success_operation_result_index = Some(stack.len() - 1);
+                    }
+                    Err(msg) => {
+                        return (
+                            wrong_type_token_indices,
+                            Err(EvalErr::new(msg, token_index)),
+                        );
+                    }
+                }
+            }
+            TokenType::Operator(OperatorTokenType::FnRef(fn_index)) => {
+                stack.push(CalcResult::new(
+                    CalcResultType::Function(*fn_index),
+                    get_token_index_into_tokens(apptokens, editor_y, i),
+                ));
+            }
+            TokenType::Operator(OperatorTokenType::Fn { typ, arg_count }) if typ.is_random() => {
+                let tokens = apptokens[content_y(editor_y)].as_ref().unwrap();
+                let fn_token_index = tokens.shunting_output_stack[i].index_into_tokens;
+                let (expected_arg_count, usage) = random::arg_count_and_usage(*typ);
+                if *arg_count != expected_arg_count || stack.len() < expected_arg_count {
+                    return (
+                        wrong_type_token_indices,
+                        Err(EvalErr::new(usage.to_owned(), fn_token_index)),
+                    );
+                }
+                let mut rng = random_seed
+                    .derive(editor_content.get_data(editor_y).line_id, fn_token_index)
+                    .rng();
+                match random::draw(
+                    *typ,
+                    &stack[stack.len() - expected_arg_count..],
+                    tokens.tokens[fn_token_index].ptr,
+                    fn_token_index,
+                    &mut rng,
+                ) {
+                    Ok(result) => {
+                        stack.truncate(stack.len() - expected_arg_count);
+                        stack.push(result);
+                        last_success_operation_result_index = Some(stack.len() - 1);
+                        op_is_fn_call = true;
+                    }
+                    Err(err) => return (wrong_type_token_indices, Err(err)),
+                }
+            }
+            TokenType::Operator(OperatorTokenType::Fn { typ, arg_count })
+                if typ.accepts_fn_arg() =>
+            {
+                let fn_token_index = get_token_index_into_tokens(apptokens, editor_y, i);
+                let (expected_arg_count, usage) = match typ {
+                    FnType::Deriv => (2, "Usage: deriv(f, x)"),
+                    FnType::Integrate => (3, "Usage: integrate(f, from, to)"),
+                    FnType::Map => (2, "Usage: map(v, f)"),
+                    FnType::Filter => (2, "Usage: filter(v, pred)"),
+                    FnType::Reduce => (3, "Usage: reduce(v, f, init)"),
+                    _ => (3, "Usage: root(f, from, to)"),
+                };
+                if *arg_count != expected_arg_count || stack.len() < expected_arg_count {
                     return (
                         wrong_type_token_indices,
-                        Err(EvalErr::new("No result".to_owned(), index_into_tokens)),
+                        Err(EvalErr::new(usage.to_owned(), fn_token_index)),
                     );
                 }
+                let args = &stack[stack.len() - expected_arg_count..];
+                let user_fn = UserFnCall {
+                    apptokens,
+                    vars,
+                    func_defs,
+                    units,
+                    editor_content,
+                    call_depth: call_depth + 1,
+                    fn_token_index,
+                    random_seed: random_seed
+                        .derive(editor_content.get_data(editor_y).line_id, fn_token_index),
+                    call_count: Cell::new(0),
+                };
+                let result = match typ {
+                    FnType::Deriv => numeric_deriv(&user_fn, &args[0], &args[1]),
+                    FnType::Integrate => numeric_integrate(&user_fn, &args[0], &args[1], &args[2]),
+                    FnType::Map => fn_map(&user_fn, &args[0], &args[1]),
+                    FnType::Filter => fn_filter(&user_fn, &args[0], &args[1]),
+                    FnType::Reduce => fn_reduce(&user_fn, &args[0], &args[1], &args[2]),
+                    _ => numeric_root(&user_fn, &args[0], &args[1], &args[2]),
+                };
+                match result {
+                    Ok(result) => {
+                        stack.truncate(stack.len() - expected_arg_count);
+                        stack.push(result);
+                        last_success_operation_result_index = Some(stack.len() - 1);
+                        op_is_fn_call = true;
+                    }
+                    Err(err) => return (wrong_type_token_indices, Err(err)),
+                }
             }
             TokenType::Operator(typ) => {
                 if *typ == OperatorTokenType::Assign {
@@ -397,17 +551,19 @@ pub fn evaluate_tokens<'text_ptr>(
             TokenType::StringLiteral | TokenType::Header => panic!(),
             TokenType::Variable { var_index } | TokenType::LineReference { var_index } => {
                 // TODO clone :(
-                match &vars[*var_index]
+                let var = vars[*var_index]
                     .as_ref()
-                    .expect("var_index should be valid!")
-                    .value
-                {
+                    .expect("var_index should be valid!");
+                match &var.value {
                     Ok(value) => {
-                        let shunting_tokens = &apptokens[content_y(editor_y)]
-                            .as_ref()
-                            .unwrap()
-                            .shunting_output_stack;
-                        let token = &shunting_tokens[i];
+                        let tokens = apptokens[content_y(editor_y)].as_ref().unwrap();
+                        let token = &tokens.shunting_output_stack[i];
+                        // e.g. 'x' is the first element of the solution of 'x + y = 10; x - y = 2'
+                        let value = tokens
+                            .tokens
+                            .get(token.index_into_tokens)
+                            .and_then(|it| unknown_value(var, it.ptr))
+                            .unwrap_or(value);
                         stack.push(CalcResult::new(value.typ.clone(), token.index_into_tokens));
                     }
                     Err(_) => {
@@ -458,6 +614,26 @@ pub fn evaluate_tokens<'text_ptr>(
     return (wrong_type_token_indices, result);
 }
 
+pub fn replace_or_insert_var(
+    vars: &mut Variables,
+    var_name: &[char],
+    result: CalcResult,
+    editor_y: usize,
+) {
+    if let Some(var) = &mut vars[editor_y] {
+        // TODO Box::from O-O
+        var.name = Box::from(var_name);
+        var.value = Ok(result);
+        var.unknowns = None;
+    } else {
+        vars[editor_y] = Some(Variable {
+            name: Box::from(var_name),
+            value: Ok(result),
+            unknowns: None,
+        });
+    };
+}
+
 pub fn process_variable_assignment_or_line_ref<'a, 'b>(
     result: &Result<Option<EvaluationResult>, EvalErr>,
     vars: &mut Variables,
@@ -465,24 +641,6 @@ pub fn process_variable_assignment_or_line_ref<'a, 'b>(
     editor_content: &EditorContent<LineData>,
 ) {
     if let Ok(Some(result)) = &result {
-        fn replace_or_insert_var(
-            vars: &mut Variables,
-            var_name: &[char],
-            result: CalcResult,
-            editor_y: usize,
-        ) {
-            if let Some(var) = &mut vars[editor_y] {
-                // TODO Box::from O-O
-                var.name = Box::from(var_name);
-                var.value = Ok(result);
-            } else {
-                vars[editor_y] = Some(Variable {
-                    name: Box::from(var_name),
-                    value: Ok(result),
-                });
-            };
-        }
-
         if result.assignment {
             let var_name = get_var_name_from_assignment(editor_y, editor_content);
             if !var_name.is_empty() {
@@ -490,7 +648,8 @@ pub fn process_variable_assignment_or_line_ref<'a, 'b>(
                     "eval> assign {:?} at {} to {:?}",
                     var_name, editor_y, &result.result
                 ));
-                replace_or_insert_var(vars, var_name, result.result.clone(), editor_y);
+                let var_name = scoped_var_name(var_name, editor_content, editor_y);
+                replace_or_insert_var(vars, &var_name, result.result.clone(), editor_y);
             }
         } else {
             let line_data = editor_content.get_data(editor_y);
@@ -508,6 +667,7 @@ pub fn process_variable_assignment_or_line_ref<'a, 'b>(
         let var_name: Vec<char> = format!("&[{}]", line_id).chars().collect();
         var.name = Box::from(var_name);
         var.value = Err(());
+        var.unknowns = None;
     } else {
         vars[editor_y] = None;
     }
@@ -562,6 +722,8 @@ fn apply_operation<'text_ptr>(
         | OperatorTokenType::BinOr
         | OperatorTokenType::BinXor
         | OperatorTokenType::Pow
+        | OperatorTokenType::PlusMinus
+        | OperatorTokenType::Interval
         | OperatorTokenType::ShiftLeft
         | OperatorTokenType::ShiftRight
         | OperatorTokenType::Percentage_Find_Base_From_Result_Increase_X
@@ -582,10 +744,13 @@ fn apply_operation<'text_ptr>(
                     stack.push(result);
                     Ok(())
                 } else {
-                    Err(EvalErr::new("Op failed".to_owned(), op_token_index))
+                    Err(EvalErr::new(
+                        binary_operation_error(op, lhs, rhs),
+                        op_token_index,
+                    ))
                 }
             } else {
-                Err(EvalErr::new("Not enugh operand".to_owned(), 0))
+                Err(EvalErr::new("Not enough operands".to_owned(), 0))
             }
         }
         OperatorTokenType::UnaryMinus
@@ -605,7 +770,10 @@ fn apply_operation<'text_ptr>(
                 stack.push(result);
                 Ok(())
             } else {
-                Err(EvalErr::new("??".to_owned(), 0))
+                Err(EvalErr::new(
+                    unary_operation_error(op, maybe_top),
+                    op_token_index,
+                ))
             }
         }
         OperatorTokenType::Matrix {
@@ -633,29 +801,772 @@ fn apply_operation<'text_ptr>(
             debug_print(&format!("calc> call Fn {:?}", typ));
             typ.execute(*arg_count, stack, op_token_index, units)
         }
+        OperatorTokenType::Index {
+            arg_count,
+            full_range,
+        } => index_op(stack, *arg_count, full_range, op_token_index),
         OperatorTokenType::Semicolon | OperatorTokenType::Comma => {
             // ignore
             Ok(())
         }
-        OperatorTokenType::Assign | OperatorTokenType::StartLock => {
-            panic!("handled in the main loop above")
-        }
+        OperatorTokenType::Assign
+        | OperatorTokenType::StartLock
+        | OperatorTokenType::FnRef(..)
+        | OperatorTokenType::ColumnLabels
+        | OperatorTokenType::Column => panic!("handled in the main loop above"),
         OperatorTokenType::ParenOpen
         | OperatorTokenType::ParenClose
         | OperatorTokenType::BracketOpen
         | OperatorTokenType::BracketClose => {
             // this branch was executed during fuzz testing, don't panic here
             // check test_panic_fuzz_3
-            return Err(EvalErr::new(String::new(), op_token_index));
+            return Err(EvalErr::new(
+                "Unbalanced parentheses or brackets".to_owned(),
+                op_token_index,
+            ));
         }
         OperatorTokenType::PercentageIs => {
             // ignore
             Ok(())
         }
+        OperatorTokenType::AsDms => {
+            let result = stack.last().and_then(|top| as_dms_op(top, units));
+            if let Some(result) = result {
+                stack.pop();
+                stack.push(result);
+                Ok(())
+            } else {
+                Err(EvalErr::new(
+                    "Only angles can be displayed as dms".to_owned(),
+                    op_token_index,
+                ))
+            }
+        }
     };
     return succeed;
 }
 
+/// m[2, 3], m[:, 1] or v[1..3]. Indices start from zero, negative ones count from the end
+/// and ranges include both of their bounds. A single index selects an element of a vector
+/// or a row of a matrix.
+fn index_op(
+    stack: &mut Vec<CalcResult>,
+    arg_count: usize,
+    full_range: &[bool; 2],
+    op_token_index: usize,
+) -> Result<(), EvalErr> {
+    if arg_count > full_range.len() {
+        return Err(EvalErr::new(
+            "At most two indices can be given (row and column)".to_owned(),
+            op_token_index,
+        ));
+    }
+    let full_range = &full_range[0..arg_count];
+    let value_count = full_range.iter().filter(|it| !**it).count();
+    if stack.len() < value_count + 1 {
+        return Err(EvalErr::new(
+            "Not enough operands".to_owned(),
+            op_token_index,
+        ));
+    }
+    let index_args = stack.split_off(stack.len() - value_count);
+    let target = stack.pop().unwrap();
+    let mat = match &target.typ {
+        CalcResultType::Matrix(mat) => mat,
+        _ => {
+            return Err(EvalErr::new2(
+                "Only matrices can be indexed".to_owned(),
+                &target,
+            ))
+        }
+    };
+    let mut index_args = index_args.iter();
+    let mut next_range = |is_full_range: bool, len: usize, what: &str| {
+        if is_full_range {
+            Ok((0..len, false))
+        } else {
+            index_range(index_args.next().unwrap(), len, what, op_token_index)
+        }
+    };
+    let ((rows, single_row), (cols, single_col)) = if arg_count == 2 {
+        (
+            next_range(full_range[0], mat.row_count, "Row index")?,
+            next_range(full_range[1], mat.col_count, "Column index")?,
+        )
+    } else if mat.row_count == 1 {
+        (
+            (0..1, true),
+            next_range(full_range[0], mat.col_count, "Index")?,
+        )
+    } else if mat.col_count == 1 {
+        (
+            next_range(full_range[0], mat.row_count, "Index")?,
+            (0..1, true),
+        )
+    } else {
+        (
+            next_range(full_range[0], mat.row_count, "Row index")?,
+            (0..mat.col_count, false),
+        )
+    };
+    let result = if single_row && single_col {
+        mat.cell(rows.start, cols.start).typ.clone()
+    } else {
+        CalcResultType::Matrix(mat.sub_matrix(rows, cols))
+    };
+    stack.push(CalcResult::new(result, op_token_index));
+    Ok(())
+}
+
+/// The selected range and whether it is a single index (and not e.g. 2..2)
+fn index_range(
+    arg: &CalcResult,
+    len: usize,
+    what: &str,
+    op_token_index: usize,
+) -> Result<(Range<usize>, bool), EvalErr> {
+    match &arg.typ {
+        CalcResultType::Number(n) => {
+            let i = resolve_index(n, len, what, op_token_index)?;
+            Ok((i..i + 1, true))
+        }
+        CalcResultType::Interval(lo, hi, unit) if unit.is_unitless() => {
+            let lo = resolve_index(lo, len, what, op_token_index)?;
+            let hi = resolve_index(hi, len, what, op_token_index)?;
+            Ok((lo.min(hi)..lo.max(hi) + 1, false))
+        }
+        _ => Err(EvalErr::new(
+            format!("{} must be an integer or a range (e.g. 1..3)", what),
+            op_token_index,
+        )),
+    }
+}
+
+fn resolve_index(
+    n: &Decimal,
+    len: usize,
+    what: &str,
+    op_token_index: usize,
+) -> Result<usize, EvalErr> {
+    let i = if n.trunc() == *n { n.to_i64() } else { None }
+        .ok_or_else(|| EvalErr::new(format!("{} must be an integer", what), op_token_index))?;
+    let resolved = if i < 0 { len as i64 + i } else { i };
+    if resolved < 0 || resolved >= len as i64 {
+        Err(EvalErr::new(
+            format!(
+                "{} {} is out of range, it must be between 0 and {} (or -{} and -1)",
+                what,
+                i,
+                len - 1,
+                len
+            ),
+            op_token_index,
+        ))
+    } else {
+        Ok(resolved as usize)
+    }
+}
+
+/// True if the line uses the parameters of a user function, so it can be evaluated only
+/// inside a call, or if it has no tokens at all
+pub(crate) fn line_depends_on_fn_param(apptokens: &AppTokens, y: usize) -> bool {
+    match &apptokens[content_y(y)] {
+        Some(tokens) => tokens.shunting_output_stack.iter().any(|it| {
+            matches!(it.typ, TokenType::Variable { var_index } if var_index >= FIRST_FUNC_PARAM_VAR_INDEX)
+        }),
+        None => true,
+    }
+}
+
+/// Evaluates the line `y` with other tokens, e.g. a cell of a sheet or an alternative
+/// of a scenario line. evaluate_tokens works on the tokens of a line, so `tokens` are put there
+/// temporarily, then the line gets back its own tokens and `tokens` gets back the token types
+/// set during the evaluation.
+pub(crate) fn evaluate_with_tokens_at<'text_ptr>(
+    apptokens: &mut AppTokens<'text_ptr>,
+    y: usize,
+    tokens: &mut Tokens<'text_ptr>,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions<'text_ptr>,
+    units: &Units,
+    editor_content: &EditorContent<LineData>,
+    random_seed: RandomSeed,
+) -> Result<Option<EvaluationResult>, EvalErr> {
+    let line_tokens = std::mem::replace(
+        &mut apptokens[content_y(y)],
+        Some(Tokens {
+            tokens: std::mem::take(&mut tokens.tokens),
+            shunting_output_stack: std::mem::take(&mut tokens.shunting_output_stack),
+        }),
+    );
+    let (_, result) = evaluate_tokens(
+        y,
+        apptokens,
+        vars,
+        func_defs,
+        units,
+        editor_content,
+        0,
+        None,
+        random_seed,
+    );
+    let evaluated_tokens = std::mem::replace(&mut apptokens[content_y(y)], line_tokens);
+    *tokens = evaluated_tokens.expect("it was put there above");
+    result
+}
+
+/// Evaluates the lines of the user function's body with the given arguments
+fn call_user_fn(
+    fn_index: usize,
+    args: Vec<CalcResult>,
+    apptokens: &AppTokens,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions,
+    units: &Units,
+    editor_content: &EditorContent<LineData>,
+    call_depth: usize,
+    random_seed: RandomSeed,
+) -> Result<Option<EvaluationResult>, EvalErr> {
+    let fd = func_defs[fn_index].as_ref().unwrap();
+    // fill variables from the arguments
+    // TODO avoid copy
+    let mut local_vars = create_vars();
+
+    // don't clone sum
+    local_vars[0..SUM_VARIABLE_INDEX].clone_from_slice(&vars[0..SUM_VARIABLE_INDEX]);
+    local_vars[SUM_VARIABLE_INDEX + 1..VARIABLE_ARR_SIZE]
+        .clone_from_slice(&vars[SUM_VARIABLE_INDEX + 1..VARIABLE_ARR_SIZE]);
+    for (i, arg) in args.into_iter().enumerate() {
+        local_vars[FIRST_FUNC_PARAM_VAR_INDEX + i] = Some(Variable {
+            // TODO: absolutely not, it would mean an alloc in hot path
+            name: Box::from(fd.param_names[i]),
+            value: Ok(arg),
+            unknowns: None,
+        });
+    }
+    let mut result: Result<Option<EvaluationResult>, EvalErr> =
+        Err(EvalErr::new("The function has no body".to_owned(), 0));
+    let mut sum_is_null = true;
+    debug_print("calc> evaluate function");
+    for i in (fd.first_row_index.as_usize() + 1)..=fd.last_row_index.as_usize() {
+        result = evaluate_tokens(
+            i,
+            apptokens,
+            &local_vars,
+            &func_defs,
+            units,
+            editor_content,
+            call_depth,
+            Some(fn_index),
+            random_seed,
+        )
+        .1;
+        process_variable_assignment_or_line_ref(&result, &mut local_vars, i, editor_content);
+        if let Ok(Some(result)) = result.as_ref() {
+            if sum_is_null {
+                sum_is_null = false;
+                local_vars[SUM_VARIABLE_INDEX].as_mut().unwrap().value = Ok(result.result.clone());
+            }
+        }
+    }
+    debug_print("calc> evaluate end");
+    return result;
+}
+
+/// Everything which is needed to call a user function from deriv, map etc.
+struct UserFnCall<'a, 'b> {
+    apptokens: &'a AppTokens<'b>,
+    vars: &'a Variables,
+    func_defs: &'a FunctionDefinitions<'b>,
+    units: &'a Units,
+    editor_content: &'a EditorContent<LineData>,
+    call_depth: usize,
+    fn_token_index: usize,
+    random_seed: RandomSeed,
+    // deriv, map etc. call the function many times, every call gets different random numbers
+    call_count: Cell<usize>,
+}
+
+const NUMERIC_ROOT_MAX_ITERATION_COUNT: usize = 100;
+const NUMERIC_INTEGRATE_MAX_DEPTH: usize = 10;
+
+impl<'a, 'b> UserFnCall<'a, 'b> {
+    fn fn_index_of(&self, arg: &CalcResult, param_count: usize) -> Result<usize, EvalErr> {
+        match &arg.typ {
+            CalcResultType::Function(fn_index)
+                if self.func_defs[*fn_index]
+                    .as_ref()
+                    .map(|it| it.param_count == param_count)
+                    .unwrap_or(false) =>
+            {
+                Ok(*fn_index)
+            }
+            _ => Err(EvalErr::new2(
+                format!(
+                    "The argument must be a function with {} parameter(s)",
+                    param_count
+                ),
+                arg,
+            )),
+        }
+    }
+
+    fn call(&self, fn_index: usize, args: Vec<CalcResult>) -> Result<CalcResult, EvalErr> {
+        let call_index = self.call_count.get();
+        self.call_count.set(call_index + 1);
+        match call_user_fn(
+            fn_index,
+            args,
+            self.apptokens,
+            self.vars,
+            self.func_defs,
+            self.units,
+            self.editor_content,
+            self.call_depth,
+            self.random_seed.derive(fn_index, call_index),
+        ) {
+            Ok(Some(result)) => Ok(result.result),
+            _ => Err(EvalErr::new(
+                "The function could not be evaluated".to_owned(),
+                self.fn_token_index,
+            )),
+        }
+    }
+
+    /// f(start + t * step) as a plain number in the unit of `reference`,
+    /// so the numeric methods can work on f64 values
+    fn sample(
+        &self,
+        fn_index: usize,
+        start: &CalcResult,
+        step: &CalcResult,
+        t: f64,
+        reference: &CalcResult,
+    ) -> Result<f64, EvalErr> {
+        let x = point_at(start, step, t)
+            .ok_or_else(|| EvalErr::new("Invalid argument".to_owned(), self.fn_token_index))?;
+        let y = self.call(fn_index, vec![x])?;
+        num_in_unit_of(&y, reference)
+            .and_then(|it| it.to_f64())
+            .ok_or_else(|| {
+                EvalErr::new(
+                    "The function must return a number or a quantity with the same unit everywhere"
+                        .to_owned(),
+                    self.fn_token_index,
+                )
+            })
+    }
+}
+
+// start + t * step
+fn point_at(start: &CalcResult, step: &CalcResult, t: f64) -> Option<CalcResult> {
+    let t = CalcResult::new(CalcResultType::Number(Decimal::from_f64(t)?), 0);
+    add_op(start, &multiply_op(step, &t)?)
+}
+
+// a Number or Quantity like `reference`, but with the value `num` (in the unit of `reference`)
+fn with_num_of(reference: &CalcResult, num: f64) -> Option<CalcResult> {
+    let num = Decimal::from_f64(num)?;
+    match &reference.typ {
+        CalcResultType::Number(..) => Some(CalcResult::new(CalcResultType::Number(num), 0)),
+        CalcResultType::Quantity(_num, unit) => Some(CalcResult::new(
+            CalcResultType::Quantity(num, unit.clone()),
+            0,
+        )),
+        _ => None,
+    }
+}
+
+fn check_numeric_arg(arg: &CalcResult) -> Result<(), EvalErr> {
+    match &arg.typ {
+        CalcResultType::Number(..) | CalcResultType::Quantity(..) => Ok(()),
+        _ => Err(EvalErr::new2(
+            "The argument must be a number or a quantity".to_owned(),
+            arg,
+        )),
+    }
+}
+
+/// deriv(f, x): central differences with Richardson extrapolation, the step is halved
+/// until two consecutive estimates agree. The unit of the result is unit(f) / unit(x).
+fn numeric_deriv(
+    user_fn: &UserFnCall,
+    f: &CalcResult,
+    x: &CalcResult,
+) -> Result<CalcResult, EvalErr> {
+    let fn_index = user_fn.fn_index_of(f, 1)?;
+    check_numeric_arg(x)?;
+    let is_zero = match &x.typ {
+        CalcResultType::Number(num) | CalcResultType::Quantity(num, _) => num.is_zero(),
+        _ => false,
+    };
+    // the scale of the step
+    let step = if is_zero {
+        with_num_of(x, 1.0).unwrap()
+    } else {
+        x.clone()
+    };
+    let fx = user_fn.call(fn_index, vec![x.clone()])?;
+    let g = |t: f64| user_fn.sample(fn_index, x, &step, t, &fx);
+    let central_diff = |h: f64| -> Result<f64, EvalErr> { Ok((g(h)? - g(-h)?) / (2.0 * h)) };
+
+    let mut h = 1e-2;
+    let mut prev: Option<f64> = None;
+    let mut d = 0.0;
+    for _ in 0..8 {
+        d = (4.0 * central_diff(h / 2.0)? - central_diff(h)?) / 3.0;
+        if let Some(prev) = prev {
+            if (d - prev).abs() <= 1e-10 * d.abs().max(1e-10) {
+                break;
+            }
+        }
+        prev = Some(d);
+        h /= 4.0;
+    }
+    with_num_of(&fx, d)
+        .and_then(|d| divide_op(&d, &step))
+        .ok_or_else(|| EvalErr::new("Invalid result".to_owned(), user_fn.fn_token_index))
+}
+
+/// integrate(f, from, to): adaptive Simpson's rule.
+/// The unit of the result is unit(f) * unit(x), e.g. integrating m/s over s yields m.
+fn numeric_integrate(
+    user_fn: &UserFnCall,
+    f: &CalcResult,
+    from: &CalcResult,
+    to: &CalcResult,
+) -> Result<CalcResult, EvalErr> {
+    fn adaptive_simpson(
+        g: &dyn Fn(f64) -> Result<f64, EvalErr>,
+        (a, fa): (f64, f64),
+        (m, fm): (f64, f64),
+        (b, fb): (f64, f64),
+        whole: f64,
+        eps: f64,
+        depth: usize,
+    ) -> Result<f64, EvalErr> {
+        let (lm, rm) = ((a + m) / 2.0, (m + b) / 2.0);
+        let (flm, frm) = (g(lm)?, g(rm)?);
+        let left = (m - a) / 6.0 * (fa + 4.0 * flm + fm);
+        let right = (b - m) / 6.0 * (fm + 4.0 * frm + fb);
+        let delta = left + right - whole;
+        if depth == 0 || delta.abs() <= 15.0 * eps {
+            return Ok(left + right + delta / 15.0);
+        }
+        let left = adaptive_simpson(g, (a, fa), (lm, flm), (m, fm), left, eps / 2.0, depth - 1)?;
+        let right = adaptive_simpson(g, (m, fm), (rm, frm), (b, fb), right, eps / 2.0, depth - 1)?;
+        return Ok(left + right);
+    }
+
+    let fn_index = user_fn.fn_index_of(f, 1)?;
+    check_numeric_arg(from)?;
+    check_numeric_arg(to)?;
+    let step = sub_op(to, from).ok_or_else(|| {
+        EvalErr::new3(
+            "The bounds must have the same unit".to_owned(),
+            user_fn.fn_token_index,
+            from,
+            to,
+        )
+    })?;
+    let f_from = user_fn.call(fn_index, vec![from.clone()])?;
+    // the integral is calculated over t in [0, 1], x = from + t * (to - from)
+    let g = |t: f64| user_fn.sample(fn_index, from, &step, t, &f_from);
+    let (g0, g_half, g1) = (g(0.0)?, g(0.5)?, g(1.0)?);
+    let whole = (g0 + 4.0 * g_half + g1) / 6.0;
+    let integral = adaptive_simpson(
+        &g,
+        (0.0, g0),
+        (0.5, g_half),
+        (1.0, g1),
+        whole,
+        1e-12 * whole.abs().max(1e-12),
+        NUMERIC_INTEGRATE_MAX_DEPTH,
+    )?;
+    with_num_of(&f_from, integral)
+        .and_then(|integral| multiply_op(&integral, &step))
+        .ok_or_else(|| EvalErr::new("Invalid result".to_owned(), user_fn.fn_token_index))
+}
+
+/// root(f, from, to): finds an x between the bounds where f(x) = 0 with the Illinois method,
+/// f(from) and f(to) must have different signs.
+fn numeric_root(
+    user_fn: &UserFnCall,
+    f: &CalcResult,
+    from: &CalcResult,
+    to: &CalcResult,
+) -> Result<CalcResult, EvalErr> {
+    let fn_index = user_fn.fn_index_of(f, 1)?;
+    check_numeric_arg(from)?;
+    check_numeric_arg(to)?;
+    let step = sub_op(to, from).ok_or_else(|| {
+        EvalErr::new3(
+            "The bounds must have the same unit".to_owned(),
+            user_fn.fn_token_index,
+            from,
+            to,
+        )
+    })?;
+    let f_from = user_fn.call(fn_index, vec![from.clone()])?;
+    // the root is searched for t in [0, 1], x = from + t * (to - from)
+    let g = |t: f64| user_fn.sample(fn_index, from, &step, t, &f_from);
+    let (mut t0, mut g0, mut t1, mut g1) = (0.0, g(0.0)?, 1.0, g(1.0)?);
+    if g0 != 0.0 && g1 != 0.0 && g0.signum() == g1.signum() {
+        return Err(EvalErr::new(
+            "The function must have different signs at the bounds".to_owned(),
+            user_fn.fn_token_index,
+        ));
+    }
+    let mut t = if g0 == 0.0 { t0 } else { t1 };
+    if g0 != 0.0 && g1 != 0.0 {
+        let mut side = 0;
+        for _ in 0..NUMERIC_ROOT_MAX_ITERATION_COUNT {
+            let prev_t = t;
+            t = (t0 * g1 - t1 * g0) / (g1 - g0);
+            let gt = g(t)?;
+            if gt == 0.0 || (t - prev_t).abs() < 1e-15 {
+                break;
+            }
+            if gt.signum() == g1.signum() {
+                t1 = t;
+                g1 = gt;
+                if side == -1 {
+                    g0 /= 2.0;
+                }
+                side = -1;
+            } else {
+                t0 = t;
+                g0 = gt;
+                if side == 1 {
+                    g1 /= 2.0;
+                }
+                side = 1;
+            }
+        }
+    }
+    point_at(from, &step, t)
+        .ok_or_else(|| EvalErr::new("Invalid result".to_owned(), user_fn.fn_token_index))
+}
+
+/// map(v, f): applies `f` to every element of the array `v`
+fn fn_map(user_fn: &UserFnCall, v: &CalcResult, f: &CalcResult) -> Result<CalcResult, EvalErr> {
+    let mat = array_param(v)?;
+    let fn_index = user_fn.fn_index_of(f, 1)?;
+    let cells = mat
+        .cells
+        .iter()
+        .map(|cell| user_fn.call(fn_index, vec![cell.clone()]))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(CalcResult::new(
+        CalcResultType::Matrix(mat.array_with_cells(cells)),
+        v.index_into_tokens,
+    ))
+}
+
+/// filter(v, pred): keeps the elements of `v` for which `pred` returns a non-zero number
+fn fn_filter(
+    user_fn: &UserFnCall,
+    v: &CalcResult,
+    pred: &CalcResult,
+) -> Result<CalcResult, EvalErr> {
+    let mat = array_param(v)?;
+    let fn_index = user_fn.fn_index_of(pred, 1)?;
+    let mut cells = Vec::with_capacity(mat.cells.len());
+    for cell in &mat.cells {
+        match user_fn.call(fn_index, vec![cell.clone()])?.typ {
+            CalcResultType::Number(n) => {
+                if !n.is_zero() {
+                    cells.push(cell.clone());
+                }
+            }
+            _ => {
+                return Err(EvalErr::new2(
+                    "The predicate must return a number".to_owned(),
+                    pred,
+                ));
+            }
+        }
+    }
+    if cells.is_empty() {
+        // empty matrices are not supported
+        return Err(EvalErr::new2("None of the elements matched".to_owned(), v));
+    }
+    Ok(CalcResult::new(
+        CalcResultType::Matrix(mat.array_with_cells(cells)),
+        v.index_into_tokens,
+    ))
+}
+
+/// reduce(v, f, init): folds the elements of `v` into `init` with the two parameter function `f`
+fn fn_reduce(
+    user_fn: &UserFnCall,
+    v: &CalcResult,
+    f: &CalcResult,
+    init: &CalcResult,
+) -> Result<CalcResult, EvalErr> {
+    let mat = array_param(v)?;
+    let fn_index = user_fn.fn_index_of(f, 2)?;
+    let mut acc = init.clone();
+    for cell in &mat.cells {
+        acc = user_fn.call(fn_index, vec![acc, cell.clone()])?;
+    }
+    Ok(CalcResult::new(acc.typ, v.index_into_tokens))
+}
+
+const GOAL_SEEK_MAX_ITERATION_COUNT: usize = 64;
+
+/// Finds the value of `var` for which `expr` equals to `target` (goalseek(expr, var, target)).
+/// Both `expr` and `var` must be variables (or line references), and the lines between
+/// their definitions are re-evaluated for every guess of the secant method.
+fn goal_seek(
+    args: &[CalcResult],
+    line_tokens: &[Token],
+    apptokens: &AppTokens,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions,
+    units: &Units,
+    editor_content: &EditorContent<LineData>,
+    call_depth: usize,
+    fn_token_index: usize,
+    random_seed: RandomSeed,
+) -> Result<CalcResult, EvalErr> {
+    let (expr, var, target) = (&args[0], &args[1], &args[2]);
+    let var_index_of =
+        |arg: &CalcResult| match line_tokens.get(arg.index_into_tokens).map(|it| &it.typ) {
+            Some(TokenType::Variable { var_index })
+            | Some(TokenType::LineReference { var_index })
+                if *var_index < SUM_VARIABLE_INDEX =>
+            {
+                Some(*var_index)
+            }
+            _ => None,
+        };
+    let expr_line = var_index_of(expr)
+        .ok_or_else(|| EvalErr::new2("The first argument must be a variable".to_owned(), expr))?;
+    let var_line = var_index_of(var)
+        .ok_or_else(|| EvalErr::new2("The second argument must be a variable".to_owned(), var))?;
+    if var_line >= expr_line {
+        return Err(EvalErr::new2(
+            "The first argument must be defined after the second one".to_owned(),
+            expr,
+        ));
+    }
+    let guess_with_num = |num: Decimal| match &var.typ {
+        CalcResultType::Number(..) => Some(CalcResult::new(CalcResultType::Number(num), 0)),
+        CalcResultType::Quantity(_num, unit) => Some(CalcResult::new(
+            CalcResultType::Quantity(num, unit.clone()),
+            0,
+        )),
+        _ => None,
+    };
+    let start = match &var.typ {
+        CalcResultType::Number(num) | CalcResultType::Quantity(num, _) => num.clone(),
+        _ => {
+            return Err(EvalErr::new2(
+                "The variable must be a number or a quantity".to_owned(),
+                var,
+            ))
+        }
+    };
+    let target_num = match &target.typ {
+        CalcResultType::Number(num) | CalcResultType::Quantity(num, _) => num.clone(),
+        _ => {
+            return Err(EvalErr::new2(
+                "The target must be a number or a quantity".to_owned(),
+                target,
+            ))
+        }
+    };
+    // the guesses are evaluated in this copy of the variables
+    let mut local_vars = create_vars();
+    local_vars[0..SUM_VARIABLE_INDEX].clone_from_slice(&vars[0..SUM_VARIABLE_INDEX]);
+    local_vars[SUM_VARIABLE_INDEX + 1..VARIABLE_ARR_SIZE]
+        .clone_from_slice(&vars[SUM_VARIABLE_INDEX + 1..VARIABLE_ARR_SIZE]);
+    // expr - target, in the unit of expr
+    let mut residual = |guess: &Decimal| -> Result<Decimal, EvalErr> {
+        // only these lines were overwritten by the previous guess
+        local_vars[var_line..=expr_line].clone_from_slice(&vars[var_line..=expr_line]);
+        if let (Some(var), Some(guess)) = (&mut local_vars[var_line], guess_with_num(*guess)) {
+            var.value = Ok(guess);
+        }
+        for y in var_line + 1..=expr_line {
+            if line_depends_on_fn_param(apptokens, y) {
+                continue;
+            }
+            let result = evaluate_tokens(
+                y,
+                apptokens,
+                &local_vars,
+                func_defs,
+                units,
+                editor_content,
+                call_depth + 1,
+                None,
+                random_seed,
+            )
+            .1;
+            process_variable_assignment_or_line_ref(&result, &mut local_vars, y, editor_content);
+        }
+        local_vars[expr_line]
+            .as_ref()
+            .and_then(|it| it.value.as_ref().ok())
+            .and_then(|value| sub_op(value, target))
+            .and_then(|diff| match diff.typ {
+                CalcResultType::Number(num) | CalcResultType::Quantity(num, _) => Some(num),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                EvalErr::new3(
+                    "The first argument could not be compared to the target".to_owned(),
+                    fn_token_index,
+                    expr,
+                    target,
+                )
+            })
+    };
+
+    let tolerance = target_num.abs().max(Decimal::one()) * Decimal::new(1, 12);
+    let mut x0 = start;
+    let mut f0 = residual(&x0)?;
+    let mut x1 = if start.is_zero() {
+        Decimal::one()
+    } else {
+        start * Decimal::new(11, 1)
+    };
+    for _ in 0..GOAL_SEEK_MAX_ITERATION_COUNT {
+        if f0.abs() <= tolerance {
+            break;
+        }
+        let f1 = residual(&x1)?;
+        // secant method: x2 = x1 - f1 * (x1 - x0) / (f1 - f0)
+        let x2 = (f1 - f0)
+            .checked_div(&(x1 - x0))
+            .filter(|slope| !slope.is_zero())
+            .and_then(|slope| f1.checked_div(&slope))
+            .and_then(|step| x1.checked_sub(&step));
+        x0 = x1;
+        f0 = f1;
+        if let Some(x2) = x2 {
+            x1 = x2;
+        } else {
+            break;
+        }
+    }
+    if f0.abs() <= tolerance {
+        guess_with_num(x0).ok_or_else(|| {
+            EvalErr::new2(
+                "The variable must be a number or a quantity".to_owned(),
+                var,
+            )
+        })
+    } else {
+        Err(EvalErr::new(
+            "goalseek did not converge".to_owned(),
+            fn_token_index,
+        ))
+    }
+}
+
 fn get_token_index_into_tokens(apptokens: &AppTokens, editor_y: usize, i: usize) -> usize {
     let tokens = apptokens[content_y(editor_y)].as_ref().unwrap();
     let shunting_tokens = &tokens.shunting_output_stack;
@@ -685,6 +1596,108 @@ fn apply_unit_to_num(
     }
 }
 
+/// The name of the type of the value in the error messages, e.g. 'Length' or 'Matrix'
+pub fn result_type_name(typ: &CalcResultType) -> String {
+    let unit_type = |unit: &UnitOutput| {
+        if unit.is_unitless() {
+            "Number".to_owned()
+        } else {
+            unit_type_name(&unit.dimensions)
+                .map(|it| it.to_owned())
+                .unwrap_or_else(|| format!("'{}'", unit))
+        }
+    };
+    match typ {
+        CalcResultType::Number(_) => "Number".to_owned(),
+        CalcResultType::Percentage(_) => "Percentage".to_owned(),
+        CalcResultType::Unit(unit)
+        | CalcResultType::Quantity(_, unit)
+        | CalcResultType::Uncertain(_, _, unit)
+        | CalcResultType::Interval(_, _, unit) => unit_type(unit),
+        CalcResultType::Matrix(_) => "Matrix".to_owned(),
+        CalcResultType::Function(_) => "Function".to_owned(),
+    }
+}
+
+/// e.g. "Cannot add Length to Mass" for `12 m + 3 kg`
+fn binary_operation_error(op: &OperatorTokenType, lhs: &CalcResult, rhs: &CalcResult) -> String {
+    let lhs_type = result_type_name(&lhs.typ);
+    let rhs_type = result_type_name(&rhs.typ);
+    let both_matrices = matches!(
+        (&lhs.typ, &rhs.typ),
+        (CalcResultType::Matrix(_), CalcResultType::Matrix(_))
+    );
+    let both_scalars = [lhs, rhs].iter().all(|it| {
+        matches!(
+            it.typ,
+            CalcResultType::Number(_) | CalcResultType::Quantity(..)
+        )
+    });
+    let rhs_is_zero = matches!(
+        &rhs.typ,
+        CalcResultType::Number(n) | CalcResultType::Quantity(n, _) if n.is_zero()
+    );
+    match op {
+        OperatorTokenType::Add | OperatorTokenType::Sub if both_matrices => {
+            "The matrices must have the same size".to_owned()
+        }
+        OperatorTokenType::Mult if both_matrices => {
+            "The column count of the left matrix must be the row count of the right one".to_owned()
+        }
+        OperatorTokenType::Div if rhs_is_zero => "Division by zero".to_owned(),
+        OperatorTokenType::Add | OperatorTokenType::Sub if both_scalars && lhs_type == rhs_type => {
+            "The result is out of range".to_owned()
+        }
+        OperatorTokenType::Mult | OperatorTokenType::Div if both_scalars => {
+            "The result is out of range".to_owned()
+        }
+        OperatorTokenType::Add => format!("Cannot add {} to {}", lhs_type, rhs_type),
+        OperatorTokenType::Sub => format!("Cannot subtract {} from {}", rhs_type, lhs_type),
+        OperatorTokenType::Mult => format!("Cannot multiply {} by {}", lhs_type, rhs_type),
+        OperatorTokenType::Div => format!("Cannot divide {} by {}", lhs_type, rhs_type),
+        OperatorTokenType::Pow => {
+            format!("Cannot raise {} to the power of {}", lhs_type, rhs_type)
+        }
+        OperatorTokenType::UnitConverter => {
+            format!("Cannot convert {} to {}", lhs_type, rhs_type)
+        }
+        OperatorTokenType::BinAnd
+        | OperatorTokenType::BinOr
+        | OperatorTokenType::BinXor
+        | OperatorTokenType::ShiftLeft
+        | OperatorTokenType::ShiftRight => {
+            "Bitwise operations can be applied only to integers".to_owned()
+        }
+        OperatorTokenType::PlusMinus => format!(
+            "The uncertainty ({}) must have the same unit as the value ({})",
+            rhs_type, lhs_type
+        ),
+        OperatorTokenType::Interval => format!(
+            "The bounds of the interval must have the same unit ({} and {})",
+            lhs_type, rhs_type
+        ),
+        _ => format!(
+            "Cannot apply the percentage operation to {} and {}",
+            lhs_type, rhs_type
+        ),
+    }
+}
+
+fn unary_operation_error(op: &OperatorTokenType, top: Option<&CalcResult>) -> String {
+    let top_type = match top {
+        Some(top) => result_type_name(&top.typ),
+        None => return "Not enough operands".to_owned(),
+    };
+    match op {
+        OperatorTokenType::UnaryMinus => format!("Cannot negate {}", top_type),
+        OperatorTokenType::Perc => format!("{} cannot be used as a percentage", top_type),
+        OperatorTokenType::BinNot => {
+            "Bitwise operations can be applied only to integers".to_owned()
+        }
+        _ => format!("Cannot apply the operation to {}", top_type),
+    }
+}
+
 fn unary_operation(
     op: &OperatorTokenType,
     top: &CalcResult,
@@ -714,6 +1727,8 @@ fn binary_operation(
         OperatorTokenType::BinOr => bitwise_or_op(lhs, rhs),
         OperatorTokenType::BinXor => bitwise_xor_op(lhs, rhs),
         OperatorTokenType::Pow => pow_op(lhs, rhs),
+        OperatorTokenType::PlusMinus => plus_minus_op(lhs, rhs),
+        OperatorTokenType::Interval => interval_op(lhs, rhs),
         OperatorTokenType::ShiftLeft => bitwise_shift_left(lhs, rhs),
         OperatorTokenType::ShiftRight => bitwise_shift_right(lhs, rhs),
         OperatorTokenType::Percentage_Find_Base_From_Result_Increase_X => {
@@ -1013,11 +2028,303 @@ fn unary_minus_op(lhs: &CalcResult) -> Option<CalcResult> {
                 lhs.index_into_tokens,
             ))
         }
+        CalcResultType::Uncertain(lhs_num, err, unit) => {
+            // -(12 ± 1)
+            Some(CalcResult::new(
+                CalcResultType::Uncertain(lhs_num.neg(), err.clone(), unit.clone()),
+                lhs.index_into_tokens,
+            ))
+        }
+        CalcResultType::Interval(lo, hi, unit) => {
+            // -(3..5)
+            Some(CalcResult::new(
+                CalcResultType::Interval(hi.neg(), lo.neg(), unit.clone()),
+                lhs.index_into_tokens,
+            ))
+        }
         _ => None, // CalcResultType::Matrix(mat) => CalcResultType::Matrix(mat.neg()),
     }
 }
 
-fn pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+fn as_dms_op(top: &CalcResult, units: &Units) -> Option<CalcResult> {
+    let deg = UnitOutput::new_deg(units);
+    match &top.typ {
+        CalcResultType::Number(num) => {
+            // 47.5 as dms, the number is considered to be in degrees
+            Some(CalcResult::new(
+                CalcResultType::Quantity(num.clone(), deg),
+                top.index_into_tokens,
+            ))
+        }
+        CalcResultType::Quantity(num, unit) if unit.is(UnitType::Angle) => {
+            let converted_num = UnitOutput::convert(unit, &deg, num)?;
+            Some(CalcResult::new(
+                CalcResultType::Quantity(converted_num, deg),
+                top.index_into_tokens,
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn plus_minus_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+    let (num, err, unit) = match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Number(num), CalcResultType::Number(err)) => {
+            // 12 ± 0.5
+            (num.clone(), err.abs(), UnitOutput::new())
+        }
+        (CalcResultType::Number(num), CalcResultType::Percentage(p)) => {
+            // 12 ± 5%
+            (num.clone(), percentage_of(p, num)?.abs(), UnitOutput::new())
+        }
+        (CalcResultType::Quantity(num, unit), CalcResultType::Number(err)) => {
+            // 5 m ± 0.02, the error is in the unit of the value
+            (num.clone(), err.abs(), unit.clone())
+        }
+        (CalcResultType::Quantity(num, unit), CalcResultType::Quantity(err, err_unit)) => {
+            // 5 m ± 2 cm
+            if !unit.is_compatible(err_unit) {
+                return None;
+            }
+            (
+                num.clone(),
+                UnitOutput::convert(err_unit, unit, err)?.abs(),
+                unit.clone(),
+            )
+        }
+        (CalcResultType::Quantity(num, unit), CalcResultType::Percentage(p)) => {
+            // 230 V ± 10%
+            (num.clone(), percentage_of(p, num)?.abs(), unit.clone())
+        }
+        _ => return None,
+    };
+    Some(CalcResult::new(
+        CalcResultType::Uncertain(num, err, unit),
+        0,
+    ))
+}
+
+fn num_with_unit(num: &Decimal, unit: &UnitOutput) -> CalcResult {
+    if unit.is_unitless() {
+        CalcResult::new(CalcResultType::Number(num.clone()), 0)
+    } else {
+        CalcResult::new(CalcResultType::Quantity(num.clone(), unit.clone()), 0)
+    }
+}
+
+/// Splits an operand into its nominal value and its absolute error,
+/// both as a Number or Quantity so the usual operators can be applied on them.
+fn split_uncertain(operand: &CalcResult) -> Option<(CalcResult, CalcResult)> {
+    match &operand.typ {
+        CalcResultType::Number(num) => Some((
+            num_with_unit(num, &UnitOutput::new()),
+            num_with_unit(&Decimal::zero(), &UnitOutput::new()),
+        )),
+        CalcResultType::Quantity(num, unit) => Some((
+            num_with_unit(num, unit),
+            num_with_unit(&Decimal::zero(), unit),
+        )),
+        CalcResultType::Uncertain(num, err, unit) => {
+            Some((num_with_unit(num, unit), num_with_unit(err, unit)))
+        }
+        _ => None,
+    }
+}
+
+fn into_uncertain(value: CalcResult, err: Decimal) -> Option<CalcResult> {
+    match value.typ {
+        CalcResultType::Number(num) => Some(CalcResult::new(
+            CalcResultType::Uncertain(num, err, UnitOutput::new()),
+            0,
+        )),
+        CalcResultType::Quantity(num, unit) => Some(CalcResult::new(
+            CalcResultType::Uncertain(num, err, unit),
+            0,
+        )),
+        _ => None,
+    }
+}
+
+fn num_in_unit_of(value: &CalcResult, reference: &CalcResult) -> Option<Decimal> {
+    match (&value.typ, &reference.typ) {
+        (CalcResultType::Number(num), CalcResultType::Number(..)) => Some(num.clone()),
+        (CalcResultType::Quantity(num, unit), CalcResultType::Quantity(_num, ref_unit)) => {
+            UnitOutput::convert(unit, ref_unit, num)
+        }
+        _ => None,
+    }
+}
+
+/// The magnitude of an error term in the unit of the result
+fn err_term_in_unit_of(term: &CalcResult, value: &CalcResult) -> Option<Decimal> {
+    num_in_unit_of(term, value).map(|it| it.abs())
+}
+
+fn add_in_quadrature(a: &Decimal, b: &Decimal) -> Option<Decimal> {
+    if a.is_zero() {
+        Some(b.abs())
+    } else if b.is_zero() {
+        Some(a.abs())
+    } else {
+        Decimal::from_f64(a.to_f64()?.hypot(b.to_f64()?))
+    }
+}
+
+/// First-order error propagation: the errors of the operands are multiplied by the
+/// partial derivatives of the operation, then the terms are added in quadrature.
+fn uncertain_binary_op(
+    op: &OperatorTokenType,
+    lhs: &CalcResult,
+    rhs: &CalcResult,
+) -> Option<CalcResult> {
+    let (lhs_value, lhs_err) = split_uncertain(lhs)?;
+    let (rhs_value, rhs_err) = split_uncertain(rhs)?;
+    let (value, lhs_term, rhs_term) = match op {
+        OperatorTokenType::Add => (add_op(&lhs_value, &rhs_value)?, lhs_err, rhs_err),
+        OperatorTokenType::Sub => (sub_op(&lhs_value, &rhs_value)?, lhs_err, rhs_err),
+        OperatorTokenType::Mult => (
+            multiply_op(&lhs_value, &rhs_value)?,
+            multiply_op(&lhs_err, &rhs_value)?,
+            multiply_op(&lhs_value, &rhs_err)?,
+        ),
+        OperatorTokenType::Div => {
+            let value = divide_op(&lhs_value, &rhs_value)?;
+            // d(a/b)/db = -(a/b)/b
+            let rhs_term = multiply_op(&divide_op(&value, &rhs_value)?, &rhs_err)?;
+            (value, divide_op(&lhs_err, &rhs_value)?, rhs_term)
+        }
+        _ => return None,
+    };
+    let err = add_in_quadrature(
+        &err_term_in_unit_of(&lhs_term, &value)?,
+        &err_term_in_unit_of(&rhs_term, &value)?,
+    )?;
+    into_uncertain(value, err)
+}
+
+fn uncertain_pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+    let exp = match &rhs.typ {
+        CalcResultType::Number(exp) => exp,
+        _ => return None,
+    };
+    let (lhs_value, lhs_err) = split_uncertain(lhs)?;
+    let value = pow_op(&lhs_value, rhs)?;
+    // d(x^n)/dx = n * x^(n-1)
+    let exp_minus_one = CalcResult::new(CalcResultType::Number(exp - Decimal::one()), 0);
+    let derivative = multiply_op(&pow_op(&lhs_value, &exp_minus_one)?, rhs)?;
+    let err = err_term_in_unit_of(&multiply_op(&derivative, &lhs_err)?, &value)?;
+    into_uncertain(value, err)
+}
+
+fn interval_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+    let (lo, hi, unit) = match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Number(lo), CalcResultType::Number(hi)) => {
+            // 3..5
+            (lo.clone(), hi.clone(), UnitOutput::new())
+        }
+        (CalcResultType::Number(lo), CalcResultType::Quantity(hi, unit)) => {
+            // 3..5 days, the unit belongs to both bounds
+            (lo.clone(), hi.clone(), unit.clone())
+        }
+        (CalcResultType::Quantity(lo, unit), CalcResultType::Number(hi)) => {
+            // 3 days..5
+            (lo.clone(), hi.clone(), unit.clone())
+        }
+        (CalcResultType::Quantity(lo, lo_unit), CalcResultType::Quantity(hi, unit)) => {
+            // 3 days..2 weeks
+            if !lo_unit.is_compatible(unit) {
+                return None;
+            }
+            (
+                UnitOutput::convert(lo_unit, unit, lo)?,
+                hi.clone(),
+                unit.clone(),
+            )
+        }
+        _ => return None,
+    };
+    Some(CalcResult::new(
+        CalcResultType::Interval(lo.min(hi), lo.max(hi), unit),
+        0,
+    ))
+}
+
+/// Splits an operand into its lower and upper bounds,
+/// both as a Number or Quantity so the usual operators can be applied on them.
+fn split_interval(operand: &CalcResult) -> Option<(CalcResult, CalcResult)> {
+    match &operand.typ {
+        CalcResultType::Number(..) | CalcResultType::Quantity(..) => {
+            Some((operand.clone(), operand.clone()))
+        }
+        CalcResultType::Interval(lo, hi, unit) => {
+            Some((num_with_unit(lo, unit), num_with_unit(hi, unit)))
+        }
+        _ => None,
+    }
+}
+
+/// The smallest interval which contains all the candidates, they must have compatible units
+fn enclosing_interval(candidates: &[CalcResult]) -> Option<CalcResult> {
+    let reference = candidates.first()?;
+    let mut lo = num_in_unit_of(reference, reference)?;
+    let mut hi = lo;
+    for candidate in candidates.iter().skip(1) {
+        let num = num_in_unit_of(candidate, reference)?;
+        lo = lo.min(num);
+        hi = hi.max(num);
+    }
+    let unit = match &reference.typ {
+        CalcResultType::Quantity(_num, unit) => unit.clone(),
+        _ => UnitOutput::new(),
+    };
+    Some(CalcResult::new(CalcResultType::Interval(lo, hi, unit), 0))
+}
+
+fn contains_zero(lo: &CalcResult, hi: &CalcResult) -> Option<bool> {
+    let lo = num_in_unit_of(lo, lo)?;
+    let hi = num_in_unit_of(hi, hi)?;
+    Some(lo <= Decimal::zero() && hi >= Decimal::zero())
+}
+
+/// The four basic operations are monotonic in both of their operands (if the divisor
+/// does not contain zero), so the bounds of the result are among the results
+/// of the operation on the bounds.
+fn interval_binary_op(
+    op: &OperatorTokenType,
+    lhs: &CalcResult,
+    rhs: &CalcResult,
+) -> Option<CalcResult> {
+    let (a, b) = split_interval(lhs)?;
+    let (c, d) = split_interval(rhs)?;
+    let op_fn: fn(&CalcResult, &CalcResult) -> Option<CalcResult> = match op {
+        OperatorTokenType::Add => add_op,
+        OperatorTokenType::Sub => sub_op,
+        OperatorTokenType::Mult => multiply_op,
+        OperatorTokenType::Div if !contains_zero(&c, &d)? => divide_op,
+        _ => return None,
+    };
+    enclosing_interval(&[
+        op_fn(&a, &c)?,
+        op_fn(&a, &d)?,
+        op_fn(&b, &c)?,
+        op_fn(&b, &d)?,
+    ])
+}
+
+fn interval_pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+    let (lo, hi) = split_interval(lhs)?;
+    let lo_powered = pow_op(&lo, rhs)?;
+    let hi_powered = pow_op(&hi, rhs)?;
+    if contains_zero(&lo, &hi)? {
+        // e.g. (-2..3)^2 = 0..9
+        let zero = multiply_op(&lo, &CalcResult::zero())?;
+        enclosing_interval(&[lo_powered, hi_powered, pow_op(&zero, rhs)?])
+    } else {
+        enclosing_interval(&[lo_powered, hi_powered])
+    }
+}
+
+pub fn pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     match (&lhs.typ, &rhs.typ) {
         //////////////
         // 1^x
@@ -1040,6 +2347,8 @@ fn pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 0,
             ))
         }
+        (CalcResultType::Uncertain(..), CalcResultType::Number(..)) => uncertain_pow_op(lhs, rhs),
+        (CalcResultType::Interval(..), CalcResultType::Number(..)) => interval_pow_op(lhs, rhs),
         _ => None,
     }
 }
@@ -1055,6 +2364,16 @@ pub fn multiply_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
         | (CalcResultType::Quantity(..), CalcResultType::Unit(..))
         | (CalcResultType::Percentage(..), CalcResultType::Unit(..))
         | (CalcResultType::Matrix(..), CalcResultType::Unit(..)) => None,
+        (CalcResultType::Uncertain(..), CalcResultType::Matrix(mat)) => mat.mult_scalar(lhs),
+        (CalcResultType::Matrix(mat), CalcResultType::Uncertain(..)) => mat.mult_scalar(rhs),
+        (CalcResultType::Interval(..), CalcResultType::Matrix(mat)) => mat.mult_scalar(lhs),
+        (CalcResultType::Matrix(mat), CalcResultType::Interval(..)) => mat.mult_scalar(rhs),
+        (CalcResultType::Uncertain(..), _) | (_, CalcResultType::Uncertain(..)) => {
+            uncertain_binary_op(&OperatorTokenType::Mult, lhs, rhs)
+        }
+        (CalcResultType::Interval(..), _) | (_, CalcResultType::Interval(..)) => {
+            interval_binary_op(&OperatorTokenType::Mult, lhs, rhs)
+        }
         //////////////
         // 12 * x
         //////////////
@@ -1200,6 +2519,12 @@ pub fn add_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
         | (CalcResultType::Quantity(..), CalcResultType::Unit(..))
         | (CalcResultType::Percentage(..), CalcResultType::Unit(..))
         | (CalcResultType::Matrix(..), CalcResultType::Unit(..)) => None,
+        (CalcResultType::Uncertain(..), _) | (_, CalcResultType::Uncertain(..)) => {
+            uncertain_binary_op(&OperatorTokenType::Add, lhs, rhs)
+        }
+        (CalcResultType::Interval(..), _) | (_, CalcResultType::Interval(..)) => {
+            interval_binary_op(&OperatorTokenType::Add, lhs, rhs)
+        }
         //////////////
         // 12 + x
         //////////////
@@ -1311,7 +2636,7 @@ pub fn add_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     }
 }
 
-fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+pub fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     match (&lhs.typ, &rhs.typ) {
         (CalcResultType::Unit(..), CalcResultType::Unit(..))
         | (CalcResultType::Unit(..), CalcResultType::Number(..))
@@ -1322,6 +2647,12 @@ fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
         | (CalcResultType::Quantity(..), CalcResultType::Unit(..))
         | (CalcResultType::Percentage(..), CalcResultType::Unit(..))
         | (CalcResultType::Matrix(..), CalcResultType::Unit(..)) => None,
+        (CalcResultType::Uncertain(..), _) | (_, CalcResultType::Uncertain(..)) => {
+            uncertain_binary_op(&OperatorTokenType::Sub, lhs, rhs)
+        }
+        (CalcResultType::Interval(..), _) | (_, CalcResultType::Interval(..)) => {
+            interval_binary_op(&OperatorTokenType::Sub, lhs, rhs)
+        }
         //////////////
         // 12 - x
         //////////////
@@ -1446,6 +2777,14 @@ pub fn divide_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
         | (CalcResultType::Unit(..), CalcResultType::Percentage(..))
         | (CalcResultType::Unit(..), CalcResultType::Matrix(..))
         | (CalcResultType::Matrix(..), CalcResultType::Unit(..)) => None,
+        (CalcResultType::Matrix(mat), CalcResultType::Uncertain(..)) => mat.div_scalar(rhs),
+        (CalcResultType::Matrix(mat), CalcResultType::Interval(..)) => mat.div_scalar(rhs),
+        (CalcResultType::Uncertain(..), _) | (_, CalcResultType::Uncertain(..)) => {
+            uncertain_binary_op(&OperatorTokenType::Div, lhs, rhs)
+        }
+        (CalcResultType::Interval(..), _) | (_, CalcResultType::Interval(..)) => {
+            interval_binary_op(&OperatorTokenType::Div, lhs, rhs)
+        }
         //////////////
         // 30 years * 12/year
         //////////////
@@ -1642,8 +2981,9 @@ mod tests {
     use crate::borrow_checker_fighter::create_vars;
     use crate::calc::{CalcResult, CalcResultType, EvaluationResult};
     use crate::editor::editor_content::EditorContent;
-    use crate::functions::FnType;
+    use crate::functions::{array_param, FnType};
     use crate::helper::{content_y, AppTokens};
+    use crate::random::RandomSeed;
     use crate::renderer::render_result;
     use crate::token_parser::{OperatorTokenType, Token};
     use bumpalo::Bump;
@@ -1681,6 +3021,7 @@ mod tests {
             &EditorContent::new(120, 120),
             0,
             None,
+            RandomSeed::default(),
         );
         if let Err(err) = result {
             Token::set_token_error_flag_by_index(
@@ -1710,6 +3051,16 @@ mod tests {
     }
 
     fn test_vars(vars: &Variables, text: &str, expected: &str, dec_count: usize) {
+        test_vars_with_format(vars, text, expected, dec_count, &ResultFormat::Dec);
+    }
+
+    fn test_vars_with_format(
+        vars: &Variables,
+        text: &str,
+        expected: &str,
+        dec_count: usize,
+        format: &ResultFormat,
+    ) {
         dbg!("===========================================================");
         dbg!(text);
         let temp = text.chars().collect::<Vec<char>>();
@@ -1760,7 +3111,7 @@ mod tests {
                 render_result(
                     &units,
                     &result.as_ref().unwrap().as_ref().unwrap().result,
-                    &ResultFormat::Dec,
+                    format,
                     *there_was_unit_conversion,
                     Some(dec_count),
                     false,
@@ -1774,7 +3125,7 @@ mod tests {
                     .map(|it| render_result(
                         &units,
                         &it.result,
-                        &ResultFormat::Dec,
+                        format,
                         false,
                         Some(dec_count),
                         false
@@ -1793,6 +3144,16 @@ mod tests {
         test_vars(&create_vars(), text, expected, dec_count);
     }
 
+    fn test_dms(text: &str, expected: &str) {
+        test_vars_with_format(
+            &create_vars(),
+            text,
+            expected,
+            DECIMAL_COUNT,
+            &ResultFormat::Dms,
+        );
+    }
+
     #[test]
     fn calc_tests() {
         test("2^-2", "0.25");
@@ -2179,6 +3540,92 @@ mod tests {
         test_with_dec_count(51, "1 deg in rad", "0.0174532925199432957692369077 rad");
     }
 
+    #[test]
+    fn test_dms_literals() {
+        test("47°29'53\"", "47.4981 deg");
+        test("47° 29′ 53″", "47.4981 deg");
+        test("47d 29m 53s", "47.4981 deg");
+        test("47d29m53s", "47.4981 deg");
+        test("-47°29'53\"", "-47.4981 deg");
+        test("10°30' + 1°30'", "12 deg");
+        test("90° in rad", "1.5708 rad");
+    }
+
+    #[test]
+    fn test_as_dms() {
+        test_dms("47°29'53\" as dms", "47d 29m 53s");
+        test_dms("-47°29'53\" as dms", "-47d 29m 53s");
+        test_dms("47.4980555555 deg as dms", "47d 29m 53s");
+        test_dms("1 rad as dms", "57d 17m 44.81s");
+        test_dms("10.5 as dms", "10d 30m 0s");
+        test_dms("12.5 deg + 30 arcmin as dms", "13d 0m 0s");
+        test_dms("5 m as dms", "Err");
+    }
+
+    #[test]
+    fn test_uncertainty() {
+        test("5 ± 0.2", "5 +- 0.2");
+        test("5 +- 0.2", "5 +- 0.2");
+        test("5 ± -0.2", "5 +- 0.2");
+        test("100 ± 5%", "100 +- 5");
+        test("5 m ± 2 cm", "5 +- 0.02 m");
+        test("5 m ± 0.02", "5 +- 0.02 m");
+        test("-(2 ± 0.1)", "-2 +- 0.1");
+        test("5 ± 2 m", "Err");
+        test("5 m ± 2 s", "Err");
+    }
+
+    #[test]
+    fn test_plus_minus_without_whitespace_is_still_an_addition() {
+        // '1 +- 2' used to be 1 + (-2), now it is an uncertainty
+        test("1 +- 2", "1 +- 2");
+        test("1+-2", "-1");
+        test("1 +-2", "-1");
+        test("1 + -2", "-1");
+    }
+
+    #[test]
+    fn test_uncertainty_propagation() {
+        test("(2 ± 0.1) + (3 ± 0.2)", "5 +- 0.2236");
+        test("(10 ± 1) - (4 ± 1)", "6 +- 1.4142");
+        test("(2 ± 0.1) + 3", "5 +- 0.1");
+        test("(5 m ± 2 cm) * 2", "10 +- 0.04 m");
+        test("(2 ± 0.1) * (3 ± 0.2)", "6 +- 0.5");
+        test("(6 ± 0.3) / (2 ± 0.1)", "3 +- 0.2121");
+        test("(2 ± 0.1)^2", "4 +- 0.4");
+        test("(2 m ± 0.1 m)^2", "4 +- 0.4 m^2");
+        test("(1 m ± 1 cm) + (50 cm ± 1 cm)", "1.5 +- 0.0141 m");
+        test("(3 m ± 0.1 m) / (2 s ± 0.1 s)", "1.5 +- 0.0901 m / s");
+        test("ln(2 ± 0.1)", "0.6931 +- 0.05");
+        test("sin(0 deg ± 1 deg)", "0 +- 0.0175");
+    }
+
+    #[test]
+    fn test_interval() {
+        test("3..5", "3 .. 5");
+        test("5..3", "3 .. 5");
+        test("1.5..2.5", "1.5 .. 2.5");
+        test("3..5 days", "3 .. 5 day");
+        test("3 day..2 week", "0.4286 .. 2 week");
+        test("-(3..5)", "-5 .. -3");
+        test("1 m..5 s", "Err");
+    }
+
+    #[test]
+    fn test_interval_arithmetic() {
+        test("3..5 days * 2", "6 .. 10 day");
+        test("(3..5) + (1..2)", "4 .. 7");
+        test("(3..5) - (1..2)", "1 .. 4");
+        test("(-2..3) * (4..5)", "-10 .. 15");
+        test("(2..4) / (1..2)", "1 .. 4");
+        test("(1..2) / (-1..1)", "Err");
+        test("(-2..3)^2", "0 .. 9");
+        test("(2..3)^2", "4 .. 9");
+        test("3 h..5 h + 30 min", "3.5 .. 5.5 h");
+        test("sum([1..2, 3..4])", "4 .. 6");
+        test("(3..5) + (1 ± 0.1)", "Err");
+    }
+
     #[test]
     fn test_cancelling_out() {
         test("3 (s^-1) * 4 s", "12");
@@ -2421,6 +3868,7 @@ mod tests {
                 CalcResultType::Number(Decimal::from_str("12").unwrap()),
                 0,
             )),
+            unknowns: None,
         });
         test_vars(&vars, "var * 2", "24", 0);
         test_vars(&vars, "var - var", "0", 0);
@@ -2507,6 +3955,50 @@ mod tests {
         test("transpose([1, 2; 3, 4; 5, 6])", "[1, 3, 5; 2, 4, 6]");
     }
 
+    #[test]
+    fn test_func_sort_reverse_unique() {
+        test("sort([3, 1, 2])", "[1, 2, 3]");
+        test("sort([3; 1; 2])", "[1; 2; 3]");
+        test("sort([1 km, 300 m, 2 m])", "[2 m, 300 m, 1 km]");
+        test("sort([1 km, 2])", "Err");
+        test("sort([1, 2; 3, 4])", "Err");
+        test("reverse([1, 2, 3])", "[3, 2, 1]");
+        test("unique([1, 2, 1, 3, 2])", "[1, 2, 3]");
+        test("unique([1 m, 100 cm, 2 m])", "[1 m, 2 m]");
+    }
+
+    #[test]
+    fn test_func_cumsum() {
+        test("cumsum([1, 2, 3, 4])", "[1, 3, 6, 10]");
+        test("cumsum([1 m; 50 cm])", "[1 m; 1.5 m]");
+        test("cumsum([1 m, 2])", "Err");
+    }
+
+    #[test]
+    fn test_matrix_indexing() {
+        test("[1, 2, 3; 4, 5, 6][1, 2]", "6");
+        test("[1, 2, 3; 4, 5, 6][-1, -3]", "4");
+        test("[1, 2, 3; 4, 5, 6][1, :]", "[4, 5, 6]");
+        test("[1, 2, 3; 4, 5, 6][:, 1]", "[2; 5]");
+        test("[1, 2, 3; 4, 5, 6][0]", "[1, 2, 3]");
+        test("[1, 2, 3; 4, 5, 6][:, 1..2]", "[2, 3; 5, 6]");
+        test("[5, 6, 7, 8][1..2]", "[6, 7]");
+        test("[5, 6, 7, 8][1..-1]", "[6, 7, 8]");
+        test("[5; 6; 7; 8][-1]", "8");
+        test("2 * [5, 6, 7][1] + 1", "13");
+    }
+
+    #[test]
+    fn test_matrix_indexing_errors() {
+        test("[5, 6, 7][3]", "Err");
+        test("[5, 6, 7][-4]", "Err");
+        test("[5, 6, 7][1.5]", "Err");
+        test("[5, 6, 7][1 m]", "Err");
+        test("[1, 2; 3, 4][2, 0]", "Err");
+        test("[1, 2; 3, 4][0, 2]", "Err");
+        test("[1, 2; 3, 4][0, 0, 0]", "Err");
+    }
+
     #[test]
     fn test_func_pi() {
         test_with_dec_count(1000, "pi()", "3.1415926535897932384626433833");
@@ -0,0 +1,333 @@
+use crate::calc::CalcResultType;
+use crate::editor::editor::Pos;
+use crate::editor::editor_content::Replacement;
+use crate::functions::FnType;
+use crate::units::consts::{UnitDimensionExponent, BASE_UNIT_DIMENSION_COUNT};
+use crate::units::units::Units;
+use crate::{FunctionDefinitions, Variables};
+use std::cmp::Reverse;
+use strum::IntoEnumIterator;
+
+pub const MAX_COMPLETION_ITEMS: usize = 50;
+
+type Dimensions = [UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT];
+
+const KEYWORDS: [&str; 12] = [
+    "in",
+    "as dms",
+    "is what percent of",
+    "of what",
+    "is what % on",
+    "is what % off",
+    "on what",
+    "off what",
+    "on what is",
+    "off what is",
+    "what plus",
+    "what minus",
+];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CompletionKind {
+    Variable,
+    Function,
+    Unit,
+    Keyword,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompletionItem {
+    // the matched and rendered text, e.g. 'sin'
+    pub label: String,
+    // e.g. the signature of a function or the target of a unit alias
+    pub detail: String,
+    pub kind: CompletionKind,
+    pub insert_text: String,
+    // where the cursor is placed in the inserted text, e.g. between the parentheses of 'sin()'
+    pub cursor_offset: usize,
+}
+
+/// The state of the completion popup, opened by Ctrl+Space
+#[derive(Debug)]
+pub struct Completion {
+    // the start of the completed word
+    pub pos: Pos,
+    pub prefix: String,
+    pub items: Vec<CompletionItem>,
+    pub selected: usize,
+}
+
+/// What precedes the completed word, it decides which items come first
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompletionContext {
+    // e.g. '12 k|'
+    AfterNumber,
+    // e.g. '12 km in |', with the dimensions of the left hand side if they are known
+    AfterIn(Option<Dimensions>),
+    Other,
+}
+
+impl Completion {
+    pub fn select_next(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+        }
+    }
+
+    /// The replacement of the prefix by the selected item and the cursor position after it
+    pub fn accept(&self) -> Option<(Replacement, Pos)> {
+        let item = self.items.get(self.selected)?;
+        Some((
+            Replacement {
+                pos: self.pos,
+                removed_text: self.prefix.clone(),
+                inserted_text: item.insert_text.clone(),
+            },
+            self.pos.add_column(item.cursor_offset),
+        ))
+    }
+}
+
+/// The completion of the word which ends at `pos`, the best matches are at the front.
+/// None if nothing matches.
+pub fn completion_at(
+    pos: Pos,
+    line: &[char],
+    units: &Units,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions,
+) -> Option<Completion> {
+    let column = pos.column.min(line.len());
+    let mut start = column;
+    while start > 0 && (line[start - 1].is_alphanumeric() || line[start - 1] == '_') {
+        start -= 1;
+    }
+    // e.g. '12km', the number is not part of the word
+    while start < column && line[start].is_ascii_digit() {
+        start += 1;
+    }
+    let prefix = &line[start..column];
+    let context = context_of(&line[0..start], units, vars);
+
+    let mut scored_items: Vec<(i32, CompletionItem)> = candidates(pos.row, units, vars, func_defs)
+        .into_iter()
+        .filter_map(|item| {
+            let score = fuzzy_score(prefix, &item.label)?;
+            Some((score + context_bonus(context, &item, units), item))
+        })
+        .collect();
+    if scored_items.is_empty() {
+        return None;
+    }
+    scored_items.sort_by(|(a_score, a), (b_score, b)| {
+        (Reverse(*a_score), a.label.chars().count(), &a.label).cmp(&(
+            Reverse(*b_score),
+            b.label.chars().count(),
+            &b.label,
+        ))
+    });
+    scored_items.truncate(MAX_COMPLETION_ITEMS);
+    Some(Completion {
+        pos: pos.with_column(start),
+        prefix: prefix.iter().collect(),
+        items: scored_items.into_iter().map(|(_, item)| item).collect(),
+        selected: 0,
+    })
+}
+
+/// The chars of the query has to be in the label in the same order (case insensitive).
+/// Matches at the start of the label or of its words and consecutive matches worth more.
+pub fn fuzzy_score(query: &[char], label: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut query_i = 0;
+    let mut prev: Option<(usize, char)> = None;
+    for (i, ch) in label.chars().enumerate() {
+        if query_i == query.len() {
+            break;
+        }
+        let query_ch = query[query_i];
+        if ch.to_lowercase().eq(query_ch.to_lowercase()) {
+            score += 1;
+            if ch == query_ch {
+                score += 1;
+            }
+            match prev {
+                None => score += 10,
+                Some((_, prev_ch)) if prev_ch == ' ' || prev_ch == '_' => score += 5,
+                _ => {}
+            }
+            if query_i > 0 && matches!(prev, Some((prev_i, _)) if prev_i + 1 == i) {
+                score += 3;
+            }
+            query_i += 1;
+        } else if prev.is_none() {
+            // the first char of the label is not matched
+            score -= 5;
+        }
+        prev = Some((i, ch));
+    }
+    if query_i == query.len() {
+        Some(score)
+    } else {
+        None
+    }
+}
+
+fn context_of(text_before: &[char], units: &Units, vars: &Variables) -> CompletionContext {
+    let text: String = text_before.iter().collect();
+    let text = text.trim_end();
+    if text.ends_with(" in") || text.ends_with(")in") {
+        let lhs = text[0..text.len() - 2].trim_end();
+        let last_word: Vec<char> = lhs
+            .chars()
+            .rev()
+            .take_while(|it| !it.is_whitespace() && !it.is_ascii_digit())
+            .collect::<Vec<char>>()
+            .into_iter()
+            .rev()
+            .collect();
+        CompletionContext::AfterIn(dimensions_of(&last_word, units, vars))
+    } else if text.ends_with(|it: char| it.is_ascii_digit()) {
+        CompletionContext::AfterNumber
+    } else {
+        CompletionContext::Other
+    }
+}
+
+/// The dimensions of a unit (e.g. 'km/h') or of a variable with a unit
+fn dimensions_of(name: &[char], units: &Units, vars: &Variables) -> Option<Dimensions> {
+    if name.is_empty() {
+        return None;
+    }
+    let var_value = vars
+        .iter()
+        .filter_map(|it| it.as_ref())
+        .find(|it| &*it.name == name)
+        .and_then(|it| it.value.as_ref().ok());
+    if let Some(value) = var_value {
+        return match &value.typ {
+            CalcResultType::Unit(unit)
+            | CalcResultType::Quantity(_, unit)
+            | CalcResultType::Uncertain(_, _, unit)
+            | CalcResultType::Interval(_, _, unit) => Some(unit.dimensions),
+            _ => None,
+        };
+    }
+    let (unit, parsed_len) = units.parse(name);
+    if parsed_len == name.len() && !unit.is_unitless() {
+        Some(unit.dimensions)
+    } else {
+        None
+    }
+}
+
+fn context_bonus(context: CompletionContext, item: &CompletionItem, units: &Units) -> i32 {
+    match (context, item.kind) {
+        (CompletionContext::AfterIn(Some(dimensions)), CompletionKind::Unit) => {
+            let unit_name = units
+                .aliases
+                .get(item.label.as_str())
+                .copied()
+                .unwrap_or(item.label.as_str());
+            let compatible = units
+                .units
+                .get(unit_name)
+                .map(|it| it.base == dimensions)
+                .unwrap_or(false);
+            if compatible {
+                200
+            } else {
+                100
+            }
+        }
+        (CompletionContext::AfterIn(_), CompletionKind::Unit)
+        | (CompletionContext::AfterNumber, CompletionKind::Unit) => 100,
+        (CompletionContext::AfterNumber, CompletionKind::Keyword) => 50,
+        (CompletionContext::Other, CompletionKind::Variable) => 30,
+        (CompletionContext::Other, CompletionKind::Function) => 20,
+        _ => 0,
+    }
+}
+
+fn candidates(
+    row: usize,
+    units: &Units,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions,
+) -> Vec<CompletionItem> {
+    let mut items = Vec::with_capacity(units.units.len() + units.aliases.len() + 64);
+    // only the variables and functions above the cursor can be used
+    for var in vars[0..row].iter().filter_map(|it| it.as_ref()) {
+        if var.name.starts_with(&['&']) {
+            continue;
+        }
+        let name: String = var.name.iter().collect();
+        items.push(CompletionItem {
+            cursor_offset: var.name.len(),
+            label: name.clone(),
+            detail: String::new(),
+            kind: CompletionKind::Variable,
+            insert_text: name,
+        });
+    }
+    for fd in func_defs[0..row].iter().filter_map(|it| it.as_ref()) {
+        let name: String = fd.func_name.iter().collect();
+        let params: Vec<String> = fd.param_names[0..fd.param_count]
+            .iter()
+            .map(|it| it.iter().collect())
+            .collect();
+        items.push(CompletionItem {
+            detail: format!("{}({})", name, params.join(", ")),
+            insert_text: format!("{}()", name),
+            cursor_offset: fd.func_name.len() + 1,
+            label: name,
+            kind: CompletionKind::Function,
+        });
+    }
+    for fn_type in FnType::iter() {
+        let signature = fn_type.signature();
+        if signature.is_empty() {
+            continue;
+        }
+        let name: String = fn_type.name().iter().collect();
+        items.push(CompletionItem {
+            insert_text: format!("{}()", name),
+            cursor_offset: fn_type.name().len() + 1,
+            label: name,
+            detail: signature.to_owned(),
+            kind: CompletionKind::Function,
+        });
+    }
+    for name in units.units.keys() {
+        items.push(unit_item(name, String::new()));
+    }
+    for (alias, unit_name) in &units.aliases {
+        items.push(unit_item(alias, format!("= {}", unit_name)));
+    }
+    for keyword in KEYWORDS.iter() {
+        items.push(CompletionItem {
+            label: (*keyword).to_owned(),
+            detail: String::new(),
+            kind: CompletionKind::Keyword,
+            insert_text: format!("{} ", keyword),
+            cursor_offset: keyword.chars().count() + 1,
+        });
+    }
+    items
+}
+
+fn unit_item(name: &str, detail: String) -> CompletionItem {
+    CompletionItem {
+        label: name.to_owned(),
+        detail,
+        kind: CompletionKind::Unit,
+        insert_text: name.to_owned(),
+        cursor_offset: name.chars().count(),
+    }
+}
@@ -1,4 +1,6 @@
-use crate::editor::editor_content::{EditDelta, EditorCommand, EditorContent, JumpMode};
+use crate::editor::editor_content::{
+    EditDelta, EditorCommand, EditorContent, JumpMode, Replacement,
+};
 use crate::LineData;
 use std::fmt::Debug;
 use std::ops::{Range, RangeInclusive};
@@ -22,6 +24,7 @@ pub enum EditorInputEvent {
     Backspace,
     Del,
     Tab,
+    F2,
     Char(char),
 }
 
@@ -58,6 +61,14 @@ impl InputModifiers {
         }
     }
 
+    pub fn alt_shift() -> InputModifiers {
+        InputModifiers {
+            shift: true,
+            ctrl: false,
+            alt: true,
+        }
+    }
+
     pub fn shift() -> InputModifiers {
         InputModifiers {
             shift: true,
@@ -325,13 +336,20 @@ impl RowModificationType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 struct CursorData {
-    selection: Selection, // Vec when multicursor
+    selection: Selection,
     last_column_index: usize,
 }
 
 impl CursorData {
+    fn new(selection: Selection) -> CursorData {
+        CursorData {
+            selection,
+            last_column_index: selection.get_cursor_pos().column,
+        }
+    }
+
     #[inline]
     #[allow(dead_code)]
     pub fn set_cursor_pos(&mut self, pos: Pos) {
@@ -371,7 +389,9 @@ pub struct Editor<T: Default + Clone + Debug> {
     modif_time_treshold_expires_at: u32,
     show_cursor: bool,
     pub clipboard: String,
+    // the primary cursor, the smart features (completion, matrix editing etc) work with it
     cursor: CursorData,
+    other_cursors: Vec<CursorData>,
 }
 
 impl<T: Default + Clone + Debug> Editor<T> {
@@ -385,6 +405,7 @@ impl<T: Default + Clone + Debug> Editor<T> {
                 selection: Selection::single_r_c(0, 0),
                 last_column_index: 0,
             },
+            other_cursors: Vec::new(),
             next_blink_at: 0,
             modif_time_treshold_expires_at: 0,
             show_cursor: false,
@@ -400,6 +421,7 @@ impl<T: Default + Clone + Debug> Editor<T> {
         self.time = 0;
         self.cursor.selection = Selection::single_r_c(0, 0);
         self.cursor.last_column_index = 0;
+        self.other_cursors.clear();
         self.next_blink_at = 0;
         self.modif_time_treshold_expires_at = 0;
         self.show_cursor = false;
@@ -458,6 +480,217 @@ impl<T: Default + Clone + Debug> Editor<T> {
 
         let col = x.min(content.line_len(y));
         self.set_cursor_pos_r_c(y, col);
+        self.other_cursors.clear();
+    }
+
+    /// Ctrl+click adds a cursor, or removes it if there is one already at the position
+    pub fn handle_ctrl_click(&mut self, x: usize, y: usize, content: &EditorContent<T>) {
+        let line_count = content.line_count();
+        let y = if y >= line_count { line_count - 1 } else { y };
+        let pos = Pos::from_row_column(y, x.min(content.line_len(y)));
+
+        if let Some(i) = self
+            .other_cursors
+            .iter()
+            .position(|it| it.selection.get_cursor_pos() == pos)
+        {
+            self.other_cursors.remove(i);
+        } else if self.cursor.selection.get_cursor_pos() == pos {
+            if let Some(cursor) = self.other_cursors.pop() {
+                self.cursor = cursor;
+            }
+        } else {
+            self.add_cursor(Selection::single(pos));
+        }
+    }
+
+    pub fn has_multiple_cursors(&self) -> bool {
+        !self.other_cursors.is_empty()
+    }
+
+    pub fn clear_other_cursors(&mut self) {
+        self.other_cursors.clear();
+    }
+
+    pub fn get_other_selections(&self) -> impl Iterator<Item = Selection> + '_ {
+        self.other_cursors.iter().map(|it| it.selection)
+    }
+
+    /// Every selection, including the primary one, in the order of their position
+    pub fn get_selections(&self) -> Vec<Selection> {
+        let mut selections: Vec<Selection> = std::iter::once(&self.cursor)
+            .chain(self.other_cursors.iter())
+            .map(|it| it.selection)
+            .collect();
+        selections.sort_by_key(|it| pos_key(it.get_first()));
+        selections
+    }
+
+    /// The new cursor becomes the primary one
+    fn add_cursor(&mut self, selection: Selection) {
+        let prev_primary = std::mem::replace(&mut self.cursor, CursorData::new(selection));
+        self.other_cursors.push(prev_primary);
+        self.normalize_cursors();
+    }
+
+    /// Removes the cursors overlapping with an other one (the primary is always kept)
+    fn normalize_cursors(&mut self) {
+        let mut kept: Vec<CursorData> = Vec::with_capacity(self.other_cursors.len());
+        for cursor in self.other_cursors.drain(..) {
+            let overlaps = std::iter::once(&self.cursor)
+                .chain(kept.iter())
+                .any(|it| selections_overlap(it.selection, cursor.selection));
+            if !overlaps {
+                kept.push(cursor);
+            }
+        }
+        kept.sort_by_key(|it| pos_key(it.selection.get_first()));
+        self.other_cursors = kept;
+    }
+
+    /// Alt+Shift+Up/Down adds a cursor above the topmost or below the bottommost cursor
+    fn add_cursor_vertically(&mut self, downwards: bool, content: &EditorContent<T>) {
+        let selections = self.get_selections();
+        let row = if downwards {
+            let row = selections[selections.len() - 1].get_cursor_pos().row + 1;
+            if row >= content.line_count() {
+                return;
+            }
+            row
+        } else {
+            let row = selections[0].get_cursor_pos().row;
+            if row == 0 {
+                return;
+            }
+            row - 1
+        };
+        // the column is kept even if the cursor is added to a shorter line
+        let last_column_index = self.cursor.last_column_index;
+        let col = last_column_index.min(content.line_len(row));
+        self.add_cursor(Selection::single_r_c(row, col));
+        self.cursor.last_column_index = last_column_index;
+    }
+
+    /// Ctrl+D with a selection selects the next occurrence of the selected text with a new cursor
+    fn select_next_occurrence(&mut self, content: &EditorContent<T>) {
+        let needle: Vec<char> =
+            match Editor::get_selected_text_single_line(self.cursor.selection, content) {
+                Some(text) if !text.is_empty() => text.to_vec(),
+                _ => return,
+            };
+        let selections = self.get_selections();
+        let from = self.cursor.selection.get_second();
+        let line_count = content.line_count();
+        // the search wraps around and ends in the row where it started
+        for i in 0..=line_count {
+            let row = (from.row + i) % line_count;
+            let line = content.get_line_valid_chars(row);
+            let first_col = if i == 0 { from.column } else { 0 };
+            let found = (first_col..(line.len() + 1).saturating_sub(needle.len())).find(|col| {
+                let range = Selection::range(
+                    Pos::from_row_column(row, *col),
+                    Pos::from_row_column(row, col + needle.len()),
+                );
+                line[*col..].starts_with(&needle)
+                    && !selections
+                        .iter()
+                        .any(|it| it.get_range_ordered() == range.get_range_ordered())
+            });
+            if let Some(col) = found {
+                self.add_cursor(Selection::range(
+                    Pos::from_row_column(row, col),
+                    Pos::from_row_column(row, col + needle.len()),
+                ));
+                return;
+            }
+        }
+    }
+
+    /// Executes the input at every cursor as if it were the only one.
+    /// The cursors are processed from the last one, so the modifications never move
+    /// the cursors still to be processed, and the processed ones are kept as their
+    /// distance from the end of the content until all of them are done.
+    fn for_each_cursor<F>(
+        &mut self,
+        content: &mut EditorContent<T>,
+        mut handle: F,
+    ) -> Option<RowModificationType>
+    where
+        F: FnMut(&mut Editor<T>, &mut EditorContent<T>) -> Option<RowModificationType>,
+    {
+        if self.other_cursors.is_empty() {
+            return handle(self, content);
+        }
+        let mut cursors: Vec<(bool, CursorData)> = std::iter::once((true, self.cursor))
+            .chain(self.other_cursors.drain(..).map(|it| (false, it)))
+            .collect();
+        let before_cursors: Vec<Selection> = cursors.iter().map(|(_, it)| it.selection).collect();
+        cursors.sort_by_key(|(_, it)| std::cmp::Reverse(pos_key(it.selection.get_first())));
+
+        let undo_group_count = self.undo_stack.len();
+        let undo_delta_count = self.undo_stack.last().map(|it| it.len()).unwrap_or(0);
+
+        let mut modif_type: Option<RowModificationType> = None;
+        let mut processed_cursors = Vec::with_capacity(cursors.len());
+        for (is_primary, cursor) in cursors {
+            self.cursor = cursor;
+            let cursor_modif_type = handle(self, content);
+            if let Some(modif_type) = &mut modif_type {
+                modif_type.merge(cursor_modif_type.as_ref());
+            } else {
+                modif_type = cursor_modif_type;
+            }
+            let content_len = offset_of(end_of_content(content), content);
+            let start = content_len - offset_of(self.cursor.selection.start, content);
+            let end = self
+                .cursor
+                .selection
+                .end
+                .map(|end| content_len - offset_of(end, content));
+            processed_cursors.push((is_primary, start, end, self.cursor.last_column_index));
+        }
+
+        let content_len = offset_of(end_of_content(content), content);
+        for (is_primary, start, end, last_column_index) in processed_cursors {
+            let cursor = CursorData {
+                selection: Selection {
+                    start: pos_of(content_len.saturating_sub(start), content),
+                    end: end.map(|end| pos_of(content_len.saturating_sub(end), content)),
+                },
+                last_column_index,
+            };
+            if is_primary {
+                self.cursor = cursor;
+            } else {
+                self.other_cursors.push(cursor);
+            }
+        }
+        self.normalize_cursors();
+
+        // undo restores all the cursors, they are stored in the first delta of the input
+        let first_delta = if self.undo_stack.len() > undo_group_count {
+            self.undo_stack.last_mut().and_then(|it| it.first_mut())
+        } else {
+            self.undo_stack
+                .last_mut()
+                .and_then(|it| it.get_mut(undo_delta_count))
+        };
+        if let Some(delta) = first_delta {
+            delta.before_cursors = before_cursors;
+        }
+        // and redo restores the cursors after the input, they are stored in its last delta
+        let has_new_delta = self.undo_stack.len() > undo_group_count
+            || self.undo_stack.last().map(|it| it.len()).unwrap_or(0) > undo_delta_count;
+        if has_new_delta {
+            let after_cursors = std::iter::once(&self.cursor)
+                .chain(self.other_cursors.iter())
+                .map(|it| it.selection)
+                .collect();
+            if let Some(delta) = self.undo_stack.last_mut().and_then(|it| it.last_mut()) {
+                delta.after_cursors = after_cursors;
+            }
+        }
+        modif_type
     }
 
     pub fn handle_drag(&mut self, x: usize, y: usize, content: &EditorContent<T>) {
@@ -523,7 +756,8 @@ impl<T: Default + Clone + Debug> Editor<T> {
             | EditorInputEvent::End
             | EditorInputEvent::PageUp
             | EditorInputEvent::PageDown
-            | EditorInputEvent::Right => {
+            | EditorInputEvent::Right
+            | EditorInputEvent::F2 => {
                 return;
             }
             EditorInputEvent::Tab => {
@@ -741,6 +975,17 @@ impl<T: Default + Clone + Debug> Editor<T> {
         str: &str,
         content: &mut EditorContent<T>,
         undoable: bool,
+    ) -> Option<RowModificationType> {
+        self.for_each_cursor(content, |editor, content| {
+            editor.insert_text_at_cursor(str, content, undoable)
+        })
+    }
+
+    fn insert_text_at_cursor(
+        &mut self,
+        str: &str,
+        content: &mut EditorContent<T>,
+        undoable: bool,
     ) -> Option<RowModificationType> {
         let selection = self.cursor.selection;
         let cur_pos = selection.get_first();
@@ -770,11 +1015,66 @@ impl<T: Default + Clone + Debug> Editor<T> {
             start_i: self.command_history.len(),
             end_i: self.command_history.len() + 1,
             before_cursor: self.cursor.selection,
+            before_cursors: Vec::new(),
+            after_cursors: Vec::new(),
         };
         self.command_history.push(command);
         return self.execute_user_input(delta, content, undoable);
     }
 
+    /// Replaces the texts as a single undoable step, e.g. for find & replace.
+    /// The replacements must not overlap
+    pub fn replace_undoable(
+        &mut self,
+        mut replacements: Vec<Replacement>,
+        content: &mut EditorContent<T>,
+    ) -> Option<RowModificationType> {
+        if replacements.is_empty() {
+            return None;
+        }
+        replacements.sort_by_key(|it| std::cmp::Reverse(pos_key(it.pos)));
+        self.other_cursors.clear();
+        let delta = EditDelta {
+            start_i: self.command_history.len(),
+            end_i: self.command_history.len() + 1,
+            before_cursor: self.cursor.selection,
+            before_cursors: Vec::new(),
+            after_cursors: Vec::new(),
+        };
+        self.command_history
+            .push(EditorCommand::Replace { replacements });
+        let modif_type = self.execute_user_input(
+            EditDelta {
+                before_cursors: Vec::new(),
+                after_cursors: Vec::new(),
+                ..delta
+            },
+            content,
+            false,
+        );
+        // it is undone on its own, not together with the typing before it
+        self.undo_stack.push(vec![delta]);
+        self.redo_stack.clear();
+        modif_type
+    }
+
+    fn replace_text(content: &mut EditorContent<T>, pos: Pos, removed_text: &str, text: &str) {
+        content.remove_selection(Selection::range(pos, end_pos_of_text(pos, removed_text)));
+        content.insert_str_at(pos, text);
+    }
+
+    fn replacement_modif_type(replacements: &[Replacement]) -> Option<RowModificationType> {
+        let first_row = replacements.iter().map(|it| it.pos.row).min()?;
+        let multiline = replacements
+            .iter()
+            .any(|it| it.removed_text.contains('\n') || it.inserted_text.contains('\n'));
+        if !multiline && replacements.iter().all(|it| it.pos.row == first_row) {
+            Some(RowModificationType::SingleLine(first_row))
+        } else {
+            Some(RowModificationType::AllLinesFrom(first_row))
+        }
+    }
+
     pub fn handle_input_no_undo(
         &mut self,
         input: EditorInputEvent,
@@ -800,14 +1100,27 @@ impl<T: Default + Clone + Debug> Editor<T> {
         content: &mut EditorContent<T>,
         undoable: bool,
     ) -> Option<RowModificationType> {
-        if (input == EditorInputEvent::Char('x') || input == EditorInputEvent::Char('c'))
-            && modifiers.ctrl
-        {
-            Editor::send_selection_to_clipboard(
-                &mut self.clipboard,
-                self.cursor.selection,
-                content,
-            );
+        let copied = (input == EditorInputEvent::Char('x') || input == EditorInputEvent::Char('c'))
+            && modifiers.ctrl;
+        if copied {
+            if self.other_cursors.is_empty() {
+                Editor::send_selection_to_clipboard(
+                    &mut self.clipboard,
+                    self.cursor.selection,
+                    content,
+                );
+            } else {
+                let texts: Vec<String> = self
+                    .get_selections()
+                    .iter()
+                    .map(|it| {
+                        let mut text = String::new();
+                        content.write_selection_into(*it, &mut text);
+                        text
+                    })
+                    .collect();
+                self.clipboard = texts.join("\n");
+            }
         }
 
         match input {
@@ -819,36 +1132,68 @@ impl<T: Default + Clone + Debug> Editor<T> {
             EditorInputEvent::Char(ch) if ch.to_ascii_lowercase() == 'z' && modifiers.ctrl => {
                 self.undo(content)
             }
+            EditorInputEvent::Esc if !self.other_cursors.is_empty() => {
+                self.other_cursors.clear();
+                None
+            }
+            EditorInputEvent::Up | EditorInputEvent::Down if modifiers.alt && modifiers.shift => {
+                self.add_cursor_vertically(input == EditorInputEvent::Down, content);
+                None
+            }
+            EditorInputEvent::Char('d') if modifiers.ctrl && self.cursor.selection.is_range() => {
+                self.select_next_occurrence(content);
+                None
+            }
             _ => {
-                let before_len = self.command_history.len();
-                Editor::create_command(
-                    self.cursor.selection,
-                    &input,
-                    modifiers,
-                    content,
-                    &mut self.command_history,
-                );
-                let after_len = self.command_history.len();
-                return if before_len != after_len {
-                    self.execute_user_input(
-                        EditDelta {
-                            start_i: before_len,
-                            end_i: after_len,
-                            before_cursor: self.cursor.selection,
-                        },
-                        content,
-                        undoable,
-                    )
-                } else {
-                    self.next_blink_at = self.time + EDITOR_CURSOR_TICK_MS;
-                    self.show_cursor = true;
-                    self.handle_navigation_input(&input, modifiers, content);
-                    None
-                };
+                let clipboard = self.clipboard.clone();
+                let modif_type = self.for_each_cursor(content, |editor, content| {
+                    editor.handle_input_at_cursor(input, modifiers, content, undoable)
+                });
+                // cutting at every cursor would overwrite the clipboard with the last cut line
+                if copied {
+                    self.clipboard = clipboard;
+                }
+                modif_type
             }
         }
     }
 
+    fn handle_input_at_cursor(
+        &mut self,
+        input: EditorInputEvent,
+        modifiers: InputModifiers,
+        content: &mut EditorContent<T>,
+        undoable: bool,
+    ) -> Option<RowModificationType> {
+        let before_len = self.command_history.len();
+        Editor::create_command(
+            self.cursor.selection,
+            &input,
+            modifiers,
+            content,
+            &mut self.command_history,
+        );
+        let after_len = self.command_history.len();
+        return if before_len != after_len {
+            self.execute_user_input(
+                EditDelta {
+                    start_i: before_len,
+                    end_i: after_len,
+                    before_cursor: self.cursor.selection,
+                    before_cursors: Vec::new(),
+                    after_cursors: Vec::new(),
+                },
+                content,
+                undoable,
+            )
+        } else {
+            self.next_blink_at = self.time + EDITOR_CURSOR_TICK_MS;
+            self.show_cursor = true;
+            self.handle_navigation_input(&input, modifiers, content);
+            None
+        };
+    }
+
     fn execute_user_input(
         &mut self,
         delta: EditDelta,
@@ -952,6 +1297,20 @@ impl<T: Default + Clone + Debug> Editor<T> {
                     Some(RowModificationType::AllLinesFrom(first.row))
                 }
             }
+            EditorCommand::Replace { replacements } => {
+                for replacement in replacements {
+                    Editor::replace_text(
+                        content,
+                        replacement.pos,
+                        &replacement.removed_text,
+                        &replacement.inserted_text,
+                    );
+                }
+                // the cursor stays where it was, only the line can be shorter
+                let col = pos.column.min(content.line_len(pos.row));
+                cursor.set_selection_save_col(Selection::single(pos.with_column(col)));
+                Editor::<T>::replacement_modif_type(replacements)
+            }
             EditorCommand::SwapLineUpwards => {
                 content.swap_lines_upward(pos.row);
                 cursor.selection = Selection::single(Pos::from_row_column(pos.row - 1, pos.column));
@@ -1315,7 +1674,8 @@ impl<T: Default + Clone + Debug> Editor<T> {
             | EditorInputEvent::Esc
             | EditorInputEvent::Enter
             | EditorInputEvent::Backspace
-            | EditorInputEvent::Tab => {}
+            | EditorInputEvent::Tab
+            | EditorInputEvent::F2 => {}
         };
     }
 
@@ -1332,6 +1692,13 @@ impl<T: Default + Clone + Debug> Editor<T> {
                         sum_modif_type = modif_type;
                     }
                 }
+                if let Some((primary, others)) = delta.before_cursors.split_first() {
+                    self.cursor = CursorData::new(*primary);
+                    self.other_cursors = others.iter().map(|it| CursorData::new(*it)).collect();
+                    self.normalize_cursors();
+                } else {
+                    self.other_cursors.clear();
+                }
             }
             self.redo_stack.push(delta_group);
         };
@@ -1341,6 +1708,7 @@ impl<T: Default + Clone + Debug> Editor<T> {
     pub(super) fn redo(&mut self, content: &mut EditorContent<T>) -> Option<RowModificationType> {
         let mut sum_modif_type: Option<RowModificationType> = None;
         self.show_cursor = true;
+        self.other_cursors.clear();
         if let Some(delta_group) = self.redo_stack.pop() {
             for delta in delta_group.iter() {
                 self.cursor.selection = delta.before_cursor;
@@ -1353,6 +1721,11 @@ impl<T: Default + Clone + Debug> Editor<T> {
                         sum_modif_type = modif_type;
                     }
                 }
+                if let Some((primary, others)) = delta.after_cursors.split_first() {
+                    self.cursor = CursorData::new(*primary);
+                    self.other_cursors = others.iter().map(|it| CursorData::new(*it)).collect();
+                    self.normalize_cursors();
+                }
             }
             self.undo_stack.push(delta_group);
         };
@@ -1541,6 +1914,17 @@ impl<T: Default + Clone + Debug> Editor<T> {
                     Some(RowModificationType::AllLinesFrom(first.row))
                 }
             }
+            EditorCommand::Replace { replacements } => {
+                for replacement in replacements.iter().rev() {
+                    Editor::replace_text(
+                        content,
+                        replacement.pos,
+                        &replacement.inserted_text,
+                        &replacement.removed_text,
+                    );
+                }
+                Editor::<T>::replacement_modif_type(replacements)
+            }
             EditorCommand::Indent { row, space_count } => {
                 content.remove_selection(Selection::range(
                     Pos::from_row_column(*row, 0),
@@ -1576,3 +1960,49 @@ impl<T: Default + Clone + Debug> Editor<T> {
         }
     }
 }
+
+fn pos_key(pos: Pos) -> (usize, usize) {
+    (pos.row, pos.column)
+}
+
+/// The position after the text if it is inserted at `pos`
+pub fn end_pos_of_text(pos: Pos, text: &str) -> Pos {
+    match text.rfind('\n') {
+        Some(i) => Pos::from_row_column(
+            pos.row + text.matches('\n').count(),
+            text[i + 1..].chars().count(),
+        ),
+        None => pos.add_column(text.chars().count()),
+    }
+}
+
+fn selections_overlap(a: Selection, b: Selection) -> bool {
+    let (a_first, a_second) = a.get_range_ordered();
+    let (b_first, b_second) = b.get_range_ordered();
+    pos_key(a_first) <= pos_key(b_second) && pos_key(b_first) <= pos_key(a_second)
+}
+
+fn end_of_content<T: Default + Clone + Debug>(content: &EditorContent<T>) -> Pos {
+    let last_row = content.line_count() - 1;
+    Pos::from_row_column(last_row, content.line_len(last_row))
+}
+
+// the index of the position as if the lines were joined by '\n'
+fn offset_of<T: Default + Clone + Debug>(pos: Pos, content: &EditorContent<T>) -> usize {
+    (0..pos.row)
+        .map(|row| content.line_len(row) + 1)
+        .sum::<usize>()
+        + pos.column
+}
+
+fn pos_of<T: Default + Clone + Debug>(offset: usize, content: &EditorContent<T>) -> Pos {
+    let mut offset = offset;
+    for row in 0..content.line_count() {
+        let line_len = content.line_len(row);
+        if offset <= line_len {
+            return Pos::from_row_column(row, offset);
+        }
+        offset -= line_len + 1;
+    }
+    end_of_content(content)
+}
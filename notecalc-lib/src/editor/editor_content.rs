@@ -67,6 +67,18 @@ pub enum EditorCommand<T: Default + Clone + Debug> {
         removed_text: String,
         is_there_line_overflow: bool,
     },
+    // find & replace, ordered backwards by position so a replacement never moves the next one
+    Replace {
+        replacements: Vec<Replacement>,
+    },
+}
+
+/// A single-line text to be replaced, the result must fit into the line
+#[derive(Debug, Clone)]
+pub struct Replacement {
+    pub pos: Pos,
+    pub removed_text: String,
+    pub inserted_text: String,
 }
 
 #[derive(Debug)]
@@ -74,6 +86,11 @@ pub struct EditDelta {
     pub start_i: usize,
     pub end_i: usize,
     pub before_cursor: Selection,
+    // every selection before a multi-cursor input, the primary one is the first.
+    // Only the first delta of the input has them
+    pub before_cursors: Vec<Selection>,
+    // every selection after a multi-cursor input for redo, only the last delta of the input has them
+    pub after_cursors: Vec<Selection>,
 }
 
 #[derive(Eq, PartialEq, Copy, Clone)]
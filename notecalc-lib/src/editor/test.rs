@@ -3,7 +3,7 @@ mod tests {
     use crate::editor::editor::{
         Editor, EditorInputEvent, InputModifiers, Pos, RowModificationType, Selection,
     };
-    use crate::editor::editor_content::EditorContent;
+    use crate::editor::editor_content::{EditorContent, Replacement};
     use crate::MAX_LINE_COUNT;
 
     const CURSOR_MARKER: char = '█';
@@ -6363,4 +6363,258 @@ interest rate / (12 (1/year))
             expected_content: "❱abcd❰",
         });
     }
+
+    #[test]
+    fn test_typing_with_multiple_cursors() {
+        let mut content = EditorContent::<usize>::new(80, MAX_LINE_COUNT);
+        let mut editor = Editor::new(&mut content);
+        content.init_with("abc\nabc\nabc");
+        editor.set_cursor_pos_r_c(0, 1);
+
+        editor.handle_input_undoable(
+            EditorInputEvent::Down,
+            InputModifiers::alt_shift(),
+            &mut content,
+        );
+        editor.handle_input_undoable(
+            EditorInputEvent::Down,
+            InputModifiers::alt_shift(),
+            &mut content,
+        );
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('X'),
+            InputModifiers::none(),
+            &mut content,
+        );
+
+        assert_eq!(content.get_content(), "aXbc\naXbc\naXbc");
+        assert_eq!(
+            editor.get_selections(),
+            vec![
+                Selection::single_r_c(0, 2),
+                Selection::single_r_c(1, 2),
+                Selection::single_r_c(2, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undo_restores_every_cursor() {
+        let mut content = EditorContent::<usize>::new(80, MAX_LINE_COUNT);
+        let mut editor = Editor::new(&mut content);
+        content.init_with("abc\nabc\nabc");
+        editor.set_cursor_pos_r_c(2, 3);
+
+        editor.handle_input_undoable(
+            EditorInputEvent::Up,
+            InputModifiers::alt_shift(),
+            &mut content,
+        );
+        editor.handle_input_undoable(
+            EditorInputEvent::Up,
+            InputModifiers::alt_shift(),
+            &mut content,
+        );
+        editor.handle_input_undoable(
+            EditorInputEvent::Backspace,
+            InputModifiers::none(),
+            &mut content,
+        );
+        assert_eq!(content.get_content(), "ab\nab\nab");
+
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('z'),
+            InputModifiers::ctrl(),
+            &mut content,
+        );
+        assert_eq!(content.get_content(), "abc\nabc\nabc");
+        assert_eq!(
+            editor.get_selections(),
+            vec![
+                Selection::single_r_c(0, 3),
+                Selection::single_r_c(1, 3),
+                Selection::single_r_c(2, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redo_restores_every_cursor() {
+        let mut content = EditorContent::<usize>::new(80, MAX_LINE_COUNT);
+        let mut editor = Editor::new(&mut content);
+        content.init_with("abc\nabc\nabc");
+        editor.set_cursor_pos_r_c(2, 3);
+
+        editor.handle_input_undoable(
+            EditorInputEvent::Up,
+            InputModifiers::alt_shift(),
+            &mut content,
+        );
+        editor.handle_input_undoable(
+            EditorInputEvent::Up,
+            InputModifiers::alt_shift(),
+            &mut content,
+        );
+        editor.handle_input_undoable(
+            EditorInputEvent::Backspace,
+            InputModifiers::none(),
+            &mut content,
+        );
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('z'),
+            InputModifiers::ctrl(),
+            &mut content,
+        );
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('z'),
+            InputModifiers::ctrl_shift(),
+            &mut content,
+        );
+        assert_eq!(content.get_content(), "ab\nab\nab");
+        assert_eq!(
+            editor.get_selections(),
+            vec![
+                Selection::single_r_c(0, 2),
+                Selection::single_r_c(1, 2),
+                Selection::single_r_c(2, 2),
+            ]
+        );
+
+        // the next input happens at every cursor again
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('X'),
+            InputModifiers::none(),
+            &mut content,
+        );
+        assert_eq!(content.get_content(), "abX\nabX\nabX");
+    }
+
+    #[test]
+    fn test_enter_with_multiple_cursors_in_the_same_line() {
+        let mut content = EditorContent::<usize>::new(80, MAX_LINE_COUNT);
+        let mut editor = Editor::new(&mut content);
+        content.init_with("abcd");
+        editor.set_cursor_pos_r_c(0, 1);
+        editor.handle_ctrl_click(3, 0, &content);
+
+        editor.handle_input_undoable(
+            EditorInputEvent::Enter,
+            InputModifiers::none(),
+            &mut content,
+        );
+
+        assert_eq!(content.get_content(), "a\nbc\nd");
+        assert_eq!(editor.get_selection(), Selection::single_r_c(2, 0));
+        assert_eq!(
+            editor.get_selections(),
+            vec![Selection::single_r_c(1, 0), Selection::single_r_c(2, 0)]
+        );
+    }
+
+    #[test]
+    fn test_ctrl_click_toggles_cursors() {
+        let mut content = EditorContent::<usize>::new(80, MAX_LINE_COUNT);
+        let mut editor = Editor::new(&mut content);
+        content.init_with("abcd\nabcd");
+        editor.set_cursor_pos_r_c(0, 1);
+
+        editor.handle_ctrl_click(2, 1, &content);
+        assert!(editor.has_multiple_cursors());
+        editor.handle_ctrl_click(2, 1, &content);
+        assert!(!editor.has_multiple_cursors());
+        assert_eq!(editor.get_selection(), Selection::single_r_c(0, 1));
+
+        editor.handle_ctrl_click(2, 1, &content);
+        editor.handle_click(0, 0, &content);
+        assert!(!editor.has_multiple_cursors());
+
+        editor.handle_ctrl_click(2, 1, &content);
+        editor.handle_input_undoable(EditorInputEvent::Esc, InputModifiers::none(), &mut content);
+        assert!(!editor.has_multiple_cursors());
+    }
+
+    #[test]
+    fn test_ctrl_d_selects_the_next_occurrence() {
+        let mut content = EditorContent::<usize>::new(80, MAX_LINE_COUNT);
+        let mut editor = Editor::new(&mut content);
+        content.init_with("ab x ab\nab");
+        editor.set_cursor_range(Pos::from_row_column(0, 5), Pos::from_row_column(0, 7));
+
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('d'),
+            InputModifiers::ctrl(),
+            &mut content,
+        );
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('d'),
+            InputModifiers::ctrl(),
+            &mut content,
+        );
+        // every occurrence is selected already
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('d'),
+            InputModifiers::ctrl(),
+            &mut content,
+        );
+        assert_eq!(editor.get_selections().len(), 3);
+
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('Y'),
+            InputModifiers::none(),
+            &mut content,
+        );
+        assert_eq!(content.get_content(), "Y x Y\nY");
+    }
+
+    #[test]
+    fn test_ctrl_d_duplicates_the_line_only_without_selection() {
+        let mut content = EditorContent::<usize>::new(80, MAX_LINE_COUNT);
+        let mut editor = Editor::new(&mut content);
+        content.init_with("ab x ab");
+        editor.set_cursor_pos_r_c(0, 1);
+
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('d'),
+            InputModifiers::ctrl(),
+            &mut content,
+        );
+        assert_eq!(content.get_content(), "ab x ab\nab x ab");
+
+        // with a selection it selects the next occurrence instead
+        editor.set_cursor_range(Pos::from_row_column(0, 0), Pos::from_row_column(0, 2));
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('d'),
+            InputModifiers::ctrl(),
+            &mut content,
+        );
+        assert_eq!(content.get_content(), "ab x ab\nab x ab");
+        assert_eq!(editor.get_selections().len(), 2);
+    }
+
+    #[test]
+    fn test_replace_and_its_undo() {
+        let mut content = EditorContent::<usize>::new(80, MAX_LINE_COUNT);
+        let mut editor = Editor::new(&mut content);
+        content.init_with("a + a\nb");
+        editor.set_cursor_pos_r_c(1, 1);
+
+        let replacement = |column: usize, removed_text: &str, inserted_text: &str| Replacement {
+            pos: Pos::from_row_column(0, column),
+            removed_text: removed_text.to_owned(),
+            inserted_text: inserted_text.to_owned(),
+        };
+        editor.replace_undoable(
+            vec![replacement(0, "a", "xyz"), replacement(4, "a", "")],
+            &mut content,
+        );
+        assert_eq!(content.get_content(), "xyz + \nb");
+        assert_eq!(editor.get_selection(), Selection::single_r_c(1, 1));
+
+        editor.handle_input_undoable(
+            EditorInputEvent::Char('z'),
+            InputModifiers::ctrl(),
+            &mut content,
+        );
+        assert_eq!(content.get_content(), "a + a\nb");
+    }
 }
@@ -0,0 +1,524 @@
+use crate::calc::{pow_op, replace_or_insert_var, CalcResult, CalcResultType, EvalErr};
+use crate::matrix::MatrixData;
+use crate::token_parser::{OperatorTokenType, Token, TokenType};
+use crate::{Variable, Variables};
+use rust_decimal::prelude::*;
+
+/// Solution of a line like `2x + 3 = 11` or `a + b = 10; a - b = 2`.
+/// `values[i]` belongs to `unknowns[i]`, the unknowns are in the order of their first appearance.
+#[derive(Debug)]
+pub struct EquationSolution<'a> {
+    pub unknowns: Vec<&'a [char]>,
+    pub values: Vec<Decimal>,
+    // index of the first '=' token
+    pub token_index: usize,
+}
+
+impl<'a> EquationSolution<'a> {
+    pub fn to_calc_result(&self) -> CalcResult {
+        let typ = if self.values.len() == 1 {
+            CalcResultType::Number(self.values[0])
+        } else {
+            let cells = self
+                .values
+                .iter()
+                .map(|it| CalcResult::new(CalcResultType::Number(*it), self.token_index))
+                .collect();
+            CalcResultType::Matrix(MatrixData::new(cells, 1, self.values.len()))
+        };
+        CalcResult::new(typ, self.token_index)
+    }
+}
+
+// coeffs[i] * unknowns[i] + ... + constant
+#[derive(Debug, Clone)]
+struct LinearExpr {
+    coeffs: Vec<Decimal>,
+    constant: Decimal,
+}
+
+impl LinearExpr {
+    fn constant(num: Decimal) -> LinearExpr {
+        LinearExpr {
+            coeffs: Vec::new(),
+            constant: num,
+        }
+    }
+
+    fn unknown(index: usize) -> LinearExpr {
+        let mut coeffs = vec![Decimal::zero(); index + 1];
+        coeffs[index] = Decimal::one();
+        LinearExpr {
+            coeffs,
+            constant: Decimal::zero(),
+        }
+    }
+
+    fn is_constant(&self) -> bool {
+        self.coeffs.iter().all(|it| it.is_zero())
+    }
+
+    fn coeff(&self, index: usize) -> Decimal {
+        self.coeffs
+            .get(index)
+            .copied()
+            .unwrap_or_else(Decimal::zero)
+    }
+
+    fn combine(
+        &self,
+        other: &LinearExpr,
+        op: fn(&Decimal, &Decimal) -> Option<Decimal>,
+    ) -> Option<LinearExpr> {
+        let len = self.coeffs.len().max(other.coeffs.len());
+        let mut coeffs = Vec::with_capacity(len);
+        for i in 0..len {
+            coeffs.push(op(&self.coeff(i), &other.coeff(i))?);
+        }
+        Some(LinearExpr {
+            coeffs,
+            constant: op(&self.constant, &other.constant)?,
+        })
+    }
+
+    fn map(&self, op: impl Fn(&Decimal) -> Option<Decimal>) -> Option<LinearExpr> {
+        let mut coeffs = Vec::with_capacity(self.coeffs.len());
+        for c in &self.coeffs {
+            coeffs.push(op(c)?);
+        }
+        Some(LinearExpr {
+            coeffs,
+            constant: op(&self.constant)?,
+        })
+    }
+}
+
+struct LinearExprParser<'a, 'b> {
+    tokens: &'b [Token<'a>],
+    index: usize,
+    end: usize,
+    vars: &'b Variables,
+    unknowns: &'b mut Vec<&'a [char]>,
+    error: Option<(usize, &'static str)>,
+}
+
+impl<'a, 'b> LinearExprParser<'a, 'b> {
+    fn peek(&mut self) -> Option<&'b Token<'a>> {
+        while self.index < self.end {
+            let tokens = self.tokens;
+            let token = &tokens[self.index];
+            let skip = match token.typ {
+                TokenType::StringLiteral => {
+                    token.ptr.iter().all(|it| it.is_whitespace())
+                        || token.ptr.starts_with(&['/', '/'])
+                }
+                _ => false,
+            };
+            if !skip {
+                return Some(token);
+            }
+            self.index += 1;
+        }
+        return None;
+    }
+
+    fn peek_op(&mut self) -> Option<&'b OperatorTokenType> {
+        match self.peek() {
+            Some(Token {
+                typ: TokenType::Operator(op),
+                ..
+            }) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn is_at_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    // remembers the first error, the parsing continues so the rest of the line is still validated
+    fn fail(&mut self, token_index: usize, reason: &'static str) -> LinearExpr {
+        if self.error.is_none() {
+            self.error = Some((token_index, reason));
+        }
+        LinearExpr::constant(Decimal::zero())
+    }
+
+    fn checked(&mut self, token_index: usize, expr: Option<LinearExpr>) -> LinearExpr {
+        match expr {
+            Some(expr) => expr,
+            None => self.fail(token_index, "overflow"),
+        }
+    }
+
+    fn parse_sum(&mut self) -> Option<LinearExpr> {
+        let mut lhs = self.parse_product()?;
+        loop {
+            let op: fn(&Decimal, &Decimal) -> Option<Decimal> = match self.peek_op() {
+                Some(OperatorTokenType::Add) => |a, b| a.checked_add(b),
+                Some(OperatorTokenType::Sub) => |a, b| a.checked_sub(b),
+                _ => return Some(lhs),
+            };
+            let op_index = self.index;
+            self.index += 1;
+            let rhs = self.parse_product()?;
+            lhs = self.checked(op_index, lhs.combine(&rhs, op));
+        }
+    }
+
+    fn parse_product(&mut self) -> Option<LinearExpr> {
+        let mut lhs = self.parse_unary()?;
+        loop {
+            let is_div = match self.peek().map(|it| &it.typ) {
+                Some(TokenType::Operator(OperatorTokenType::Mult)) => false,
+                Some(TokenType::Operator(OperatorTokenType::Div)) => true,
+                // implicit multiplication is allowed only after a coefficient: 2x, 2 x, 2(x + 1)
+                Some(TokenType::Operator(OperatorTokenType::ParenOpen))
+                | Some(TokenType::StringLiteral)
+                | Some(TokenType::Unit(..))
+                | Some(TokenType::Variable { .. })
+                | Some(TokenType::NumberLiteral(..))
+                    if lhs.is_constant() =>
+                {
+                    let op_index = self.index;
+                    let rhs = self.parse_power()?;
+                    lhs = self.multiply(op_index, &lhs, &rhs);
+                    continue;
+                }
+                _ => return Some(lhs),
+            };
+            let op_index = self.index;
+            self.index += 1;
+            let rhs = self.parse_unary()?;
+            lhs = if !is_div {
+                self.multiply(op_index, &lhs, &rhs)
+            } else if !rhs.is_constant() {
+                self.fail(op_index, "only linear equations can be solved")
+            } else if rhs.constant.is_zero() {
+                self.fail(op_index, "division by zero")
+            } else {
+                let expr = lhs.map(|it| it.checked_div(&rhs.constant));
+                self.checked(op_index, expr)
+            };
+        }
+    }
+
+    fn multiply(&mut self, op_index: usize, lhs: &LinearExpr, rhs: &LinearExpr) -> LinearExpr {
+        let expr = if lhs.is_constant() {
+            rhs.map(|it| it.checked_mul(&lhs.constant))
+        } else if rhs.is_constant() {
+            lhs.map(|it| it.checked_mul(&rhs.constant))
+        } else {
+            return self.fail(op_index, "only linear equations can be solved");
+        };
+        self.checked(op_index, expr)
+    }
+
+    fn parse_unary(&mut self) -> Option<LinearExpr> {
+        let op = self.peek_op();
+        let op_index = self.index;
+        match op {
+            Some(OperatorTokenType::Sub) | Some(OperatorTokenType::UnaryMinus) => {
+                self.index += 1;
+                let expr = self.parse_unary()?;
+                let expr = expr.map(|it| Some(-*it));
+                Some(self.checked(op_index, expr))
+            }
+            Some(OperatorTokenType::Add) | Some(OperatorTokenType::UnaryPlus) => {
+                self.index += 1;
+                self.parse_unary()
+            }
+            _ => self.parse_power(),
+        }
+    }
+
+    fn parse_power(&mut self) -> Option<LinearExpr> {
+        let base = self.parse_atom()?;
+        if self.peek_op() != Some(&OperatorTokenType::Pow) {
+            return Some(base);
+        }
+        let op_index = self.index;
+        self.index += 1;
+        let exp = self.parse_unary()?;
+        if !exp.is_constant() {
+            return Some(self.fail(op_index, "only linear equations can be solved"));
+        }
+        let exp = exp.constant;
+        if !base.is_constant() {
+            return Some(if exp == Decimal::one() {
+                base
+            } else {
+                self.fail(op_index, "only linear equations can be solved")
+            });
+        }
+        let result = pow_op(
+            &CalcResult::new(CalcResultType::Number(base.constant), op_index),
+            &CalcResult::new(CalcResultType::Number(exp), op_index),
+        )
+        .and_then(|it| match it.typ {
+            CalcResultType::Number(num) => Some(LinearExpr::constant(num)),
+            _ => None,
+        });
+        Some(self.checked(op_index, result))
+    }
+
+    fn parse_atom(&mut self) -> Option<LinearExpr> {
+        let token = self.peek()?;
+        self.index += 1;
+        match &token.typ {
+            TokenType::NumberLiteral(num) => Some(LinearExpr::constant(*num)),
+            TokenType::Variable { var_index } => match self.vars.get(*var_index) {
+                Some(Some(var)) => {
+                    match unknown_value(var, token.ptr).or_else(|| var.value.as_ref().ok()) {
+                        Some(CalcResult {
+                            typ: CalcResultType::Number(num),
+                            ..
+                        }) => Some(LinearExpr::constant(*num)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+            // units (e.g. 'm' or 'b') are not unknowns, so lines like '12 m = 1200 cm' remain untouched
+            TokenType::StringLiteral if is_identifier(token.ptr) => {
+                let index =
+                    if let Some(index) = self.unknowns.iter().position(|it| *it == token.ptr) {
+                        index
+                    } else {
+                        self.unknowns.push(token.ptr);
+                        self.unknowns.len() - 1
+                    };
+                Some(LinearExpr::unknown(index))
+            }
+            TokenType::Operator(OperatorTokenType::ParenOpen) => {
+                let expr = self.parse_sum()?;
+                if self.peek_op() != Some(&OperatorTokenType::ParenClose) {
+                    return None;
+                }
+                self.index += 1;
+                Some(expr)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_identifier(str: &[char]) -> bool {
+    str.first().map(|it| it.is_alphabetic()).unwrap_or(false)
+        && str.iter().all(|it| it.is_alphanumeric() || *it == '_')
+}
+
+/// Returns None if the line is not a linear equation (or a system of them, separated by ';'),
+/// in that case it has to be evaluated as any other line.
+/// A single `name = ...` is an ordinary assignment, not an equation.
+pub fn try_solve_linear_equations<'a>(
+    tokens: &[Token<'a>],
+    vars: &Variables,
+) -> Option<Result<EquationSolution<'a>, EvalErr>> {
+    let mut unknowns: Vec<&'a [char]> = Vec::new();
+    let mut equations: Vec<LinearExpr> = Vec::new();
+    let mut first_assign_index = None;
+    let mut error = None;
+
+    let part_count = tokens
+        .iter()
+        .filter(|it| it.typ == TokenType::Operator(OperatorTokenType::Semicolon))
+        .count()
+        + 1;
+    let all_tokens: &[Token<'a>] = tokens;
+    let mut start = 0;
+    while start <= all_tokens.len() {
+        let end = all_tokens[start..]
+            .iter()
+            .position(|it| it.typ == TokenType::Operator(OperatorTokenType::Semicolon))
+            .map(|it| start + it)
+            .unwrap_or_else(|| all_tokens.len());
+        let part = &all_tokens[start..end];
+        let assign_index = {
+            let mut assign_indices = part
+                .iter()
+                .enumerate()
+                .filter(|(_, it)| it.typ == TokenType::Operator(OperatorTokenType::Assign))
+                .map(|(i, _)| start + i);
+            match (assign_indices.next(), assign_indices.next()) {
+                (Some(i), None) => i,
+                _ => return None,
+            }
+        };
+        if first_assign_index.is_none() {
+            first_assign_index = Some(assign_index);
+        }
+        if part_count == 1 {
+            let is_ws = |token: &Token| token.ptr.iter().all(|ch| ch.is_whitespace());
+            let lhs_tokens = &all_tokens[start..assign_index];
+            let mut lhs = lhs_tokens.iter().filter(|it| !is_ws(it));
+            match (lhs.next(), lhs.next()) {
+                (Some(token), None) if !token.is_number() => return None,
+                _ => {}
+            }
+            // words separated by whitespaces are the name of a variable,
+            // e.g. '2 apples = 5' is an ordinary assignment, while '2x = 5' is an equation
+            let words = match (
+                lhs_tokens.iter().position(|it| !is_ws(it)),
+                lhs_tokens.iter().rposition(|it| !is_ws(it)),
+            ) {
+                (Some(first), Some(last)) => &lhs_tokens[first..=last],
+                _ => &lhs_tokens[0..0],
+            };
+            if words.iter().any(|it| is_ws(it))
+                && words.iter().all(|it| {
+                    matches!(
+                        it.typ,
+                        TokenType::NumberLiteral(..) | TokenType::StringLiteral
+                    )
+                })
+            {
+                return None;
+            }
+        }
+        let mut parse = |from: usize, to: usize| -> Option<LinearExpr> {
+            let mut parser = LinearExprParser {
+                tokens: all_tokens,
+                index: from,
+                end: to,
+                vars,
+                unknowns: &mut unknowns,
+                error: None,
+            };
+            let expr = parser.parse_sum()?;
+            if !parser.is_at_end() {
+                return None;
+            }
+            if error.is_none() {
+                error = parser.error;
+            }
+            Some(expr)
+        };
+        let lhs = parse(start, assign_index)?;
+        let rhs = parse(assign_index + 1, end)?;
+        equations.push(lhs.combine(&rhs, |a, b| a.checked_sub(b))?);
+        start = end + 1;
+    }
+    if unknowns.is_empty() {
+        return None;
+    }
+    let token_index = first_assign_index.unwrap_or(0);
+    if let Some((token_index, reason)) = error {
+        return Some(Err(EvalErr::new(reason.to_owned(), token_index)));
+    }
+
+    let unknown_count = unknowns.len();
+    let rows = equations
+        .iter()
+        .map(|eq| {
+            let mut row: Vec<Decimal> = (0..unknown_count).map(|i| eq.coeff(i)).collect();
+            row.push(-eq.constant);
+            row
+        })
+        .collect();
+    return Some(match solve(rows, unknown_count) {
+        Ok(values) => Ok(EquationSolution {
+            unknowns,
+            values,
+            token_index,
+        }),
+        Err(reason) => Err(EvalErr::new(reason.to_owned(), token_index)),
+    });
+}
+
+/// Gauss-Jordan elimination, every row is [coeff_0, ..., coeff_n-1, rhs]
+fn solve(mut rows: Vec<Vec<Decimal>>, unknown_count: usize) -> Result<Vec<Decimal>, &'static str> {
+    fn is_negligible(num: Decimal) -> bool {
+        // rounding errors of the divisions (e.g. 1/3*3) must not count as a non-zero value
+        num.abs() < Decimal::new(1, 20)
+    }
+    let n = unknown_count;
+    let mut pivot_row = 0;
+    for col in 0..n {
+        let best = (pivot_row..rows.len())
+            .max_by_key(|r| rows[*r][col].abs())
+            .filter(|r| !is_negligible(rows[*r][col]));
+        let best = if let Some(best) = best {
+            best
+        } else {
+            // free variable
+            continue;
+        };
+        rows.swap(pivot_row, best);
+        let pivot = rows[pivot_row][col];
+        for c in col..=n {
+            rows[pivot_row][c] = rows[pivot_row][c].checked_div(&pivot).ok_or("overflow")?;
+        }
+        for r in 0..rows.len() {
+            let factor = rows[r][col];
+            if r == pivot_row || factor.is_zero() {
+                continue;
+            }
+            for c in col..=n {
+                let num = factor
+                    .checked_mul(&rows[pivot_row][c])
+                    .and_then(|it| rows[r][c].checked_sub(&it))
+                    .ok_or("overflow")?;
+                rows[r][c] = num;
+            }
+        }
+        pivot_row += 1;
+    }
+    return if rows[pivot_row..].iter().any(|row| !is_negligible(row[n])) {
+        Err("no solution")
+    } else if pivot_row < n {
+        Err("infinitely many solutions")
+    } else {
+        Ok(rows.iter().take(n).map(|row| row[n]).collect())
+    };
+}
+
+/// The value of `name` if it is one of the solved unknowns of the system of equations in `var`
+pub fn unknown_value<'a>(var: &'a Variable, name: &[char]) -> Option<&'a CalcResult> {
+    let index = var
+        .unknowns
+        .as_ref()?
+        .iter()
+        .position(|it| &it[..] == name)?;
+    match &var.value {
+        Ok(CalcResult {
+            typ: CalcResultType::Matrix(mat),
+            ..
+        }) => Some(mat.cell(0, index)),
+        _ => None,
+    }
+}
+
+/// A single unknown is assigned to the line's variable, so the following lines can refer to it.
+/// The solution of a system is a row vector, available as a line reference,
+/// and its unknowns can be referred to by their names as well.
+pub fn process_equation_solution(
+    solution: &Result<EquationSolution, EvalErr>,
+    vars: &mut Variables,
+    editor_y: usize,
+    line_ref_name: &[char],
+) {
+    match solution {
+        Ok(solution) if solution.unknowns.len() == 1 => {
+            replace_or_insert_var(
+                vars,
+                solution.unknowns[0],
+                solution.to_calc_result(),
+                editor_y,
+            );
+        }
+        Ok(solution) => {
+            replace_or_insert_var(vars, line_ref_name, solution.to_calc_result(), editor_y);
+            if let Some(var) = &mut vars[editor_y] {
+                var.unknowns = Some(solution.unknowns.iter().map(|it| Box::from(*it)).collect());
+            }
+        }
+        Err(_) => {
+            if let Some(var) = &mut vars[editor_y] {
+                var.name = Box::from(line_ref_name);
+                var.value = Err(());
+                var.unknowns = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use crate::editor::editor::Pos;
+use crate::editor::editor_content::{EditorContent, Replacement};
+use regex::{Regex, RegexBuilder};
+use std::fmt::Debug;
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct FindOptions {
+    pub case_sensitive: bool,
+    // the match can't be part of a longer identifier, e.g. 'rate' is not found in 'tax_rate'
+    pub whole_word: bool,
+    pub regex: bool,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FindField {
+    Query,
+    Replacement,
+}
+
+/// The char range of a match in a line
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FindMatch {
+    pub row: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The state of the find bar, opened by Ctrl+F (or by Ctrl+H with the replace field)
+#[derive(Debug)]
+pub struct Find {
+    pub query: String,
+    pub replacement: String,
+    pub options: FindOptions,
+    pub show_replace: bool,
+    pub focused_field: FindField,
+    pub matches: Vec<FindMatch>,
+    pub current_match: Option<usize>,
+    // e.g. an invalid regex
+    pub error: Option<String>,
+    regex: Option<Regex>,
+}
+
+impl Find {
+    pub fn new(query: String, show_replace: bool) -> Find {
+        let mut find = Find {
+            query,
+            replacement: String::new(),
+            options: FindOptions::default(),
+            show_replace,
+            focused_field: if show_replace {
+                FindField::Replacement
+            } else {
+                FindField::Query
+            },
+            matches: Vec::new(),
+            current_match: None,
+            error: None,
+            regex: None,
+        };
+        find.compile();
+        find
+    }
+
+    pub fn focused_text_mut(&mut self) -> &mut String {
+        match self.focused_field {
+            FindField::Query => &mut self.query,
+            FindField::Replacement => &mut self.replacement,
+        }
+    }
+
+    /// Has to be called when the query or the options change
+    pub fn compile(&mut self) {
+        self.error = None;
+        self.regex = if self.query.is_empty() {
+            None
+        } else {
+            let pattern = if self.options.regex {
+                self.query.clone()
+            } else {
+                regex::escape(&self.query)
+            };
+            match RegexBuilder::new(&pattern)
+                .case_insensitive(!self.options.case_sensitive)
+                .build()
+            {
+                Ok(regex) => Some(regex),
+                Err(_) => {
+                    self.error = Some("Invalid regex".to_owned());
+                    None
+                }
+            }
+        };
+    }
+
+    pub fn update_matches<T: Default + Clone + Debug>(&mut self, content: &EditorContent<T>) {
+        self.matches.clear();
+        if let Some(regex) = &self.regex {
+            for row in 0..content.line_count() {
+                let line = content.get_line_valid_chars(row);
+                for (start, end) in find_in_line(regex, line) {
+                    if !self.options.whole_word || is_whole_word(line, start, end) {
+                        self.matches.push(FindMatch { row, start, end });
+                    }
+                }
+            }
+        }
+        self.current_match = match self.current_match {
+            Some(_) if self.matches.is_empty() => None,
+            Some(i) => Some(i.min(self.matches.len() - 1)),
+            None => None,
+        };
+    }
+
+    /// The first match at or after `pos`, wrapping around to the start of the document
+    pub fn select_match_from(&mut self, pos: Pos) -> Option<FindMatch> {
+        self.current_match = self
+            .matches
+            .iter()
+            .position(|it| (it.row, it.start) >= (pos.row, pos.column))
+            .or_else(|| {
+                if self.matches.is_empty() {
+                    None
+                } else {
+                    Some(0)
+                }
+            });
+        self.current_match.map(|i| self.matches[i])
+    }
+
+    pub fn select_next_match(&mut self) -> Option<FindMatch> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        let next = match self.current_match {
+            Some(i) => (i + 1) % self.matches.len(),
+            None => 0,
+        };
+        self.current_match = Some(next);
+        Some(self.matches[next])
+    }
+
+    pub fn select_prev_match(&mut self) -> Option<FindMatch> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        let prev = match self.current_match {
+            Some(0) | None => self.matches.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.current_match = Some(prev);
+        Some(self.matches[prev])
+    }
+
+    /// The replacement of the current match, or of every match if `all` is true.
+    /// In regex mode the replacement can refer to the groups of the match, e.g. `$1`.
+    /// Replacements which would not fit into their line are left out.
+    pub fn replacements<T: Default + Clone + Debug>(
+        &self,
+        content: &EditorContent<T>,
+        all: bool,
+    ) -> Vec<Replacement> {
+        let regex = match &self.regex {
+            Some(regex) => regex,
+            None => return Vec::new(),
+        };
+        let matches: Vec<FindMatch> = if all {
+            self.matches.clone()
+        } else {
+            self.current_match
+                .map(|i| vec![self.matches[i]])
+                .unwrap_or_default()
+        };
+        let mut replacements: Vec<Replacement> = Vec::with_capacity(matches.len());
+        // the row and its length after the replacements so far, the matches are ordered by row
+        let mut row_len: Option<(usize, usize)> = None;
+        for m in matches {
+            let line = content.get_line_valid_chars(m.row);
+            let removed_text: String = line[m.start..m.end].iter().collect();
+            let inserted_text = if self.options.regex {
+                let text: String = line.iter().collect();
+                let mut expanded = String::new();
+                if let Some(captures) = regex
+                    .captures_iter(&text)
+                    .find(|it| char_index(&text, it.get(0).map(|it| it.start())) == m.start)
+                {
+                    captures.expand(&self.replacement, &mut expanded);
+                }
+                expanded
+            } else {
+                self.replacement.clone()
+            };
+            let line_len = match row_len {
+                Some((row, len)) if row == m.row => len,
+                _ => line.len(),
+            };
+            let new_len = line_len - (m.end - m.start) + inserted_text.chars().count();
+            if inserted_text.contains('\n') || new_len > content.max_line_len() {
+                continue;
+            }
+            row_len = Some((m.row, new_len));
+            replacements.push(Replacement {
+                pos: Pos::from_row_column(m.row, m.start),
+                removed_text,
+                inserted_text,
+            });
+        }
+        replacements
+    }
+
+    /// e.g. "2/5", "No results" or the error
+    pub fn status(&self) -> String {
+        if let Some(error) = &self.error {
+            error.clone()
+        } else if self.matches.is_empty() {
+            "No results".to_owned()
+        } else if let Some(i) = self.current_match {
+            format!("{}/{}", i + 1, self.matches.len())
+        } else {
+            format!("{} results", self.matches.len())
+        }
+    }
+}
+
+/// The char ranges of the non-empty matches in the line
+fn find_in_line(regex: &Regex, line: &[char]) -> Vec<(usize, usize)> {
+    let text: String = line.iter().collect();
+    regex
+        .find_iter(&text)
+        .filter(|it| it.start() < it.end())
+        .map(|it| {
+            (
+                char_index(&text, Some(it.start())),
+                char_index(&text, Some(it.end())),
+            )
+        })
+        .collect()
+}
+
+fn char_index(text: &str, byte_index: Option<usize>) -> usize {
+    byte_index.map(|it| text[..it].chars().count()).unwrap_or(0)
+}
+
+fn is_whole_word(line: &[char], start: usize, end: usize) -> bool {
+    let is_identifier_char = |ch: &char| ch.is_alphanumeric() || *ch == '_';
+    let before = start.checked_sub(1).and_then(|i| line.get(i));
+    !before.map(is_identifier_char).unwrap_or(false)
+        && !line.get(end).map(is_identifier_char).unwrap_or(false)
+}
@@ -0,0 +1,138 @@
+use crate::calc::{add_op, CalcResult};
+use crate::editor::editor_content::EditorContent;
+use crate::helper::{content_y, Results};
+use crate::renderer::render_result;
+use crate::units::units::Units;
+use crate::{FunctionDefinitions, LineData, ResultFormat, RENDERED_RESULT_PRECISION};
+use std::ops::Range;
+
+/// The rows which are hidden when a row is folded, for every row: the lines under a header
+/// until the next header of the same or a higher level, or the body of a user function.
+/// The empty lines at the end of a header region remain visible.
+pub fn fold_ranges(
+    editor_content: &EditorContent<LineData>,
+    func_defs: &FunctionDefinitions,
+) -> Vec<Option<Range<usize>>> {
+    let line_count = editor_content.line_count();
+    let mut header_ends: Vec<Option<usize>> = vec![None; line_count];
+    // the rows and levels of the headers whose region has not ended yet, the levels are increasing
+    let mut open_headers: Vec<(usize, usize)> = Vec::new();
+    for row in 0..line_count {
+        if let Some(level) = header_level(editor_content.get_line_valid_chars(row)) {
+            while let Some(&(start, _)) = open_headers.last().filter(|(_, it)| *it >= level) {
+                header_ends[start] = Some(row);
+                open_headers.pop();
+            }
+            open_headers.push((row, level));
+        }
+    }
+    for (start, _) in open_headers {
+        header_ends[start] = Some(line_count);
+    }
+    (0..line_count)
+        .map(|row| {
+            let end = if let Some(fd) = func_defs[row].as_ref() {
+                fd.last_row_index.as_usize() + 1
+            } else {
+                let mut end = header_ends[row]?;
+                while end > row + 1
+                    && editor_content
+                        .get_line_valid_chars(end - 1)
+                        .iter()
+                        .all(|it| it.is_whitespace())
+                {
+                    end -= 1;
+                }
+                end
+            };
+            if end > row + 1 {
+                Some(row + 1..end)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The folded regions as (first row, hidden rows), the folded regions inside them are not listed
+pub fn folded_regions(
+    editor_content: &EditorContent<LineData>,
+    fold_ranges: &[Option<Range<usize>>],
+) -> Vec<(usize, Range<usize>)> {
+    let mut regions = Vec::new();
+    let mut row = 0;
+    while row < fold_ranges.len() {
+        if let (true, Some(range)) = (editor_content.get_data(row).folded, &fold_ranges[row]) {
+            regions.push((row, range.clone()));
+            row = range.end;
+        } else {
+            row += 1;
+        }
+    }
+    regions
+}
+
+/// The first row of the innermost region which contains `row` (or starts at it)
+/// and whose folded state is `folded`
+pub fn region_containing(
+    row: usize,
+    folded: bool,
+    editor_content: &EditorContent<LineData>,
+    fold_ranges: &[Option<Range<usize>>],
+) -> Option<usize> {
+    (0..=row).rev().find(|start| {
+        editor_content.get_data(*start).folded == folded
+            && matches!(fold_ranges.get(*start), Some(Some(range)) if *start == row || range.contains(&row))
+    })
+}
+
+/// The text after the first line of a folded region, e.g. "⋯ 12 lines, total 1 250 $".
+/// The total is shown only if the results of the region can be added together,
+/// the totals of the headers inside the region are not counted again.
+pub fn summary(
+    range: &Range<usize>,
+    editor_content: &EditorContent<LineData>,
+    results: &Results,
+    units: &Units,
+) -> String {
+    let line_count = range.end - range.start;
+    let mut summary = format!(
+        "⋯ {} line{}",
+        line_count,
+        if line_count > 1 { "s" } else { "" }
+    );
+    let mut total: Option<Result<CalcResult, ()>> = None;
+    for row in range.clone() {
+        if header_level(editor_content.get_line_valid_chars(row)).is_some() {
+            continue;
+        }
+        if let Ok(Some(result)) = &results[content_y(row)] {
+            total = Some(match total {
+                None => Ok(result.clone()),
+                Some(Ok(sum)) => add_op(&sum, result).ok_or(()),
+                Some(Err(())) => Err(()),
+            });
+        }
+    }
+    if let Some(Ok(total)) = total {
+        summary.push_str(", total ");
+        summary.push_str(&render_result(
+            units,
+            &total,
+            &ResultFormat::Dec,
+            false,
+            Some(RENDERED_RESULT_PRECISION),
+            true,
+        ));
+    }
+    summary
+}
+
+fn header_level(line: &[char]) -> Option<usize> {
+    let level = line.iter().take_while(|it| **it == '#').count();
+    if level > 0 {
+        Some(level)
+    } else {
+        None
+    }
+}
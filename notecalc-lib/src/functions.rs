@@ -1,8 +1,10 @@
-use crate::calc::{add_op, CalcResult, CalcResultType, EvalErr};
+use crate::calc::{add_op, result_type_name, sub_op, CalcResult, CalcResultType, EvalErr};
+use crate::matrix::MatrixData;
 use crate::token_parser::{DECIMAL_E, DECIMAL_PI};
 use crate::units::consts::UnitType;
 use crate::units::units::{UnitOutput, Units};
 use rust_decimal::prelude::*;
+use std::cmp::Ordering;
 use std::ops::Neg;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -10,9 +12,27 @@ use strum_macros::EnumIter;
 #[derive(PartialEq, Eq, Clone, Copy, Debug, EnumIter)]
 pub enum FnType {
     UserDefined(usize),
+    GoalSeek,
+    Deriv,
+    Integrate,
+    Root,
+    Map,
+    Filter,
+    Reduce,
+    Sort,
+    Reverse,
+    Unique,
+    CumSum,
     Nth,
     Sum,
     Transpose,
+    Rand,
+    RandInt,
+    Normal,
+    Uniform,
+    Triangular,
+    // 3d6, the literal is the function token itself
+    Dice,
     Pi,
     E,
     Ceil,
@@ -50,8 +70,25 @@ impl FnType {
             FnType::Tan => &['t', 'a', 'n'],
             FnType::Atan => &['a', 't', 'a', 'n'],
             FnType::Nth => &['n', 't', 'h'],
+            FnType::GoalSeek => &['g', 'o', 'a', 'l', 's', 'e', 'e', 'k'],
+            FnType::Deriv => &['d', 'e', 'r', 'i', 'v'],
+            FnType::Integrate => &['i', 'n', 't', 'e', 'g', 'r', 'a', 't', 'e'],
+            FnType::Root => &['r', 'o', 'o', 't'],
+            FnType::Map => &['m', 'a', 'p'],
+            FnType::Filter => &['f', 'i', 'l', 't', 'e', 'r'],
+            FnType::Reduce => &['r', 'e', 'd', 'u', 'c', 'e'],
+            FnType::Sort => &['s', 'o', 'r', 't'],
+            FnType::Reverse => &['r', 'e', 'v', 'e', 'r', 's', 'e'],
+            FnType::Unique => &['u', 'n', 'i', 'q', 'u', 'e'],
+            FnType::CumSum => &['c', 'u', 'm', 's', 'u', 'm'],
             FnType::Sum => &['s', 'u', 'm'],
             FnType::Transpose => &['t', 'r', 'a', 'n', 's', 'p', 'o', 's', 'e'],
+            FnType::Rand => &['r', 'a', 'n', 'd'],
+            FnType::RandInt => &['r', 'a', 'n', 'd', 'i', 'n', 't'],
+            FnType::Normal => &['n', 'o', 'r', 'm', 'a', 'l'],
+            FnType::Uniform => &['u', 'n', 'i', 'f', 'o', 'r', 'm'],
+            FnType::Triangular => &['t', 'r', 'i', 'a', 'n', 'g', 'u', 'l', 'a', 'r'],
+            FnType::Dice => &[],
             FnType::Pi => &['p', 'i'],
             FnType::E => &['e'],
             FnType::Ceil => &['c', 'e', 'i', 'l'],
@@ -62,6 +99,114 @@ impl FnType {
         }
     }
 
+    /// The signature shown in the completion popup and in the hover tooltip
+    pub fn signature(&self) -> &'static str {
+        match self {
+            FnType::Abs => "abs(x)",
+            FnType::Sin => "sin(angle)",
+            FnType::Cos => "cos(angle)",
+            FnType::Asin => "asin(x)",
+            FnType::Acos => "acos(x)",
+            FnType::Tan => "tan(angle)",
+            FnType::Atan => "atan(x)",
+            FnType::Nth => "nth(v, i)",
+            FnType::GoalSeek => "goalseek(expr, var, target)",
+            FnType::Deriv => "deriv(f, x)",
+            FnType::Integrate => "integrate(f, from, to)",
+            FnType::Root => "root(f, from, to)",
+            FnType::Map => "map(v, f)",
+            FnType::Filter => "filter(v, pred)",
+            FnType::Reduce => "reduce(v, f, init)",
+            FnType::Sort => "sort(v)",
+            FnType::Reverse => "reverse(v)",
+            FnType::Unique => "unique(v)",
+            FnType::CumSum => "cumsum(v)",
+            FnType::Sum => "sum(v)",
+            FnType::Transpose => "transpose(m)",
+            FnType::Rand => "rand()",
+            FnType::RandInt => "randint(from, to)",
+            FnType::Normal => "normal(mean, sd)",
+            FnType::Uniform => "uniform(from, to)",
+            FnType::Triangular => "triangular(lo, mode, hi)",
+            FnType::Dice => "",
+            FnType::Pi => "pi()",
+            FnType::E => "e()",
+            FnType::Ceil => "ceil(x)",
+            FnType::Ln => "ln(x)",
+            FnType::Lg => "lg(x)",
+            FnType::Log => "log(base, x)",
+            FnType::UserDefined(_) => "",
+        }
+    }
+
+    /// The one line description shown in the hover tooltip
+    pub fn doc(&self) -> &'static str {
+        match self {
+            FnType::Abs => "The absolute value of x",
+            FnType::Sin => "The sine of the angle",
+            FnType::Cos => "The cosine of the angle",
+            FnType::Asin => "The arcsine of x, in radians",
+            FnType::Acos => "The arccosine of x, in radians",
+            FnType::Tan => "The tangent of the angle",
+            FnType::Atan => "The arctangent of x, in radians",
+            FnType::Nth => "The i-th element of the vector, starting from zero",
+            FnType::GoalSeek => "The value of var for which expr equals the target",
+            FnType::Deriv => "The derivative of the user function f at x",
+            FnType::Integrate => "The definite integral of the user function f",
+            FnType::Root => "A root of the user function f between the two bounds",
+            FnType::Map => "Applies the user function f to every element",
+            FnType::Filter => "The elements for which the user function pred is not zero",
+            FnType::Reduce => "Folds the elements into one value with the user function f",
+            FnType::Sort => "The elements in ascending order",
+            FnType::Reverse => "The elements in reverse order",
+            FnType::Unique => "The elements without the repeated ones",
+            FnType::CumSum => "The running totals of the elements",
+            FnType::Sum => "The sum of the elements",
+            FnType::Transpose => "The transpose of the matrix",
+            FnType::Rand => "A random number between 0 and 1",
+            FnType::RandInt => "A random integer between the two bounds",
+            FnType::Normal => "A random number from the normal distribution",
+            FnType::Uniform => "A random number between the two bounds",
+            FnType::Triangular => "A random number from the triangular distribution",
+            FnType::Dice => "The sum of the dice rolls, e.g. 3d6",
+            FnType::Pi => "The ratio of a circle's circumference to its diameter",
+            FnType::E => "Euler's number, the base of the natural logarithm",
+            FnType::Ceil => "The smallest integer greater than or equal to x",
+            FnType::Ln => "The natural logarithm of x",
+            FnType::Lg => "The base 2 logarithm of x",
+            FnType::Log => "The logarithm of x in the given base",
+            FnType::UserDefined(_) => "",
+        }
+    }
+
+    /// These builtins get a user function as an argument, e.g. deriv(f, 2) or map(v, f)
+    #[inline]
+    pub fn accepts_fn_arg(&self) -> bool {
+        matches!(
+            self,
+            FnType::Deriv
+                | FnType::Integrate
+                | FnType::Root
+                | FnType::Map
+                | FnType::Filter
+                | FnType::Reduce
+        )
+    }
+
+    /// These builtins draw random numbers, their result depends on the seed of the line
+    #[inline]
+    pub fn is_random(&self) -> bool {
+        matches!(
+            self,
+            FnType::Rand
+                | FnType::RandInt
+                | FnType::Normal
+                | FnType::Uniform
+                | FnType::Triangular
+                | FnType::Dice
+        )
+    }
+
     #[inline]
     pub fn execute<'text_ptr>(
         &self,
@@ -76,6 +221,12 @@ impl FnType {
             }),
             FnType::Nth => arg_count_limited_fn(2, arg_count, stack, fn_token_index, fn_nth),
             FnType::Sum => arg_count_limited_fn(1, arg_count, stack, fn_token_index, fn_sum),
+            FnType::Sort => arg_count_limited_fn(1, arg_count, stack, fn_token_index, fn_sort),
+            FnType::Reverse => {
+                arg_count_limited_fn(1, arg_count, stack, fn_token_index, fn_reverse)
+            }
+            FnType::Unique => arg_count_limited_fn(1, arg_count, stack, fn_token_index, fn_unique),
+            FnType::CumSum => arg_count_limited_fn(1, arg_count, stack, fn_token_index, fn_cumsum),
             FnType::Transpose => {
                 arg_count_limited_fn(1, arg_count, stack, fn_token_index, fn_transpose)
             }
@@ -119,6 +270,25 @@ impl FnType {
             FnType::UserDefined(_i) => {
                 panic!("User fn is handled manually")
             }
+            FnType::GoalSeek => {
+                panic!("goalseek is handled manually, it has to re-evaluate other lines")
+            }
+            FnType::Deriv
+            | FnType::Integrate
+            | FnType::Root
+            | FnType::Map
+            | FnType::Filter
+            | FnType::Reduce => {
+                panic!("it is handled manually, it has to call a user function")
+            }
+            FnType::Rand
+            | FnType::RandInt
+            | FnType::Normal
+            | FnType::Uniform
+            | FnType::Triangular
+            | FnType::Dice => {
+                panic!("it is handled manually, it needs the random seed of the line")
+            }
         }
     }
 }
@@ -150,17 +320,30 @@ where
     return if expected_arg_count != arg_count {
         if arg_count > 0 {
             Err(EvalErr::new(
-                "Illegal argument".to_owned(),
+                wrong_arg_count_msg(expected_arg_count, arg_count),
                 stack[stack.len() - 1].get_index_into_tokens(),
             ))
         } else {
-            Err(EvalErr::new("Illegal argument".to_owned(), fn_token_index))
+            Err(EvalErr::new(
+                wrong_arg_count_msg(expected_arg_count, 0),
+                fn_token_index,
+            ))
         }
     } else {
         action(stack)
     };
 }
 
+/// e.g. "Expected 1 argument, provided 2"
+pub fn wrong_arg_count_msg(expected_arg_count: usize, arg_count: usize) -> String {
+    format!(
+        "Expected {} argument{}, provided {}",
+        expected_arg_count,
+        if expected_arg_count == 1 { "" } else { "s" },
+        arg_count
+    )
+}
+
 fn fn_single_param_f64<'text_ptr, F>(stack: &mut Vec<CalcResult>, action: F) -> Result<(), EvalErr>
 where
     F: Fn(f64) -> f64,
@@ -179,18 +362,48 @@ where
                 Ok(())
             } else {
                 Err(EvalErr::new2(
-                    "Number cannot be represented as f64".to_owned(),
+                    "The number is out of range".to_owned(),
+                    param,
+                ))
+            }
+        }
+        CalcResultType::Uncertain(num, err, unit) if unit.is_unitless() => {
+            if let Some((result, result_err)) = uncertain_f64(num, err, &action) {
+                let token_index = param.get_index_into_tokens();
+                stack.pop();
+                stack.push(CalcResult::new(
+                    CalcResultType::Uncertain(result, result_err, UnitOutput::new()),
+                    token_index,
+                ));
+                Ok(())
+            } else {
+                Err(EvalErr::new2(
+                    "The number is out of range".to_owned(),
                     param,
                 ))
             }
         }
         _ => Err(EvalErr::new2(
-            "Only numbers are supported currently".to_owned(),
+            "The argument must be a number".to_owned(),
             param,
         )),
     }
 }
 
+/// Applies `action` on an uncertain value, the error is propagated by
+/// the derivative of `action` (approximated by a central difference).
+fn uncertain_f64<F>(num: &Decimal, err: &Decimal, action: &F) -> Option<(Decimal, Decimal)>
+where
+    F: Fn(f64) -> f64,
+{
+    let x = num.to_f64()?;
+    let h = x.abs().max(1.0) * 1e-6;
+    let derivative = (action(x + h) - action(x - h)) / (2.0 * h);
+    let result = Decimal::from_f64(action(x))?;
+    let result_err = Decimal::from_f64((derivative * err.to_f64()?).abs())?;
+    Some((result, result_err))
+}
+
 fn fn_double_param_f64<'text_ptr, F>(
     stack: &mut Vec<CalcResult>,
     fn_token_index: usize,
@@ -214,26 +427,26 @@ where
                     Ok(())
                 } else {
                     Err(EvalErr::new(
-                        "The result cannot be converted from f64 to Decimal".to_owned(),
+                        "The result is out of range".to_owned(),
                         fn_token_index,
                     ))
                 }
             } else if p1_f64.is_none() {
                 // TODO: format!
                 Err(EvalErr::new2(
-                    "The first arg could not be represented as f64".to_owned(),
+                    "The first argument is out of range".to_owned(),
                     first_param,
                 ))
             } else {
                 // TODO: format!
                 Err(EvalErr::new2(
-                    "The second arg could not be represented as f64".to_owned(),
+                    "The second argument is out of range".to_owned(),
                     second_param,
                 ))
             }
         }
         _ => Err(EvalErr::new3(
-            "Only numbers are supported currently".to_owned(),
+            "The argument must be a number".to_owned(),
             fn_token_index,
             first_param,
             second_param,
@@ -254,8 +467,10 @@ where
         CalcResultType::Quantity(num, unit) if unit.is(UnitType::Angle) => {
             // TODO make it const
             let rad_unit = UnitOutput::new_rad(units);
-            let rad = UnitOutput::convert(unit, &rad_unit, num)
-                .ok_or(EvalErr::new2("Could not convert to rad".to_owned(), param))?;
+            let rad = UnitOutput::convert(unit, &rad_unit, num).ok_or(EvalErr::new2(
+                "The argument must be an angle or a number".to_owned(),
+                param,
+            ))?;
             if let Some(result) = rad
                 .to_f64()
                 .map(|it| action(it))
@@ -267,13 +482,38 @@ where
                 Ok(())
             } else {
                 Err(EvalErr::new2(
-                    "Param or result could not be represented as f64".to_owned(),
+                    "The argument or the result is out of range".to_owned(),
+                    param,
+                ))
+            }
+        }
+        CalcResultType::Uncertain(num, err, unit) if unit.is(UnitType::Angle) => {
+            let rad_unit = UnitOutput::new_rad(units);
+            let rad = UnitOutput::convert(unit, &rad_unit, num).ok_or(EvalErr::new2(
+                "The argument must be an angle or a number".to_owned(),
+                param,
+            ))?;
+            let rad_err = UnitOutput::convert(unit, &rad_unit, err).ok_or(EvalErr::new2(
+                "The argument must be an angle or a number".to_owned(),
+                param,
+            ))?;
+            if let Some((result, result_err)) = uncertain_f64(&rad, &rad_err, &action) {
+                let token_index = param.get_index_into_tokens();
+                stack.pop();
+                stack.push(CalcResult::new(
+                    CalcResultType::Uncertain(result, result_err, UnitOutput::new()),
+                    token_index,
+                ));
+                Ok(())
+            } else {
+                Err(EvalErr::new2(
+                    "The argument or the result is out of range".to_owned(),
                     param,
                 ))
             }
         }
         _ => Err(EvalErr::new2(
-            "Only numbers are supported currently".to_owned(),
+            "The argument must be an angle or a number".to_owned(),
             param,
         )),
     }
@@ -291,7 +531,10 @@ where
     match &param.typ {
         CalcResultType::Number(num) => {
             if num > &Decimal::one() || num < &Decimal::one().neg() {
-                return Err(EvalErr::new2("".to_owned(), param));
+                return Err(EvalErr::new2(
+                    "The argument must be between -1 and 1".to_owned(),
+                    param,
+                ));
             }
             if let Some(result) = num
                 .to_f64()
@@ -307,13 +550,35 @@ where
                 Ok(())
             } else {
                 Err(EvalErr::new2(
-                    "Param or result could not be represented as f64".to_owned(),
+                    "The argument or the result is out of range".to_owned(),
+                    param,
+                ))
+            }
+        }
+        CalcResultType::Uncertain(num, err, unit) if unit.is_unitless() => {
+            if num > &Decimal::one() || num < &Decimal::one().neg() {
+                return Err(EvalErr::new2(
+                    "The argument must be between -1 and 1".to_owned(),
+                    param,
+                ));
+            }
+            if let Some((result, result_err)) = uncertain_f64(num, err, &action) {
+                let token_index = param.get_index_into_tokens();
+                stack.pop();
+                stack.push(CalcResult::new(
+                    CalcResultType::Uncertain(result, result_err, UnitOutput::new_rad(units)),
+                    token_index,
+                ));
+                Ok(())
+            } else {
+                Err(EvalErr::new2(
+                    "The argument or the result is out of range".to_owned(),
                     param,
                 ))
             }
         }
         _ => Err(EvalErr::new2(
-            "Only numbers are supported currently".to_owned(),
+            "The argument must be a number".to_owned(),
             param,
         )),
     }
@@ -336,7 +601,7 @@ where
             Ok(())
         }
         _ => Err(EvalErr::new2(
-            "Only numbers are supported currently".to_owned(),
+            "The argument must be a number".to_owned(),
             param,
         )),
     }
@@ -350,7 +615,7 @@ fn fn_nth<'text_ptr>(stack: &mut Vec<CalcResult>) -> Result<(), EvalErr> {
             if let Some(index) = n.to_u32() {
                 if mat.col_count < (index + 1) as usize {
                     Err(EvalErr::new(
-                        "Index is out of range".to_owned(),
+                        "The index is out of range".to_owned(),
                         index_token.get_index_into_tokens(),
                     ))
                 } else {
@@ -361,17 +626,17 @@ fn fn_nth<'text_ptr>(stack: &mut Vec<CalcResult>) -> Result<(), EvalErr> {
                 }
             } else {
                 Err(EvalErr::new(
-                    "Index must be zero or a positive integer".to_owned(),
+                    "The index must be zero or a positive integer".to_owned(),
                     index_token.get_index_into_tokens(),
                 ))
             }
         }
         (CalcResultType::Number(_), _) => Err(EvalErr::new(
-            "Second param must be a Matrix".to_owned(),
+            "The first argument must be a matrix".to_owned(),
             mat_token.get_index_into_tokens(),
         )),
         (_, CalcResultType::Matrix(_)) => Err(EvalErr::new(
-            "First param must be zero or a positive integer".to_owned(),
+            "The index must be zero or a positive integer".to_owned(),
             index_token.get_index_into_tokens(),
         )),
         _ => Err(EvalErr::new(
@@ -392,7 +657,11 @@ fn fn_sum(stack: &mut Vec<CalcResult>) -> Result<(), EvalErr> {
                     sum = result;
                 } else {
                     return Err(EvalErr::new(
-                        format!("'{:?}' + '{:?}' failed", &sum, &cell),
+                        format!(
+                            "Cannot add {} to {}",
+                            result_type_name(&sum.typ),
+                            result_type_name(&cell.typ)
+                        ),
                         cell.get_index_into_tokens(),
                     ));
                 }
@@ -401,10 +670,112 @@ fn fn_sum(stack: &mut Vec<CalcResult>) -> Result<(), EvalErr> {
             stack.push(sum);
             Ok(())
         }
-        _ => Err(EvalErr::new2("Param must be a matrix".to_owned(), param)),
+        _ => Err(EvalErr::new2(
+            "The argument must be a matrix".to_owned(),
+            param,
+        )),
+    }
+}
+
+pub fn array_param(param: &CalcResult) -> Result<&MatrixData, EvalErr> {
+    match &param.typ {
+        CalcResultType::Matrix(mat) if mat.is_array() => Ok(mat),
+        _ => Err(EvalErr::new2(
+            "The argument must be a single row or single column matrix".to_owned(),
+            param,
+        )),
+    }
+}
+
+fn replace_array_param(stack: &mut Vec<CalcResult>, cells: Vec<CalcResult>) {
+    let param = stack.pop().unwrap();
+    let index_into_tokens = param.get_index_into_tokens();
+    if let CalcResultType::Matrix(mat) = &param.typ {
+        stack.push(CalcResult::new(
+            CalcResultType::Matrix(mat.array_with_cells(cells)),
+            index_into_tokens,
+        ));
     }
 }
 
+// e.g. 1 km > 500 m
+pub fn compare_results(lhs: &CalcResult, rhs: &CalcResult) -> Option<Ordering> {
+    match sub_op(lhs, rhs)?.typ {
+        CalcResultType::Number(diff)
+        | CalcResultType::Percentage(diff)
+        | CalcResultType::Quantity(diff, _) => Some(diff.cmp(&Decimal::zero())),
+        _ => None,
+    }
+}
+
+fn fn_sort(stack: &mut Vec<CalcResult>) -> Result<(), EvalErr> {
+    let param = &stack[stack.len() - 1];
+    let mut cells = array_param(param)?.cells.clone();
+    let mut incomparable = false;
+    cells.sort_by(|a, b| {
+        compare_results(a, b).unwrap_or_else(|| {
+            incomparable = true;
+            Ordering::Equal
+        })
+    });
+    if incomparable {
+        return Err(EvalErr::new2(
+            "The elements can not be compared".to_owned(),
+            param,
+        ));
+    }
+    replace_array_param(stack, cells);
+    Ok(())
+}
+
+fn fn_reverse(stack: &mut Vec<CalcResult>) -> Result<(), EvalErr> {
+    let param = &stack[stack.len() - 1];
+    let cells = array_param(param)?.cells.iter().rev().cloned().collect();
+    replace_array_param(stack, cells);
+    Ok(())
+}
+
+fn fn_unique(stack: &mut Vec<CalcResult>) -> Result<(), EvalErr> {
+    let param = &stack[stack.len() - 1];
+    let mut cells: Vec<CalcResult> = Vec::new();
+    for cell in &array_param(param)?.cells {
+        // different types (e.g. 1 m and 1 kg) are considered different
+        if !cells
+            .iter()
+            .any(|it| compare_results(it, cell) == Some(Ordering::Equal))
+        {
+            cells.push(cell.clone());
+        }
+    }
+    replace_array_param(stack, cells);
+    Ok(())
+}
+
+fn fn_cumsum(stack: &mut Vec<CalcResult>) -> Result<(), EvalErr> {
+    let param = &stack[stack.len() - 1];
+    let mat = array_param(param)?;
+    let mut cells: Vec<CalcResult> = Vec::with_capacity(mat.cells.len());
+    for cell in &mat.cells {
+        let sum = if let Some(prev) = cells.last() {
+            add_op(prev, cell).ok_or_else(|| {
+                EvalErr::new(
+                    format!(
+                        "Cannot add {} to {}",
+                        result_type_name(&prev.typ),
+                        result_type_name(&cell.typ)
+                    ),
+                    cell.get_index_into_tokens(),
+                )
+            })?
+        } else {
+            cell.clone()
+        };
+        cells.push(sum);
+    }
+    replace_array_param(stack, cells);
+    Ok(())
+}
+
 fn fn_transpose(stack: &mut Vec<CalcResult>) -> Result<(), EvalErr> {
     let param = &stack[stack.len() - 1];
     let index_into_tokens = param.get_index_into_tokens();
@@ -419,6 +790,9 @@ fn fn_transpose(stack: &mut Vec<CalcResult>) -> Result<(), EvalErr> {
         stack.push(CalcResult::new(transposed, index_into_tokens));
         Ok(())
     } else {
-        Err(EvalErr::new2("Param must be a matrix".to_owned(), param))
+        Err(EvalErr::new2(
+            "The argument must be a matrix".to_owned(),
+            param,
+        ))
     }
 }
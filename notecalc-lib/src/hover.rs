@@ -0,0 +1,266 @@
+use crate::calc::CalcResult;
+use crate::editor::editor::Pos;
+use crate::editor::editor_content::EditorContent;
+use crate::equation::unknown_value;
+use crate::functions::FnType;
+use crate::helper::{content_y, AppTokens, Results};
+use crate::rename::token_ranges;
+use crate::renderer::render_result;
+use crate::token_parser::{OperatorTokenType, TokenType};
+use crate::units::consts::{
+    get_base_unit_for, UnitDimensionExponent, BASE_UNIT_DIMENSIONS, BASE_UNIT_DIMENSION_COUNT,
+};
+use crate::units::units::{UnitOutput, Units};
+use crate::{
+    local_var_name, FunctionDefinitions, LineData, ResultFormat, Variables,
+    FIRST_FUNC_PARAM_VAR_INDEX, MAX_LINE_COUNT, RENDERED_RESULT_PRECISION,
+};
+use rust_decimal::prelude::*;
+
+const DIMENSION_SYMBOLS: [&str; BASE_UNIT_DIMENSION_COUNT] =
+    ["M", "L", "T", "I", "Θ", "J", "N", "rad", "bit", "$"];
+
+/// The tooltip which is shown while the mouse is over a token in the editor
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Tooltip {
+    pub row: usize,
+    // the rendered x of the hovered editor object, relative to the editor area
+    pub x: usize,
+    pub lines: Vec<String>,
+}
+
+/// The content of the tooltip for the token at `pos`:
+/// the value and the definition of variables and line references, the conversion of units
+/// to SI units, the signature of functions, or the reason of the error if the token is erroneous
+pub fn tooltip_lines_at(
+    pos: Pos,
+    editor_content: &EditorContent<LineData>,
+    apptokens: &AppTokens,
+    results: &Results,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions,
+    units: &Units,
+) -> Option<Vec<String>> {
+    let tokens = &apptokens[content_y(pos.row)].as_ref()?.tokens;
+    let token = token_ranges(tokens)
+        .find(|(range, _)| range.contains(&pos.column))
+        .map(|(_, token)| token)?;
+    if let (true, Err(err)) = (token.has_error(), &results[content_y(pos.row)]) {
+        return Some(vec![err.reason.clone()]);
+    }
+    match &token.typ {
+        TokenType::Variable { var_index } if *var_index < FIRST_FUNC_PARAM_VAR_INDEX => {
+            let var = vars[*var_index].as_ref()?;
+            // the variable of a solved system of equations holds the values of all its unknowns
+            let (name, value) = match unknown_value(var, token.ptr) {
+                Some(value) => (token.ptr.iter().collect(), Some(value)),
+                None => (
+                    local_var_name(&var.name).iter().collect(),
+                    var.value.as_ref().ok(),
+                ),
+            };
+            Some(value_and_definition(
+                name,
+                value,
+                *var_index,
+                editor_content,
+                results,
+                units,
+            ))
+        }
+        TokenType::LineReference { var_index } => Some(value_and_definition(
+            format!("line {}", var_index + 1),
+            vars[*var_index]
+                .as_ref()
+                .and_then(|it| it.value.as_ref().ok()),
+            *var_index,
+            editor_content,
+            results,
+            units,
+        )),
+        TokenType::Unit(_, unit) => {
+            let name: String = token.ptr.iter().collect();
+            Some(vec![unit_description(name.trim(), unit, units)])
+        }
+        TokenType::Operator(OperatorTokenType::Fn { typ, .. }) => match typ {
+            FnType::UserDefined(row) => {
+                user_fn_signature_and_definition(*row, editor_content, func_defs)
+            }
+            _ if typ.signature().is_empty() => Some(vec![typ.doc().to_owned()]),
+            _ => Some(vec![typ.signature().to_owned(), typ.doc().to_owned()]),
+        },
+        TokenType::Operator(OperatorTokenType::FnRef(row)) => {
+            user_fn_signature_and_definition(*row, editor_content, func_defs)
+        }
+        _ => None,
+    }
+}
+
+/// e.g. "psi = 6894.76 Pa, M L⁻¹ T⁻²"
+pub fn unit_description(name: &str, unit: &UnitOutput, units: &Units) -> String {
+    let conversion = si_unit_of(&unit.dimensions, units).and_then(|si_unit| {
+        let coeff = unit
+            .get_unit_coeff()?
+            .checked_div(&si_unit.get_unit_coeff()?)?;
+        let si_unit_name = si_unit.to_string();
+        if si_unit_name == name {
+            None
+        } else {
+            Some(format!(
+                "{} {}",
+                round_to_significant_digits(coeff, 6),
+                si_unit_name
+            ))
+        }
+    });
+    let dimensions = dimensions_to_string(&unit.dimensions);
+    let mut description = name.to_owned();
+    if let Some(conversion) = conversion {
+        description.push_str(" = ");
+        description.push_str(&conversion);
+    }
+    if !dimensions.is_empty() {
+        description.push_str(", ");
+        description.push_str(&dimensions);
+    }
+    description
+}
+
+fn value_and_definition(
+    name: String,
+    value: Option<&CalcResult>,
+    def_row: usize,
+    editor_content: &EditorContent<LineData>,
+    results: &Results,
+    units: &Units,
+) -> Vec<String> {
+    let value = match value {
+        Some(value) => {
+            let format = if def_row < MAX_LINE_COUNT {
+                editor_content.get_data(def_row).result_format
+            } else {
+                ResultFormat::Dec
+            };
+            render_result(
+                units,
+                value,
+                &format,
+                false,
+                Some(RENDERED_RESULT_PRECISION),
+                true,
+            )
+        }
+        None => match results.as_slice().get(def_row) {
+            Some(Err(err)) => format!("Err ({})", err.reason),
+            _ => "Err".to_owned(),
+        },
+    };
+    let mut lines = vec![format!("{} = {}", name, value)];
+    if def_row < editor_content.line_count() {
+        lines.push(definition_line(def_row, editor_content));
+    }
+    lines
+}
+
+fn user_fn_signature_and_definition(
+    row: usize,
+    editor_content: &EditorContent<LineData>,
+    func_defs: &FunctionDefinitions,
+) -> Option<Vec<String>> {
+    let fd = func_defs[row].as_ref()?;
+    let name: String = fd.func_name.iter().collect();
+    let params: Vec<String> = fd.param_names[0..fd.param_count]
+        .iter()
+        .map(|it| it.iter().collect())
+        .collect();
+    Some(vec![
+        format!("{}({})", name, params.join(", ")),
+        definition_line(row, editor_content),
+    ])
+}
+
+fn definition_line(row: usize, editor_content: &EditorContent<LineData>) -> String {
+    let line: String = editor_content.get_line_valid_chars(row).iter().collect();
+    format!("line {}: {}", row + 1, line.trim())
+}
+
+/// The SI unit with the given dimensions, e.g. 'Pa', or the product of the SI base units
+/// if there is no named one, e.g. 'm s^-1'
+fn si_unit_of(
+    dimensions: &[UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT],
+    units: &Units,
+) -> Option<UnitOutput> {
+    let mut si_unit = UnitOutput::new();
+    if let Some(unit) = get_base_unit_for(units, dimensions) {
+        return if si_unit.add_unit(unit) {
+            Some(si_unit)
+        } else {
+            None
+        };
+    }
+    for (i, power) in dimensions.iter().enumerate() {
+        if *power == 0 {
+            continue;
+        }
+        let mut unit = get_base_unit_for(units, &BASE_UNIT_DIMENSIONS[i])?;
+        unit.power = *power;
+        if !si_unit.checked_add_unit(unit) {
+            return None;
+        }
+    }
+    Some(si_unit)
+}
+
+/// e.g. "M L⁻¹ T⁻²"
+fn dimensions_to_string(dimensions: &[UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT]) -> String {
+    dimensions
+        .iter()
+        .zip(DIMENSION_SYMBOLS.iter())
+        .filter(|(power, _)| **power != 0)
+        .map(|(power, symbol)| {
+            if *power == 1 {
+                (*symbol).to_owned()
+            } else {
+                let exponent: String = power.to_string().chars().map(superscript).collect();
+                format!("{}{}", symbol, exponent)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn superscript(ch: char) -> char {
+    match ch {
+        '-' => '⁻',
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        _ => ch,
+    }
+}
+
+fn round_to_significant_digits(num: Decimal, digits: u32) -> Decimal {
+    let abs = num.abs();
+    let int_digits = abs.trunc().to_string().trim_start_matches('0').len() as u32;
+    let decimal_places = if int_digits > 0 {
+        digits.saturating_sub(int_digits)
+    } else {
+        // e.g. 0.000123
+        let leading_zeros = abs
+            .normalize()
+            .to_string()
+            .chars()
+            .skip(2)
+            .take_while(|it| *it == '0')
+            .count() as u32;
+        leading_zeros + digits
+    };
+    num.round_dp(decimal_places).normalize()
+}
@@ -24,20 +24,34 @@ use strum_macros::EnumDiscriminants;
 use helper::*;
 
 use crate::calc::{
-    add_op, evaluate_tokens, get_var_name_from_assignment, process_variable_assignment_or_line_ref,
-    CalcResult, CalcResultType, EvalErr, EvaluationResult, ShuntingYardResult,
+    add_op, divide_op, evaluate_tokens, get_var_name_from_assignment,
+    process_variable_assignment_or_line_ref, CalcResult, CalcResultType, EvalErr, EvaluationResult,
+    ShuntingYardResult,
 };
+use crate::completion::{Completion, CompletionItem, CompletionKind};
 use crate::consts::{LINE_NUM_CONSTS, LINE_NUM_CONSTS2, LINE_NUM_CONSTS3};
 use crate::editor::editor::{
-    Editor, EditorInputEvent, InputModifiers, Pos, RowModificationType, Selection,
+    end_pos_of_text, Editor, EditorInputEvent, InputModifiers, Pos, RowModificationType, Selection,
 };
-use crate::editor::editor_content::EditorContent;
+use crate::editor::editor_content::{EditorContent, Replacement};
+use crate::equation::{process_equation_solution, try_solve_linear_equations};
+use crate::find::{Find, FindField, FindMatch};
 use crate::functions::FnType;
+use crate::hover::Tooltip;
+use crate::lint::{LintSettings, Warning, WarningKind};
 use crate::matrix::MatrixData;
+use crate::random::RandomSeed;
+use crate::rename::Rename;
 use crate::renderer::{get_int_frac_part_len, render_result, render_result_into};
+use crate::scenario::{ScenarioComparison, ScenarioLine, ScenarioView};
 use crate::shunting_yard::ShuntingYard;
+use crate::simulation::Simulation;
+use crate::snippets::{Placeholder, Snippet, SnippetSession};
+use crate::spreadsheet::{SheetBlock, SheetEvaluator};
 use crate::token_parser::{debug_print, OperatorTokenType, Token, TokenParser, TokenType};
 use crate::units::units::Units;
+use crate::usages::Usages;
+use rust_decimal::prelude::*;
 use tinyvec::ArrayVec;
 
 pub mod functions;
@@ -46,12 +60,25 @@ pub mod shunting_yard;
 pub mod test_common;
 pub mod token_parser;
 pub mod units;
+pub mod usages;
 
 pub mod borrow_checker_fighter;
 pub mod calc;
+pub mod completion;
 pub mod consts;
 pub mod editor;
+pub mod equation;
+pub mod find;
+pub mod folding;
+pub mod hover;
+pub mod lint;
+pub mod random;
+pub mod rename;
 pub mod renderer;
+pub mod scenario;
+pub mod simulation;
+pub mod snippets;
+pub mod spreadsheet;
 
 #[inline]
 fn _readonly_<T: ?Sized>(e: &mut T) -> &T {
@@ -85,10 +112,13 @@ pub const MIN_RESULT_PANEL_WIDTH: usize = 7;
 // the maximum lines rendered at once, so it is limited to 64
 pub const MAX_CLIENT_HEIGHT: usize = 64;
 pub const DEFAULT_RESULT_PANEL_WIDTH_PERCENT: usize = 30;
+pub const USAGES_PANEL_MAX_ROWS: usize = 10;
+pub const COMPLETION_POPUP_MAX_ROWS: usize = 8;
 pub const SUM_VARIABLE_INDEX: usize = MAX_LINE_COUNT;
+pub const SECTION_TOTAL_VARIABLE_INDEX: usize = SUM_VARIABLE_INDEX + 1;
 #[allow(dead_code)]
-pub const FIRST_FUNC_PARAM_VAR_INDEX: usize = SUM_VARIABLE_INDEX + 1;
-pub const VARIABLE_ARR_SIZE: usize = MAX_LINE_COUNT + 1 + MAX_FUNCTION_PARAM_COUNT;
+pub const FIRST_FUNC_PARAM_VAR_INDEX: usize = SECTION_TOTAL_VARIABLE_INDEX + 1;
+pub const VARIABLE_ARR_SIZE: usize = MAX_LINE_COUNT + 2 + MAX_FUNCTION_PARAM_COUNT;
 pub const MATRIX_ASCII_HEADER_FOOTER_LINE_COUNT: usize = 2;
 pub const ACTIVE_LINE_REF_HIGHLIGHT_COLORS: [u32; 9] = [
     0xFFD300FF, 0xDE3163FF, 0x73c2fbFF, 0xc7ea46FF, 0x702963FF, 0x997950FF, 0x777b73FF, 0xFC6600FF,
@@ -128,6 +158,7 @@ pub struct Theme {
     pub left_gutter_bg: u32,
     pub line_num_active: u32,
     pub line_num_simple: u32,
+    pub line_num_warning: u32,
     pub scrollbar_hovered: u32,
     pub scrollbar_normal: u32,
     pub line_ref_bg: u32,
@@ -138,6 +169,8 @@ pub struct Theme {
     pub change_result_pulse_end: u32,
     pub current_line_bg: u32,
     pub parenthesis: u32,
+    pub find_match_bg: u32,
+    pub input_bar_bg: u32,
 }
 
 #[allow(dead_code)]
@@ -182,6 +215,7 @@ pub const THEMES: [Theme; 2] = [
         left_gutter_bg: 0xF2F2F2_FF,
         line_num_active: 0x000000_FF,
         line_num_simple: 0xADADAD_FF,
+        line_num_warning: 0xE08A00_FF,
         scrollbar_hovered: 0xFFBBBB_FF,
         scrollbar_normal: 0xFFCCCC_FF,
         line_ref_text: 0x000000_FF,
@@ -192,6 +226,8 @@ pub const THEMES: [Theme; 2] = [
         change_result_pulse_end: 0xFFFFFF_55,
         current_line_bg: 0xFFFFCC_FF,
         parenthesis: 0x565869_FF,
+        find_match_bg: 0xFFD966_99,
+        input_bar_bg: 0xE0E0E0_FF,
     },
     // DARK
     Theme {
@@ -219,6 +255,7 @@ pub const THEMES: [Theme; 2] = [
         left_gutter_bg: 0x3c3f41_FF,
         line_num_active: 0xa3a2a0_FF,
         line_num_simple: 0x4e6164_FF,
+        line_num_warning: Theme::DRACULA_ORANGE,
         scrollbar_hovered: 0x4f4f4f_FF,
         scrollbar_normal: 0x4b4b4b_FF,
         line_ref_bg: 0x7C92A7_FF,
@@ -229,6 +266,8 @@ pub const THEMES: [Theme; 2] = [
         change_result_pulse_end: Theme::DRACULA_BG - 0xFF,
         current_line_bg: Theme::DRACULA_CURRENT_LINE,
         parenthesis: Theme::DRACULA_PINK,
+        find_match_bg: Theme::DRACULA_YELLOW - 0xAA,
+        input_bar_bg: Theme::DRACULA_CURRENT_LINE,
     },
 ];
 
@@ -278,15 +317,21 @@ pub fn try_extract_function_def<'b>(
     parsed_tokens: &mut [Token<'b>],
     allocator: &'b Bump,
 ) -> Option<FunctionDef<'b>> {
-    if parsed_tokens.len() < 4
-        || (!parsed_tokens[0].ptr[0].is_alphabetic() && parsed_tokens[0].ptr[0] != '_')
-        || parsed_tokens[1].typ != TokenType::Operator(OperatorTokenType::ParenOpen)
+    // the definition can be indented
+    let name_index = parsed_tokens
+        .iter()
+        .take_while(|it| it.ptr[0].is_whitespace())
+        .count();
+    if parsed_tokens.len() < name_index + 4
+        || (!parsed_tokens[name_index].ptr[0].is_alphabetic()
+            && parsed_tokens[name_index].ptr[0] != '_')
+        || parsed_tokens[name_index + 1].typ != TokenType::Operator(OperatorTokenType::ParenOpen)
         || parsed_tokens.last().unwrap().ptr != &[':']
     {
         return None;
     }
     let mut fd = FunctionDef {
-        func_name: parsed_tokens[0].ptr,
+        func_name: parsed_tokens[name_index].ptr,
         param_names: [&[]; MAX_FUNCTION_PARAM_COUNT],
         param_count: 0,
         first_row_index: content_y(0),
@@ -310,7 +355,7 @@ pub fn try_extract_function_def<'b>(
         *param_index += 1;
     }
     let mut param_index = 0;
-    let mut token_index = 2;
+    let mut token_index = name_index + 2;
     // TODO Bitflag u16 or u32 is enough
     let mut token_indices_for_params = BitFlag256::empty();
     let mut tmp_var_name: ArrayVec<[char; MAX_VAR_NAME_LEN]> = ArrayVec::new();
@@ -345,7 +390,7 @@ pub fn try_extract_function_def<'b>(
 
     fd.param_count = param_index;
 
-    parsed_tokens[0].typ = TokenType::Operator(OperatorTokenType::Fn {
+    parsed_tokens[name_index].typ = TokenType::Operator(OperatorTokenType::Fn {
         arg_count: fd.param_count,
         typ: FnType::UserDefined(0),
     });
@@ -591,6 +636,8 @@ pub mod helper {
         pub current_result_panel_width: usize,
         editor_y_to_render_y: [Option<CanvasY>; MAX_LINE_COUNT],
         editor_y_to_rendered_height: [usize; MAX_LINE_COUNT],
+        // the rows inside folded regions, their rendered height is zero
+        editor_y_is_hidden: [bool; MAX_LINE_COUNT],
         pub theme_index: usize,
     }
 
@@ -620,6 +667,7 @@ pub mod helper {
                 current_result_panel_width: 0,
                 editor_y_to_render_y: [None; MAX_LINE_COUNT],
                 editor_y_to_rendered_height: [0; MAX_LINE_COUNT],
+                editor_y_is_hidden: [false; MAX_LINE_COUNT],
                 client_height: client_height.min(MAX_CLIENT_HEIGHT),
                 client_width,
                 theme_index: 0,
@@ -666,10 +714,16 @@ pub mod helper {
             for e in self.editor_y_to_rendered_height.iter_mut() {
                 *e = 0;
             }
+            for e in self.editor_y_is_hidden.iter_mut() {
+                *e = false;
+            }
             self.scroll_y = 0;
         }
 
         pub fn is_visible(&self, y: ContentIndex) -> bool {
+            if self.is_hidden(y) {
+                return false;
+            }
             let top = match self.get_render_y(content_y(self.scroll_y)) {
                 Some(y) => y.as_isize(),
                 None => {
@@ -697,12 +751,24 @@ pub mod helper {
         }
 
         pub fn get_rendered_height(&self, y: ContentIndex) -> usize {
-            self.editor_y_to_rendered_height[y.0]
+            if self.is_hidden(y) {
+                0
+            } else {
+                self.editor_y_to_rendered_height[y.0]
+            }
         }
 
         pub fn set_rendered_height(&mut self, y: ContentIndex, h: usize) {
             self.editor_y_to_rendered_height[y.0] = h;
         }
+
+        pub fn is_hidden(&self, y: ContentIndex) -> bool {
+            self.editor_y_is_hidden[y.0]
+        }
+
+        pub fn set_hidden(&mut self, y: ContentIndex, hidden: bool) {
+            self.editor_y_is_hidden[y.0] = hidden;
+        }
     }
 
     #[derive(Debug)]
@@ -1172,6 +1238,8 @@ pub enum ResultFormat {
     Bin,
     Dec,
     Hex,
+    // degrees-minutes-seconds, set by the 'as dms' operator
+    Dms,
 }
 
 #[derive(Clone, Debug)]
@@ -1179,6 +1247,10 @@ pub struct LineData {
     // has to be pub because of external tests...
     pub line_id: usize,
     result_format: ResultFormat,
+    // the header region or the function body which starts at this line is collapsed
+    folded: bool,
+    // the title of the `[scoped]` header region the line belongs to, see [update_header_scopes]
+    header_scope: Option<Box<[char]>>,
 }
 
 impl Default for LineData {
@@ -1186,6 +1258,8 @@ impl Default for LineData {
         LineData {
             line_id: 0,
             result_format: ResultFormat::Dec,
+            folded: false,
+            header_scope: None,
         }
     }
 }
@@ -1201,6 +1275,8 @@ pub struct MatrixEditing {
     end_text_index: usize,
     row_index: ContentIndex,
     cell_strings: Vec<String>,
+    // '|' for tables, e.g. [rate | days; 90 | 20]
+    col_separator: char,
 }
 
 impl MatrixEditing {
@@ -1240,6 +1316,7 @@ impl MatrixEditing {
             col_count,
             current_cell,
             cell_strings: Vec::with_capacity((row_count * col_count).max(4)),
+            col_separator: ',',
         };
         let mut str: String = String::with_capacity(8);
         let mut can_ignore_ws = true;
@@ -1251,10 +1328,11 @@ impl MatrixEditing {
                 ']' => {
                     break;
                 }
-                ',' => {
+                ',' | '|' => {
                     mat_edit.cell_strings.push(str);
                     str = String::with_capacity(8);
                     can_ignore_ws = true;
+                    mat_edit.col_separator = *ch;
                 }
                 ';' => {
                     mat_edit.cell_strings.push(str);
@@ -1510,6 +1588,9 @@ pub struct EditorObject {
 pub struct Variable {
     pub name: Box<[char]>,
     pub value: Result<CalcResult, ()>,
+    // the unknowns of a solved system of equations (e.g. 'x' and 'y' in 'x + y = 10; x - y = 2'),
+    // their values are the elements of `value` in the same order
+    pub unknowns: Option<Box<[Box<[char]>]>>,
 }
 
 pub type LineResult = Result<Option<CalcResult>, EvalErr>;
@@ -1558,6 +1639,27 @@ pub struct NoteCalcApp {
     pub render_data: GlobalRenderData,
     // when pressing Ctrl-c without any selection, the result of the current line will be put into this clipboard
     pub clipboard: Option<String>,
+    // set by a `seed 42` line, every line is recalculated when it changes
+    pub document_seed: u64,
+    pub scenario_view: ScenarioView,
+    // the names of the scenarios in the document, every line is recalculated when they change
+    pub scenario_names: Vec<String>,
+    // the results of every scenario while all of them are shown
+    pub scenario_comparison: Option<ScenarioComparison>,
+    pub find: Option<Find>,
+    pub rename: Option<Rename>,
+    pub usages: Option<Usages>,
+    pub completion: Option<Completion>,
+    // the user's own snippets, the document can define more with '// snippet name: body' lines
+    pub snippets: Vec<Snippet>,
+    pub snippet_session: Option<SnippetSession>,
+    // the value, unit or function description of the token under the mouse
+    pub tooltip: Option<Tooltip>,
+    pub lint_settings: LintSettings,
+    // refreshed on every render, the line numbers of these rows are highlighted
+    pub warnings: Vec<Warning>,
+    // refreshed on every render, the rows which are hidden when a row is folded, see [folding::fold_ranges]
+    pub fold_ranges: Vec<Option<Range<usize>>>,
 }
 
 pub const EMPTY_FILE_DEFUALT_CONTENT: &str = "\n\n\n\n\n\n\n\n\n\n";
@@ -1583,6 +1685,20 @@ impl NoteCalcApp {
                 RIGHT_GUTTER_WIDTH,
             ),
             clipboard: None,
+            document_seed: 0,
+            scenario_view: ScenarioView::Active(0),
+            scenario_names: Vec::new(),
+            scenario_comparison: None,
+            find: None,
+            rename: None,
+            usages: None,
+            completion: None,
+            snippets: Vec::new(),
+            snippet_session: None,
+            tooltip: None,
+            lint_settings: LintSettings::default(),
+            warnings: Vec::new(),
+            fold_ranges: Vec::new(),
         }
     }
 
@@ -1593,6 +1709,16 @@ impl NoteCalcApp {
         self.mouse_hover_type = MouseHoverType::Normal;
         self.updated_line_ref_obj_indices.clear();
         self.clipboard = None;
+        self.scenario_view = ScenarioView::Active(0);
+        self.scenario_comparison = None;
+        self.find = None;
+        self.rename = None;
+        self.usages = None;
+        self.completion = None;
+        self.snippet_session = None;
+        self.tooltip = None;
+        self.warnings.clear();
+        self.fold_ranges.clear();
         self.render_data = GlobalRenderData::new(
             self.render_data.client_width,
             self.render_data.client_height,
@@ -1665,6 +1791,12 @@ impl NoteCalcApp {
         vars[SUM_VARIABLE_INDEX] = Some(Variable {
             name: Box::from(&['s', 'u', 'm'][..]),
             value: Err(()),
+            unknowns: None,
+        });
+        vars[SECTION_TOTAL_VARIABLE_INDEX] = Some(Variable {
+            name: Box::from(SECTION_TOTAL_VAR_NAME),
+            value: Err(()),
+            unknowns: None,
         });
         self.render_data.clear();
         self.process_and_render_tokens(
@@ -1698,9 +1830,11 @@ impl NoteCalcApp {
         let mut h = 0;
         for i in 0..content_len.min(MAX_LINE_COUNT) {
             let editor_y = content_y(i);
+            // the folded rows above the scroll position count as one, like in scroll_y
+            let folded_below_top = gr.is_hidden(editor_y) && i >= gr.scroll_y;
             if gr.is_visible(editor_y) {
                 h += gr.get_rendered_height(editor_y);
-            } else if gr.get_render_y(editor_y).is_some() {
+            } else if gr.get_render_y(editor_y).is_some() && !folded_below_top {
                 h += 1;
             }
         }
@@ -1724,6 +1858,13 @@ impl NoteCalcApp {
         editor_objs: &mut EditorObjects,
         updated_line_ref_obj_indices: &[EditorObjId],
         mouse_hover_type: MouseHoverType,
+        find: &Option<Find>,
+        rename: &Option<Rename>,
+        usages: &Option<Usages>,
+        completion: &Option<Completion>,
+        tooltip: &Option<Tooltip>,
+        warnings: &[Warning],
+        fold_ranges: &[Option<Range<usize>>],
     ) {
         let theme = &THEMES[gr.theme_index];
         gr.longest_visible_editor_line_len = 0;
@@ -1752,19 +1893,47 @@ impl NoteCalcApp {
 
         // x, h
         let mut editor_y_to_render_w: [usize; MAX_LINE_COUNT] = [0; MAX_LINE_COUNT];
+        let folded_regions = folding::folded_regions(editor_content, fold_ranges);
+        for i in 0..MAX_LINE_COUNT {
+            gr.set_hidden(content_y(i), false);
+        }
+        for (_, range) in &folded_regions {
+            for i in range.clone() {
+                gr.set_hidden(content_y(i), true);
+            }
+        }
         {
             let mut r = PerLineRenderData::new();
             r.render_y = canvas_y(-(gr.scroll_y as isize));
             gr.clear_editor_y_to_render_y();
+            // the folded rows do not take space, so more rows fit into the screen
+            let mut hidden_row_count = 0;
+            // the column widths of the spreadsheet block we are in
+            let mut sheet_col_widths: Option<Vec<usize>> = None;
             for line in editor_content.lines().take(MAX_LINE_COUNT) {
                 r.new_line_started();
                 let editor_y = r.editor_y;
+                if spreadsheet::is_fence(line) {
+                    sheet_col_widths = if spreadsheet::is_sheet_start(line) {
+                        Some(
+                            SheetBlock::starting_at(editor_content, editor_y.as_usize())
+                                .column_widths(editor_content),
+                        )
+                    } else {
+                        None
+                    };
+                }
                 {
                     if gr.scroll_y > editor_y.as_usize()
-                        || editor_y.as_usize() >= gr.scroll_y + gr.client_height
+                        || editor_y.as_usize() >= gr.scroll_y + gr.client_height + hidden_row_count
                     {
                         gr.set_render_y(editor_y, Some(r.render_y));
-                        r.line_render_ended(1);
+                        let below_top = editor_y.as_usize() >= gr.scroll_y;
+                        r.line_render_ended(if below_top && gr.is_hidden(editor_y) {
+                            0
+                        } else {
+                            1
+                        });
                         continue;
                     } else if editor_y.as_usize() > 0 {
                         let prev_editor_y = editor_y.sub(1);
@@ -1783,6 +1952,14 @@ impl NoteCalcApp {
                     }
                 }
 
+                if gr.is_hidden(editor_y) {
+                    gr.set_render_y(editor_y, Some(r.render_y));
+                    editor_objs[editor_y].clear();
+                    hidden_row_count += 1;
+                    r.line_render_ended(0);
+                    continue;
+                }
+
                 let render_y = r.render_y;
                 gr.set_render_y(editor_y, Some(render_y));
                 r.rendered_row_height = gr.get_rendered_height(editor_y);
@@ -1813,6 +1990,7 @@ impl NoteCalcApp {
                         &units,
                         need_matrix_renderer,
                         Some(RENDERED_RESULT_PRECISION),
+                        sheet_col_widths.as_deref(),
                         theme,
                     );
                     highlight_line_ref_background(
@@ -1832,6 +2010,19 @@ impl NoteCalcApp {
 
                 editor_y_to_render_w[r.editor_y.as_usize()] = r.render_x;
 
+                if let Some((_, range)) = folded_regions
+                    .iter()
+                    .find(|(start, _)| *start == editor_y.as_usize())
+                {
+                    render_fold_summary(
+                        render_buckets,
+                        folding::summary(range, editor_content, results, units),
+                        &r,
+                        gr,
+                        theme,
+                    );
+                }
+
                 draw_line_ref_chooser(
                     render_buckets,
                     &r,
@@ -1889,7 +2080,8 @@ impl NoteCalcApp {
             let mut i = gr.scroll_y;
             let to = (i + gr.client_height).min(MAX_LINE_COUNT);
             while i < to {
-                if let Some(fd) = func_defs[i].as_ref() {
+                // a function inside a folded header region is not rendered
+                if let (Some(fd), false) = (func_defs[i].as_ref(), gr.is_hidden(content_y(i))) {
                     let mut rendered_h = 1;
                     for j in fd.first_row_index.add(1).as_usize()..=fd.last_row_index.as_usize() {
                         rendered_h += gr.get_rendered_height(content_y(j));
@@ -1923,13 +2115,19 @@ impl NoteCalcApp {
         // line numbers
         {
             let mut target_y = canvas_y(0);
+            let mut rendered_count = 0;
             render_buckets.set_color(Layer::Text, theme.line_num_simple);
-            for i in 0..gr.client_height {
-                let y = gr.scroll_y + i;
-                if y >= MAX_LINE_COUNT {
+            for y in gr.scroll_y..MAX_LINE_COUNT {
+                if rendered_count == gr.client_height {
                     break;
+                } else if gr.is_hidden(content_y(y)) {
+                    continue;
                 }
-                if y == editor.get_selection().get_cursor_pos().row {
+                rendered_count += 1;
+                let has_warning = warnings.iter().any(|it| it.row == y);
+                if has_warning {
+                    render_buckets.set_color(Layer::Text, theme.line_num_warning);
+                } else if y == editor.get_selection().get_cursor_pos().row {
                     render_buckets.set_color(Layer::Text, theme.line_num_active);
                 }
                 let row_height = gr.get_rendered_height(content_y(y)).max(1);
@@ -1947,8 +2145,24 @@ impl NoteCalcApp {
                     target_y.add(vert_align_offset),
                     line_num_str,
                 );
+                // the last column of the gutter is always free
+                let fold_marker = if folded_regions.iter().any(|(start, _)| *start == y) {
+                    Some('▸')
+                } else if matches!(fold_ranges.get(y), Some(Some(_))) {
+                    Some('▾')
+                } else {
+                    None
+                };
+                if let Some(fold_marker) = fold_marker {
+                    render_buckets.draw_char(
+                        Layer::Text,
+                        gr.left_gutter_width - 1,
+                        target_y.add(vert_align_offset),
+                        fold_marker,
+                    );
+                }
                 target_y = target_y.add(row_height);
-                if y == editor.get_selection().get_cursor_pos().row {
+                if has_warning || y == editor.get_selection().get_cursor_pos().row {
                     render_buckets.set_color(Layer::Text, theme.line_num_simple);
                 }
             }
@@ -1996,6 +2210,22 @@ impl NoteCalcApp {
             apptokens,
         );
 
+        if let Some(find) = find {
+            render_find(render_buckets, find, gr, theme);
+        }
+        if let Some(rename) = rename {
+            render_rename(render_buckets, rename, vars, func_defs, units, gr, theme);
+        }
+        if let Some(usages) = usages {
+            render_usages(render_buckets, usages, editor_content, gr, theme);
+        }
+        if let Some(completion) = completion {
+            render_completion(render_buckets, completion, gr, theme);
+        }
+        if let Some(tooltip) = tooltip {
+            render_tooltip(render_buckets, tooltip, gr, theme);
+        }
+
         let mut tmp = ResultRender::new(ArrayVec::new());
 
         render_results_into_buf_and_calc_len(
@@ -2049,6 +2279,14 @@ impl NoteCalcApp {
     ) -> bool {
         let has_moved = if dir == 0 && self.render_data.scroll_y > 0 {
             self.render_data.scroll_y -= 1;
+            // jump over the folded rows
+            while self.render_data.scroll_y > 0
+                && self
+                    .render_data
+                    .is_hidden(content_y(self.render_data.scroll_y))
+            {
+                self.render_data.scroll_y -= 1;
+            }
             true
         } else if dir == 1 {
             let content_height = NoteCalcApp::calc_full_content_height(
@@ -2057,6 +2295,13 @@ impl NoteCalcApp {
             );
             if (self.render_data.scroll_y + self.render_data.client_height) < content_height {
                 self.render_data.scroll_y += 1;
+                while self.render_data.scroll_y < MAX_LINE_COUNT - 1
+                    && self
+                        .render_data
+                        .is_hidden(content_y(self.render_data.scroll_y))
+                {
+                    self.render_data.scroll_y += 1;
+                }
                 true
             } else {
                 false
@@ -2107,6 +2352,21 @@ impl NoteCalcApp {
         let scroll_bar_x = self.render_data.result_gutter_x - SCROLLBAR_WIDTH;
         if x < self.render_data.left_gutter_width {
             // clicked on left gutter
+            if let Some(row) = self.rendered_y_to_editor_y(clicked_y) {
+                if self.toggle_fold(row.as_usize()) {
+                    self.generate_render_commands_and_fill_editor_objs(
+                        units,
+                        render_buckets,
+                        allocator,
+                        tokens,
+                        results,
+                        vars,
+                        func_defs,
+                        editor_objs,
+                        BitFlag256::empty(),
+                    );
+                }
+            }
         } else if x < scroll_bar_x {
             self.handle_editor_area_click(
                 x,
@@ -2149,6 +2409,82 @@ impl NoteCalcApp {
         }
     }
 
+    /// Ctrl+click in the editor area adds a cursor or removes an existing one
+    pub fn handle_ctrl_click<'b>(
+        &mut self,
+        x: usize,
+        clicked_y: CanvasY,
+        editor_objs: &mut EditorObjects,
+        units: &Units,
+        allocator: &'b Bump,
+        tokens: &mut AppTokens<'b>,
+        results: &mut Results,
+        vars: &mut Variables,
+        func_defs: &mut FunctionDefinitions<'b>,
+        render_buckets: &mut RenderBuckets<'b>,
+    ) {
+        let scroll_bar_x = self.render_data.result_gutter_x - SCROLLBAR_WIDTH;
+        if x < self.render_data.left_gutter_width
+            || x >= scroll_bar_x
+            || self.matrix_editing.is_some()
+        {
+            self.handle_click(
+                x,
+                clicked_y,
+                editor_objs,
+                units,
+                allocator,
+                tokens,
+                results,
+                vars,
+                func_defs,
+                render_buckets,
+            );
+            return;
+        }
+        let clicked_x = x - self.render_data.left_gutter_width;
+        let clicked_row = self.get_clicked_row_clamped(clicked_y);
+        let editor_click_pos = if let Some(editor_obj) =
+            self.get_obj_at(clicked_x, clicked_row, clicked_y, editor_objs)
+        {
+            match editor_obj.typ {
+                EditorObjectType::SimpleTokens | EditorObjectType::Variable { .. } => {
+                    let x_pos_within = clicked_x - editor_obj.rendered_x;
+                    Pos::from_row_column(
+                        editor_obj.row.as_usize(),
+                        editor_obj.start_x + x_pos_within,
+                    )
+                }
+                // no matrix editing with multiple cursors
+                EditorObjectType::LineReference { .. } | EditorObjectType::Matrix { .. } => {
+                    Pos::from_row_column(editor_obj.row.as_usize(), editor_obj.end_x)
+                }
+            }
+        } else {
+            let eol = self.editor_content.line_len(clicked_row.as_usize());
+            Pos::from_row_column(clicked_row.as_usize(), eol)
+        };
+
+        self.editor.handle_ctrl_click(
+            editor_click_pos.column,
+            editor_click_pos.row,
+            &self.editor_content,
+        );
+        self.editor.blink_cursor();
+
+        self.generate_render_commands_and_fill_editor_objs(
+            units,
+            render_buckets,
+            allocator,
+            tokens,
+            results,
+            vars,
+            func_defs,
+            editor_objs,
+            BitFlag256::empty(),
+        );
+    }
+
     pub fn handle_mouse_up(&mut self) {
         match self.mouse_state {
             Some(MouseClickType::RightGutterIsDragged) => {}
@@ -2315,17 +2651,21 @@ impl NoteCalcApp {
 
     pub fn rendered_y_to_editor_y(&self, clicked_y: CanvasY) -> Option<ContentIndex> {
         let editor_y_to_render_y = self.render_data.editor_y_to_render_y();
-        let mut was_visible_row = false;
+        // the folded rows share their render_y with the next row
+        let mut prev_visible_row = None;
         for (ed_y, r_y) in editor_y_to_render_y.iter().enumerate() {
+            if self.render_data.is_hidden(content_y(ed_y)) {
+                continue;
+            }
             if let Some(r_y) = r_y {
-                was_visible_row = true;
                 if *r_y == clicked_y {
                     return Some(content_y(ed_y));
                 } else if *r_y > clicked_y {
-                    return Some(content_y(ed_y - 1));
+                    return prev_visible_row.map(content_y);
                 }
-            } else if was_visible_row {
-                return Some(content_y(ed_y - 1));
+                prev_visible_row = Some(ed_y);
+            } else if prev_visible_row.is_some() {
+                return prev_visible_row.map(content_y);
             }
         }
         return None;
@@ -2398,7 +2738,7 @@ impl NoteCalcApp {
     pub fn handle_mouse_move<'b>(
         &mut self,
         x: usize,
-        _y: CanvasY,
+        y: CanvasY,
         editor_objs: &mut EditorObjects,
         units: &Units,
         allocator: &'b Bump,
@@ -2409,6 +2749,22 @@ impl NoteCalcApp {
         render_buckets: &mut RenderBuckets<'b>,
     ) -> usize {
         let scroll_bar_x = self.render_data.result_gutter_x - SCROLLBAR_WIDTH;
+        let new_tooltip = if (self.render_data.left_gutter_width..scroll_bar_x).contains(&x) {
+            self.tooltip_at(
+                x - self.render_data.left_gutter_width,
+                y,
+                editor_objs,
+                units,
+                tokens,
+                results,
+                vars,
+                func_defs,
+            )
+        } else if x < self.render_data.left_gutter_width {
+            self.warning_tooltip_at(y)
+        } else {
+            None
+        };
         let new_mouse_state = if x < self.render_data.left_gutter_width {
             MouseHoverType::Normal
         } else if x < scroll_bar_x {
@@ -2424,8 +2780,9 @@ impl NoteCalcApp {
             // result
             return MouseHoverType::Result as usize;
         };
-        if self.mouse_hover_type != new_mouse_state {
+        if self.mouse_hover_type != new_mouse_state || self.tooltip != new_tooltip {
             self.mouse_hover_type = new_mouse_state;
+            self.tooltip = new_tooltip;
             self.generate_render_commands_and_fill_editor_objs(
                 units,
                 render_buckets,
@@ -2441,6 +2798,58 @@ impl NoteCalcApp {
         return self.mouse_hover_type as usize;
     }
 
+    fn tooltip_at(
+        &self,
+        x: usize,
+        y: CanvasY,
+        editor_objs: &EditorObjects,
+        units: &Units,
+        tokens: &AppTokens,
+        results: &Results,
+        vars: &Variables,
+        func_defs: &FunctionDefinitions,
+    ) -> Option<Tooltip> {
+        let row = self.rendered_y_to_editor_y(y)?;
+        let editor_obj = self.get_obj_at(x, row, y, editor_objs)?;
+        let column = match editor_obj.typ {
+            EditorObjectType::LineReference { .. } => editor_obj.start_x,
+            EditorObjectType::SimpleTokens | EditorObjectType::Variable { .. } => {
+                editor_obj.start_x + (x - editor_obj.rendered_x)
+            }
+            EditorObjectType::Matrix { .. } => return None,
+        };
+        let lines = hover::tooltip_lines_at(
+            Pos::from_row_column(row.as_usize(), column),
+            &self.editor_content,
+            tokens,
+            results,
+            vars,
+            func_defs,
+            units,
+        )?;
+        Some(Tooltip {
+            row: row.as_usize(),
+            x: editor_obj.rendered_x,
+            lines,
+        })
+    }
+
+    /// The warnings of the row whose line number is under the mouse
+    fn warning_tooltip_at(&self, y: CanvasY) -> Option<Tooltip> {
+        let row = self.rendered_y_to_editor_y(y)?.as_usize();
+        let lines: Vec<String> = self
+            .warnings
+            .iter()
+            .filter(|it| it.row == row)
+            .map(|it| it.message.clone())
+            .collect();
+        if lines.is_empty() {
+            None
+        } else {
+            Some(Tooltip { row, x: 0, lines })
+        }
+    }
+
     pub fn handle_drag<'b>(
         &mut self,
         x: usize,
@@ -2547,6 +2956,92 @@ impl NoteCalcApp {
         }
     }
 
+    /// Folds or unfolds the header region or the function body which starts at `row`,
+    /// returns false if there is nothing to fold there
+    pub fn toggle_fold(&mut self, row: usize) -> bool {
+        if !matches!(self.fold_ranges.get(row), Some(Some(_))) {
+            return false;
+        }
+        let folded = !self.editor_content.get_data(row).folded;
+        self.editor_content.mut_data(row).folded = folded;
+        let cursor_pos = self.editor.get_selection().get_cursor_pos();
+        if let Some((start, _)) = self.folded_region_containing(cursor_pos.row) {
+            self.set_cursor_row(start, cursor_pos.column);
+        }
+        true
+    }
+
+    fn folded_region_containing(&self, row: usize) -> Option<(usize, Range<usize>)> {
+        folding::folded_regions(&self.editor_content, &self.fold_ranges)
+            .into_iter()
+            .find(|(_, range)| range.contains(&row))
+    }
+
+    fn set_cursor_row(&mut self, row: usize, column: usize) {
+        let column = column.min(self.editor_content.line_len(row));
+        self.editor
+            .set_selection_save_col(Selection::single_r_c(row, column));
+    }
+
+    /// The cursor can not be inside a folded region: moving up or down jumps over it,
+    /// any other way of getting there (typing, find, jumping to a definition) unfolds it
+    fn keep_cursor_out_of_folds(&mut self, input: EditorInputEvent, prev_row: usize) {
+        loop {
+            let selection = self.editor.get_selection();
+            let cursor_pos = selection.get_cursor_pos();
+            let (start, range) = match self.folded_region_containing(cursor_pos.row) {
+                Some(region) => region,
+                None => return,
+            };
+            if input == EditorInputEvent::Down
+                && !selection.is_range()
+                && cursor_pos.row > prev_row
+                && range.end < self.editor_content.line_count()
+            {
+                self.set_cursor_row(range.end, cursor_pos.column);
+            } else if input == EditorInputEvent::Up
+                && !selection.is_range()
+                && cursor_pos.row < prev_row
+            {
+                self.set_cursor_row(start, cursor_pos.column);
+            } else {
+                self.editor_content.mut_data(start).folded = false;
+            }
+        }
+    }
+
+    /// One 'name: body' definition per line, see [Snippet]
+    pub fn set_user_snippets(&mut self, text: &str) {
+        self.snippets = snippets::parse_snippets(text);
+    }
+
+    pub fn set_lint_enabled<'b>(
+        &mut self,
+        kind: WarningKind,
+        enabled: bool,
+        editor_objs: &mut EditorObjects,
+        units: &Units,
+        allocator: &'b Bump,
+        tokens: &AppTokens<'b>,
+        results: &Results,
+        vars: &Variables,
+        func_defs: &FunctionDefinitions<'b>,
+        render_buckets: &mut RenderBuckets<'b>,
+    ) {
+        self.lint_settings.set_enabled(kind, enabled);
+        self.generate_render_commands_and_fill_editor_objs(
+            units,
+            render_buckets,
+            allocator,
+            tokens,
+            results,
+            vars,
+            func_defs,
+            editor_objs,
+            BitFlag256::empty(),
+        );
+    }
+
     pub fn set_theme<'b>(
         &mut self,
         new_theme_index: usize,
@@ -2937,35 +3432,496 @@ impl NoteCalcApp {
         );
     }
 
-    pub fn handle_paste<'b>(
+    /// Ctrl+F, or Ctrl+H with the replace field. The selected text becomes the query
+    fn open_find(&mut self, show_replace: bool) -> Option<RowModificationType> {
+        let selected_text = Editor::get_selected_text_single_line(
+            self.editor.get_selection(),
+            &self.editor_content,
+        )
+        .filter(|it| !it.is_empty())
+        .map(|it| it.iter().collect::<String>());
+        let prev_find = self.find.take();
+        let query = selected_text
+            .or_else(|| prev_find.as_ref().map(|it| it.query.clone()))
+            .unwrap_or_default();
+        let mut find = Find::new(query, show_replace);
+        if let Some(prev_find) = prev_find {
+            find.options = prev_find.options;
+            find.replacement = prev_find.replacement;
+            find.compile();
+        }
+        self.find = Some(find);
+        self.find_query_changed()
+    }
+
+    /// The input goes into the find bar while it is open, except undo and redo.
+    /// Returns None if the input was not handled
+    fn handle_find_input(
         &mut self,
-        text: String,
-        units: &Units,
-        allocator: &'b Bump,
-        tokens: &mut AppTokens<'b>,
-        results: &mut Results,
-        vars: &mut Variables,
-        func_defs: &mut FunctionDefinitions<'b>,
-        editor_objs: &mut EditorObjects,
-        render_buckets: &mut RenderBuckets<'b>,
-    ) {
-        let prev_row = self.editor.get_selection().get_cursor_pos().row;
-        let prev_line_count = self.editor_content.line_count();
-        match self
-            .editor
-            .insert_text_undoable(&text, &mut self.editor_content)
-        {
-            Some(modif) => {
-                if self.editor.get_selection().get_cursor_pos().row >= MAX_LINE_COUNT {
-                    self.editor.set_cursor_pos_r_c(MAX_LINE_COUNT - 1, 0);
+        input: EditorInputEvent,
+        modifiers: InputModifiers,
+    ) -> Option<Option<RowModificationType>> {
+        let find = self.find.as_mut()?;
+        let modif = match input {
+            EditorInputEvent::Char(ch) if modifiers.ctrl && ch.to_ascii_lowercase() == 'z' => {
+                return None;
+            }
+            EditorInputEvent::Esc => {
+                self.find = None;
+                None
+            }
+            EditorInputEvent::Tab if find.show_replace => {
+                find.focused_field = match find.focused_field {
+                    FindField::Query => FindField::Replacement,
+                    FindField::Replacement => FindField::Query,
+                };
+                None
+            }
+            EditorInputEvent::Enter if find.focused_field == FindField::Replacement => {
+                self.replace_find_matches(modifiers.ctrl)
+            }
+            EditorInputEvent::Enter if modifiers.shift => {
+                let m = find.select_prev_match();
+                self.select_find_match(m)
+            }
+            EditorInputEvent::Up => {
+                let m = find.select_prev_match();
+                self.select_find_match(m)
+            }
+            EditorInputEvent::Enter | EditorInputEvent::Down => {
+                let m = find.select_next_match();
+                self.select_find_match(m)
+            }
+            EditorInputEvent::Char(ch) if modifiers.alt => {
+                match ch.to_ascii_lowercase() {
+                    'c' => find.options.case_sensitive = !find.options.case_sensitive,
+                    'w' => find.options.whole_word = !find.options.whole_word,
+                    'r' => find.options.regex = !find.options.regex,
+                    _ => return Some(None),
                 }
-                let cursor_pos = self.editor.get_selection().get_cursor_pos();
-                let scroll_y =
-                    get_scroll_y_after_cursor_movement(prev_row, cursor_pos.row, &self.render_data);
-                if let Some(scroll_y) = scroll_y {
-                    self.render_data.scroll_y = scroll_y;
+                find.compile();
+                self.find_query_changed()
+            }
+            EditorInputEvent::Backspace => {
+                find.focused_text_mut().pop();
+                if find.focused_field == FindField::Query {
+                    find.compile();
+                    self.find_query_changed()
+                } else {
+                    None
                 }
-                self.process_and_render_tokens(
+            }
+            EditorInputEvent::Char(ch) if !modifiers.ctrl => {
+                find.focused_text_mut().push(ch);
+                if find.focused_field == FindField::Query {
+                    find.compile();
+                    self.find_query_changed()
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        Some(modif)
+    }
+
+    /// The input goes into the rename bar while it is open.
+    /// Returns None if the input was not handled
+    fn handle_rename_input(
+        &mut self,
+        input: EditorInputEvent,
+        modifiers: InputModifiers,
+        units: &Units,
+        tokens: &AppTokens,
+        vars: &Variables,
+        func_defs: &FunctionDefinitions,
+    ) -> Option<Option<RowModificationType>> {
+        let rename = self.rename.as_mut()?;
+        let modif = match input {
+            EditorInputEvent::Esc => {
+                self.rename = None;
+                None
+            }
+            EditorInputEvent::Enter if rename.is_new_name_valid(vars, func_defs, units) => {
+                let replacements = rename::rename_replacements(
+                    rename.symbol,
+                    &rename.new_name,
+                    &self.editor_content,
+                    tokens,
+                    vars,
+                    func_defs,
+                );
+                self.rename = None;
+                self.editor
+                    .replace_undoable(replacements, &mut self.editor_content)
+            }
+            EditorInputEvent::Backspace => {
+                rename.new_name.pop();
+                None
+            }
+            EditorInputEvent::Char(ch) if !modifiers.ctrl && !modifiers.alt => {
+                rename.new_name.push(ch);
+                None
+            }
+            _ => None,
+        };
+        Some(modif)
+    }
+
+    /// Up and Down select an item in the completion popup, Enter or Tab inserts it.
+    /// Any other key goes to the editor, the popup is refreshed afterwards
+    fn handle_completion_popup_input(
+        &mut self,
+        input: EditorInputEvent,
+    ) -> Option<Option<RowModificationType>> {
+        let completion = self.completion.as_mut()?;
+        let modif = match input {
+            EditorInputEvent::Up => {
+                completion.select_prev();
+                None
+            }
+            EditorInputEvent::Down => {
+                completion.select_next();
+                None
+            }
+            EditorInputEvent::Enter | EditorInputEvent::Tab => {
+                let accepted = completion.accept();
+                self.completion = None;
+                match accepted {
+                    Some((replacement, cursor_pos))
+                        if self
+                            .editor_content
+                            .get_line_valid_chars(cursor_pos.row)
+                            .len()
+                            + replacement.inserted_text.chars().count()
+                            - replacement.removed_text.chars().count()
+                            <= self.editor_content.max_line_len() =>
+                    {
+                        let modif = self
+                            .editor
+                            .replace_undoable(vec![replacement], &mut self.editor_content);
+                        self.editor
+                            .set_cursor_pos_r_c(cursor_pos.row, cursor_pos.column);
+                        modif
+                    }
+                    _ => None,
+                }
+            }
+            EditorInputEvent::Esc => {
+                self.completion = None;
+                None
+            }
+            _ => return None,
+        };
+        Some(modif)
+    }
+
+    /// The completed word changes as the user types, the popup is closed when
+    /// the cursor leaves the word or nothing matches anymore
+    fn update_completion(
+        &mut self,
+        units: &Units,
+        vars: &Variables,
+        func_defs: &FunctionDefinitions,
+    ) {
+        let prev = match self.completion.take() {
+            Some(completion) => completion,
+            None => return,
+        };
+        let selection = self.editor.get_selection();
+        let cursor_pos = selection.get_cursor_pos();
+        if selection.is_range()
+            || self.matrix_editing.is_some()
+            || cursor_pos.row != prev.pos.row
+            || cursor_pos.column < prev.pos.column
+        {
+            return;
+        }
+        self.completion = completion::completion_at(
+            cursor_pos,
+            self.editor_content.get_line_valid_chars(cursor_pos.row),
+            units,
+            vars,
+            func_defs,
+        )
+        .filter(|it| it.pos == prev.pos)
+        .map(|mut it| {
+            // keep the selected item if it still matches
+            let prev_label = prev.items.get(prev.selected).map(|it| &it.label);
+            it.selected = it
+                .items
+                .iter()
+                .position(|item| Some(&item.label) == prev_label)
+                .unwrap_or(0);
+            it
+        });
+    }
+
+    /// Tab after '.name' expands the snippet with that name, the document's snippets
+    /// override the user's ones. While the placeholders of the expansion are not visited,
+    /// Tab selects the next one, Esc leaves them.
+    fn handle_snippet_input(
+        &mut self,
+        input: EditorInputEvent,
+        modifiers: InputModifiers,
+    ) -> Option<Option<RowModificationType>> {
+        if input == EditorInputEvent::Esc {
+            self.snippet_session = None;
+            return None;
+        } else if input != EditorInputEvent::Tab
+            || modifiers.shift
+            || modifiers.ctrl
+            || modifiers.alt
+            || self.editor.has_multiple_cursors()
+        {
+            return None;
+        }
+        if let Some(session) = &mut self.snippet_session {
+            if let Some(placeholder) = session.next(&self.editor_content) {
+                if !session.has_next() {
+                    self.snippet_session = None;
+                }
+                self.select_placeholder(placeholder);
+                return Some(None);
+            }
+            self.snippet_session = None;
+        }
+        self.expand_snippet()
+    }
+
+    fn expand_snippet(&mut self) -> Option<Option<RowModificationType>> {
+        let selection = self.editor.get_selection();
+        if selection.is_range() {
+            return None;
+        }
+        let cursor_pos = selection.get_cursor_pos();
+        let line = self.editor_content.get_line_valid_chars(cursor_pos.row);
+        let mut name_start = cursor_pos.column;
+        while name_start > 0
+            && (line[name_start - 1].is_alphanumeric() || line[name_start - 1] == '_')
+        {
+            name_start -= 1;
+        }
+        if name_start == 0 || name_start == cursor_pos.column || line[name_start - 1] != '.' {
+            return None;
+        }
+        let name: String = line[name_start..cursor_pos.column].iter().collect();
+        let snippet = snippets::document_snippets(&self.editor_content)
+            .into_iter()
+            .find(|it| it.name == name)
+            .or_else(|| self.snippets.iter().find(|it| it.name == name).cloned())?;
+        let expansion = snippets::expand(&snippet.body);
+
+        // the expansion is inserted in place of '.name', it must fit into the editor
+        let dot_pos = cursor_pos.with_column(name_start - 1);
+        let new_line_count = expansion.text.matches('\n').count();
+        let mut new_lines: Vec<usize> = expansion
+            .text
+            .split('\n')
+            .map(|it| it.chars().count())
+            .collect();
+        new_lines[0] += dot_pos.column;
+        *new_lines.last_mut()? += line.len() - cursor_pos.column;
+        if self.editor_content.line_count() + new_line_count > MAX_LINE_COUNT
+            || new_lines
+                .iter()
+                .any(|it| *it > self.editor_content.max_line_len())
+        {
+            return None;
+        }
+
+        let modif = self.editor.replace_undoable(
+            vec![Replacement {
+                pos: dot_pos,
+                removed_text: format!(".{}", name),
+                inserted_text: expansion.text.clone(),
+            }],
+            &mut self.editor_content,
+        );
+        let to_absolute = |pos: Pos| {
+            if pos.row == 0 {
+                dot_pos.add_column(pos.column)
+            } else {
+                Pos::from_row_column(dot_pos.row + pos.row, pos.column)
+            }
+        };
+        let placeholders: Vec<Placeholder> = expansion
+            .placeholders
+            .iter()
+            .map(|it| Placeholder {
+                start: to_absolute(it.start),
+                len: it.len,
+            })
+            .collect();
+        match placeholders.first() {
+            Some(first) => {
+                self.select_placeholder(*first);
+                if placeholders.len() > 1 {
+                    self.snippet_session =
+                        Some(SnippetSession::new(placeholders, &self.editor_content));
+                }
+            }
+            None => {
+                let end = end_pos_of_text(dot_pos, &expansion.text);
+                self.editor.set_cursor_pos_r_c(end.row, end.column);
+            }
+        }
+        Some(modif)
+    }
+
+    /// The default text of the placeholder is selected so typing replaces it
+    fn select_placeholder(&mut self, placeholder: Placeholder) {
+        let start = placeholder.start;
+        if placeholder.len == 0 {
+            self.editor.set_cursor_pos_r_c(start.row, start.column);
+        } else {
+            self.editor
+                .set_selection_save_col(Selection::range(start, start.add_column(placeholder.len)));
+        }
+    }
+
+    /// Up and Down select the usages in the usages panel, Enter jumps to the selected one.
+    /// Any other key closes the panel and returns None so the key is processed as usual
+    fn handle_usages_input(
+        &mut self,
+        input: EditorInputEvent,
+    ) -> Option<Option<RowModificationType>> {
+        let usages = self.usages.as_mut()?;
+        let modif = match input {
+            EditorInputEvent::Up => {
+                let m = usages.select_prev();
+                self.select_find_match(m)
+            }
+            EditorInputEvent::Down => {
+                let m = usages.select_next();
+                self.select_find_match(m)
+            }
+            EditorInputEvent::Enter => {
+                let m = usages
+                    .selected
+                    .and_then(|i| usages.usages.get(i))
+                    .or_else(|| usages.usages.first())
+                    .copied();
+                self.usages = None;
+                self.select_find_match(m)
+            }
+            EditorInputEvent::Esc => {
+                self.usages = None;
+                None
+            }
+            _ => {
+                self.usages = None;
+                return None;
+            }
+        };
+        Some(modif)
+    }
+
+    /// Selects the first match from the cursor (incremental search)
+    fn find_query_changed(&mut self) -> Option<RowModificationType> {
+        let find = self.find.as_mut()?;
+        find.update_matches(&self.editor_content);
+        let m = find.select_match_from(self.editor.get_selection().get_first());
+        self.select_find_match(m)
+    }
+
+    fn select_find_match(&mut self, m: Option<FindMatch>) -> Option<RowModificationType> {
+        if let Some(m) = m {
+            self.editor.clear_other_cursors();
+            self.editor.set_cursor_range(
+                Pos::from_row_column(m.row, m.start),
+                Pos::from_row_column(m.row, m.end),
+            );
+        }
+        None
+    }
+
+    /// Enter in the replace field replaces the current match, Ctrl+Enter every match,
+    /// both of them are a single undoable step
+    fn replace_find_matches(&mut self, all: bool) -> Option<RowModificationType> {
+        let find = self.find.as_mut()?;
+        let replacements = find.replacements(&self.editor_content, all);
+        let next_pos = replacements
+            .first()
+            .map(|it| it.pos.add_column(it.inserted_text.chars().count()));
+        let modif = self
+            .editor
+            .replace_undoable(replacements, &mut self.editor_content);
+        let find = self.find.as_mut()?;
+        find.update_matches(&self.editor_content);
+        if !all {
+            if let Some(next_pos) = next_pos {
+                let m = find.select_match_from(next_pos);
+                self.select_find_match(m);
+            }
+        }
+        modif
+    }
+
+    pub fn handle_paste<'b>(
+        &mut self,
+        text: String,
+        units: &Units,
+        allocator: &'b Bump,
+        tokens: &mut AppTokens<'b>,
+        results: &mut Results,
+        vars: &mut Variables,
+        func_defs: &mut FunctionDefinitions<'b>,
+        editor_objs: &mut EditorObjects,
+        render_buckets: &mut RenderBuckets<'b>,
+    ) {
+        if let Some(rename) = &mut self.rename {
+            rename.new_name.push_str(text.lines().next().unwrap_or(""));
+            self.generate_render_commands_and_fill_editor_objs(
+                units,
+                render_buckets,
+                allocator,
+                tokens,
+                results,
+                vars,
+                func_defs,
+                editor_objs,
+                BitFlag256::empty(),
+            );
+            return;
+        }
+        if let Some(find) = &mut self.find {
+            find.focused_text_mut()
+                .push_str(text.lines().next().unwrap_or(""));
+            if find.focused_field == FindField::Query {
+                find.compile();
+                self.find_query_changed();
+            }
+            self.generate_render_commands_and_fill_editor_objs(
+                units,
+                render_buckets,
+                allocator,
+                tokens,
+                results,
+                vars,
+                func_defs,
+                editor_objs,
+                BitFlag256::empty(),
+            );
+            return;
+        }
+        let prev_row = self.editor.get_selection().get_cursor_pos().row;
+        let prev_line_count = self.editor_content.line_count();
+        match self
+            .editor
+            .insert_text_undoable(&text, &mut self.editor_content)
+        {
+            Some(modif) => {
+                if self.editor.get_selection().get_cursor_pos().row >= MAX_LINE_COUNT {
+                    self.editor.set_cursor_pos_r_c(MAX_LINE_COUNT - 1, 0);
+                }
+                let cursor_pos = self.editor.get_selection().get_cursor_pos();
+                let scroll_y =
+                    get_scroll_y_after_cursor_movement(prev_row, cursor_pos.row, &self.render_data);
+                if let Some(scroll_y) = scroll_y {
+                    self.render_data.scroll_y = scroll_y;
+                }
+                self.process_and_render_tokens(
                     modif,
                     units,
                     allocator,
@@ -3031,7 +3987,7 @@ impl NoteCalcApp {
                 for row_i in start.row..=end.row {
                     let new_format = match &app.editor_content.get_data(row_i).result_format {
                         ResultFormat::Bin => ResultFormat::Hex,
-                        ResultFormat::Dec => ResultFormat::Bin,
+                        ResultFormat::Dec | ResultFormat::Dms => ResultFormat::Bin,
                         ResultFormat::Hex => ResultFormat::Dec,
                     };
                     app.editor_content.mut_data(row_i).result_format = new_format;
@@ -3043,7 +3999,7 @@ impl NoteCalcApp {
                 for row_i in start.row..=end.row {
                     let new_format = match &app.editor_content.get_data(row_i).result_format {
                         ResultFormat::Bin => ResultFormat::Dec,
-                        ResultFormat::Dec => ResultFormat::Hex,
+                        ResultFormat::Dec | ResultFormat::Dms => ResultFormat::Hex,
                         ResultFormat::Hex => ResultFormat::Bin,
                     };
                     app.editor_content.mut_data(row_i).result_format = new_format;
@@ -3085,6 +4041,9 @@ impl NoteCalcApp {
                 } else {
                     None
                 }
+            } else if input == EditorInputEvent::Char('s') && !app.scenario_names.is_empty() {
+                app.scenario_view = app.scenario_view.next(app.scenario_names.len());
+                Some(RowModificationType::AllLinesFrom(0))
             } else {
                 None
             }
@@ -3098,8 +4057,89 @@ impl NoteCalcApp {
         let prev_selection = self.editor.get_selection();
         let prev_row = self.editor.get_selection().get_cursor_pos().row;
         let mut refactor_me = false;
-        let modif = if self.matrix_editing.is_none() && modifiers.alt {
+        // the hovered token can change or move
+        self.tooltip = None;
+        let modif = if let Some(modif_type) = self.handle_usages_input(input) {
+            modif_type
+        } else if let Some(modif_type) = self.handle_completion_popup_input(input) {
+            modif_type
+        } else if self.matrix_editing.is_none()
+            && self.find.is_none()
+            && self.rename.is_none()
+            && modifiers.ctrl
+            && input == EditorInputEvent::Char(' ')
+        {
+            let cursor_pos = self.editor.get_selection().get_cursor_pos();
+            self.completion = completion::completion_at(
+                cursor_pos,
+                self.editor_content.get_line_valid_chars(cursor_pos.row),
+                units,
+                vars,
+                func_defs,
+            );
+            None
+        } else if self.matrix_editing.is_none()
+            && modifiers.ctrl
+            && (input == EditorInputEvent::Char('f') || input == EditorInputEvent::Char('h'))
+        {
+            self.open_find(input == EditorInputEvent::Char('h'))
+        } else if let Some(modif_type) = self.handle_find_input(input, modifiers) {
+            modif_type
+        } else if input == EditorInputEvent::F2 && self.matrix_editing.is_none() {
+            self.rename = rename::symbol_at(
+                self.editor.get_selection().get_cursor_pos(),
+                tokens,
+                vars,
+                func_defs,
+            )
+            .map(|(symbol, name)| Rename::new(symbol, name));
+            None
+        } else if let Some(modif_type) =
+            self.handle_rename_input(input, modifiers, units, tokens, vars, func_defs)
+        {
+            modif_type
+        } else if self.matrix_editing.is_none()
+            && modifiers.is_ctrl_shift()
+            && (input == EditorInputEvent::Char('b') || input == EditorInputEvent::Char('B'))
+        {
+            self.usages = usages::find_usages(
+                self.editor.get_selection().get_cursor_pos(),
+                &self.editor_content,
+                tokens,
+                vars,
+                func_defs,
+            );
+            None
+        } else if self.matrix_editing.is_none()
+            && modifiers.is_ctrl_shift()
+            && matches!(
+                input,
+                EditorInputEvent::Char('[')
+                    | EditorInputEvent::Char('{')
+                    | EditorInputEvent::Char(']')
+                    | EditorInputEvent::Char('}')
+            )
+        {
+            // Ctrl+Shift+[ folds the innermost region around the cursor, Ctrl+Shift+] unfolds it
+            let fold = matches!(
+                input,
+                EditorInputEvent::Char('[') | EditorInputEvent::Char('{')
+            );
+            let row = self.editor.get_selection().get_cursor_pos().row;
+            if let Some(start) =
+                folding::region_containing(row, !fold, &self.editor_content, &self.fold_ranges)
+            {
+                self.toggle_fold(start);
+            }
+            None
+        } else if self.matrix_editing.is_none() && modifiers.alt && !modifiers.shift {
             handle_input_with_alt(&mut *self, input)
+        } else if self.matrix_editing.is_none()
+            && (self.editor.has_multiple_cursors() || modifiers.alt)
+        {
+            // the smart features work only with a single cursor
+            self.editor
+                .handle_input_undoable(input, modifiers, &mut self.editor_content)
         } else if self.is_matrix_editing_or_need_to_create_one(
             input,
             _readonly_(editor_objs),
@@ -3122,6 +4162,8 @@ impl NoteCalcApp {
                 };
                 None
             }
+        } else if let Some(modif_type) = self.handle_snippet_input(input, modifiers) {
+            modif_type
         } else if self.handle_completion(&input, editor_objs, _readonly_(vars)) {
             Some(RowModificationType::SingleLine(prev_row))
         } else if let Some(modif_type) = self.handle_obj_deletion(&input, editor_objs, modifiers) {
@@ -3199,6 +4241,14 @@ impl NoteCalcApp {
                 modif_type
             }
         };
+        if self.completion.is_some() {
+            self.update_completion(units, vars, func_defs);
+        }
+        if modif.is_some() {
+            // the regions of the previous render pass do not match the modified content
+            self.fold_ranges = folding::fold_ranges(&self.editor_content, func_defs);
+        }
+        self.keep_cursor_out_of_folds(input, prev_row);
 
         let cursor_pos = self.editor.get_selection().get_cursor_pos();
         let scroll_y =
@@ -3310,6 +4360,8 @@ impl NoteCalcApp {
             updated_line_ref_obj_indices: &mut Vec<EditorObjId>,
             function_def_index: &Option<usize>,
             argument_dependend_lines: &mut BitFlag256,
+            random_seed: RandomSeed,
+            active_scenario: Option<&str>,
         ) -> (bool, BitFlag256, Option<FunctionDef<'a>>) {
             let _span = tracy_span("eval_line", file!(), line!());
 
@@ -3332,9 +4384,18 @@ impl NoteCalcApp {
                 allocator,
                 func_param_count,
                 func_defs,
+                header_scope_of_line(editor_content, editor_y.as_usize()),
             );
 
-            if let Some(mut fd) = try_extract_function_def(&mut parsed_tokens, allocator) {
+            // the lines of spreadsheet blocks are evaluated cell by cell
+            let sheet_block = SheetBlock::of_line(editor_content, editor_y.as_usize());
+
+            let func_def = if sheet_block.is_none() {
+                try_extract_function_def(&mut parsed_tokens, allocator)
+            } else {
+                None
+            };
+            if let Some(mut fd) = func_def {
                 fd.first_row_index = editor_y;
                 fd.last_row_index = editor_y;
                 apptokens[editor_y] = Some(Tokens {
@@ -3352,23 +4413,108 @@ impl NoteCalcApp {
                 );
             }
 
+            // e.g. rate = $90 | $100 | $120 @ low, mid, high
+            let scenario_line = if sheet_block.is_none() {
+                ScenarioLine::of_line(line)
+            } else {
+                None
+            };
+
+            let can_be_equation =
+                function_def_index.is_none() && sheet_block.is_none() && scenario_line.is_none();
+            let equation_solution = if can_be_equation {
+                try_solve_linear_equations(&parsed_tokens, vars)
+            } else {
+                None
+            };
+
             // TODO: measure is 128 necessary? and remove allocation
             let mut shunting_output_stack = Vec::with_capacity(128);
-            ShuntingYard::shunting_yard(
-                &mut parsed_tokens,
-                &mut shunting_output_stack,
-                units,
-                &func_defs[0..editor_y.as_usize()],
-            );
+            let mut scenario_err = None;
+            match scenario_line {
+                Some(Ok(scenario_line)) => {
+                    let alternative =
+                        scenario_line.alternative_of(active_scenario.unwrap_or_default());
+                    // only the alternative of the active scenario is evaluated
+                    shunting_output_stack = scenario_line.shunting_yard(
+                        &mut parsed_tokens,
+                        alternative,
+                        units,
+                        &func_defs[0..editor_y.as_usize()],
+                    );
+                }
+                Some(Err(err)) => scenario_err = Some(err),
+                None if equation_solution.is_none() && sheet_block.is_none() => {
+                    ShuntingYard::shunting_yard(
+                        &mut parsed_tokens,
+                        &mut shunting_output_stack,
+                        units,
+                        &func_defs[0..editor_y.as_usize()],
+                    );
+                }
+                None => {}
+            }
 
             // TODO avoid clone
             let prev_var_name = vars[editor_y.as_usize()].as_ref().map(|it| it.name.clone());
+            let was_equation_system = vars[editor_y.as_usize()]
+                .as_ref()
+                .map(|it| it.unknowns.is_some())
+                .unwrap_or(false);
+            let was_simulate_directive = starts_with_simulate_keyword(&apptokens[editor_y]);
             apptokens[editor_y] = Some(Tokens {
                 tokens: parsed_tokens,
                 shunting_output_stack,
             });
 
-            let new_result = if apptokens[editor_y].is_some() {
+            let is_directive = random::parse_directive(line, random::SEED_KEYWORD).is_some()
+                || Simulation::of_line(line).is_some();
+            let new_result = if is_directive {
+                // `seed 42` and `simulate 1000` are not calculations
+                vars[editor_y.as_usize()] = None;
+                match Simulation::of_line(line) {
+                    Some(Err(err)) => Err(err),
+                    _ => Ok(None),
+                }
+            } else if let Some(err) = scenario_err {
+                let result = Err(err);
+                process_variable_assignment_or_line_ref(
+                    &result,
+                    vars,
+                    editor_y.as_usize(),
+                    editor_content,
+                );
+                result.map(|it| it.map(|it| it.result))
+            } else if let Some(sheet_block) = sheet_block {
+                vars[editor_y.as_usize()] = None;
+                let mut line_tokens = apptokens[editor_y].take().expect("it was set above");
+                let result = SheetEvaluator {
+                    block: sheet_block,
+                    editor_content,
+                    units,
+                    allocator,
+                    vars,
+                    func_defs,
+                    random_seed,
+                }
+                .eval_line(editor_y.as_usize(), &mut line_tokens.tokens, apptokens);
+                apptokens[editor_y] = Some(line_tokens);
+                result
+            } else if let Some(solution) = equation_solution {
+                if let Err(err) = &solution {
+                    Token::set_token_error_flag_by_index(
+                        err.token_index,
+                        &mut apptokens[editor_y].as_mut().unwrap().tokens,
+                    );
+                }
+                process_equation_solution(
+                    &solution,
+                    vars,
+                    editor_y.as_usize(),
+                    &NoteCalcApp::get_line_ref_name(&editor_content, editor_y.as_usize()),
+                );
+                solution.map(|it| Some(it.to_calc_result()))
+            } else if apptokens[editor_y].is_some() {
                 let result_depends_on_argument =
                     if let Some(function_def_index) = function_def_index {
                         fn determine_argument_dependend_lines<'a>(
@@ -3433,8 +4579,13 @@ impl NoteCalcApp {
                         // just a Dummy value to register the variable name so following line
                         // in the function body can refer to it
                         vars[editor_y.as_usize()] = Some(Variable {
-                            name: Box::from(var_name),
+                            name: Box::from(scoped_var_name(
+                                var_name,
+                                editor_content,
+                                editor_y.as_usize(),
+                            )),
                             value: Err(()),
+                            unknowns: None,
                         });
                     }
                     Ok(None)
@@ -3448,6 +4599,7 @@ impl NoteCalcApp {
                         editor_content,
                         0,
                         None,
+                        random_seed,
                     );
                     for (i, token) in apptokens[editor_y]
                         .as_mut()
@@ -3525,6 +4677,13 @@ impl NoteCalcApp {
             }
 
             let curr_var_name = vars[editor_y.as_usize()].as_ref().map(|it| &it.name);
+            if prev_var_name.is_some() != curr_var_name.is_some() {
+                // 'prev' in the lines below might refer to a different line from now
+                rows_to_recalc.merge(find_prev_line_ref_dependant_lines(
+                    apptokens,
+                    editor_y.as_usize(),
+                ));
+            }
             rows_to_recalc.merge(find_lines_that_affected_by_var_change(
                 result_has_changed,
                 curr_var_name,
@@ -3533,10 +4692,58 @@ impl NoteCalcApp {
                 editor_y.as_usize(),
             ));
 
+            let is_equation_system = vars[editor_y.as_usize()]
+                .as_ref()
+                .map(|it| it.unknowns.is_some())
+                .unwrap_or(false);
+            if was_equation_system || is_equation_system {
+                // the unknowns are not the name of the variable, so the lines using them can't be
+                // found by name
+                rows_to_recalc.merge(BitFlag256::all_rows_starting_at(editor_y.as_usize() + 1));
+            }
+
             rows_to_recalc.merge(find_sum_variable_name(apptokens, editor_y.as_usize()));
+            if let Some(sheet_block) = sheet_block {
+                // the rows depend on the header, the footer on the rows
+                if editor_y.as_usize() < sheet_block.last_y {
+                    rows_to_recalc.merge(BitFlag256::range_incl(
+                        editor_y.as_usize() + 1,
+                        sheet_block.last_y,
+                    ));
+                }
+            } else if spreadsheet::is_fence(line) || line.starts_with(&['#']) {
+                // the lines below might have become part of a block or vice versa,
+                // or the scope of the variables under a header might have changed
+                rows_to_recalc.merge(BitFlag256::all_rows_starting_at(editor_y.as_usize() + 1));
+            }
+            if was_simulate_directive || starts_with_simulate_keyword(&apptokens[editor_y]) {
+                // the lines below might have become part of a simulation or vice versa
+                rows_to_recalc.merge(BitFlag256::all_rows_starting_at(editor_y.as_usize() + 1));
+            }
             return (result_has_changed, rows_to_recalc, None);
         }
 
+        fn find_prev_line_ref_dependant_lines(
+            tokens_per_lines: &AppTokens,
+            editor_y: usize,
+        ) -> BitFlag256 {
+            let mut rows_to_recalc = BitFlag256::empty();
+            for (line_index, tokens) in tokens_per_lines.iter().skip(editor_y + 1).enumerate() {
+                if let Some(tokens) = tokens {
+                    let refers_to_line_above = tokens.tokens.iter().any(|token| match token.typ {
+                        TokenType::LineReference { var_index } => {
+                            var_index <= editor_y && is_prev_line_ref(token)
+                        }
+                        _ => false,
+                    });
+                    if refers_to_line_above {
+                        rows_to_recalc.merge(BitFlag256::single_row(editor_y + 1 + line_index));
+                    }
+                }
+            }
+            return rows_to_recalc;
+        }
+
         fn find_sum_variable_name(tokens_per_lines: &AppTokens, editor_y: usize) -> BitFlag256 {
             let mut rows_to_recalc = BitFlag256::empty();
             'outer: for (line_index, tokens) in
@@ -3549,7 +4756,8 @@ impl NoteCalcApp {
                                 break 'outer;
                             }
                             TokenType::Variable { var_index }
-                                if var_index == SUM_VARIABLE_INDEX =>
+                                if var_index == SUM_VARIABLE_INDEX
+                                    || var_index == SECTION_TOTAL_VARIABLE_INDEX =>
                             {
                                 rows_to_recalc
                                     .merge(BitFlag256::single_row(editor_y + 1 + line_index));
@@ -3579,7 +4787,9 @@ impl NoteCalcApp {
                         if let Some(tokens) = tokens {
                             for token in &tokens.tokens {
                                 match token.typ {
-                                    TokenType::StringLiteral if *token.ptr == **var_name => {
+                                    TokenType::StringLiteral
+                                        if is_name_of_var(token.ptr, var_name) =>
+                                    {
                                         rows_to_recalc
                                             .merge(BitFlag256::single_row(editor_y + 1 + i));
                                         break;
@@ -3597,7 +4807,9 @@ impl NoteCalcApp {
                         if let Some(tokens) = tokens {
                             for token in &tokens.tokens {
                                 match token.typ {
-                                    TokenType::Variable { .. } if *token.ptr == *old_var_name => {
+                                    TokenType::Variable { .. }
+                                        if is_name_of_var(token.ptr, &old_var_name) =>
+                                    {
                                         rows_to_recalc
                                             .merge(BitFlag256::single_row(editor_y + 1 + i));
                                         break;
@@ -3614,8 +4826,13 @@ impl NoteCalcApp {
                         if let Some(tokens) = tokens {
                             for token in &tokens.tokens {
                                 let recalc = match token.typ {
-                                    TokenType::StringLiteral => var_name.starts_with(token.ptr),
-                                    TokenType::Variable { .. } => *token.ptr == *old_var_name,
+                                    TokenType::StringLiteral => {
+                                        var_name.starts_with(token.ptr)
+                                            || local_var_name(var_name).starts_with(token.ptr)
+                                    }
+                                    TokenType::Variable { .. } => {
+                                        is_name_of_var(token.ptr, &old_var_name)
+                                    }
                                     _ => false,
                                 };
                                 if recalc {
@@ -3635,7 +4852,11 @@ impl NoteCalcApp {
                         if let Some(tokens) = tokens {
                             for token in &tokens.tokens {
                                 let recalc = match token.typ {
-                                    TokenType::Variable { .. } if *token.ptr == **var_name => true,
+                                    TokenType::Variable { .. }
+                                        if is_name_of_var(token.ptr, var_name) =>
+                                    {
+                                        true
+                                    }
                                     _ => false,
                                 };
                                 if recalc {
@@ -3688,7 +4909,10 @@ impl NoteCalcApp {
                                     TokenType::Operator(OperatorTokenType::Fn {
                                         typ: FnType::UserDefined(_),
                                         ..
-                                    }) if *token.ptr == *old_var_name => {
+                                    })
+                                    | TokenType::Operator(OperatorTokenType::FnRef(..))
+                                        if *token.ptr == *old_var_name =>
+                                    {
                                         rows_to_recalc
                                             .merge(BitFlag256::single_row(editor_y + 1 + i));
                                         break;
@@ -3709,7 +4933,10 @@ impl NoteCalcApp {
                                     TokenType::Operator(OperatorTokenType::Fn {
                                         typ: FnType::UserDefined(_),
                                         ..
-                                    }) => *token.ptr == *old_var_name,
+                                    })
+                                    | TokenType::Operator(OperatorTokenType::FnRef(..)) => {
+                                        *token.ptr == *old_var_name
+                                    }
                                     _ => false,
                                 };
                                 if recalc {
@@ -3732,7 +4959,12 @@ impl NoteCalcApp {
                                     TokenType::Operator(OperatorTokenType::Fn {
                                         typ: FnType::UserDefined(_),
                                         ..
-                                    }) if *token.ptr == *var_name => true,
+                                    })
+                                    | TokenType::Operator(OperatorTokenType::FnRef(..))
+                                        if *token.ptr == *var_name =>
+                                    {
+                                        true
+                                    }
                                     _ => false,
                                 };
                                 if recalc {
@@ -3748,6 +4980,66 @@ impl NoteCalcApp {
             return rows_to_recalc;
         }
 
+        fn finish_simulation<'b>(
+            simulation: Simulation,
+            last_y: usize,
+            editor_content: &EditorContent<LineData>,
+            units: &Units,
+            tokens: &AppTokens<'b>,
+            results: &mut Results,
+            vars: &mut Variables,
+            func_defs: &FunctionDefinitions<'b>,
+            document_seed: u64,
+            render_data: &mut GlobalRenderData,
+            matrix_editing: &Option<MatrixEditing>,
+            result_change_flag: &mut BitFlag256,
+            dependant_rows: &mut BitFlag256,
+        ) {
+            if !simulation.has_changed || simulation.first_y > last_y {
+                return;
+            }
+            simulation.run(
+                last_y,
+                editor_content,
+                tokens,
+                results,
+                vars,
+                func_defs,
+                units,
+                document_seed,
+            );
+            result_change_flag.merge(BitFlag256::range_incl(simulation.first_y, last_y));
+            for y in simulation.first_y..=last_y {
+                let y = content_y(y);
+                let new_h = calc_rendered_height(y, matrix_editing, tokens, results, vars);
+                render_data.set_rendered_height(y, new_h);
+            }
+            // the lines below might use the variables of the simulation
+            dependant_rows.merge(BitFlag256::all_rows_starting_at(last_y + 1));
+        }
+
+        update_header_scopes(&mut self.editor_content);
+        let document_seed = random::document_seed(&self.editor_content);
+        let scenario_names = scenario::scenario_names(&self.editor_content);
+        let compare_scenarios =
+            self.scenario_view == ScenarioView::All && !scenario_names.is_empty();
+        if !compare_scenarios || scenario_names != self.scenario_names {
+            self.scenario_comparison = None;
+        }
+        // the results of the other scenarios are calculated from scratch when they are shown
+        let input_effect = if document_seed != self.document_seed
+            || scenario_names != self.scenario_names
+            || (compare_scenarios && self.scenario_comparison.is_none())
+        {
+            self.document_seed = document_seed;
+            self.scenario_names = scenario_names.clone();
+            RowModificationType::AllLinesFrom(0)
+        } else {
+            input_effect
+        };
+        let random_seed = RandomSeed::new(document_seed, 0);
+        let active_scenario = self.scenario_view.active_name(&scenario_names);
+
         if matches!(input_effect, RowModificationType::AllLinesFrom(_)) {
             let curr_line_count = self.editor_content.line_count();
             for i in curr_line_count..prev_line_count.min(MAX_LINE_COUNT) {
@@ -3756,8 +5048,10 @@ impl NoteCalcApp {
         }
 
         let mut sum_is_null = true;
+        let mut section_total = SectionTotal::new(None, SectionTotalType::Sum);
         let mut dependant_rows = BitFlag256::empty();
         let mut result_change_flag = BitFlag256::empty();
+        let mut evaluated_rows = BitFlag256::empty();
         // HACK: currently this flag is here, but it makes the parsing
         // depends on previous parsing results, which works now
         // because a change in a function causes the whole function
@@ -3766,8 +5060,32 @@ impl NoteCalcApp {
 
         // the index of the FunctionDef whose body is currently processed
         let mut function_def_index: Option<usize> = None;
+        let mut simulation: Option<Simulation> = None;
 
         for editor_y in 0..self.editor_content.line_count().min(MAX_LINE_COUNT) {
+            {
+                // a simulation lasts until the next header or simulation
+                let line = self.editor_content.get_line_valid_chars(editor_y);
+                if line.starts_with(&['#']) || matches!(Simulation::of_line(line), Some(Ok(_))) {
+                    if let Some(simulation) = simulation.take() {
+                        finish_simulation(
+                            simulation,
+                            editor_y - 1,
+                            &self.editor_content,
+                            units,
+                            tokens,
+                            results,
+                            vars,
+                            func_defs,
+                            document_seed,
+                            &mut self.render_data,
+                            &self.matrix_editing,
+                            &mut result_change_flag,
+                            &mut dependant_rows,
+                        );
+                    }
+                }
+            }
             let recalc = match input_effect {
                 RowModificationType::SingleLine(to_change_index) if to_change_index == editor_y => {
                     true
@@ -3828,6 +5146,7 @@ impl NoteCalcApp {
                     self.line_id_generator += 1;
                 }
                 let y = content_y(editor_y);
+                evaluated_rows.merge(BitFlag256::single_row(editor_y));
 
                 let (result_has_changed, rows_to_recalc, func_def) = eval_line(
                     &self.editor_content,
@@ -3842,7 +5161,26 @@ impl NoteCalcApp {
                     &mut self.updated_line_ref_obj_indices,
                     &function_def_index,
                     &mut argument_dependend_lines,
+                    random_seed,
+                    active_scenario,
                 );
+                {
+                    // 'as dms' at the end of the line overrides its result format
+                    let as_dms = tokens[y]
+                        .as_ref()
+                        .map(|it| {
+                            it.shunting_output_stack
+                                .iter()
+                                .any(|it| it.typ == TokenType::Operator(OperatorTokenType::AsDms))
+                        })
+                        .unwrap_or(false);
+                    let line_data = self.editor_content.mut_data(editor_y);
+                    if as_dms {
+                        line_data.result_format = ResultFormat::Dms;
+                    } else if line_data.result_format == ResultFormat::Dms {
+                        line_data.result_format = ResultFormat::Dec;
+                    }
+                }
                 if let Some(fd) = func_def {
                     // a new function has been defined in the current row
                     func_defs[editor_y] = Some(fd);
@@ -3871,6 +5209,9 @@ impl NoteCalcApp {
                 dependant_rows.merge(rows_to_recalc);
                 let new_h = calc_rendered_height(y, &self.matrix_editing, tokens, results, vars);
                 self.render_data.set_rendered_height(y, new_h);
+                if let Some(simulation) = &mut simulation {
+                    simulation.has_changed = true;
+                }
             }
 
             if let Some(fd) = &func_defs[editor_y] {
@@ -3881,6 +5222,7 @@ impl NoteCalcApp {
                     vars[FIRST_FUNC_PARAM_VAR_INDEX + i] = Some(Variable {
                         name: Box::from(fd.param_names[i]),
                         value: Err(()),
+                        unknowns: None,
                     })
                 }
             } else if let Some(fdi) = function_def_index {
@@ -3890,16 +5232,31 @@ impl NoteCalcApp {
             }
 
             let function_def_index = function_def_index;
-            if self
-                .editor_content
-                .get_line_valid_chars(editor_y)
-                .starts_with(&['#'])
-            {
+            let line = self.editor_content.get_line_valid_chars(editor_y);
+            if let Some(Ok(mut new_simulation)) = Simulation::of_line(line) {
+                new_simulation.first_y = editor_y + 1;
+                new_simulation.has_changed = recalc;
+                simulation = Some(new_simulation);
+            }
+            if line.starts_with(&['#']) {
                 vars[SUM_VARIABLE_INDEX] = Some(Variable {
                     name: Box::from(&['s', 'u', 'm'][..]),
                     value: Err(()),
+                    unknowns: None,
                 });
                 sum_is_null = true;
+
+                section_total.show_in_header(results, &mut result_change_flag);
+                section_total = if let Some(typ) = SectionTotalType::from_header(line) {
+                    SectionTotal::new(Some(content_y(editor_y)), typ)
+                } else {
+                    SectionTotal::new(None, SectionTotalType::Sum)
+                };
+                vars[SECTION_TOTAL_VARIABLE_INDEX] = Some(Variable {
+                    name: Box::from(SECTION_TOTAL_VAR_NAME),
+                    value: section_total.value(),
+                    unknowns: None,
+                });
             } else if function_def_index.is_none() {
                 match &results[content_y(editor_y)] {
                     Ok(Some(result)) => {
@@ -3910,11 +5267,76 @@ impl NoteCalcApp {
                             result,
                             &mut sum_is_null,
                         );
+                        // e.g. 'section_total * 2' would be counted twice
+                        if !refers_to_totals(&tokens[content_y(editor_y)]) {
+                            section_total.add(result);
+                            vars[SECTION_TOTAL_VARIABLE_INDEX]
+                                .as_mut()
+                                .expect("section_total always exists")
+                                .value = section_total.value();
+                        }
                     }
                     Err(_) | Ok(None) => {}
                 }
             }
         }
+        section_total.show_in_header(results, &mut result_change_flag);
+        if let Some(simulation) = simulation.take() {
+            finish_simulation(
+                simulation,
+                self.editor_content.line_count().min(MAX_LINE_COUNT) - 1,
+                &self.editor_content,
+                units,
+                tokens,
+                results,
+                vars,
+                func_defs,
+                document_seed,
+                &mut self.render_data,
+                &self.matrix_editing,
+                &mut result_change_flag,
+                &mut dependant_rows,
+            );
+        }
+        if compare_scenarios {
+            let last_y = self.editor_content.line_count().min(MAX_LINE_COUNT) - 1;
+            let first_scenario_y = (0..=last_y).find(|y| {
+                matches!(
+                    ScenarioLine::of_line(self.editor_content.get_line_valid_chars(*y)),
+                    Some(Ok(_))
+                )
+            });
+            if let Some(first_y) = first_scenario_y {
+                let comparison = self
+                    .scenario_comparison
+                    .get_or_insert_with(|| ScenarioComparison::new(scenario_names.len(), vars));
+                // e.g. the lines of a simulation are evaluated after the others
+                evaluated_rows.merge(result_change_flag);
+                let changed_rows = scenario::compare_scenarios(
+                    comparison,
+                    &scenario_names,
+                    evaluated_rows,
+                    first_y,
+                    last_y,
+                    &self.editor_content,
+                    tokens,
+                    results,
+                    vars,
+                    func_defs,
+                    units,
+                    random_seed,
+                );
+                for y in first_y..=last_y {
+                    if changed_rows.is_true(y) {
+                        let y = content_y(y);
+                        let new_h =
+                            calc_rendered_height(y, &self.matrix_editing, tokens, results, vars);
+                        self.render_data.set_rendered_height(y, new_h);
+                    }
+                }
+                result_change_flag.merge(changed_rows);
+            }
+        }
 
         if self.editor_content.line_count() > 99 {
             self.render_data
@@ -4071,7 +5493,8 @@ impl NoteCalcApp {
                     let var_index = match token.typ {
                         TokenType::LineReference { var_index }
                             if already_added.is_false(var_index)
-                                && token.ptr == editor_obj_name =>
+                                && (token.ptr == editor_obj_name
+                                    || is_prev_line_ref_to(token, editor_y)) =>
                         {
                             var_index
                         }
@@ -4158,6 +5581,7 @@ impl NoteCalcApp {
                         &units,
                         true, // force matrix rendering
                         None,
+                        None,
                         theme,
                     );
                     r.line_render_ended(r.rendered_row_height);
@@ -4997,7 +6421,19 @@ impl NoteCalcApp {
             file!(),
             line!(),
         );
-        render_buckets.clear();
+        render_buckets.clear();
+        if let Some(find) = &mut self.find {
+            find.update_matches(&self.editor_content);
+        }
+        self.warnings = lint::lint(
+            &self.editor_content,
+            tokens,
+            vars,
+            func_defs,
+            units,
+            &self.lint_settings,
+        );
+        self.fold_ranges = folding::fold_ranges(&self.editor_content, func_defs);
         NoteCalcApp::renderr(
             &mut self.editor,
             &self.editor_content,
@@ -5015,6 +6451,13 @@ impl NoteCalcApp {
             editor_objs,
             &self.updated_line_ref_obj_indices,
             self.mouse_hover_type,
+            &self.find,
+            &self.rename,
+            &self.usages,
+            &self.completion,
+            &self.tooltip,
+            &self.warnings,
+            &self.fold_ranges,
         );
         self.updated_line_ref_obj_indices.clear();
     }
@@ -5066,6 +6509,20 @@ fn draw_cursor(
             );
         }
     }
+    if editor.is_cursor_shown() && matrix_editing.is_none() {
+        for selection in editor.get_other_selections() {
+            let pos = selection.get_cursor_pos();
+            if pos.row == r.editor_y.as_usize() && pos.column <= gr.current_editor_width {
+                render_buckets.set_color(Layer::AboveText, theme.cursor);
+                render_buckets.draw_char(
+                    Layer::AboveText,
+                    pos.column + gr.left_gutter_width,
+                    r.render_y.add(r.vert_align_offset),
+                    '▏',
+                );
+            }
+        }
+    }
 }
 
 pub fn pulse_modified_line_references(
@@ -5282,6 +6739,7 @@ fn render_tokens<'text_ptr>(
     units: &Units,
     need_matrix_renderer: bool,
     decimal_count: Option<usize>,
+    sheet_col_widths: Option<&[usize]>,
     theme: &Theme,
 ) {
     editor_objects.clear();
@@ -5289,6 +6747,8 @@ fn render_tokens<'text_ptr>(
 
     let parenthesis_around_cursor = find_parentesis_around_cursor(tokens, r, cursor_pos);
 
+    let line_start_render_x = r.render_x;
+    let mut sheet_col_index = 0;
     let mut token_index = 0;
     while token_index < tokens.len() {
         let token = &tokens[token_index];
@@ -5296,6 +6756,45 @@ fn render_tokens<'text_ptr>(
         if !need_matrix_renderer {
             simple_draw_normal(r, gr, render_buckets, editor_objects, token, theme);
             token_index += 1;
+        } else if let (Some(col_widths), ['|']) = (sheet_col_widths, token.ptr) {
+            // align the '|' separators of the spreadsheet block under each other
+            let separator_x = line_start_render_x
+                + col_widths.iter().take(sheet_col_index + 1).sum::<usize>()
+                + sheet_col_index;
+            let padding = separator_x.saturating_sub(r.render_x);
+            r.token_render_done(
+                0,
+                padding,
+                if cursor_pos.column > r.editor_x {
+                    padding as isize
+                } else {
+                    0
+                },
+            );
+            // a new object, so clicks after the padding are mapped to the right column
+            editor_objects.push(EditorObject {
+                typ: EditorObjectType::SimpleTokens,
+                row: r.editor_y,
+                start_x: r.editor_x,
+                end_x: r.editor_x + token.ptr.len(),
+                rendered_x: r.render_x,
+                rendered_y: r.render_y,
+                rendered_w: token.ptr.len(),
+                rendered_h: r.rendered_row_height,
+            });
+            draw_token(
+                token,
+                r.render_x,
+                r.render_y.add(r.vert_align_offset),
+                gr.current_editor_width,
+                gr.left_gutter_width,
+                render_buckets,
+                false, // is bold
+                theme,
+            );
+            r.token_render_done(token.ptr.len(), token.ptr.len(), 0);
+            sheet_col_index += 1;
+            token_index += 1;
         } else {
             match &token.typ {
                 TokenType::Operator(OperatorTokenType::Matrix {
@@ -5357,7 +6856,8 @@ fn render_tokens<'text_ptr>(
                         theme,
                     );
 
-                    let var_name_len = var.name.len();
+                    // it is the name of the variable, except for 'prev' and 'ans'
+                    let var_name_len = token.ptr.len();
                     editor_objects.push(EditorObject {
                         typ: EditorObjectType::LineReference {
                             var_index: *var_index,
@@ -5549,6 +7049,28 @@ fn render_wrap_dots(
     }
 }
 
+/// e.g. "# Groceries ⋯ 12 lines, total 1250 $" when the region under the header is folded
+fn render_fold_summary(
+    render_buckets: &mut RenderBuckets,
+    summary: String,
+    r: &PerLineRenderData,
+    gr: &GlobalRenderData,
+    theme: &Theme,
+) {
+    let x = r.render_x + 1;
+    if x >= gr.current_editor_width {
+        return;
+    }
+    let text: String = summary.chars().take(gr.current_editor_width - x).collect();
+    render_buckets.set_color(Layer::AboveText, theme.line_num_simple);
+    render_buckets.draw_string(
+        Layer::AboveText,
+        gr.left_gutter_width + x,
+        r.render_y.add(r.vert_align_offset),
+        text,
+    );
+}
+
 fn draw_line_ref_chooser(
     render_buckets: &mut RenderBuckets,
     r: &PerLineRenderData,
@@ -5587,7 +7109,7 @@ fn draw_right_gutter_num_prefixes(
             render_buckets.set_color(Layer::AboveText, theme.cursor);
             render_buckets.draw_text(Layer::AboveText, result_gutter_x, r.render_y, &['0', 'b']);
         }
-        ResultFormat::Dec => {}
+        ResultFormat::Dec | ResultFormat::Dms => {}
     }
 }
 
@@ -5649,6 +7171,208 @@ fn highlight_current_line(
     };
 }
 
+// 'prev' or 'ans'
+fn is_prev_line_ref(token: &Token) -> bool {
+    matches!(token.typ, TokenType::LineReference { .. })
+        && (token.ptr == &['p', 'r', 'e', 'v'][..] || token.ptr == &['a', 'n', 's'][..])
+}
+
+fn is_prev_line_ref_to(token: &Token, editor_y: usize) -> bool {
+    match token.typ {
+        TokenType::LineReference { var_index } => var_index == editor_y && is_prev_line_ref(token),
+        _ => false,
+    }
+}
+
+pub const SECTION_TOTAL_VAR_NAME: &[char] = &[
+    's', 'e', 'c', 't', 'i', 'o', 'n', '_', 't', 'o', 't', 'a', 'l',
+];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum SectionTotalType {
+    Sum,
+    Avg,
+    Count,
+}
+
+impl SectionTotalType {
+    // e.g. `# Groceries [sum]`
+    fn from_header(header: &[char]) -> Option<SectionTotalType> {
+        let (_title, tags) = parse_header(header);
+        tags.iter().rev().find_map(|tag| match tag.as_str() {
+            "sum" => Some(SectionTotalType::Sum),
+            "avg" => Some(SectionTotalType::Avg),
+            "count" => Some(SectionTotalType::Count),
+            _ => None,
+        })
+    }
+}
+
+fn starts_with_simulate_keyword(tokens: &Option<Tokens>) -> bool {
+    tokens
+        .as_ref()
+        .and_then(|it| it.tokens.iter().find(|it| !it.ptr[0].is_ascii_whitespace()))
+        .map(|it| Simulation::is_directive_keyword(it.ptr))
+        .unwrap_or(false)
+}
+
+/// Splits a header line into its title and the tags at its end,
+/// e.g. `# Groceries [scoped] [sum]` -> ("Groceries", ["scoped", "sum"])
+fn parse_header(header: &[char]) -> (String, Vec<String>) {
+    let header: String = header.iter().collect();
+    let mut title = header.trim_start_matches('#').trim();
+    let mut tags = Vec::new();
+    while title.ends_with(']') {
+        if let Some(start) = title.rfind('[') {
+            tags.push(title[start + 1..title.len() - 1].trim().to_owned());
+            title = title[..start].trim_end();
+        } else {
+            break;
+        }
+    }
+    tags.reverse();
+    (title.to_owned(), tags)
+}
+
+/// The variables defined under a `# Groceries [scoped]` header are local to its region,
+/// they are registered as `Groceries.name` and can be referred to by that name from anywhere.
+/// The regions are determined once per recalculation in one pass over the lines.
+fn update_header_scopes(editor_content: &mut EditorContent<LineData>) {
+    let mut scope: Option<Box<[char]>> = None;
+    for y in 0..editor_content.line_count() {
+        let line = editor_content.get_line_valid_chars(y);
+        if line.starts_with(&['#']) {
+            let (title, tags) = parse_header(line);
+            scope = if !title.is_empty() && tags.iter().any(|it| it == "scoped") {
+                Some(title.chars().collect())
+            } else {
+                None
+            };
+        }
+        if editor_content.get_data(y).header_scope != scope {
+            editor_content.mut_data(y).header_scope = scope.clone();
+        }
+    }
+}
+
+/// Returns the qualifier of the region the given line belongs to (see [update_header_scopes])
+pub fn header_scope_of_line(editor_content: &EditorContent<LineData>, y: usize) -> Option<&[char]> {
+    editor_content.get_data(y).header_scope.as_deref()
+}
+
+/// `price` assigned under a `# Groceries [scoped]` header is registered as `Groceries.price`
+pub fn scoped_var_name(
+    var_name: &[char],
+    editor_content: &EditorContent<LineData>,
+    y: usize,
+) -> Vec<char> {
+    match header_scope_of_line(editor_content, y) {
+        Some(scope)
+            if !(var_name.starts_with(scope) && var_name.get(scope.len()) == Some(&'.')) =>
+        {
+            let mut scoped_name = scope.to_vec();
+            scoped_name.push('.');
+            scoped_name.extend_from_slice(var_name);
+            scoped_name
+        }
+        _ => var_name.to_vec(),
+    }
+}
+
+/// true if the token refers to the variable either by its full or its local name (see [header_scope_of_line])
+fn is_name_of_var(token_ptr: &[char], var_name: &[char]) -> bool {
+    token_ptr == var_name || local_var_name(var_name) == token_ptr
+}
+
+fn local_var_name(var_name: &[char]) -> &[char] {
+    match var_name.iter().rposition(|it| *it == '.') {
+        Some(dot_index) => &var_name[dot_index + 1..],
+        None => var_name,
+    }
+}
+
+/// The total of the results under a #Header, it is the value of the `section_total` variable,
+/// and it is shown in the result panel next to the header if the header asks for it (`# Groceries [sum]`)
+struct SectionTotal {
+    header_y: Option<ContentIndex>,
+    typ: SectionTotalType,
+    sum: Result<CalcResult, ()>,
+    count: usize,
+}
+
+impl SectionTotal {
+    fn new(header_y: Option<ContentIndex>, typ: SectionTotalType) -> SectionTotal {
+        SectionTotal {
+            header_y,
+            typ,
+            sum: Err(()),
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, result: &CalcResult) {
+        self.sum = if self.count == 0 {
+            Ok(result.clone())
+        } else {
+            match &self.sum {
+                Ok(sum) => add_op(sum, result).ok_or(()),
+                Err(()) => Err(()),
+            }
+        };
+        self.count += 1;
+    }
+
+    fn value(&self) -> Result<CalcResult, ()> {
+        let count = CalcResult::new(CalcResultType::Number(Decimal::from(self.count)), 0);
+        match self.typ {
+            SectionTotalType::Sum => self.sum.clone(),
+            SectionTotalType::Avg => {
+                divide_op(self.sum.as_ref().map_err(|_| ())?, &count).ok_or(())
+            }
+            SectionTotalType::Count => Ok(count),
+        }
+    }
+
+    fn show_in_header(&self, results: &mut Results, result_change_flag: &mut BitFlag256) {
+        let header_y = if let Some(header_y) = self.header_y {
+            header_y
+        } else {
+            return;
+        };
+        let total = if self.count == 0 && self.typ != SectionTotalType::Count {
+            Ok(None)
+        } else {
+            self.value().map(Some).map_err(|_| {
+                EvalErr::new(
+                    "The results of the section can not be added together".to_owned(),
+                    0,
+                )
+            })
+        };
+        let changed = match (&results[header_y], &total) {
+            (Ok(prev), Ok(new)) => prev != new,
+            (Err(_), Err(_)) => false,
+            _ => true,
+        };
+        if changed {
+            result_change_flag.merge(BitFlag256::single_row(header_y.as_usize()));
+        }
+        results[header_y] = total;
+    }
+}
+
+fn refers_to_totals(tokens: &Option<Tokens>) -> bool {
+    tokens
+        .as_ref()
+        .map(|it| {
+            it.tokens.iter().any(|token| {
+                matches!(token.typ, TokenType::Variable { var_index }
+                    if var_index == SUM_VARIABLE_INDEX || var_index == SECTION_TOTAL_VARIABLE_INDEX)
+            })
+        })
+        .unwrap_or(false)
+}
+
 fn sum_result(sum_var: &mut Variable, result: &CalcResult, sum_is_null: &mut bool) {
     if *sum_is_null {
         sum_var.value = Ok(result.clone());
@@ -5856,6 +7580,7 @@ fn evaluate_text<'text_ptr>(
         allocator,
         0,
         &func_def_tmp,
+        header_scope_of_line(editor_content, editor_y),
     );
     let mut shunting_output_stack = Vec::with_capacity(4);
     ShuntingYard::shunting_yard(parsing_tokens, &mut shunting_output_stack, units, func_defs);
@@ -5868,6 +7593,7 @@ fn evaluate_text<'text_ptr>(
         editor_content,
         0,
         None,
+        RandomSeed::new(random::document_seed(editor_content), 0),
     );
     return result;
 }
@@ -6089,12 +7815,15 @@ fn render_matrix_result<'text_ptr>(
 ) -> usize {
     let start_x = render_x;
 
+    // the labels of tables are rendered as the first row
+    let header_row_count = if mat.col_labels.is_some() { 1 } else { 0 };
+    let rendered_row_count = mat.row_count + header_row_count;
     let vert_align_offset = (rendered_row_height - mat.render_height()) / 2;
     if render_x < clip_right {
         render_matrix_left_brackets(
             start_x,
             render_y,
-            mat.row_count,
+            rendered_row_count,
             render_buckets,
             vert_align_offset,
             false,
@@ -6134,23 +7863,42 @@ fn render_matrix_result<'text_ptr>(
         }
         max_lengths
     };
+    let cell_width =
+        max_lengths.int_part_len + max_lengths.frac_part_len + max_lengths.unit_part_len;
+    let col_width = mat
+        .col_labels
+        .iter()
+        .flatten()
+        .map(|it| it.chars().count())
+        .fold(cell_width, usize::max);
     render_buckets.set_color(Layer::Text, text_color);
 
     for col_i in 0..mat.col_count {
         if render_x >= clip_right {
             break;
         }
+        // the content of the matrix starts from the second row
+        let matrix_ascii_header_offset = if rendered_row_count == 1 { 0 } else { 1 };
+        if let Some(label) = mat.col_labels.as_ref().and_then(|it| it.get(col_i)) {
+            render_buckets.draw_string(
+                Layer::Text,
+                render_x + col_width - label.chars().count(),
+                render_y.add(vert_align_offset + matrix_ascii_header_offset),
+                label.clone(),
+            );
+        }
+        // the cells are aligned to the right if a label is wider than them
+        let cells_x = render_x + (col_width - cell_width);
         for row_i in 0..mat.row_count {
             let cell_str = &cells_strs[row_i * mat.col_count + col_i];
             let lengths = get_int_frac_part_len(cell_str);
             // Draw integer part
             let offset_x = max_lengths.int_part_len - lengths.int_part_len;
-            // the content of the matrix starts from the second row
-            let matrix_ascii_header_offset = if mat.row_count == 1 { 0 } else { 1 };
-            let dst_y = render_y.add(row_i + vert_align_offset + matrix_ascii_header_offset);
+            let dst_y = render_y
+                .add(row_i + vert_align_offset + matrix_ascii_header_offset + header_row_count);
             render_buckets.draw_string(
                 Layer::Text,
-                render_x + offset_x,
+                cells_x + offset_x,
                 dst_y,
                 // TOOD nem kell clone, csinálj iter into vhogy
                 cell_str[0..lengths.int_part_len].to_owned(),
@@ -6160,7 +7908,7 @@ fn render_matrix_result<'text_ptr>(
             if lengths.frac_part_len > 0 {
                 render_buckets.draw_string(
                     Layer::Text,
-                    render_x + offset_x + lengths.int_part_len,
+                    cells_x + offset_x + lengths.int_part_len,
                     dst_y,
                     // TOOD nem kell clone, csinálj iter into vhogy
                     cell_str[lengths.int_part_len..lengths.int_part_len + lengths.frac_part_len]
@@ -6169,7 +7917,7 @@ fn render_matrix_result<'text_ptr>(
             } else if max_lengths.frac_part_len > 0 {
                 render_buckets.draw_char(
                     Layer::Text,
-                    render_x + offset_x + lengths.int_part_len,
+                    cells_x + offset_x + lengths.int_part_len,
                     dst_y,
                     '.',
                 );
@@ -6178,7 +7926,7 @@ fn render_matrix_result<'text_ptr>(
             for i in 0..max_lengths.frac_part_len - lengths.frac_part_len - frac_offset_x {
                 render_buckets.draw_char(
                     Layer::Text,
-                    render_x
+                    cells_x
                         + offset_x
                         + lengths.int_part_len
                         + lengths.frac_part_len
@@ -6191,7 +7939,7 @@ fn render_matrix_result<'text_ptr>(
             if lengths.unit_part_len > 0 {
                 render_buckets.draw_string(
                     Layer::Text,
-                    render_x + offset_x + lengths.int_part_len + max_lengths.frac_part_len + 1,
+                    cells_x + offset_x + lengths.int_part_len + max_lengths.frac_part_len + 1,
                     dst_y,
                     // TOOD nem kell clone, csinálj iter into vhogy
                     // +1, skip space
@@ -6200,9 +7948,9 @@ fn render_matrix_result<'text_ptr>(
             }
         }
         render_x += if col_i + 1 < mat.col_count {
-            (max_lengths.int_part_len + max_lengths.frac_part_len + max_lengths.unit_part_len) + 2
+            col_width + 2
         } else {
-            max_lengths.int_part_len + max_lengths.frac_part_len + max_lengths.unit_part_len
+            col_width
         };
     }
 
@@ -6210,7 +7958,7 @@ fn render_matrix_result<'text_ptr>(
         render_matrix_right_brackets(
             render_x,
             render_y,
-            mat.row_count,
+            rendered_row_count,
             render_buckets,
             vert_align_offset,
             false,
@@ -6353,7 +8101,10 @@ fn render_results_into_buf_and_calc_len<'text_ptr>(
             if region_index < MAX_VISIBLE_HEADER_COUNT - 1 {
                 region_index += 1;
             }
-            continue;
+            // the total of the section (`# Groceries [sum]`) is aligned with the results of the section
+            if let Ok(None) = result {
+                continue;
+            }
         }
 
         if let Err(..) = result {
@@ -6497,6 +8248,11 @@ fn create_render_commands_for_results_and_render_matrices<'text_ptr>(
                 row,
                 column: x,
             });
+            if let (ResultOffsetX::Err, Err(err)) =
+                (&offset_x, &results[result_tmp.editor_y.as_usize()])
+            {
+                render_error_reason(render_buckets, &err.reason, x + int_w + 1, row, gr, theme);
+            }
             if lengths.frac_part_len > 0 {
                 let from = result_range.start + lengths.int_part_len;
                 render_buckets.ascii_texts.push(RenderAsciiTextMsg {
@@ -6585,6 +8341,33 @@ fn create_render_commands_for_results_and_render_matrices<'text_ptr>(
     return matrix_len;
 }
 
+/// e.g. "Err Cannot add Length to Mass", the reason is cut if it does not fit into the result panel
+fn render_error_reason(
+    render_buckets: &mut RenderBuckets,
+    reason: &str,
+    x: usize,
+    row: CanvasY,
+    gr: &GlobalRenderData,
+    theme: &Theme,
+) {
+    let max_w =
+        (gr.result_gutter_x + RIGHT_GUTTER_WIDTH + gr.current_result_panel_width).saturating_sub(x);
+    if max_w == 0 {
+        return;
+    }
+    let text: String = if reason.chars().count() > max_w {
+        reason
+            .chars()
+            .take(max_w - 1)
+            .chain(std::iter::once('…'))
+            .collect()
+    } else {
+        reason.to_owned()
+    };
+    render_buckets.set_color(Layer::AboveText, theme.number_error);
+    render_buckets.draw_string(Layer::AboveText, x, row, text);
+}
+
 fn calc_consecutive_matrices_max_lengths(
     units: &Units,
     results: &[LineResult],
@@ -6907,70 +8690,325 @@ fn render_buckets_into(buckets: &RenderBuckets, canvas: &mut [[char; 256]]) {
     }
 }
 
-fn render_selection_and_its_sum<'text_ptr>(
-    units: &Units,
-    render_buckets: &mut RenderBuckets<'text_ptr>,
-    results: &Results,
-    editor: &Editor<LineData>,
-    editor_content: &EditorContent<LineData>,
+fn render_find(
+    render_buckets: &mut RenderBuckets,
+    find: &Find,
     gr: &GlobalRenderData,
+    theme: &Theme,
+) {
+    draw_match_rects(render_buckets, &find.matches, gr, theme);
+
+    let option = |enabled: bool, name: &str| {
+        if enabled {
+            format!("[{}]", name)
+        } else {
+            format!(" {} ", name)
+        }
+    };
+    let focus_mark = |field: FindField| {
+        if find.focused_field == field {
+            '▏'
+        } else {
+            ' '
+        }
+    };
+    let mut rows = vec![format!(
+        "Find: {}{} {}{}{} {}",
+        find.query,
+        focus_mark(FindField::Query),
+        option(find.options.case_sensitive, "Aa"),
+        option(find.options.whole_word, "W"),
+        option(find.options.regex, ".*"),
+        find.status(),
+    )];
+    if find.show_replace {
+        rows.push(format!(
+            "Replace: {}{}",
+            find.replacement,
+            focus_mark(FindField::Replacement)
+        ));
+    }
+    draw_input_bar(render_buckets, rows, gr, theme);
+}
+
+fn render_rename(
+    render_buckets: &mut RenderBuckets,
+    rename: &Rename,
     vars: &Variables,
-    func_defs: &FunctionDefinitions<'text_ptr>,
-    allocator: &'text_ptr Bump,
+    func_defs: &FunctionDefinitions,
+    units: &Units,
+    gr: &GlobalRenderData,
     theme: &Theme,
-    apptokens: &AppTokens,
 ) {
-    render_buckets.set_color(Layer::BehindTextAboveCursor, theme.selection_color);
-    if let Some((start, end)) = editor.get_selection().is_range_ordered() {
-        if end.row > start.row {
-            // first line
-            if let Some(start_render_y) = gr.get_render_y(content_y(start.row)) {
-                let height = gr.get_rendered_height(content_y(start.row));
-                let w = editor_content.line_len(start.row);
-                if w > start.column {
-                    render_buckets.draw_rect(
-                        Layer::BehindTextAboveCursor,
-                        start.column + gr.left_gutter_width,
-                        start_render_y,
-                        (w - start.column).min(gr.current_editor_width),
-                        height,
-                    );
-                }
+    let mut row = format!("Rename '{}' to: {}▏", rename.old_name, rename.new_name);
+    if let Some(error) = rename.new_name_error(vars, func_defs, units) {
+        if !rename.new_name.is_empty() {
+            row.push(' ');
+            row.push_str(error);
+        }
+    }
+    draw_input_bar(render_buckets, vec![row], gr, theme);
+}
+
+/// The highlighted ranges of the find matches and of the usages
+fn draw_match_rects(
+    render_buckets: &mut RenderBuckets,
+    matches: &[FindMatch],
+    gr: &GlobalRenderData,
+    theme: &Theme,
+) {
+    render_buckets.set_color(Layer::BehindTextAboveCursor, theme.find_match_bg);
+    for m in matches {
+        if let Some(render_y) = gr.get_render_y(content_y(m.row)) {
+            if m.start < gr.current_editor_width {
+                render_buckets.draw_rect(
+                    Layer::BehindTextAboveCursor,
+                    gr.left_gutter_width + m.start,
+                    render_y,
+                    (m.end - m.start).min(gr.current_editor_width - m.start),
+                    gr.get_rendered_height(content_y(m.row)),
+                );
             }
-            // full lines
-            for i in start.row + 1..end.row {
-                if let Some(render_y) = gr.get_render_y(content_y(i)) {
-                    let height = gr.get_rendered_height(content_y(i));
-                    render_buckets.draw_rect(
-                        Layer::BehindTextAboveCursor,
-                        gr.left_gutter_width,
-                        render_y,
-                        editor_content.line_len(i).min(gr.current_editor_width),
-                        height,
-                    );
-                }
+        }
+    }
+}
+
+fn render_usages(
+    render_buckets: &mut RenderBuckets,
+    usages: &Usages,
+    editor_content: &EditorContent<LineData>,
+    gr: &GlobalRenderData,
+    theme: &Theme,
+) {
+    draw_match_rects(render_buckets, &usages.usages, gr, theme);
+
+    let mut rows = vec![format!(
+        "Usages of '{}': {}",
+        usages.name,
+        usages.usages.len()
+    )];
+    // only a window of the list is shown which contains the selected usage
+    let first = usages
+        .selected
+        .map(|it| (it + 1).saturating_sub(USAGES_PANEL_MAX_ROWS))
+        .unwrap_or(0);
+    for (i, m) in usages
+        .usages
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(USAGES_PANEL_MAX_ROWS)
+    {
+        let line: String = editor_content.get_line_valid_chars(m.row).iter().collect();
+        rows.push(format!(
+            "{}{:>3}: {}",
+            if usages.selected == Some(i) { '>' } else { ' ' },
+            m.row + 1,
+            line.trim()
+        ));
+    }
+    draw_input_bar(render_buckets, rows, gr, theme);
+}
+
+/// The popup is rendered below the completed word
+fn render_completion(
+    render_buckets: &mut RenderBuckets,
+    completion: &Completion,
+    gr: &GlobalRenderData,
+    theme: &Theme,
+) {
+    let row = content_y(completion.pos.row);
+    let render_y = match gr.get_render_y(row) {
+        Some(render_y) => render_y.add(gr.get_rendered_height(row)),
+        None => return,
+    };
+    let first = (completion.selected + 1).saturating_sub(COMPLETION_POPUP_MAX_ROWS);
+    let items = completion
+        .items
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(COMPLETION_POPUP_MAX_ROWS);
+    let label_w = completion
+        .items
+        .iter()
+        .map(|it| it.label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let rows: Vec<(usize, &CompletionItem, String)> = items
+        .map(|(i, item)| {
+            (
+                i,
+                item,
+                format!("{:w$} {}", item.label, item.detail, w = label_w),
+            )
+        })
+        .collect();
+    let w = rows
+        .iter()
+        .map(|(_, _, text)| text.chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(gr.current_editor_width);
+    let x = gr.left_gutter_width + completion.pos.column.min(gr.current_editor_width - w);
+    render_buckets.set_color(Layer::AboveText, theme.input_bar_bg);
+    render_buckets.draw_rect(Layer::AboveText, x, render_y, w, rows.len());
+    for (row_i, (i, item, text)) in rows.into_iter().enumerate() {
+        let y = render_y.add(row_i);
+        if i == completion.selected {
+            render_buckets.set_color(Layer::AboveText, theme.selection_color);
+            render_buckets.draw_rect(Layer::AboveText, x, y, w, 1);
+        }
+        render_buckets.set_color(
+            Layer::AboveText,
+            match item.kind {
+                CompletionKind::Variable => theme.variable,
+                CompletionKind::Unit => theme.unit,
+                CompletionKind::Keyword => theme.operator,
+                CompletionKind::Function => theme.text,
+            },
+        );
+        render_buckets.draw_string(Layer::AboveText, x, y, text);
+    }
+}
+
+/// Below the hovered line, or above it if there is no room for it
+fn render_tooltip(
+    render_buckets: &mut RenderBuckets,
+    tooltip: &Tooltip,
+    gr: &GlobalRenderData,
+    theme: &Theme,
+) {
+    let row = content_y(tooltip.row);
+    let line_render_y = match gr.get_render_y(row) {
+        Some(render_y) => render_y,
+        None => return,
+    };
+    let h = tooltip.lines.len();
+    let below = line_render_y.add(gr.get_rendered_height(row));
+    let render_y = if below.as_usize() + h <= gr.client_height || line_render_y.as_usize() < h {
+        below
+    } else {
+        canvas_y(line_render_y.as_isize() - h as isize)
+    };
+    let w = tooltip
+        .lines
+        .iter()
+        .map(|it| it.chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(gr.current_editor_width);
+    let x = gr.left_gutter_width + tooltip.x.min(gr.current_editor_width - w);
+    render_buckets.set_color(Layer::AboveText, theme.input_bar_bg);
+    render_buckets.draw_rect(Layer::AboveText, x, render_y, w, h);
+    render_buckets.set_color(Layer::AboveText, theme.text);
+    for (i, line) in tooltip.lines.iter().enumerate() {
+        let line: String = line.chars().take(w).collect();
+        render_buckets.draw_string(Layer::AboveText, x, render_y.add(i), line);
+    }
+}
+
+/// The find and rename bars and the usages panel in the top right corner of the editor
+fn draw_input_bar(
+    render_buckets: &mut RenderBuckets,
+    rows: Vec<String>,
+    gr: &GlobalRenderData,
+    theme: &Theme,
+) {
+    let w = rows
+        .iter()
+        .map(|it| it.chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(gr.current_editor_width);
+    let x = gr.left_gutter_width + gr.current_editor_width - w;
+    render_buckets.set_color(Layer::AboveText, theme.input_bar_bg);
+    render_buckets.draw_rect(Layer::AboveText, x, canvas_y(0), w, rows.len());
+    render_buckets.set_color(Layer::AboveText, theme.text);
+    for (i, row) in rows.into_iter().enumerate() {
+        render_buckets.draw_string(Layer::AboveText, x, canvas_y(i as isize), row);
+    }
+}
+
+fn draw_selection_rects(
+    render_buckets: &mut RenderBuckets,
+    start: Pos,
+    end: Pos,
+    editor_content: &EditorContent<LineData>,
+    gr: &GlobalRenderData,
+) {
+    if end.row > start.row {
+        // first line
+        if let Some(start_render_y) = gr.get_render_y(content_y(start.row)) {
+            let height = gr.get_rendered_height(content_y(start.row));
+            let w = editor_content.line_len(start.row);
+            if w > start.column {
+                render_buckets.draw_rect(
+                    Layer::BehindTextAboveCursor,
+                    start.column + gr.left_gutter_width,
+                    start_render_y,
+                    (w - start.column).min(gr.current_editor_width),
+                    height,
+                );
             }
-            // last line
-            if let Some(end_render_y) = gr.get_render_y(content_y(end.row)) {
-                let height = gr.get_rendered_height(content_y(end.row));
+        }
+        // full lines
+        for i in start.row + 1..end.row {
+            if let Some(render_y) = gr.get_render_y(content_y(i)) {
+                let height = gr.get_rendered_height(content_y(i));
                 render_buckets.draw_rect(
                     Layer::BehindTextAboveCursor,
                     gr.left_gutter_width,
-                    end_render_y,
-                    end.column.min(gr.current_editor_width),
+                    render_y,
+                    editor_content.line_len(i).min(gr.current_editor_width),
                     height,
                 );
             }
-        } else if let Some(start_render_y) = gr.get_render_y(content_y(start.row)) {
-            let height = gr.get_rendered_height(content_y(start.row));
+        }
+        // last line
+        if let Some(end_render_y) = gr.get_render_y(content_y(end.row)) {
+            let height = gr.get_rendered_height(content_y(end.row));
             render_buckets.draw_rect(
                 Layer::BehindTextAboveCursor,
-                start.column + gr.left_gutter_width,
-                start_render_y,
-                (end.column - start.column).min(gr.current_editor_width),
+                gr.left_gutter_width,
+                end_render_y,
+                end.column.min(gr.current_editor_width),
                 height,
             );
         }
+    } else if let Some(start_render_y) = gr.get_render_y(content_y(start.row)) {
+        let height = gr.get_rendered_height(content_y(start.row));
+        render_buckets.draw_rect(
+            Layer::BehindTextAboveCursor,
+            start.column + gr.left_gutter_width,
+            start_render_y,
+            (end.column - start.column).min(gr.current_editor_width),
+            height,
+        );
+    }
+}
+
+fn render_selection_and_its_sum<'text_ptr>(
+    units: &Units,
+    render_buckets: &mut RenderBuckets<'text_ptr>,
+    results: &Results,
+    editor: &Editor<LineData>,
+    editor_content: &EditorContent<LineData>,
+    gr: &GlobalRenderData,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions<'text_ptr>,
+    allocator: &'text_ptr Bump,
+    theme: &Theme,
+    apptokens: &AppTokens,
+) {
+    render_buckets.set_color(Layer::BehindTextAboveCursor, theme.selection_color);
+    for selection in editor.get_other_selections() {
+        if let Some((start, end)) = selection.is_range_ordered() {
+            draw_selection_rects(render_buckets, start, end, editor_content, gr);
+        }
+    }
+    if let Some((start, end)) = editor.get_selection().is_range_ordered() {
+        draw_selection_rects(render_buckets, start, end, editor_content, gr);
         // evaluated result of selection, selected text
         if let Some(mut partial_result) = evaluate_selection(
             &units,
@@ -7134,7 +9172,7 @@ pub fn end_matrix_editing(
         }
         for col_i in 0..mat_editor.col_count {
             if col_i > 0 {
-                concat.push(',');
+                concat.push(mat_editor.col_separator);
             }
             let cell_str = &mat_editor.cell_strings[row_i * mat_editor.col_count + col_i];
             concat += &cell_str;
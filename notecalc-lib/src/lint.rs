@@ -0,0 +1,245 @@
+use crate::editor::editor_content::EditorContent;
+use crate::helper::{content_y, AppTokens};
+use crate::rename::{lhs_name_range, symbol_of_token, token_ranges, Symbol};
+use crate::token_parser::{Token, TokenType};
+use crate::units::units::{UnitInstance, UnitOutput, Units};
+use crate::{local_var_name, FunctionDefinitions, LineData, Variables};
+
+const WARNING_KIND_COUNT: usize = 4;
+
+/// The categories of the warnings, each of them can be turned off
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum WarningKind {
+    // a variable is defined again, e.g. `price = 12` in line 1 and `price = 14` in line 5
+    Shadowing = 0,
+    // a variable or a user function which is never referenced
+    Unused = 1,
+    // a text right after a number which is probably a mistyped unit, e.g. `5 kmh`
+    UnknownUnit = 2,
+    // a unit which contains different units of the same kind, e.g. `km/m`
+    MixedUnits = 3,
+}
+
+impl WarningKind {
+    pub fn from_name(name: &str) -> Option<WarningKind> {
+        match name {
+            "shadowing" => Some(WarningKind::Shadowing),
+            "unused" => Some(WarningKind::Unused),
+            "unknown_unit" => Some(WarningKind::UnknownUnit),
+            "mixed_units" => Some(WarningKind::MixedUnits),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Warning {
+    pub row: usize,
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LintSettings {
+    enabled: [bool; WARNING_KIND_COUNT],
+}
+
+impl Default for LintSettings {
+    fn default() -> Self {
+        let mut enabled = [true; WARNING_KIND_COUNT];
+        // the last assignments of a calculation are its results, they are rarely referenced
+        enabled[WarningKind::Unused as usize] = false;
+        LintSettings { enabled }
+    }
+}
+
+impl LintSettings {
+    pub fn is_enabled(&self, kind: WarningKind) -> bool {
+        self.enabled[kind as usize]
+    }
+
+    pub fn set_enabled(&mut self, kind: WarningKind, enabled: bool) {
+        self.enabled[kind as usize] = enabled;
+    }
+}
+
+/// The warnings of the enabled categories, ordered by their rows
+pub fn lint(
+    editor_content: &EditorContent<LineData>,
+    apptokens: &AppTokens,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions,
+    units: &Units,
+    settings: &LintSettings,
+) -> Vec<Warning> {
+    let line_count = editor_content.line_count();
+    let (referenced_vars, referenced_fns) = referenced_rows(line_count, apptokens);
+    let mut warnings = Vec::new();
+    for row in 0..line_count {
+        let tokens = match &apptokens[content_y(row)] {
+            Some(tokens) => &tokens.tokens,
+            None => continue,
+        };
+        let mut warn = |kind: WarningKind, message: String| {
+            if settings.is_enabled(kind) {
+                warnings.push(Warning { row, kind, message });
+            }
+        };
+        // line references are registered as variables as well, e.g. '&[1]'
+        if let Some(var) = vars[row].as_ref().filter(|it| !it.name.starts_with(&['&'])) {
+            let name: String = local_var_name(&var.name).iter().collect();
+            let shadowed_row = (0..row)
+                .rev()
+                .find(|i| matches!(&vars[*i], Some(prev) if prev.name == var.name));
+            if let Some(shadowed_row) = shadowed_row {
+                // e.g. 'total = total + 12' is an intentional redefinition
+                let lhs_end = lhs_name_range(tokens).map(|it| it.end).unwrap_or(0);
+                let refers_to_shadowed = token_ranges(tokens).any(|(range, it)| {
+                    range.start >= lhs_end
+                        && matches!(it.typ, TokenType::Variable { var_index } if var_index == shadowed_row)
+                });
+                if !refers_to_shadowed {
+                    warn(
+                        WarningKind::Shadowing,
+                        format!("'{}' is already defined in line {}", name, shadowed_row + 1),
+                    );
+                }
+            }
+            if !referenced_vars[row] {
+                warn(WarningKind::Unused, format!("'{}' is never used", name));
+            }
+        }
+        if let Some(fd) = func_defs[row].as_ref() {
+            if !referenced_fns[row] {
+                let name: String = fd.func_name.iter().collect();
+                warn(
+                    WarningKind::Unused,
+                    format!("The function '{}' is never used", name),
+                );
+            }
+        }
+        for (word, suggestions) in mistyped_units(tokens, units) {
+            let suggestions: Vec<String> =
+                suggestions.iter().map(|it| format!("'{}'", it)).collect();
+            warn(
+                WarningKind::UnknownUnit,
+                format!(
+                    "'{}' is not a unit, did you mean {}?",
+                    word,
+                    suggestions.join(" or ")
+                ),
+            );
+        }
+        for token in tokens {
+            if let TokenType::Unit(_, unit) = &token.typ {
+                if let Some((a, b)) = same_kind_units(unit) {
+                    warn(
+                        WarningKind::MixedUnits,
+                        format!("'{}' and '{}' are units of the same kind", a, b),
+                    );
+                }
+            }
+        }
+    }
+    warnings
+}
+
+/// The rows whose variable and whose function are referenced from anywhere in the document
+fn referenced_rows(line_count: usize, apptokens: &AppTokens) -> (Vec<bool>, Vec<bool>) {
+    let mut referenced_vars = vec![false; line_count];
+    let mut referenced_fns = vec![false; line_count];
+    for row in 0..line_count {
+        let tokens = match &apptokens[content_y(row)] {
+            Some(tokens) => &tokens.tokens,
+            None => continue,
+        };
+        // the assigned name itself is not a reference
+        let lhs_end = lhs_name_range(tokens).map(|it| it.end).unwrap_or(0);
+        for (range, token) in token_ranges(tokens) {
+            if range.start < lhs_end {
+                continue;
+            }
+            let symbol = match token.typ {
+                TokenType::LineReference { var_index } => Some(Symbol::Variable(var_index)),
+                _ => symbol_of_token(token),
+            };
+            match symbol {
+                Some(Symbol::Variable(i)) if i < line_count => referenced_vars[i] = true,
+                Some(Symbol::Function(i)) if i < line_count => referenced_fns[i] = true,
+                _ => {}
+            }
+        }
+    }
+    (referenced_vars, referenced_fns)
+}
+
+/// The words right after numbers which are not units but look like mistyped ones,
+/// with the suggested units, e.g. ('kmh', ['km/h', 'km h']), ('KG', ['kg']) or ('pints', ['pint'])
+fn mistyped_units(tokens: &[Token], units: &Units) -> Vec<(String, Vec<String>)> {
+    let mut result = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if !matches!(token.typ, TokenType::NumberLiteral(_)) {
+            continue;
+        }
+        let word = tokens[i + 1..]
+            .iter()
+            .find(|it| !it.ptr.iter().all(|ch| ch.is_whitespace()))
+            .filter(|it| matches!(it.typ, TokenType::StringLiteral))
+            .map(|it| it.ptr)
+            .filter(|word| word.iter().all(|ch| ch.is_alphabetic()));
+        if let Some(word) = word {
+            let suggestions = unit_suggestions(word, units);
+            if !suggestions.is_empty() {
+                result.push((word.iter().collect(), suggestions));
+            }
+        }
+    }
+    result
+}
+
+fn unit_suggestions(word: &[char], units: &Units) -> Vec<String> {
+    let is_unit = |text: &[char]| !text.is_empty() && units.parse(text).1 == text.len();
+    if is_unit(word) {
+        return vec![];
+    }
+    let lowercase: Vec<char> = word.iter().flat_map(|it| it.to_lowercase()).collect();
+    if is_unit(&lowercase) {
+        return vec![lowercase.iter().collect()];
+    }
+    if let Some((last, singular)) = word.split_last() {
+        if *last == 's' && is_unit(singular) {
+            return vec![singular.iter().collect()];
+        }
+    }
+    // two units without an operator between them
+    match (1..word.len()).find(|i| is_unit(&word[..*i]) && is_unit(&word[*i..])) {
+        Some(i) => {
+            let lhs: String = word[..i].iter().collect();
+            let rhs: String = word[i..].iter().collect();
+            vec![format!("{}/{}", lhs, rhs), format!("{} {}", lhs, rhs)]
+        }
+        None => vec![],
+    }
+}
+
+/// The first two different units in `unit` which measure the same quantity, e.g. 'km' and 'm'
+fn same_kind_units(unit: &UnitOutput) -> Option<(String, String)> {
+    let instances: Vec<&UnitInstance> = unit.iter_unit_instances().collect();
+    for (i, a) in instances.iter().enumerate() {
+        for b in &instances[i + 1..] {
+            let (a_name, b_name) = (unit_instance_name(a), unit_instance_name(b));
+            if a.unit.base == b.unit.base && a_name != b_name {
+                return Some((a_name, b_name));
+            }
+        }
+    }
+    None
+}
+
+fn unit_instance_name(unit: &UnitInstance) -> String {
+    unit.prefix
+        .name
+        .iter()
+        .chain(unit.unit.name.iter())
+        .collect()
+}
@@ -1,5 +1,6 @@
 use crate::calc::{divide_op, multiply_op, CalcResult, CalcResultType};
 use crate::MATRIX_ASCII_HEADER_FOOTER_LINE_COUNT;
+use std::ops::Range;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct MatrixData {
@@ -7,6 +8,8 @@ pub struct MatrixData {
     pub cells: Vec<CalcResult>,
     pub row_count: usize,
     pub col_count: usize,
+    // the header of tables, e.g. [rate | days; 90 | 20]
+    pub col_labels: Option<Vec<String>>,
 }
 
 impl MatrixData {
@@ -15,9 +18,22 @@ impl MatrixData {
             cells,
             row_count,
             col_count,
+            col_labels: None,
         }
     }
 
+    pub fn with_col_labels(mut self, col_labels: Vec<String>) -> MatrixData {
+        self.col_labels = Some(col_labels);
+        self
+    }
+
+    pub fn col_index_of(&self, label: &[char]) -> Option<usize> {
+        self.col_labels
+            .as_ref()?
+            .iter()
+            .position(|it| it.chars().eq(label.iter().cloned()))
+    }
+
     #[inline]
     pub fn calc_render_height(row_count: usize) -> usize {
         if row_count == 1 {
@@ -29,7 +45,9 @@ impl MatrixData {
 
     #[inline]
     pub fn render_height(&self) -> usize {
-        MatrixData::calc_render_height(self.row_count)
+        // the labels are rendered as the first row
+        let header_row_count = if self.col_labels.is_some() { 1 } else { 0 };
+        MatrixData::calc_render_height(self.row_count + header_row_count)
     }
 
     pub fn cell(&self, row: usize, col: usize) -> &CalcResult {
@@ -40,6 +58,40 @@ impl MatrixData {
         self.cells.len() == 1
     }
 
+    /// single row or single column matrices work as arrays
+    pub fn is_array(&self) -> bool {
+        self.row_count == 1 || self.col_count == 1
+    }
+
+    /// an array with the same orientation as this one
+    pub fn array_with_cells(&self, cells: Vec<CalcResult>) -> MatrixData {
+        let len = cells.len();
+        if self.row_count == 1 {
+            MatrixData::new(cells, 1, len)
+        } else {
+            MatrixData::new(cells, len, 1)
+        }
+    }
+
+    pub fn sub_matrix(&self, rows: Range<usize>, cols: Range<usize>) -> MatrixData {
+        let (row_count, col_count) = (rows.len(), cols.len());
+        let mut cells = Vec::with_capacity(row_count * col_count);
+        for row in rows {
+            for col in cols.clone() {
+                cells.push(self.cell(row, col).clone());
+            }
+        }
+        MatrixData {
+            cells,
+            row_count,
+            col_count,
+            col_labels: self
+                .col_labels
+                .as_ref()
+                .map(|labels| labels[cols.start..cols.end].to_vec()),
+        }
+    }
+
     pub fn neg(&self) -> MatrixData {
         todo!()
     }
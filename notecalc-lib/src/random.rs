@@ -0,0 +1,303 @@
+use crate::calc::{add_op, divide_op, multiply_op, sub_op, CalcResult, CalcResultType, EvalErr};
+use crate::editor::editor_content::EditorContent;
+use crate::functions::{compare_results, FnType};
+use crate::LineData;
+use rust_decimal::prelude::*;
+use std::cmp::Ordering;
+
+pub const SEED_KEYWORD: &str = "seed";
+const MAX_DICE_COUNT: usize = 1000;
+
+/// The random numbers of a line are derived from this seed, the id of the line and the position
+/// of the function in the line, so re-rendering the document gives back the same numbers.
+/// The seed of the document can be set by a `seed 42` line, and every iteration of
+/// a simulation has its own seed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct RandomSeed(u64);
+
+impl RandomSeed {
+    pub fn new(document_seed: u64, iteration: usize) -> RandomSeed {
+        RandomSeed(document_seed).derive(iteration, 0)
+    }
+
+    pub fn derive(&self, a: usize, b: usize) -> RandomSeed {
+        let mut rng = Rng(self.0
+            ^ (a as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (b as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F));
+        RandomSeed(rng.next_u64())
+    }
+
+    pub fn rng(&self) -> Rng {
+        Rng(self.0)
+    }
+}
+
+/// SplitMix64, small and good enough for estimations
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// uniformly distributed in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// uniformly distributed in [0, n)
+    fn next_below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+/// e.g. `seed 42` or `simulate 1000`
+pub fn parse_directive(line: &[char], keyword: &str) -> Option<u64> {
+    let line: String = line.iter().collect();
+    let rest = line.trim().strip_prefix(keyword)?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    rest.trim().parse().ok()
+}
+
+pub fn document_seed(editor_content: &EditorContent<LineData>) -> u64 {
+    (0..editor_content.line_count())
+        .find_map(|y| parse_directive(editor_content.get_line_valid_chars(y), SEED_KEYWORD))
+        .unwrap_or(0)
+}
+
+/// `3d6` rolls three six-sided dice
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Dice {
+    pub count: usize,
+    pub sides: usize,
+    // the length of the literal
+    pub len: usize,
+}
+
+pub fn parse_dice(str: &[char]) -> Option<Dice> {
+    fn parse_digits(str: &[char], i: &mut usize) -> Option<usize> {
+        let start = *i;
+        while *i < str.len() && str[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        if *i == start {
+            return None;
+        }
+        str[start..*i].iter().collect::<String>().parse().ok()
+    }
+    let mut i = 0;
+    let count = parse_digits(str, &mut i)?;
+    if str.get(i) != Some(&'d') {
+        return None;
+    }
+    i += 1;
+    let sides = parse_digits(str, &mut i)?;
+    let is_end_of_literal = str
+        .get(i)
+        .map(|it| !it.is_alphanumeric() && *it != '_' && *it != '.')
+        .unwrap_or(true);
+    if is_end_of_literal {
+        Some(Dice {
+            count,
+            sides,
+            len: i,
+        })
+    } else {
+        None
+    }
+}
+
+pub fn arg_count_and_usage(typ: FnType) -> (usize, &'static str) {
+    match typ {
+        FnType::Rand => (0, "Usage: rand()"),
+        FnType::RandInt => (2, "Usage: randint(from, to)"),
+        FnType::Normal => (2, "Usage: normal(mean, sd)"),
+        FnType::Uniform => (2, "Usage: uniform(from, to)"),
+        FnType::Triangular => (3, "Usage: triangular(lo, mode, hi)"),
+        FnType::Dice => (0, "Usage: 3d6"),
+        FnType::UserDefined(_)
+        | FnType::GoalSeek
+        | FnType::Deriv
+        | FnType::Integrate
+        | FnType::Root
+        | FnType::Map
+        | FnType::Filter
+        | FnType::Reduce
+        | FnType::Sort
+        | FnType::Reverse
+        | FnType::Unique
+        | FnType::CumSum
+        | FnType::Nth
+        | FnType::Sum
+        | FnType::Transpose
+        | FnType::Pi
+        | FnType::E
+        | FnType::Ceil
+        | FnType::Ln
+        | FnType::Lg
+        | FnType::Log
+        | FnType::Abs
+        | FnType::Sin
+        | FnType::Asin
+        | FnType::Cos
+        | FnType::Acos
+        | FnType::Tan
+        | FnType::Atan => panic!("only the random functions are evaluated here"),
+    }
+}
+
+/// Draws a number from the distribution of a random function, `fn_token` is the
+/// token of the function (it holds the dice literal itself in case of dice).
+pub fn draw(
+    typ: FnType,
+    args: &[CalcResult],
+    fn_token: &[char],
+    fn_token_index: usize,
+    rng: &mut Rng,
+) -> Result<CalcResult, EvalErr> {
+    let result = match typ {
+        FnType::Rand => number(rng.next_f64()),
+        FnType::RandInt => {
+            let (from, to) = (int_arg(&args[0])?, int_arg(&args[1])?);
+            if from > to {
+                return Err(EvalErr::new3(
+                    "The lower bound must not be greater than the upper bound".to_owned(),
+                    fn_token_index,
+                    &args[0],
+                    &args[1],
+                ));
+            }
+            let range_len = to
+                .checked_sub(from)
+                .map(|it| it as u64 + 1)
+                .ok_or_else(|| EvalErr::new("The range is too large".to_owned(), fn_token_index))?;
+            Some(CalcResult::new(
+                CalcResultType::Number(Decimal::from(from + rng.next_below(range_len) as i64)),
+                0,
+            ))
+        }
+        FnType::Uniform => scale(&args[0], &args[1], rng.next_f64()),
+        FnType::Normal => {
+            // Box-Muller transform, 1 - u is used so ln is never called with 0
+            let (u1, u2) = (1.0 - rng.next_f64(), rng.next_f64());
+            let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+            number(z)
+                .and_then(|z| multiply_op(&args[1], &z))
+                .and_then(|deviation| add_op(&args[0], &deviation))
+        }
+        FnType::Triangular => {
+            let (lo, mode, hi) = (&args[0], &args[1], &args[2]);
+            let order = [compare_results(lo, mode), compare_results(mode, hi)];
+            if order.contains(&Some(Ordering::Greater)) {
+                return Err(EvalErr::new(
+                    "The arguments must be in increasing order: lo <= mode <= hi".to_owned(),
+                    fn_token_index,
+                ));
+            }
+            if compare_results(lo, hi) == Some(Ordering::Equal) {
+                // a triangle of zero width, e.g. triangular(5, 5, 5)
+                Some(lo.clone())
+            } else {
+                // the position of the mode in [lo, hi]
+                sub_op(mode, lo)
+                    .and_then(|lhs| sub_op(hi, lo).and_then(|rhs| divide_op(&lhs, &rhs)))
+                    .and_then(|it| match it.typ {
+                        CalcResultType::Number(num) => num.to_f64(),
+                        CalcResultType::Quantity(num, unit) if unit.is_unitless() => num.to_f64(),
+                        _ => None,
+                    })
+                    .and_then(|c| {
+                        // inverse of the cumulative distribution function of the triangle over [0, 1]
+                        let u = rng.next_f64();
+                        let t = if u < c {
+                            (u * c).sqrt()
+                        } else {
+                            1.0 - ((1.0 - u) * (1.0 - c)).sqrt()
+                        };
+                        scale(lo, hi, t)
+                    })
+            }
+        }
+        FnType::Dice => {
+            let dice = parse_dice(fn_token)
+                .filter(|it| it.count >= 1 && it.count <= MAX_DICE_COUNT && it.sides >= 1)
+                .ok_or_else(|| {
+                    EvalErr::new(
+                        format!(
+                            "The number of dice must be between 1 and {}, e.g. 3d6",
+                            MAX_DICE_COUNT
+                        ),
+                        fn_token_index,
+                    )
+                })?;
+            let sum: u64 = (0..dice.count)
+                .map(|_| 1 + rng.next_below(dice.sides as u64))
+                .sum();
+            Some(CalcResult::new(
+                CalcResultType::Number(Decimal::from(sum)),
+                0,
+            ))
+        }
+        FnType::UserDefined(_)
+        | FnType::GoalSeek
+        | FnType::Deriv
+        | FnType::Integrate
+        | FnType::Root
+        | FnType::Map
+        | FnType::Filter
+        | FnType::Reduce
+        | FnType::Sort
+        | FnType::Reverse
+        | FnType::Unique
+        | FnType::CumSum
+        | FnType::Nth
+        | FnType::Sum
+        | FnType::Transpose
+        | FnType::Pi
+        | FnType::E
+        | FnType::Ceil
+        | FnType::Ln
+        | FnType::Lg
+        | FnType::Log
+        | FnType::Abs
+        | FnType::Sin
+        | FnType::Asin
+        | FnType::Cos
+        | FnType::Acos
+        | FnType::Tan
+        | FnType::Atan => panic!("only the random functions are evaluated here"),
+    };
+    result
+        .map(|it| CalcResult::new(it.typ, fn_token_index))
+        .ok_or_else(|| {
+            EvalErr::new(
+                "The arguments must be numbers or quantities of the same unit".to_owned(),
+                fn_token_index,
+            )
+        })
+}
+
+fn number(num: f64) -> Option<CalcResult> {
+    Decimal::from_f64(num).map(|it| CalcResult::new(CalcResultType::Number(it), 0))
+}
+
+fn int_arg(arg: &CalcResult) -> Result<i64, EvalErr> {
+    match &arg.typ {
+        CalcResultType::Number(num) if num.fract().is_zero() => num.to_i64(),
+        _ => None,
+    }
+    .ok_or_else(|| EvalErr::new2("The argument must be an integer".to_owned(), arg))
+}
+
+/// from + (to - from) * t
+fn scale(from: &CalcResult, to: &CalcResult, t: f64) -> Option<CalcResult> {
+    let t = number(t)?;
+    let len = sub_op(to, from)?;
+    add_op(from, &multiply_op(&len, &t)?)
+}
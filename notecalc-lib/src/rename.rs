@@ -0,0 +1,244 @@
+use crate::editor::editor::Pos;
+use crate::editor::editor_content::{EditorContent, Replacement};
+use crate::functions::FnType;
+use crate::helper::{content_y, AppTokens};
+use crate::token_parser::{OperatorTokenType, Token, TokenType};
+use crate::units::units::Units;
+use crate::{
+    is_name_of_var, local_var_name, FunctionDefinitions, LineData, Variables,
+    FIRST_FUNC_PARAM_VAR_INDEX, MAX_LINE_COUNT,
+};
+use std::ops::Range;
+
+/// A variable or a user function, identified by the line of its definition
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Symbol {
+    Variable(usize),
+    Function(usize),
+}
+
+/// The state of the rename bar, opened by F2 on a variable or a user function
+#[derive(Debug)]
+pub struct Rename {
+    pub symbol: Symbol,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+impl Rename {
+    pub fn new(symbol: Symbol, old_name: String) -> Rename {
+        Rename {
+            symbol,
+            old_name,
+            new_name: String::new(),
+        }
+    }
+
+    pub fn is_new_name_valid(
+        &self,
+        vars: &Variables,
+        func_defs: &FunctionDefinitions,
+        units: &Units,
+    ) -> bool {
+        self.new_name_error(vars, func_defs, units).is_none()
+    }
+
+    /// Variable names can contain spaces (e.g. `price per day`), function names can't.
+    /// The new name must not be taken by another variable or function, a unit or a builtin.
+    pub fn new_name_error(
+        &self,
+        vars: &Variables,
+        func_defs: &FunctionDefinitions,
+        units: &Units,
+    ) -> Option<&'static str> {
+        let name: Vec<char> = self.new_name.chars().collect();
+        let allowed_char = |ch: &char| {
+            ch.is_alphanumeric()
+                || *ch == '_'
+                || (*ch == ' ' && matches!(self.symbol, Symbol::Variable(_)))
+        };
+        let is_well_formed = name
+            .first()
+            .map(|it| it.is_alphabetic() || *it == '_')
+            .unwrap_or(false)
+            && name.iter().all(allowed_char)
+            && name.last() != Some(&' ');
+        if !is_well_formed {
+            return Some("Invalid name");
+        }
+        if self.new_name == self.old_name {
+            return None;
+        }
+        let is_taken_by_var = vars[0..MAX_LINE_COUNT].iter().flatten().any(|var| {
+            is_name_of_var(&name, &var.name)
+                || var
+                    .unknowns
+                    .as_ref()
+                    .map(|unknowns| unknowns.iter().any(|it| it[..] == name[..]))
+                    .unwrap_or(false)
+        });
+        let is_taken_by_fn = func_defs
+            .iter()
+            .flatten()
+            .any(|fd| fd.func_name == &name[..]);
+        if is_taken_by_var || is_taken_by_fn {
+            Some("The name is already used")
+        } else if units.parse(&name).1 == name.len() {
+            Some("The name of a unit")
+        } else if FnType::value_of(&name).is_some()
+            || ["sum", "section_total", "prev", "ans"].contains(&self.new_name.as_str())
+        {
+            Some("The name of a builtin")
+        } else {
+            None
+        }
+    }
+}
+
+/// The symbol under the cursor and its name
+pub fn symbol_at(
+    pos: Pos,
+    apptokens: &AppTokens,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions,
+) -> Option<(Symbol, String)> {
+    let on_func_name = func_defs[pos.row]
+        .as_ref()
+        .map(|fd| pos.column <= fd.func_name.len())
+        .unwrap_or(false);
+    if on_func_name {
+        let name = func_defs[pos.row].as_ref()?.func_name.iter().collect();
+        return Some((Symbol::Function(pos.row), name));
+    }
+    let tokens = &apptokens[content_y(pos.row)].as_ref()?.tokens;
+    let name_range = lhs_name_range(tokens);
+    let symbol = if func_defs[pos.row].is_none()
+        && name_range
+            .as_ref()
+            .map(|it| it.contains(&pos.column) || it.end == pos.column)
+            .unwrap_or(false)
+    {
+        Some(Symbol::Variable(pos.row))
+    } else {
+        None
+    };
+    let symbol = symbol.or_else(|| {
+        token_ranges(tokens)
+            .filter(|(range, _)| range.start <= pos.column && pos.column <= range.end)
+            .find_map(|(_, token)| symbol_of_token(token))
+    })?;
+    let name: String = match symbol {
+        Symbol::Variable(row) => local_var_name(&vars[row].as_ref()?.name).iter().collect(),
+        Symbol::Function(row) => func_defs[row].as_ref()?.func_name.iter().collect(),
+    };
+    Some((symbol, name))
+}
+
+/// Renames the definition and the usages which are resolved to the symbol,
+/// the same text in comments or in other scopes is left intact
+pub fn rename_replacements(
+    symbol: Symbol,
+    new_name: &str,
+    editor_content: &EditorContent<LineData>,
+    apptokens: &AppTokens,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions,
+) -> Vec<Replacement> {
+    let old_name: Vec<char> = match symbol {
+        Symbol::Variable(row) => match &vars[row] {
+            Some(var) => local_var_name(&var.name).to_vec(),
+            None => return Vec::new(),
+        },
+        Symbol::Function(row) => match &func_defs[row] {
+            Some(fd) => fd.func_name.to_vec(),
+            None => return Vec::new(),
+        },
+    };
+    let mut replacements = Vec::new();
+    let mut add = |row: usize, range: Range<usize>| {
+        // with header scopes the usage can be 'Header.name', only the name is renamed
+        let line = editor_content.get_line_valid_chars(row);
+        if range.end <= line.len() && line[range.clone()].ends_with(&old_name) {
+            replacements.push(Replacement {
+                pos: Pos::from_row_column(row, range.end - old_name.len()),
+                removed_text: old_name.iter().collect(),
+                inserted_text: new_name.to_owned(),
+            });
+        }
+    };
+    for row in 0..editor_content.line_count() {
+        let tokens = match &apptokens[content_y(row)] {
+            Some(tokens) => &tokens.tokens,
+            None => continue,
+        };
+        if symbol == Symbol::Function(row) {
+            // the name of the definition, it can be indented
+            if let Some((range, _)) = token_ranges(tokens)
+                .find(|(_, it)| matches!(it.typ, TokenType::Operator(OperatorTokenType::Fn { .. })))
+            {
+                add(row, range);
+            }
+            continue;
+        }
+        let name_range = lhs_name_range(tokens);
+        if let (Symbol::Variable(def_row), Some(range), Some(var)) =
+            (symbol, &name_range, &vars[row])
+        {
+            let line = editor_content.get_line_valid_chars(row);
+            if def_row == row && is_name_of_var(&line[range.clone()], &var.name) {
+                add(row, range.clone());
+            }
+        }
+        let lhs_end = name_range.map(|it| it.end).unwrap_or(0);
+        for (range, token) in token_ranges(tokens) {
+            // the left side of an assignment is a definition, not a usage
+            if range.start >= lhs_end && symbol_of_token(token) == Some(symbol) {
+                add(row, range);
+            }
+        }
+    }
+    replacements
+}
+
+pub fn symbol_of_token(token: &Token) -> Option<Symbol> {
+    match token.typ {
+        TokenType::Variable { var_index } if var_index < FIRST_FUNC_PARAM_VAR_INDEX => {
+            Some(Symbol::Variable(var_index))
+        }
+        TokenType::Operator(OperatorTokenType::Fn {
+            typ: FnType::UserDefined(i),
+            ..
+        })
+        | TokenType::Operator(OperatorTokenType::FnRef(i)) => Some(Symbol::Function(i)),
+        _ => None,
+    }
+}
+
+/// The range of the assigned name without the surrounding whitespaces, e.g. `price` in `price = 12`
+pub fn lhs_name_range(tokens: &[Token]) -> Option<Range<usize>> {
+    let mut name_range: Option<Range<usize>> = None;
+    for (range, token) in token_ranges(tokens) {
+        match token.typ {
+            TokenType::Operator(OperatorTokenType::Assign) => return name_range,
+            _ if token.ptr.iter().all(|it| it.is_whitespace()) => {}
+            _ => {
+                name_range = Some(match name_range {
+                    Some(name_range) => name_range.start..range.end,
+                    None => range,
+                })
+            }
+        }
+    }
+    None
+}
+
+/// The char ranges of the tokens in the line
+pub fn token_ranges<'a, 'b>(
+    tokens: &'a [Token<'b>],
+) -> impl Iterator<Item = (Range<usize>, &'a Token<'b>)> {
+    tokens.iter().scan(0, |start, token| {
+        let range = *start..*start + token.ptr.len();
+        *start = range.end;
+        Some((range, token))
+    })
+}
@@ -1,4 +1,5 @@
 use crate::calc::{CalcResult, CalcResultType};
+use crate::units::consts::UnitType;
 use crate::units::units::{UnitOutput, Units};
 use crate::{ResultFormat, ResultLengths};
 use byteorder::WriteBytesExt;
@@ -7,6 +8,8 @@ use rust_decimal::Decimal;
 use std::io::Cursor;
 use tinyvec::ArrayVec;
 
+const MAX_DMS_SECONDS_DECIMAL_COUNT: usize = 2;
+
 pub fn render_result(
     units: &Units,
     result: &CalcResult,
@@ -39,7 +42,12 @@ pub fn render_result_into(
 ) -> ResultLengths {
     match &result.typ {
         CalcResultType::Quantity(num, unit) => {
-            if *format != ResultFormat::Dec {
+            if *format == ResultFormat::Dms && unit.is(UnitType::Angle) {
+                if let Some(degrees) = UnitOutput::convert(unit, &UnitOutput::new_deg(units), num) {
+                    return dms_to_string(f, &degrees, decimal_count);
+                }
+            }
+            if matches!(format, ResultFormat::Bin | ResultFormat::Hex) {
                 f.write_u8(b'E').expect("");
                 f.write_u8(b'r').expect("");
                 f.write_u8(b'r').expect("");
@@ -49,27 +57,8 @@ pub fn render_result_into(
                     unit_part_len: 0,
                 };
             }
-            let final_unit_and_coeff = if there_was_unit_conversion {
-                None
-            } else {
-                if let Some(new_unit) = unit.simplify(units) {
-                    if let Some(coeff) = new_unit.get_unit_coeff() {
-                        if let Some(orig_coeff) = unit.get_unit_coeff() {
-                            if let Some(rust_is_a_joke) = orig_coeff.checked_div(&coeff) {
-                                Some((new_unit, rust_is_a_joke))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            };
+            let final_unit_and_coeff =
+                simplified_unit_and_coeff(units, unit, there_was_unit_conversion);
             fn rust_is_a_joke_lang(
                 num: &Decimal,
                 unit: &UnitOutput,
@@ -122,6 +111,30 @@ pub fn render_result_into(
                 rust_is_a_joke_lang(num, unit, f, decimal_count, use_grouping)
             };
         }
+        CalcResultType::Uncertain(..) | CalcResultType::Interval(..)
+            if matches!(format, ResultFormat::Bin | ResultFormat::Hex) =>
+        {
+            f.write_u8(b'E').expect("");
+            f.write_u8(b'r').expect("");
+            f.write_u8(b'r').expect("");
+            ResultLengths {
+                int_part_len: 3,
+                frac_part_len: 0,
+                unit_part_len: 0,
+            }
+        }
+        CalcResultType::Uncertain(num, err, unit) => {
+            let (num, err, unit) =
+                simplify_num_pair(units, num, err, unit, there_was_unit_conversion);
+            // '+-' is used instead of '±' since the result panel can render only ascii
+            num_pair_to_string(f, &num, b"+-", &err, &unit, decimal_count, use_grouping)
+        }
+        CalcResultType::Interval(lo, hi, unit) => {
+            let (lo, hi, unit) = simplify_num_pair(units, lo, hi, unit, there_was_unit_conversion);
+            // '..' as in the input, a '-' could be confused with a subtraction, e.g. '-5 - -3',
+            // and an en-dash can't be written into the result buffer which holds only ascii
+            num_pair_to_string(f, &lo, b"..", &hi, &unit, decimal_count, use_grouping)
+        }
         CalcResultType::Unit(unit) => {
             // TODO:mem to_string -> into(buf)
             // implement a into(std::io:Write) method for UnitOutput
@@ -141,7 +154,7 @@ pub fn render_result_into(
             num_to_string(f, num, format, decimal_count, use_grouping)
         }
         CalcResultType::Percentage(num) => {
-            if *format != ResultFormat::Dec {
+            if matches!(format, ResultFormat::Bin | ResultFormat::Hex) {
                 f.write_u8(b'E').expect("");
                 f.write_u8(b'r').expect("");
                 f.write_u8(b'r').expect("");
@@ -182,7 +195,85 @@ pub fn render_result_into(
                 unit_part_len: 0,
             }
         }
+        CalcResultType::Function(..) => {
+            // it can be only an argument of deriv, integrate etc., never the result of a line
+            ResultLengths {
+                int_part_len: 0,
+                frac_part_len: 0,
+                unit_part_len: 0,
+            }
+        }
+    }
+}
+
+fn simplify_num_pair(
+    units: &Units,
+    first: &Decimal,
+    second: &Decimal,
+    unit: &UnitOutput,
+    there_was_unit_conversion: bool,
+) -> (Decimal, Decimal, UnitOutput) {
+    let simplified = if unit.unit_count == 0 {
+        None
+    } else {
+        simplified_unit_and_coeff(units, unit, there_was_unit_conversion).and_then(
+            |(final_unit, coeff)| {
+                Some((
+                    first.checked_mul(&coeff)?,
+                    second.checked_mul(&coeff)?,
+                    final_unit,
+                ))
+            },
+        )
+    };
+    simplified.unwrap_or_else(|| (*first, *second, unit.clone()))
+}
+
+/// Renders values like `12.3 +- 0.4 m^2` or `6 .. 10 day`. Only the first number goes
+/// into the int and frac parts, the rest is part of the unit, so the first numbers stay aligned.
+fn num_pair_to_string(
+    f: &mut impl std::io::Write,
+    first: &Decimal,
+    separator: &[u8],
+    second: &Decimal,
+    unit: &UnitOutput,
+    decimal_count: Option<usize>,
+    use_grouping: bool,
+) -> ResultLengths {
+    let mut lens = num_to_string(f, first, &ResultFormat::Dec, decimal_count, use_grouping);
+    f.write_u8(b' ').expect("");
+    for ch in separator {
+        f.write_u8(*ch).expect("");
+    }
+    f.write_u8(b' ').expect("");
+    let second_lens = num_to_string(f, second, &ResultFormat::Dec, decimal_count, use_grouping);
+    // the separator is surrounded by whitespaces
+    lens.unit_part_len +=
+        separator.len() + 2 + second_lens.int_part_len + second_lens.frac_part_len;
+    if unit.unit_count > 0 {
+        f.write_u8(b' ').expect("");
+        lens.unit_part_len += 1;
+        for ch in unit.to_string().as_bytes() {
+            f.write_u8(*ch).expect("");
+            lens.unit_part_len += 1;
+        }
     }
+    lens
+}
+
+fn simplified_unit_and_coeff(
+    units: &Units,
+    unit: &UnitOutput,
+    there_was_unit_conversion: bool,
+) -> Option<(UnitOutput, Decimal)> {
+    if there_was_unit_conversion {
+        return None;
+    }
+    let new_unit = unit.simplify(units)?;
+    let coeff = new_unit.get_unit_coeff()?;
+    let orig_coeff = unit.get_unit_coeff()?;
+    let rust_is_a_joke = orig_coeff.checked_div(&coeff)?;
+    Some((new_unit, rust_is_a_joke))
 }
 
 fn num_to_string(
@@ -295,6 +386,50 @@ fn num_to_string(
     };
 }
 
+// The result panel can render only ascii characters, so the letter form is used
+// (47d 29m 53s) instead of 47°29'53"
+fn dms_to_string(
+    f: &mut impl std::io::Write,
+    degrees: &Decimal,
+    decimal_count: Option<usize>,
+) -> ResultLengths {
+    // rounding is done on the seconds so e.g. 59.9999 won't be rendered as 60s
+    let seconds_decimal_count = decimal_count
+        .unwrap_or(0)
+        .min(MAX_DMS_SECONDS_DECIMAL_COUNT);
+    let total_seconds = degrees
+        .abs()
+        .checked_mul(&Decimal::from(3600))
+        .map(|it| it.round_dp(seconds_decimal_count as u32));
+    let string = if let Some(total_seconds) = total_seconds {
+        let d = (total_seconds / Decimal::from(3600)).trunc();
+        let rem = total_seconds - d * Decimal::from(3600);
+        let m = (rem / Decimal::from(60)).trunc();
+        let s = rem - m * Decimal::from(60);
+        format!(
+            "{}{}d {}m {}s",
+            if degrees.is_sign_negative() && !total_seconds.is_zero() {
+                "-"
+            } else {
+                ""
+            },
+            d,
+            m,
+            s.normalize()
+        )
+    } else {
+        "Err".to_owned()
+    };
+    for ch in string.as_bytes() {
+        f.write_u8(*ch).expect("");
+    }
+    ResultLengths {
+        int_part_len: string.len(),
+        frac_part_len: 0,
+        unit_part_len: 0,
+    }
+}
+
 fn remove_repeatings(num: &Decimal) -> Option<Decimal> {
     let string = num.to_string();
     if let Some(pos) = string.bytes().position(|it| it == b'.') {
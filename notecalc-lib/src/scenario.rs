@@ -0,0 +1,411 @@
+use crate::calc::{
+    evaluate_tokens, evaluate_with_tokens_at, line_depends_on_fn_param,
+    process_variable_assignment_or_line_ref, CalcResult, CalcResultType, EvalErr, EvaluationResult,
+    ShuntingYardResult,
+};
+use crate::editor::editor_content::EditorContent;
+use crate::helper::{content_y, AppTokens, BitFlag256, Results};
+use crate::matrix::MatrixData;
+use crate::random::RandomSeed;
+use crate::shunting_yard::ShuntingYard;
+use crate::spreadsheet::SheetBlock;
+use crate::token_parser::{Token, TokenType};
+use crate::units::units::Units;
+use crate::{
+    FunctionDefinitions, LineData, LineResult, Tokens, Variable, Variables, MAX_LINE_COUNT,
+};
+use std::ops::Range;
+
+/// `rate = $90 | $100 | $120 @ low, mid, high` gives alternative values to a line,
+/// only the alternative of the active scenario is used.
+pub struct ScenarioLine {
+    // the char ranges of the alternative expressions in the line
+    pub alternatives: Vec<Range<usize>>,
+    pub names: Vec<String>,
+}
+
+impl ScenarioLine {
+    pub fn of_line(line: &[char]) -> Option<Result<ScenarioLine, EvalErr>> {
+        let at_index = line.iter().rposition(|ch| *ch == '@')?;
+        let names: Vec<String> = line[at_index + 1..]
+            .split(|ch| *ch == ',')
+            .map(|name| name.iter().collect::<String>().trim().to_owned())
+            .collect();
+        if !names.iter().all(|name| is_identifier(name)) {
+            return None;
+        }
+        let expr_start = line[0..at_index]
+            .iter()
+            .position(|ch| *ch == '=')
+            .map(|it| it + 1)
+            .unwrap_or(0);
+        let mut alternatives = Vec::with_capacity(names.len());
+        let mut alternative_start = expr_start;
+        // '|' also separates the columns of tables, e.g. [rate | days; 90 | 20]
+        let mut depth = 0;
+        for (i, ch) in line.iter().enumerate().take(at_index).skip(expr_start) {
+            match ch {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                '|' if depth == 0 => {
+                    alternatives.push(trimmed_range(line, alternative_start, i));
+                    alternative_start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        alternatives.push(trimmed_range(line, alternative_start, at_index));
+        if alternatives.len() < 2 {
+            return None;
+        }
+        Some(if alternatives.len() != names.len() {
+            Err(EvalErr::new(
+                format!(
+                    "There are {} alternatives but {} scenario names",
+                    alternatives.len(),
+                    names.len()
+                ),
+                0,
+            ))
+        } else if alternatives.iter().any(|it| it.is_empty()) {
+            Err(EvalErr::new("An alternative is empty".to_owned(), 0))
+        } else if names
+            .iter()
+            .enumerate()
+            .any(|(i, name)| names[0..i].contains(name))
+        {
+            Err(EvalErr::new(
+                "The scenario names must be different".to_owned(),
+                0,
+            ))
+        } else {
+            Ok(ScenarioLine {
+                alternatives,
+                names,
+            })
+        })
+    }
+
+    /// A line which does not know the scenario keeps its first alternative
+    pub fn alternative_of(&self, scenario: &str) -> usize {
+        self.names.iter().position(|it| it == scenario).unwrap_or(0)
+    }
+
+    /// The shunting yard output of the assignment part of the line and the given alternative,
+    /// the other alternatives are left out. The token indices of the output point into
+    /// the tokens of the whole line.
+    pub fn shunting_yard<'a>(
+        &self,
+        tokens: &mut [Token<'a>],
+        alternative: usize,
+        units: &Units,
+        func_defs: &FunctionDefinitions<'a>,
+    ) -> Vec<ShuntingYardResult> {
+        let expr_start = self.alternatives[0].start;
+        let alternative = &self.alternatives[alternative];
+        let mut selected_indices = Vec::with_capacity(tokens.len());
+        let mut token_start = 0;
+        for (i, token) in tokens.iter().enumerate() {
+            if token_start < expr_start || alternative.contains(&token_start) {
+                selected_indices.push(i);
+            }
+            token_start += token.ptr.len();
+        }
+        let mut selected_tokens: Vec<Token<'a>> = selected_indices
+            .iter()
+            .map(|i| tokens[*i].clone())
+            .collect();
+        let mut output_stack = Vec::with_capacity(selected_tokens.len());
+        ShuntingYard::shunting_yard(&mut selected_tokens, &mut output_stack, units, func_defs);
+        // keep the token types set by the shunting yard for rendering
+        for (i, token) in selected_indices.iter().zip(selected_tokens) {
+            tokens[*i] = token;
+        }
+        for output in &mut output_stack {
+            if let Some(i) = selected_indices.get(output.index_into_tokens) {
+                output.index_into_tokens = *i;
+            }
+        }
+        output_stack
+    }
+}
+
+/// Which scenario gives the results, it is switched by Alt+S
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ScenarioView {
+    Active(usize),
+    // the results of every scenario are shown side by side
+    All,
+}
+
+impl ScenarioView {
+    /// low -> mid -> high -> all of them -> low
+    pub fn next(&self, scenario_count: usize) -> ScenarioView {
+        match self {
+            ScenarioView::Active(i) if i + 1 < scenario_count => ScenarioView::Active(i + 1),
+            ScenarioView::Active(_) => ScenarioView::All,
+            ScenarioView::All => ScenarioView::Active(0),
+        }
+    }
+
+    /// The scenario whose values are assigned to the variables, it is the first one
+    /// when all of them are shown
+    pub fn active_name<'a>(&self, names: &'a [String]) -> Option<&'a str> {
+        let index = match self {
+            ScenarioView::Active(i) => *i,
+            ScenarioView::All => 0,
+        };
+        names
+            .get(index)
+            .or_else(|| names.first())
+            .map(|it| it.as_str())
+    }
+}
+
+/// The names of the scenarios of the document in the order of their first appearance
+pub fn scenario_names(editor_content: &EditorContent<LineData>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for y in 0..editor_content.line_count() {
+        if let Some(Ok(scenario_line)) =
+            ScenarioLine::of_line(editor_content.get_line_valid_chars(y))
+        {
+            for name in scenario_line.names {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+    names
+}
+
+/// The results and the variables of every scenario while all of them are shown side by side,
+/// so after a change only the affected lines have to be evaluated again for every scenario
+pub struct ScenarioComparison {
+    // the variables of the scenarios after the first one, the first one uses the app's variables
+    vars: Vec<Vec<Option<Variable>>>,
+    // the results of the lines in every scenario
+    results: Vec<Vec<LineResult>>,
+}
+
+impl ScenarioComparison {
+    pub fn new(scenario_count: usize, vars: &Variables) -> ScenarioComparison {
+        ScenarioComparison {
+            vars: (1..scenario_count).map(|_| vars.to_vec()).collect(),
+            results: (0..scenario_count)
+                .map(|_| (0..MAX_LINE_COUNT).map(|_| Ok(None)).collect())
+                .collect(),
+        }
+    }
+}
+
+/// Evaluates the `evaluated_rows` with the other scenarios as well (they have just been
+/// evaluated with the first one), and also the lines using a variable whose value has changed
+/// in a scenario. The lines whose result depends on the scenario get a table of their results
+/// with a column for every scenario.
+/// Returns the lines whose result has been replaced.
+pub fn compare_scenarios<'b>(
+    comparison: &mut ScenarioComparison,
+    names: &[String],
+    evaluated_rows: BitFlag256,
+    first_y: usize,
+    last_y: usize,
+    editor_content: &EditorContent<LineData>,
+    apptokens: &mut AppTokens<'b>,
+    results: &mut Results,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions<'b>,
+    units: &Units,
+    random_seed: RandomSeed,
+) -> BitFlag256 {
+    let mut rows_to_update = BitFlag256::empty();
+    for y in first_y..=last_y {
+        if evaluated_rows.is_true(y) {
+            comparison.results[0][y] = results[content_y(y)].clone();
+            rows_to_update.merge(BitFlag256::single_row(y));
+        }
+    }
+    for (i, name) in names.iter().enumerate().skip(1) {
+        let local_vars = &mut comparison.vars[i - 1];
+        // e.g. sum and the parameters of the functions
+        local_vars[MAX_LINE_COUNT..].clone_from_slice(&vars[MAX_LINE_COUNT..]);
+        let mut changed_vars = BitFlag256::empty();
+        for y in 0..=last_y {
+            if !evaluated_rows.is_true(y) && !uses_any_of(&apptokens[content_y(y)], changed_vars) {
+                continue;
+            }
+            let prev_value = local_vars[y].as_ref().map(|it| it.value.clone());
+            // e.g. comments, empty lines or directives
+            let has_no_result = matches!(results[content_y(y)], Ok(None));
+            let is_sheet_line = SheetBlock::of_line(editor_content, y).is_some();
+            let result = if y < first_y
+                || has_no_result
+                || line_depends_on_fn_param(apptokens, y)
+                || is_sheet_line
+                || func_defs[y].is_some()
+            {
+                // the same in every scenario
+                local_vars[y] = vars[y].clone();
+                Ok(None)
+            } else {
+                let result = evaluate_with_scenario(
+                    name,
+                    y,
+                    editor_content,
+                    apptokens,
+                    local_vars,
+                    func_defs,
+                    units,
+                    random_seed,
+                );
+                process_variable_assignment_or_line_ref(&result, local_vars, y, editor_content);
+                result.map(|it| it.map(|it| it.result))
+            };
+            if local_vars[y].as_ref().map(|it| it.value.clone()) != prev_value {
+                changed_vars.merge(BitFlag256::single_row(y));
+            }
+            if y >= first_y {
+                comparison.results[i][y] = result;
+                rows_to_update.merge(BitFlag256::single_row(y));
+            }
+        }
+    }
+
+    let mut changed_rows = BitFlag256::empty();
+    'lines: for y in first_y..=last_y {
+        if !rows_to_update.is_true(y) {
+            continue;
+        }
+        changed_rows.merge(BitFlag256::single_row(y));
+        let first = match &comparison.results[0][y] {
+            Ok(Some(result)) if !matches!(result.typ, CalcResultType::Matrix(..)) => result.clone(),
+            first => {
+                results[content_y(y)] = first.clone();
+                continue;
+            }
+        };
+        let index_into_tokens = first.index_into_tokens;
+        let mut cells = Vec::with_capacity(names.len());
+        cells.push(first);
+        for (name, scenario_results) in names.iter().skip(1).zip(&comparison.results[1..]) {
+            match &scenario_results[y] {
+                Ok(Some(result)) if !matches!(result.typ, CalcResultType::Matrix(..)) => {
+                    cells.push(result.clone())
+                }
+                Err(err) => {
+                    results[content_y(y)] = Err(EvalErr::new(
+                        format!("Scenario '{}': {}", name, err.reason),
+                        0,
+                    ));
+                    continue 'lines;
+                }
+                _ => {
+                    results[content_y(y)] = comparison.results[0][y].clone();
+                    continue 'lines;
+                }
+            }
+        }
+        results[content_y(y)] = if cells.iter().all(|it| it.typ == cells[0].typ) {
+            comparison.results[0][y].clone()
+        } else {
+            let col_count = cells.len();
+            Ok(Some(CalcResult::new(
+                CalcResultType::Matrix(
+                    MatrixData::new(cells, 1, col_count).with_col_labels(names.to_vec()),
+                ),
+                index_into_tokens,
+            )))
+        };
+    }
+    changed_rows
+}
+
+// whether the line uses the variable of any of the given rows
+fn uses_any_of(tokens: &Option<Tokens>, rows: BitFlag256) -> bool {
+    tokens
+        .as_ref()
+        .map(|it| {
+            it.shunting_output_stack.iter().any(|it| match it.typ {
+                TokenType::Variable { var_index } | TokenType::LineReference { var_index } => {
+                    var_index < MAX_LINE_COUNT && rows.is_true(var_index)
+                }
+                _ => false,
+            })
+        })
+        .unwrap_or(false)
+}
+
+fn evaluate_with_scenario<'b>(
+    name: &str,
+    y: usize,
+    editor_content: &EditorContent<LineData>,
+    apptokens: &mut AppTokens<'b>,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions<'b>,
+    units: &Units,
+    random_seed: RandomSeed,
+) -> Result<Option<EvaluationResult>, EvalErr> {
+    let scenario_line = match ScenarioLine::of_line(editor_content.get_line_valid_chars(y)) {
+        Some(Ok(scenario_line)) => scenario_line,
+        _ => {
+            return evaluate_tokens(
+                y,
+                apptokens,
+                vars,
+                func_defs,
+                units,
+                editor_content,
+                0,
+                None,
+                random_seed,
+            )
+            .1
+        }
+    };
+    let alternative = scenario_line.alternative_of(name);
+    let mut alternative_tokens = apptokens[content_y(y)]
+        .as_ref()
+        .expect("lines with result have tokens")
+        .tokens
+        .clone();
+    let shunting_output_stack = scenario_line.shunting_yard(
+        &mut alternative_tokens,
+        alternative,
+        units,
+        &func_defs[0..y],
+    );
+    evaluate_with_tokens_at(
+        apptokens,
+        y,
+        &mut Tokens {
+            tokens: alternative_tokens,
+            shunting_output_stack,
+        },
+        vars,
+        func_defs,
+        units,
+        editor_content,
+        random_seed,
+    )
+}
+
+/// from..to without the whitespaces at its ends
+fn trimmed_range(line: &[char], from: usize, to: usize) -> Range<usize> {
+    let text = &line[from..to];
+    let start = text
+        .iter()
+        .position(|ch| !ch.is_whitespace())
+        .map(|it| from + it)
+        .unwrap_or(to);
+    let end = text
+        .iter()
+        .rposition(|ch| !ch.is_whitespace())
+        .map(|it| from + it + 1)
+        .unwrap_or(start);
+    start..end
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(char::is_alphabetic)
+        && name.chars().all(|ch| ch.is_alphanumeric() || ch == '_')
+}
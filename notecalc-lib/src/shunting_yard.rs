@@ -1,6 +1,7 @@
 use crate::calc::ShuntingYardResult;
 use crate::functions::FnType;
 use crate::helper::BitFlag256;
+use crate::random;
 use crate::token_parser::{
     debug_print, Assoc, OperatorTokenType, Token, TokenType, UnitTokenType, APPLY_UNIT_OP_PREC,
 };
@@ -21,6 +22,8 @@ struct MatrixStackEntry {
     pub matrix_row_count: usize,
     pub matrix_prev_row_len: Option<usize>,
     pub matrix_current_row_len: usize,
+    // the column count of tables, e.g. [rate | days; 90 | 20], zero for simple matrices
+    pub label_count: usize,
 }
 
 #[derive(Debug)]
@@ -30,12 +33,21 @@ struct FnStackEntry {
     fn_token_index: usize,
 }
 
+#[derive(Debug)]
+struct IndexStackEntry {
+    arg_count: usize,
+    full_range: [bool; 2],
+    bracket_token_index: usize,
+}
+
 #[derive(Debug)]
 enum ParenStackEntry {
     /// e.g. [1, 2]
     Matrix(MatrixStackEntry),
     /// e.g. sin(60)
     Fn(FnStackEntry),
+    /// e.g. m[2, 3]
+    Index(IndexStackEntry),
     /// e.g. (12 + 3)
     Simple,
 }
@@ -47,6 +59,7 @@ impl ParenStackEntry {
             matrix_row_count: 1,
             matrix_prev_row_len: None,
             matrix_current_row_len: 1,
+            label_count: 0,
         })
     }
 
@@ -57,6 +70,14 @@ impl ParenStackEntry {
             fn_token_index,
         })
     }
+
+    fn new_index(bracket_token_index: usize) -> ParenStackEntry {
+        ParenStackEntry::Index(IndexStackEntry {
+            arg_count: 1,
+            full_range: [false; 2],
+            bracket_token_index,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -155,6 +176,13 @@ impl ValidationState {
         }
     }
 
+    fn pop_as_index(&mut self) -> IndexStackEntry {
+        match self.parenthesis_stack.pop() {
+            Some(ParenStackEntry::Index(entry)) => entry,
+            _ => panic!(),
+        }
+    }
+
     fn pop_as_fn(&mut self) -> Option<FnStackEntry> {
         match self.parenthesis_stack.pop() {
             Some(ParenStackEntry::Fn(entry)) => Some(entry),
@@ -169,6 +197,7 @@ impl ValidationState {
                 matrix_row_count: _,
                 matrix_prev_row_len,
                 matrix_current_row_len,
+                label_count: _,
             })) => matrix_prev_row_len.map(|it| it != *matrix_current_row_len),
             _ => Some(true), // if there is no matrix at the top of stack, it is an error
         }
@@ -182,6 +211,7 @@ impl ValidationState {
                 matrix_row_count,
                 matrix_prev_row_len,
                 matrix_current_row_len,
+                label_count: _,
             })) => {
                 *matrix_prev_row_len = Some(*matrix_current_row_len);
                 *matrix_current_row_len = 1;
@@ -198,6 +228,7 @@ impl ValidationState {
                 matrix_row_count: _,
                 matrix_prev_row_len,
                 matrix_current_row_len,
+                label_count: _,
             })) => {
                 self.open_brackets == 0
                     || matrix_prev_row_len
@@ -211,6 +242,8 @@ impl ValidationState {
                 // red in case of e.g. missing/wrong parameter
                 false
             }
+            // an index can't be empty, e.g. m[, 1]
+            Some(ParenStackEntry::Index(..)) => self.expect_expression,
             Some(ParenStackEntry::Simple) => true,
             None => true, // if there is no matrix/fn at the top of stack, it is an error
         }
@@ -223,12 +256,16 @@ impl ValidationState {
                 matrix_row_count: _,
                 matrix_prev_row_len: _,
                 matrix_current_row_len,
+                label_count: _,
             })) => {
                 *matrix_current_row_len += 1;
             }
             Some(ParenStackEntry::Fn(FnStackEntry { fn_arg_count, .. })) => {
                 *fn_arg_count += 1;
             }
+            Some(ParenStackEntry::Index(IndexStackEntry { arg_count, .. })) => {
+                *arg_count += 1;
+            }
             Some(ParenStackEntry::Simple) | None => panic!(),
         }
     }
@@ -288,18 +325,42 @@ impl ShuntingYard {
                     debug_print(&format!("  ignore ({:?})", input_token.ptr));
                     return;
                 }
+                TokenType::StringLiteral
+                    if random::parse_dice(input_token.ptr)
+                        .map(|it| it.len == input_token.ptr.len())
+                        .unwrap_or(false) =>
+                {
+                    debug_print("  dice");
+                    // it is evaluated as a function without arguments
+                    let typ = TokenType::Operator(OperatorTokenType::Fn {
+                        arg_count: 0,
+                        typ: FnType::Dice,
+                    });
+                    tokens[input_index as usize].typ = typ.clone();
+                    ShuntingYard::handle_num_token(
+                        typ,
+                        &mut v,
+                        tokens,
+                        output_stack,
+                        &mut operator_stack,
+                        &mut input_index,
+                    );
+                }
                 TokenType::StringLiteral => {
-                    // to allow func name reusing, search in reverse
-                    if let Some(fn_type) = FnType::value_of(input_token.ptr).or_else(|| {
-                        for (i, fd) in func_defs.iter().enumerate().rev() {
-                            if let Some(fd) = fd {
-                                if fd.func_name == input_token.ptr {
-                                    return Some(FnType::UserDefined(i));
-                                }
+                    // to allow func name reusing, search in reverse.
+                    // User functions precede the builtins, so a new builtin does not change
+                    // the documents which already define a function with its name
+                    let user_fn = func_defs
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .find_map(|(i, fd)| match fd {
+                            Some(fd) if fd.func_name == input_token.ptr => {
+                                Some(FnType::UserDefined(i))
                             }
-                        }
-                        None
-                    }) {
+                            _ => None,
+                        });
+                    if let Some(fn_type) = user_fn.or_else(|| FnType::value_of(input_token.ptr)) {
                         // next token is parenthesis
                         if tokens
                             .get(input_index as usize + 1)
@@ -325,9 +386,66 @@ impl ShuntingYard {
                             // skip the next paren
                             input_index += 1;
                             continue;
+                        } else if let FnType::UserDefined(fn_index) = fn_type {
+                            let is_fn_arg = match v.parenthesis_stack.last() {
+                                Some(ParenStackEntry::Fn(FnStackEntry { typ, .. })) => {
+                                    typ.accepts_fn_arg()
+                                }
+                                _ => false,
+                            };
+                            if is_fn_arg && v.expect_expression {
+                                debug_print(&format!("  function reference"));
+                                let typ = TokenType::Operator(OperatorTokenType::FnRef(fn_index));
+                                to_out(output_stack, &typ, input_index);
+                                tokens[input_index as usize].typ = typ;
+                                v.prev_token_type = ValidationTokenType::Expr;
+                                v.expect_expression = false;
+                                continue;
+                            }
                         }
                     }
 
+                    if input_token.ptr == &[':']
+                        && v.prev_token_type == ValidationTokenType::Nothing
+                    {
+                        // the whole row or column, e.g. m[:, 1]
+                        if let Some(ParenStackEntry::Index(entry)) = v.parenthesis_stack.last_mut()
+                        {
+                            if let Some(full_range) = entry.full_range.get_mut(entry.arg_count - 1)
+                            {
+                                debug_print("  full range index");
+                                *full_range = true;
+                                v.prev_token_type = ValidationTokenType::Expr;
+                                v.expect_expression = false;
+                                continue;
+                            }
+                        }
+                    }
+                    if input_token.ptr.len() > 1
+                        && input_token.ptr[0] == '.'
+                        && v.prev_token_type == ValidationTokenType::Expr
+                    {
+                        // a column of a table, e.g. offers.rate
+                        debug_print("  column");
+                        let typ = TokenType::Operator(OperatorTokenType::Column);
+                        to_out(output_stack, &typ, input_index);
+                        tokens[input_index as usize].typ = typ;
+                        if v.can_be_valid_closing_token() {
+                            ShuntingYard::send_everything_to_output(
+                                &mut operator_stack,
+                                output_stack,
+                                &mut v.last_valid_operator_index,
+                                &mut v.last_valid_output_range,
+                                &mut v.last_valid_input_token_range,
+                            );
+                            v.close_valid_range(
+                                output_stack.len(),
+                                input_index,
+                                operator_stack.len(),
+                            );
+                        }
+                        continue;
+                    }
                     if !input_token.ptr[0].is_ascii_whitespace() {
                         v.had_non_ws_string_literal = true;
                     }
@@ -429,7 +547,9 @@ impl ShuntingYard {
                     }
                     OperatorTokenType::ParenClose => {
                         let is_error = match v.parenthesis_stack.last() {
-                            None | Some(ParenStackEntry::Matrix(..)) => true,
+                            None
+                            | Some(ParenStackEntry::Matrix(..))
+                            | Some(ParenStackEntry::Index(..)) => true,
                             Some(ParenStackEntry::Simple) | Some(ParenStackEntry::Fn(..)) => false,
                         };
                         let prev_token_is_open_paren = input_index > 0
@@ -485,6 +605,28 @@ impl ShuntingYard {
                         }
                     }
                     OperatorTokenType::BracketOpen => {
+                        // '[' right after a variable, matrix, column or function call is indexing
+                        let prev_token_is_indexable = input_index > 0
+                            && matches!(
+                                tokens[(input_index - 1) as usize].typ,
+                                TokenType::Variable { .. }
+                                    | TokenType::LineReference { .. }
+                                    | TokenType::Operator(OperatorTokenType::BracketClose)
+                                    | TokenType::Operator(OperatorTokenType::ParenClose)
+                                    | TokenType::Operator(OperatorTokenType::Column)
+                            );
+                        if !v.expect_expression && prev_token_is_indexable {
+                            debug_print("    index");
+                            v.parenthesis_stack
+                                .push(ParenStackEntry::new_index(input_index as usize));
+                            v.prev_token_type = ValidationTokenType::Nothing;
+                            v.expect_expression = true;
+                            operator_stack.push(ShuntingYardOperatorResult {
+                                op_type: op.clone(),
+                                index_into_tokens: input_index,
+                            });
+                            continue;
+                        }
                         if v.open_brackets > 0 || !v.expect_expression {
                             ShuntingYard::rollback(
                                 &mut operator_stack,
@@ -543,6 +685,66 @@ impl ShuntingYard {
                             op_type: op.clone(),
                             index_into_tokens: input_index,
                         });
+                        if let Some((label_count, header_end_index)) =
+                            ShuntingYard::try_parse_table_header(tokens, input_index as usize + 1)
+                        {
+                            debug_print("    table header");
+                            if let Some(ParenStackEntry::Matrix(entry)) =
+                                v.parenthesis_stack.last_mut()
+                            {
+                                entry.label_count = label_count;
+                                // so every row must have exactly one cell for each label
+                                entry.matrix_prev_row_len = Some(label_count);
+                            }
+                            // continue after the ';' of the header
+                            input_index = header_end_index as isize;
+                        }
+                    }
+                    OperatorTokenType::BracketClose
+                        if matches!(
+                            v.parenthesis_stack.last(),
+                            Some(ParenStackEntry::Index(..))
+                        ) =>
+                    {
+                        if v.expect_expression {
+                            // missing index, e.g. m[] or m[1, ]
+                            ShuntingYard::rollback(
+                                &mut operator_stack,
+                                output_stack,
+                                input_index + 1,
+                                &mut v,
+                            );
+                            continue;
+                        }
+                        v.prev_token_type = ValidationTokenType::Expr;
+                        ShuntingYard::send_anything_until_opening_bracket(
+                            &mut operator_stack,
+                            output_stack,
+                            &OperatorTokenType::BracketOpen,
+                        );
+                        let index_entry = v.pop_as_index();
+                        to_out2(
+                            output_stack,
+                            TokenType::Operator(OperatorTokenType::Index {
+                                arg_count: index_entry.arg_count,
+                                full_range: index_entry.full_range,
+                            }),
+                            index_entry.bracket_token_index as isize,
+                        );
+                        if v.can_be_valid_closing_token() {
+                            ShuntingYard::send_everything_to_output(
+                                &mut operator_stack,
+                                output_stack,
+                                &mut v.last_valid_operator_index,
+                                &mut v.last_valid_output_range,
+                                &mut v.last_valid_input_token_range,
+                            );
+                            v.close_valid_range(
+                                output_stack.len(),
+                                input_index,
+                                operator_stack.len(),
+                            );
+                        }
                     }
                     OperatorTokenType::BracketClose => {
                         if v.open_brackets == 0 || v.is_matrix_row_len_err() {
@@ -572,7 +774,22 @@ impl ShuntingYard {
                         to_out(output_stack, &matrix_token_type, input_index);
 
                         debug_print("    Replace '[' with Matrix Token");
-                        tokens[mat_entry.matrix_start_input_pos].typ = matrix_token_type.clone();
+                        if mat_entry.label_count > 0 {
+                            to_out2(
+                                output_stack,
+                                TokenType::Operator(OperatorTokenType::ColumnLabels),
+                                mat_entry.matrix_start_input_pos as isize,
+                            );
+                            // in the editor, the header is rendered as the first row
+                            tokens[mat_entry.matrix_start_input_pos].typ =
+                                TokenType::Operator(OperatorTokenType::Matrix {
+                                    row_count: mat_entry.matrix_row_count + 1,
+                                    col_count: mat_entry.matrix_current_row_len,
+                                });
+                        } else {
+                            tokens[mat_entry.matrix_start_input_pos].typ =
+                                matrix_token_type.clone();
+                        }
                         if v.can_be_valid_closing_token() {
                             ShuntingYard::send_everything_to_output(
                                 &mut operator_stack,
@@ -733,6 +950,39 @@ impl ShuntingYard {
                             );
                         }
                     }
+                    OperatorTokenType::AsDms => {
+                        // like the unit converter, it must be the last operator and
+                        // it is applied on the whole expression
+                        if !v.expect_expression
+                            && v.can_be_valid_closing_token()
+                            && ShuntingYard::get_next_nonstring_token(
+                                tokens,
+                                input_index as usize + 1,
+                            )
+                            .is_none()
+                        {
+                            ShuntingYard::send_everything_to_output(
+                                &mut operator_stack,
+                                output_stack,
+                                &mut v.last_valid_operator_index,
+                                &mut v.last_valid_output_range,
+                                &mut v.last_valid_input_token_range,
+                            );
+                            to_out2(output_stack, TokenType::Operator(op.clone()), input_index);
+                            v.close_valid_range(
+                                output_stack.len(),
+                                input_index,
+                                operator_stack.len(),
+                            );
+                            v.prev_token_type = ValidationTokenType::Expr;
+                        } else {
+                            // demote it to String
+                            debug_print("  convert to String");
+                            tokens[input_index as usize].typ = TokenType::StringLiteral;
+                            // and reparse it
+                            input_index -= 1;
+                        }
+                    }
                     OperatorTokenType::UnitConverter => {
                         // the converter must be the last operator, only a unit can follow it
                         // so clear the operator stack, push the next unit onto the output
@@ -1191,6 +1441,45 @@ impl ShuntingYard {
         v.expect_expression = false;
     }
 
+    /// The first row of a table holds the labels of the columns, e.g. [rate | days; 90 | 20].
+    /// The labels are converted to StringLiterals, and the label count and the index of the ';'
+    /// which closes the header are returned.
+    fn try_parse_table_header<'text_ptr>(
+        tokens: &mut [Token<'text_ptr>],
+        start_index: usize,
+    ) -> Option<(usize, usize)> {
+        let mut label_count = 0;
+        let mut expect_label = true;
+        for i in start_index..tokens.len() {
+            let token = &tokens[i];
+            if token.ptr[0].is_ascii_whitespace() {
+                continue;
+            }
+            let is_label = matches!(
+                token.typ,
+                TokenType::StringLiteral | TokenType::Unit(..) | TokenType::Variable { .. }
+            ) && token.ptr[0].is_alphabetic();
+            let is_header_end =
+                matches!(token.typ, TokenType::Operator(OperatorTokenType::Semicolon));
+            if expect_label && is_label {
+                label_count += 1;
+                expect_label = false;
+            } else if !expect_label && token.ptr == &['|'] {
+                expect_label = true;
+            } else if !expect_label && is_header_end && label_count > 1 {
+                for token in tokens[start_index..i].iter_mut() {
+                    if !matches!(token.typ, TokenType::Operator(..)) {
+                        token.typ = TokenType::StringLiteral;
+                    }
+                }
+                return Some((label_count, i));
+            } else {
+                return None;
+            }
+        }
+        return None;
+    }
+
     fn set_tokens_to_string<'text_ptr>(tokens: &mut [Token<'text_ptr>], from: usize, to: usize) {
         for token in tokens[from..=to].iter_mut() {
             match token.typ {
@@ -1543,7 +1832,17 @@ pub mod tests {
     ) -> Vec<ShuntingYardResult> {
         let mut output = vec![];
         let func_def_tmp: [Option<FunctionDef>; MAX_LINE_COUNT] = [None; MAX_LINE_COUNT];
-        TokenParser::parse_line(&text, vars, tokens, &units, 10, allocator, 0, &func_def_tmp);
+        TokenParser::parse_line(
+            &text,
+            vars,
+            tokens,
+            &units,
+            10,
+            allocator,
+            0,
+            &func_def_tmp,
+            None,
+        );
         let fds = [None; MAX_LINE_COUNT];
         ShuntingYard::shunting_yard(tokens, &mut output, units, &fds);
         return output;
@@ -1557,6 +1856,7 @@ pub mod tests {
                     Some(Variable {
                         name: Box::from(*var_name),
                         value: Err(()),
+                        unknowns: None,
                     })
                 } else {
                     None
@@ -1601,10 +1901,12 @@ pub mod tests {
         vars[0] = Some(Variable {
             name: Box::from(&['b', '0'][..]),
             value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+            unknowns: None,
         });
         vars[1] = Some(Variable {
             name: Box::from(&['&', '[', '1', ']'][..]),
             value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+            unknowns: None,
         });
         let _ = do_shunting_yard_for_tests(&temp, &units, &mut tokens, &vars, &arena);
         compare_tokens(text, &expected_tokens, &tokens);
@@ -2574,6 +2876,43 @@ pub mod tests {
         )
     }
 
+    #[test]
+    fn test_shunting_index() {
+        test_output_vars(
+            &[&['b']],
+            "2 * b[1, :]",
+            &[
+                num(2),
+                var(""),
+                num(1),
+                op(OperatorTokenType::Index {
+                    arg_count: 2,
+                    full_range: [false, true],
+                }),
+                op(OperatorTokenType::Mult),
+            ],
+        );
+        test_output(
+            "[2, 3][-1]",
+            &[
+                op(OperatorTokenType::StartLock),
+                num(2),
+                num(3),
+                op(OperatorTokenType::Matrix {
+                    row_count: 1,
+                    col_count: 2,
+                }),
+                num(-1),
+                op(OperatorTokenType::Index {
+                    arg_count: 1,
+                    full_range: [false, false],
+                }),
+            ],
+        );
+        // the index can't be empty
+        test_output_vars(&[&['b']], "b[]", &[var("")]);
+    }
+
     #[test]
     fn test_empty_matrix() {
         test_tokens(
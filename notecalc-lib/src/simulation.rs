@@ -0,0 +1,185 @@
+use crate::calc::{
+    evaluate_tokens, line_depends_on_fn_param, process_variable_assignment_or_line_ref, CalcResult,
+    CalcResultType, EvalErr,
+};
+use crate::editor::editor_content::EditorContent;
+use crate::helper::{content_y, AppTokens, Results};
+use crate::matrix::MatrixData;
+use crate::random::{self, RandomSeed};
+use crate::units::units::{UnitOutput, Units};
+use crate::{FunctionDefinitions, LineData, Variables};
+use rust_decimal::prelude::*;
+
+pub const SIMULATE_KEYWORD: &str = "simulate";
+pub const MAX_SIMULATION_ITERATION_COUNT: usize = 10_000;
+
+/// The lines after a `simulate 1000` line until the next header are evaluated as many times
+/// as it is given, with different random numbers in every iteration.
+/// The result of these lines is the summary of their distribution, a [mean | p5 | p95] table.
+pub struct Simulation {
+    pub first_y: usize,
+    pub iteration_count: usize,
+    // true if any of the lines of the simulation was re-evaluated
+    pub has_changed: bool,
+}
+
+impl Simulation {
+    pub fn of_line(line: &[char]) -> Option<Result<Simulation, EvalErr>> {
+        let iteration_count = random::parse_directive(line, SIMULATE_KEYWORD)?;
+        Some(
+            if (1..=MAX_SIMULATION_ITERATION_COUNT as u64).contains(&iteration_count) {
+                Ok(Simulation {
+                    first_y: 0,
+                    iteration_count: iteration_count as usize,
+                    has_changed: false,
+                })
+            } else {
+                Err(EvalErr::new(
+                    format!(
+                        "The iteration count must be between 1 and {}",
+                        MAX_SIMULATION_ITERATION_COUNT
+                    ),
+                    0,
+                ))
+            },
+        )
+    }
+
+    /// 'simulate' as the first token, so the lines below can be recalculated when
+    /// the directive is modified or removed
+    pub fn is_directive_keyword(ptr: &[char]) -> bool {
+        ptr.iter().cloned().eq(SIMULATE_KEYWORD.chars())
+    }
+
+    pub fn run(
+        &self,
+        last_y: usize,
+        editor_content: &EditorContent<LineData>,
+        apptokens: &AppTokens,
+        results: &mut Results,
+        vars: &mut Variables,
+        func_defs: &FunctionDefinitions,
+        units: &Units,
+        document_seed: u64,
+    ) {
+        let line_count = (last_y + 1).saturating_sub(self.first_y);
+        let mut samples: Vec<Vec<CalcResult>> = (0..line_count)
+            .map(|_| Vec::with_capacity(self.iteration_count))
+            .collect();
+        let mut errors: Vec<Option<EvalErr>> = (0..line_count).map(|_| None).collect();
+        // the lines are evaluated in order in every iteration, so the variables of
+        // the simulated lines are always overwritten before they are used
+        let mut local_vars = vars.to_vec();
+        for iteration in 0..self.iteration_count {
+            let random_seed = RandomSeed::new(document_seed, iteration + 1);
+            for y in self.first_y..=last_y {
+                let has_no_result = matches!(results[content_y(y)], Ok(None));
+                if has_no_result || line_depends_on_fn_param(apptokens, y) || func_defs[y].is_some()
+                {
+                    continue;
+                }
+                let result = evaluate_tokens(
+                    y,
+                    apptokens,
+                    &local_vars,
+                    func_defs,
+                    units,
+                    editor_content,
+                    0,
+                    None,
+                    random_seed,
+                )
+                .1;
+                process_variable_assignment_or_line_ref(
+                    &result,
+                    &mut local_vars,
+                    y,
+                    editor_content,
+                );
+                match result {
+                    Ok(Some(result)) => samples[y - self.first_y].push(result.result),
+                    Ok(None) => {}
+                    Err(err) => {
+                        let error = &mut errors[y - self.first_y];
+                        if error.is_none() {
+                            *error = Some(err);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (i, (samples, error)) in samples.iter().zip(errors).enumerate() {
+            let y = self.first_y + i;
+            let summary = if let Some(err) = error {
+                Err(err)
+            } else if samples.is_empty() {
+                continue;
+            } else {
+                summarize(samples)
+            };
+            if let Some(var) = &mut vars[y] {
+                var.value = match &summary {
+                    Ok(summary) => Ok(summary.clone()),
+                    Err(_) => Err(()),
+                };
+            }
+            results[content_y(y)] = summary.map(Some);
+        }
+    }
+}
+
+/// The mean and the 5th and 95th percentiles of the samples
+fn summarize(samples: &[CalcResult]) -> Result<CalcResult, EvalErr> {
+    let unit: Option<&UnitOutput> = match &samples[0].typ {
+        CalcResultType::Quantity(_, unit) => Some(unit),
+        _ => None,
+    };
+    let mut nums = Vec::with_capacity(samples.len());
+    for sample in samples {
+        match (&sample.typ, unit) {
+            (CalcResultType::Number(num), None) => nums.push(*num),
+            (CalcResultType::Quantity(num, sample_unit), Some(unit)) if sample_unit == unit => {
+                nums.push(*num)
+            }
+            _ => {
+                return Err(EvalErr::new(
+                    "Only numbers and quantities of the same unit can be simulated".to_owned(),
+                    0,
+                ))
+            }
+        }
+    }
+    nums.sort();
+    let sum = nums
+        .iter()
+        .try_fold(Decimal::zero(), |sum, num| sum.checked_add(*num))
+        .ok_or_else(|| EvalErr::new("The sum of the samples is too large".to_owned(), 0))?;
+    let mean = sum / Decimal::from(nums.len());
+    let cell = |num: Decimal| {
+        let typ = match unit {
+            Some(unit) => CalcResultType::Quantity(num, unit.clone()),
+            None => CalcResultType::Number(num),
+        };
+        CalcResult::new(typ, 0)
+    };
+    let cells = vec![
+        cell(mean),
+        cell(percentile(&nums, 5)),
+        cell(percentile(&nums, 95)),
+    ];
+    Ok(CalcResult::new(
+        CalcResultType::Matrix(MatrixData::new(cells, 1, 3).with_col_labels(vec![
+            "mean".to_owned(),
+            "p5".to_owned(),
+            "p95".to_owned(),
+        ])),
+        0,
+    ))
+}
+
+/// nearest-rank method on sorted numbers
+fn percentile(sorted: &[Decimal], percent: usize) -> Decimal {
+    let rank = (sorted.len() * percent + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
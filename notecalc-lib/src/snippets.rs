@@ -0,0 +1,242 @@
+use crate::editor::editor::Pos;
+use crate::editor::editor_content::EditorContent;
+use crate::LineData;
+
+// e.g. "// snippet vat: * 27%"
+const DOCUMENT_SNIPPET_PREFIX: &[char] = &['/', '/', ' ', 's', 'n', 'i', 'p', 'p', 'e', 't', ' '];
+
+/// The expansion of '.name' when Tab is pressed after it, e.g. '.vat' -> '* 27%'.
+/// The body can contain line breaks ('\n' in the definition) and numbered placeholders,
+/// `$1` or `${1:default}`. Tab jumps to the next placeholder, `$0` is the final cursor position.
+/// A '$' sign which is followed by a digit can be escaped as '\$'.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Snippet {
+    pub name: String,
+    pub body: String,
+}
+
+/// A placeholder in an expanded snippet
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Placeholder {
+    pub start: Pos,
+    pub len: usize,
+}
+
+/// The text of an expanded snippet and its placeholders in Tab order.
+/// The positions are relative to the start of the text.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Expansion {
+    pub text: String,
+    pub placeholders: Vec<Placeholder>,
+}
+
+/// The placeholders of the last expanded snippet which are not visited yet
+#[derive(Debug)]
+pub struct SnippetSession {
+    // in Tab order, the first one is the current
+    placeholders: Vec<Placeholder>,
+    // the line count and the length of the current placeholder's line when it was selected,
+    // the edits inside the placeholder shift the following ones in the same line
+    line_count: usize,
+    line_len: usize,
+}
+
+impl SnippetSession {
+    pub fn new(
+        placeholders: Vec<Placeholder>,
+        content: &EditorContent<LineData>,
+    ) -> SnippetSession {
+        let line_len = placeholders
+            .first()
+            .map(|it| content.line_len(it.start.row))
+            .unwrap_or(0);
+        SnippetSession {
+            placeholders,
+            line_count: content.line_count(),
+            line_len,
+        }
+    }
+
+    /// The next placeholder, None if there is no more or if lines were added or removed
+    /// since the last one (the positions are not known anymore)
+    pub fn next(&mut self, content: &EditorContent<LineData>) -> Option<Placeholder> {
+        if self.placeholders.is_empty() || content.line_count() != self.line_count {
+            return None;
+        }
+        let current = self.placeholders.remove(0);
+        let new_line_len = content.line_len(current.start.row);
+        for p in &mut self.placeholders {
+            if p.start.row == current.start.row && p.start.column >= current.start.column {
+                p.start.column = (p.start.column + new_line_len).saturating_sub(self.line_len);
+            }
+        }
+        let next = *self.placeholders.first()?;
+        self.line_len = content.line_len(next.start.row);
+        Some(next)
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.placeholders.len() > 1
+    }
+}
+
+/// One 'name: body' definition per line, invalid lines are skipped
+pub fn parse_snippets(text: &str) -> Vec<Snippet> {
+    text.lines().filter_map(parse_snippet).collect()
+}
+
+/// The snippets defined in the document by '// snippet name: body' lines
+pub fn document_snippets(content: &EditorContent<LineData>) -> Vec<Snippet> {
+    content
+        .lines()
+        .filter(|line| line.starts_with(DOCUMENT_SNIPPET_PREFIX))
+        .filter_map(|line| {
+            let definition: String = line[DOCUMENT_SNIPPET_PREFIX.len()..].iter().collect();
+            parse_snippet(&definition)
+        })
+        .collect()
+}
+
+fn parse_snippet(line: &str) -> Option<Snippet> {
+    let colon = line.find(':')?;
+    let name = line[..colon].trim();
+    if name.is_empty() || !name.chars().all(|it| it.is_alphanumeric() || it == '_') {
+        return None;
+    }
+    Some(Snippet {
+        name: name.to_owned(),
+        body: line[colon + 1..].trim().replace("\\n", "\n"),
+    })
+}
+
+pub fn expand(body: &str) -> Expansion {
+    let chars: Vec<char> = body.chars().collect();
+    let mut text = String::with_capacity(body.len());
+    let mut numbered_placeholders: Vec<(usize, Placeholder)> = Vec::new();
+    let mut pos = Pos::from_row_column(0, 0);
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        if ch == '\\' && chars.get(i + 1) == Some(&'$') {
+            text.push('$');
+            pos = pos.add_column(1);
+            i += 2;
+            continue;
+        } else if ch == '$' {
+            if let Some((number, default, len)) = parse_placeholder(&chars[i..]) {
+                let default_len = default.chars().count();
+                numbered_placeholders.push((
+                    number,
+                    Placeholder {
+                        start: pos,
+                        len: default_len,
+                    },
+                ));
+                text.push_str(&default);
+                pos = pos.add_column(default_len);
+                i += len;
+                continue;
+            }
+        }
+        text.push(ch);
+        pos = if ch == '\n' {
+            Pos::from_row_column(pos.row + 1, 0)
+        } else {
+            pos.add_column(1)
+        };
+        i += 1;
+    }
+    // $1, $2... and $0 is the last one, only the first occurrence of a number is used
+    numbered_placeholders
+        .sort_by_key(|(number, _)| if *number == 0 { usize::MAX } else { *number });
+    numbered_placeholders.dedup_by_key(|(number, _)| *number);
+    Expansion {
+        text,
+        placeholders: numbered_placeholders
+            .into_iter()
+            .map(|(_, placeholder)| placeholder)
+            .collect(),
+    }
+}
+
+/// `$1` or `${1:default}`, returns the number, the default text and the length of the placeholder
+fn parse_placeholder(chars: &[char]) -> Option<(usize, String, usize)> {
+    if chars.get(1) == Some(&'{') {
+        let end = chars.iter().position(|it| *it == '}')?;
+        let inner: String = chars[2..end].iter().collect();
+        let (number, default) = match inner.find(':') {
+            Some(i) => (&inner[..i], &inner[i + 1..]),
+            None => (&inner[..], ""),
+        };
+        if default.contains('\n') {
+            return None;
+        }
+        Some((number.parse().ok()?, default.to_owned(), end + 1))
+    } else {
+        let digit_count = chars[1..]
+            .iter()
+            .take_while(|it| it.is_ascii_digit())
+            .count();
+        let number: String = chars[1..1 + digit_count].iter().collect();
+        Some((number.parse().ok()?, String::new(), 1 + digit_count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_placeholders() {
+        let expansion = expand("principal = ${1:100000}\nrate = ${2:5%}\n$0principal * $3rate");
+        assert_eq!(
+            expansion.text,
+            "principal = 100000\nrate = 5%\nprincipal * rate"
+        );
+        assert_eq!(
+            expansion.placeholders,
+            vec![
+                Placeholder {
+                    start: Pos::from_row_column(0, 12),
+                    len: 6
+                },
+                Placeholder {
+                    start: Pos::from_row_column(1, 7),
+                    len: 2
+                },
+                Placeholder {
+                    start: Pos::from_row_column(2, 12),
+                    len: 0
+                },
+                Placeholder {
+                    start: Pos::from_row_column(2, 0),
+                    len: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escaped_dollar_sign_and_currency() {
+        let expansion = expand("\\$1 + $ + 90$");
+        assert_eq!(expansion.text, "$1 + $ + 90$");
+        assert!(expansion.placeholders.is_empty());
+    }
+
+    #[test]
+    fn test_parse_snippets() {
+        assert_eq!(
+            parse_snippets("vat: * 27%\n\ninvalid name: 1\nloan: a = 1\\nb = 2"),
+            vec![
+                Snippet {
+                    name: "vat".to_owned(),
+                    body: "* 27%".to_owned()
+                },
+                Snippet {
+                    name: "loan".to_owned(),
+                    body: "a = 1\nb = 2".to_owned()
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,483 @@
+use crate::borrow_checker_fighter::create_vars;
+use crate::calc::{
+    add_op, divide_op, evaluate_with_tokens_at, CalcResult, CalcResultType, EvalErr,
+};
+use crate::editor::editor_content::EditorContent;
+use crate::functions::compare_results;
+use crate::helper::AppTokens;
+use crate::matrix::MatrixData;
+use crate::random::RandomSeed;
+use crate::shunting_yard::ShuntingYard;
+use crate::token_parser::{Token, TokenParser, TokenType};
+use crate::units::units::Units;
+use crate::{
+    header_scope_of_line, FunctionDefinitions, LineData, Tokens, Variable, Variables,
+    MAX_LINE_COUNT,
+};
+use bumpalo::Bump;
+use rust_decimal::prelude::*;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+// Spreadsheet blocks look like this:
+//
+// ```sheet
+// item  | qty | price | total = qty * price
+// apple | 3   | 2$    |
+// pear  | 2   | 3$    |
+//       | sum |       | sum
+// ```
+//
+// The first line is the header, formula columns are evaluated for every row
+// with the other columns of the row as variables, and the footer row holds aggregates.
+
+const FENCE: &[char] = &['`', '`', '`'];
+const SHEET_FENCE: &[char] = &['`', '`', '`', 's', 'h', 'e', 'e', 't'];
+
+pub fn is_fence(line: &[char]) -> bool {
+    line.starts_with(FENCE)
+}
+
+pub fn is_sheet_start(line: &[char]) -> bool {
+    line.starts_with(SHEET_FENCE)
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SheetBlock {
+    // the line of the opening "```sheet"
+    pub fence_y: usize,
+    // the last line before the closing "```" (or the last line of the note if it is not closed)
+    pub last_y: usize,
+}
+
+impl SheetBlock {
+    /// The block which contains the given line, the fences are not part of it
+    pub fn of_line(editor_content: &EditorContent<LineData>, y: usize) -> Option<SheetBlock> {
+        if is_fence(editor_content.get_line_valid_chars(y)) {
+            return None;
+        }
+        let fence_y = (0..y)
+            .rev()
+            .find(|i| is_fence(editor_content.get_line_valid_chars(*i)))?;
+        if is_sheet_start(editor_content.get_line_valid_chars(fence_y)) {
+            Some(SheetBlock::starting_at(editor_content, fence_y))
+        } else {
+            None
+        }
+    }
+
+    pub fn starting_at(editor_content: &EditorContent<LineData>, fence_y: usize) -> SheetBlock {
+        let line_count = editor_content.line_count().min(MAX_LINE_COUNT);
+        let last_y = (fence_y + 1..line_count)
+            .find(|i| is_fence(editor_content.get_line_valid_chars(*i)))
+            .map(|closing_fence_y| closing_fence_y - 1)
+            .unwrap_or(line_count - 1);
+        SheetBlock { fence_y, last_y }
+    }
+
+    pub fn header_y(&self) -> usize {
+        self.fence_y + 1
+    }
+
+    /// The widest cell (in chars) of every column, used for aligning the columns in the editor
+    pub fn column_widths(&self, editor_content: &EditorContent<LineData>) -> Vec<usize> {
+        let mut widths: Vec<usize> = Vec::new();
+        for y in self.header_y()..=self.last_y {
+            for (i, cell) in split_cells(editor_content.get_line_valid_chars(y)).enumerate() {
+                if i < widths.len() {
+                    widths[i] = widths[i].max(cell.len());
+                } else {
+                    widths.push(cell.len());
+                }
+            }
+        }
+        widths
+    }
+}
+
+struct Column<'a> {
+    name: &'a [char],
+    formula: Option<&'a [char]>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Aggregate {
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Count,
+}
+
+impl Aggregate {
+    fn from_cell(cell: &[char]) -> Option<Aggregate> {
+        match cell {
+            ['s', 'u', 'm'] => Some(Aggregate::Sum),
+            ['a', 'v', 'g'] => Some(Aggregate::Avg),
+            ['m', 'i', 'n'] => Some(Aggregate::Min),
+            ['m', 'a', 'x'] => Some(Aggregate::Max),
+            ['c', 'o', 'u', 'n', 't'] => Some(Aggregate::Count),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Sum => "sum",
+            Aggregate::Avg => "avg",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+            Aggregate::Count => "count",
+        }
+    }
+
+    fn apply(&self, values: &[CalcResult]) -> Option<CalcResult> {
+        let count = CalcResult::new(CalcResultType::Number(Decimal::from(values.len())), 0);
+        match self {
+            Aggregate::Count => Some(count),
+            Aggregate::Sum => sum_of(values),
+            Aggregate::Avg => divide_op(&sum_of(values)?, &count),
+            Aggregate::Min | Aggregate::Max => {
+                let wanted = if *self == Aggregate::Min {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+                let (first, rest) = values.split_first()?;
+                rest.iter().try_fold(first.clone(), |selected, value| {
+                    if compare_results(value, &selected)? == wanted {
+                        Some(value.clone())
+                    } else {
+                        Some(selected)
+                    }
+                })
+            }
+        }
+    }
+}
+
+fn sum_of(values: &[CalcResult]) -> Option<CalcResult> {
+    let (first, rest) = values.split_first()?;
+    rest.iter()
+        .try_fold(first.clone(), |sum, value| add_op(&sum, value))
+}
+
+fn split_cells(line: &[char]) -> impl Iterator<Item = &[char]> {
+    line.split(|ch| *ch == '|')
+}
+
+fn trim(text: &[char]) -> &[char] {
+    let from = text
+        .iter()
+        .position(|ch| !ch.is_whitespace())
+        .unwrap_or(text.len());
+    let to = text
+        .iter()
+        .rposition(|ch| !ch.is_whitespace())
+        .map(|it| it + 1)
+        .unwrap_or(from);
+    &text[from..to]
+}
+
+// e.g. `item | qty | total = qty * price`
+fn parse_header(line: &[char]) -> Vec<Column> {
+    split_cells(line)
+        .map(|cell| {
+            if let Some(eq_index) = cell.iter().position(|ch| *ch == '=') {
+                Column {
+                    name: trim(&cell[0..eq_index]),
+                    formula: Some(&cell[eq_index + 1..]),
+                }
+            } else {
+                Column {
+                    name: trim(cell),
+                    formula: None,
+                }
+            }
+        })
+        .collect()
+}
+
+// a footer row consists of aggregates and empty cells, e.g. `| sum | | avg`
+fn parse_footer(line: &[char]) -> Option<Vec<Option<Aggregate>>> {
+    let mut aggregates = Vec::new();
+    for cell in split_cells(line).map(trim) {
+        if cell.is_empty() {
+            aggregates.push(None);
+        } else {
+            aggregates.push(Some(Aggregate::from_cell(cell)?));
+        }
+    }
+    if aggregates.iter().any(|it| it.is_some()) {
+        Some(aggregates)
+    } else {
+        None
+    }
+}
+
+// the token ranges of the cells, '|' outside of brackets is tokenized as a StringLiteral
+fn cell_token_ranges(tokens: &[Token]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut cell_start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        if token.typ == TokenType::StringLiteral && token.ptr == &['|'][..] {
+            ranges.push(cell_start..i);
+            cell_start = i + 1;
+        }
+    }
+    ranges.push(cell_start..tokens.len());
+    ranges
+}
+
+// one value is returned as it is, more values as a single row matrix
+fn values_into_result(mut values: Vec<CalcResult>) -> Option<CalcResult> {
+    if values.len() < 2 {
+        values.pop()
+    } else {
+        let col_count = values.len();
+        Some(CalcResult::new(
+            CalcResultType::Matrix(MatrixData::new(values, 1, col_count)),
+            0,
+        ))
+    }
+}
+
+/// Everything which is needed to evaluate the lines of a spreadsheet block
+pub struct SheetEvaluator<'a, 'b> {
+    pub block: SheetBlock,
+    pub editor_content: &'a EditorContent<LineData>,
+    pub units: &'a Units,
+    pub allocator: &'b Bump,
+    pub vars: &'a Variables,
+    pub func_defs: &'a FunctionDefinitions<'b>,
+    pub random_seed: RandomSeed,
+}
+
+impl<'a, 'b> SheetEvaluator<'a, 'b> {
+    /// The result of a row is the value of its formula columns, the result of the footer
+    /// is its aggregates. `tokens` are the parsed tokens of the line.
+    pub fn eval_line(
+        &self,
+        editor_y: usize,
+        tokens: &mut [Token<'b>],
+        apptokens: &mut AppTokens<'b>,
+    ) -> Result<Option<CalcResult>, EvalErr> {
+        let line = self.editor_content.get_line_valid_chars(editor_y);
+        if editor_y == self.block.header_y() {
+            Ok(None)
+        } else if let Some(aggregates) = parse_footer(line) {
+            self.eval_footer(editor_y, &aggregates, apptokens)
+        } else {
+            let columns = parse_header(
+                self.editor_content
+                    .get_line_valid_chars(self.block.header_y()),
+            );
+            let values = self.eval_row(&columns, tokens, editor_y, editor_y, apptokens)?;
+            let formula_values = columns
+                .iter()
+                .zip(values.into_iter())
+                .filter(|(column, _)| column.formula.is_some())
+                .filter_map(|(_, value)| value)
+                .collect();
+            Ok(values_into_result(formula_values))
+        }
+    }
+
+    fn eval_footer(
+        &self,
+        footer_y: usize,
+        aggregates: &[Option<Aggregate>],
+        apptokens: &mut AppTokens<'b>,
+    ) -> Result<Option<CalcResult>, EvalErr> {
+        let columns = parse_header(
+            self.editor_content
+                .get_line_valid_chars(self.block.header_y()),
+        );
+        let mut column_values: Vec<Vec<CalcResult>> = vec![Vec::new(); columns.len()];
+        for row_y in self.block.header_y() + 1..footer_y {
+            let line = self.editor_content.get_line_valid_chars(row_y);
+            if parse_footer(line).is_some() {
+                continue;
+            }
+            let mut tokens = self.parse(line, row_y);
+            let values = self.eval_row(&columns, &mut tokens, row_y, footer_y, apptokens)?;
+            for (i, value) in values.into_iter().enumerate() {
+                if let Some(value) = value {
+                    column_values[i].push(value);
+                }
+            }
+        }
+
+        let mut results = Vec::with_capacity(aggregates.len());
+        for (i, aggregate) in aggregates.iter().enumerate() {
+            if let Some(aggregate) = aggregate {
+                let values = column_values.get(i).map(|it| it.as_slice()).unwrap_or(&[]);
+                let result = aggregate.apply(values).ok_or_else(|| {
+                    EvalErr::new(
+                        format!(
+                            "The {} of column '{}' can not be calculated",
+                            aggregate.name(),
+                            columns
+                                .get(i)
+                                .map(|it| it.name.iter().collect::<String>())
+                                .unwrap_or_default()
+                        ),
+                        0,
+                    )
+                })?;
+                results.push(result);
+            }
+        }
+        Ok(values_into_result(results))
+    }
+
+    /// Evaluates every cell of the row, the value of a cell is None if it is empty or it is
+    /// not a value (e.g. a text)
+    fn eval_row(
+        &self,
+        columns: &[Column],
+        tokens: &mut [Token<'b>],
+        row_y: usize,
+        slot_y: usize,
+        apptokens: &mut AppTokens<'b>,
+    ) -> Result<Vec<Option<CalcResult>>, EvalErr> {
+        // every cell has its own random numbers, even if they are evaluated in the same slot
+        let row_line_id = self.editor_content.get_data(row_y).line_id;
+        let cell_random_seed =
+            |column_index: usize| self.random_seed.derive(row_line_id, column_index);
+        let fence_y = self.block.fence_y;
+        if fence_y + columns.len() > MAX_LINE_COUNT {
+            return Err(EvalErr::new(
+                "The sheet has too many columns".to_string(),
+                0,
+            ));
+        }
+        let cell_ranges = cell_token_ranges(tokens);
+        let mut values: Vec<Option<CalcResult>> = Vec::with_capacity(columns.len());
+        for (i, column) in columns.iter().enumerate() {
+            let value = match (column.formula, cell_ranges.get(i)) {
+                (None, Some(range)) => {
+                    let mut cell_tokens = tokens[range.clone()].to_vec();
+                    let value = self
+                        .eval_tokens(
+                            &mut cell_tokens,
+                            slot_y,
+                            self.vars,
+                            apptokens,
+                            cell_random_seed(i),
+                        )
+                        .unwrap_or(None);
+                    tokens[range.clone()].clone_from_slice(&cell_tokens);
+                    value
+                }
+                _ => None,
+            };
+            values.push(value);
+        }
+
+        // The columns are visible for the formulas as variables, they are put into the slots
+        // of the lines after the opening fence so the variables above the sheet are still
+        // accessible. Formulas are evaluated from left to right, so a formula can use
+        // the result of the formulas on its left.
+        // TODO avoid copy
+        let mut local_vars = create_vars();
+        local_vars[0..fence_y].clone_from_slice(&self.vars[0..fence_y]);
+        for (i, column) in columns.iter().enumerate() {
+            local_vars[fence_y + i] = Some(Variable {
+                name: Box::from(column.name),
+                value: values[i].clone().ok_or(()),
+                unknowns: None,
+            });
+        }
+        for (i, column) in columns.iter().enumerate() {
+            if let Some(formula) = column.formula {
+                let column_name = || column.name.iter().collect::<String>();
+                let mut formula_tokens = Vec::with_capacity(32);
+                TokenParser::parse_line(
+                    formula,
+                    &local_vars,
+                    &mut formula_tokens,
+                    self.units,
+                    fence_y + columns.len(),
+                    self.allocator,
+                    0,
+                    self.func_defs,
+                    header_scope_of_line(self.editor_content, fence_y),
+                );
+                let value = self
+                    .eval_tokens(
+                        &mut formula_tokens,
+                        slot_y,
+                        &local_vars,
+                        apptokens,
+                        cell_random_seed(i),
+                    )
+                    .map_err(|err| {
+                        EvalErr::new(format!("Column '{}': {}", column_name(), err.reason), 0)
+                    })?
+                    .ok_or_else(|| {
+                        EvalErr::new(
+                            format!("The formula of column '{}' has no result", column_name()),
+                            0,
+                        )
+                    })?;
+                local_vars[fence_y + i] = Some(Variable {
+                    name: Box::from(column.name),
+                    value: Ok(value.clone()),
+                    unknowns: None,
+                });
+                values[i] = Some(value);
+            }
+        }
+        Ok(values)
+    }
+
+    fn parse(&self, line: &[char], editor_y: usize) -> Vec<Token<'b>> {
+        let mut tokens = Vec::with_capacity(32);
+        TokenParser::parse_line(
+            line,
+            self.vars,
+            &mut tokens,
+            self.units,
+            editor_y,
+            self.allocator,
+            0,
+            self.func_defs,
+            header_scope_of_line(self.editor_content, editor_y),
+        );
+        tokens
+    }
+
+    fn eval_tokens(
+        &self,
+        tokens: &mut Vec<Token<'b>>,
+        slot_y: usize,
+        vars: &Variables,
+        apptokens: &mut AppTokens<'b>,
+        random_seed: RandomSeed,
+    ) -> Result<Option<CalcResult>, EvalErr> {
+        let mut shunting_output_stack = Vec::with_capacity(16);
+        ShuntingYard::shunting_yard(
+            tokens,
+            &mut shunting_output_stack,
+            self.units,
+            &self.func_defs[0..self.block.fence_y],
+        );
+        let mut cell_tokens = Tokens {
+            tokens: std::mem::take(tokens),
+            shunting_output_stack,
+        };
+        let result = evaluate_with_tokens_at(
+            apptokens,
+            slot_y,
+            &mut cell_tokens,
+            vars,
+            self.func_defs,
+            self.units,
+            self.editor_content,
+            random_seed,
+        );
+        *tokens = cell_tokens.tokens;
+        result.map(|it| it.map(|it| it.result))
+    }
+}
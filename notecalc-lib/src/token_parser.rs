@@ -1,8 +1,9 @@
 use crate::functions::FnType;
+use crate::random;
 use crate::units::units::{UnitOutput, Units};
 use crate::{
     tracy_span, FunctionDefinitions, Variable, Variables, FIRST_FUNC_PARAM_VAR_INDEX,
-    SUM_VARIABLE_INDEX,
+    SECTION_TOTAL_VARIABLE_INDEX, SECTION_TOTAL_VAR_NAME, SUM_VARIABLE_INDEX,
 };
 use bumpalo::Bump;
 use rust_decimal::prelude::*;
@@ -79,6 +80,10 @@ pub enum OperatorTokenType {
     BinXor,
     BinNot,
     Pow,
+    // 5.00 m ± 0.02 m
+    PlusMinus,
+    // 3..5 days
+    Interval,
     ParenOpen,
     ParenClose,
     BracketOpen,
@@ -88,9 +93,19 @@ pub enum OperatorTokenType {
     ShiftRight,
     Assign,
     UnitConverter,
+    // 47.5 deg as dms
+    AsDms,
     StartLock,
     Matrix { row_count: usize, col_count: usize },
     Fn { arg_count: usize, typ: FnType },
+    // a user function passed as an argument, e.g. 'f' in deriv(f, 2)
+    FnRef(usize),
+    // m[2, 3], m[:, 1] or v[1..3], `full_range` is true for the ':' arguments
+    Index { arg_count: usize, full_range: [bool; 2] },
+    // the first row of [rate | days; 90 | 20], it follows the Matrix op
+    ColumnLabels,
+    // '.rate' in offers.rate
+    Column,
     PercentageIs,
     // 41 is 17% on what
     Percentage_Find_Base_From_Result_Increase_X,
@@ -138,18 +153,25 @@ impl OperatorTokenType {
             OperatorTokenType::BinXor => 0,
             OperatorTokenType::BinNot => 4,
             OperatorTokenType::Pow => APPLY_UNIT_OP_PREC + 1,
+            OperatorTokenType::PlusMinus => 4,
+            OperatorTokenType::Interval => 4,
             OperatorTokenType::ParenOpen => 0,
             OperatorTokenType::ParenClose => 0,
             OperatorTokenType::ShiftLeft => 0,
             OperatorTokenType::ShiftRight => 0,
             OperatorTokenType::Assign => 0,
             OperatorTokenType::UnitConverter => 0,
+            OperatorTokenType::AsDms => 0,
             OperatorTokenType::Semicolon | OperatorTokenType::Comma => 0,
             OperatorTokenType::BracketOpen => 0,
             OperatorTokenType::StartLock => 0,
             OperatorTokenType::BracketClose => 0,
             OperatorTokenType::Matrix { .. } => 0,
             OperatorTokenType::Fn { .. } => 0,
+            OperatorTokenType::FnRef(..) => 0,
+            OperatorTokenType::Index { .. } => 0,
+            OperatorTokenType::ColumnLabels => 0,
+            OperatorTokenType::Column => 0,
             OperatorTokenType::PercentageIs => 20,
             OperatorTokenType::Percentage_Find_Base_From_Result_Increase_X => 10,
             OperatorTokenType::Percentage_Find_Base_From_X_Icrease_Result => 10,
@@ -179,11 +201,14 @@ impl OperatorTokenType {
             OperatorTokenType::BinXor => Assoc::Left,
             OperatorTokenType::BinNot => Assoc::Left,
             OperatorTokenType::Pow => Assoc::Right,
+            OperatorTokenType::PlusMinus => Assoc::Left,
+            OperatorTokenType::Interval => Assoc::Left,
             OperatorTokenType::ParenOpen => Assoc::Left,
             OperatorTokenType::ShiftLeft => Assoc::Left,
             OperatorTokenType::ShiftRight => Assoc::Left,
             OperatorTokenType::Assign => Assoc::Left,
             OperatorTokenType::UnitConverter => Assoc::Left,
+            OperatorTokenType::AsDms => Assoc::Left,
             // Right, so 1 comma won't replace an other on the operator stack
             OperatorTokenType::Semicolon | OperatorTokenType::Comma => Assoc::Right,
             OperatorTokenType::BracketOpen => Assoc::Left,
@@ -191,6 +216,10 @@ impl OperatorTokenType {
             OperatorTokenType::Matrix { .. } => Assoc::Left,
             OperatorTokenType::StartLock => Assoc::Left,
             OperatorTokenType::Fn { .. } => Assoc::Left,
+            OperatorTokenType::FnRef(..) => Assoc::Left,
+            OperatorTokenType::Index { .. } => Assoc::Left,
+            OperatorTokenType::ColumnLabels => Assoc::Left,
+            OperatorTokenType::Column => Assoc::Left,
             OperatorTokenType::PercentageIs => Assoc::Left,
             OperatorTokenType::Percentage_Find_Base_From_Result_Increase_X => Assoc::Left,
             OperatorTokenType::Percentage_Find_Base_From_X_Icrease_Result => Assoc::Left,
@@ -224,6 +253,7 @@ impl TokenParser {
         allocator: &'text_ptr Bump,
         function_param_count: usize,
         func_defs: &FunctionDefinitions<'text_ptr>,
+        scope: Option<&[char]>,
     ) {
         tracy_span("parse_line", file!(), line!());
         let mut index = 0;
@@ -238,6 +268,29 @@ impl TokenParser {
             return;
         }
         while index < line.len() {
+            // it must precede the dms literals and the units, '3d' would be parsed as 3 days
+            if let Some(dice) = random::parse_dice(&line[index..]) {
+                dst.push(Token {
+                    ptr: allocator
+                        .alloc_slice_fill_iter(line[index..index + dice.len].iter().map(|it| *it)),
+                    typ: TokenType::StringLiteral,
+                    has_error: false,
+                });
+                index += dice.len;
+                can_be_unit = None;
+                can_be_unit_converter = false;
+                continue;
+            }
+            if let Some((num_token, unit_token)) =
+                TokenParser::try_extract_dms_literal(&line[index..], units, allocator)
+            {
+                index += num_token.ptr.len() + unit_token.ptr.len();
+                dst.push(num_token);
+                dst.push(unit_token);
+                can_be_unit = Some(UnitTokenType::StandInItself);
+                can_be_unit_converter = true;
+                continue;
+            }
             let parse_result = TokenParser::try_extract_token(
                 &line[index..],
                 variable_names,
@@ -249,6 +302,7 @@ impl TokenParser {
                 allocator,
                 function_param_count,
                 func_defs,
+                scope,
             );
 
             match &parse_result.typ {
@@ -312,9 +366,12 @@ impl TokenParser {
         allocator: &'text_ptr Bump,
         function_param_count: usize,
         func_defs: &FunctionDefinitions<'text_ptr>,
+        scope: Option<&[char]>,
     ) -> Token<'text_ptr> {
         return if let Some(token) = TokenParser::try_extract_comment(rest_str, allocator) {
             token
+        } else if let Some(token) = TokenParser::try_extract_column_name(rest_str, dst, allocator) {
+            token
         } else {
             let prev_was_lineref = dst
                 .last()
@@ -328,8 +385,12 @@ impl TokenParser {
                 prev_was_lineref,
                 function_param_count,
                 func_defs,
+                scope,
             ) {
                 token
+            } else if let Some(token) = TokenParser::try_extract_as_dms(rest_str, allocator) {
+                // it must precede the units, 'as' would be parsed as attosecond
+                token
             } else if let Some(token) = TokenParser::try_extract_unit(
                 rest_str,
                 units,
@@ -339,7 +400,7 @@ impl TokenParser {
             ) {
                 token
             } else if let Some(token) =
-                TokenParser::try_extract_operator(rest_str, allocator, can_be_unit_converter)
+                TokenParser::try_extract_operator(rest_str, dst, allocator, can_be_unit_converter)
             {
                 token
             } else if let Some(token) = TokenParser::try_extract_number_literal(rest_str, allocator)
@@ -466,7 +527,12 @@ impl TokenParser {
             let mut multiplier = None;
 
             while i < str.len() {
-                if str[i] == '.' && decimal_point_count < 1 && e_count < 1 {
+                // '..' is the interval operator, e.g. 3..5
+                if str[i] == '.'
+                    && decimal_point_count < 1
+                    && e_count < 1
+                    && str.get(i + 1) != Some(&'.')
+                {
                     decimal_point_count += 1;
                     end_index_before_last_whitespace = i + 1;
                     number_str[number_str_index] = str[i] as u8;
@@ -572,6 +638,135 @@ impl TokenParser {
         return result;
     }
 
+    /// Angles in degrees-minutes-seconds, e.g. `47°29'53"` or `47d 29m 53s`.
+    /// The literal is split into a number token holding the value in degrees
+    /// and a `deg` unit token for its last designator (`"` in the example above),
+    /// so the rest of the pipeline sees a simple quantity.
+    fn try_extract_dms_literal<'text_ptr>(
+        str: &[char],
+        units: &Units,
+        allocator: &'text_ptr Bump,
+    ) -> Option<(Token<'text_ptr>, Token<'text_ptr>)> {
+        fn parse_component(str: &[char], i: &mut usize) -> Option<(Decimal, bool)> {
+            let start = *i;
+            let mut has_fraction = false;
+            while *i < str.len() && (str[*i].is_ascii_digit() || (str[*i] == '.' && !has_fraction))
+            {
+                if str[*i] == '.' {
+                    has_fraction = true;
+                }
+                *i += 1;
+            }
+            if *i == start {
+                return None;
+            }
+            let num_str: String = str[start..*i].iter().collect();
+            Decimal::from_str(&num_str)
+                .ok()
+                .map(|it| (it, has_fraction))
+        }
+        fn is_designator(str: &[char], i: usize, designators: &[char], letter_form: bool) -> bool {
+            str.get(i)
+                .map(|it| designators.contains(it))
+                .unwrap_or(false)
+                && (!letter_form
+                    || str
+                        .get(i + 1)
+                        .map(|it| !it.is_alphabetic() && *it != '_')
+                        .unwrap_or(true))
+        }
+
+        if !str[0].is_ascii_digit() {
+            return None;
+        }
+        let mut i = 0;
+        let (degrees, mut has_fraction) = parse_component(str, &mut i)?;
+        let letter_form = if is_designator(str, i, &['°'], false) {
+            false
+        } else if is_designator(str, i, &['d'], true) {
+            true
+        } else {
+            return None;
+        };
+        let mut last_designator_index = i;
+        i += 1;
+
+        let designators: [&[char]; 2] = if letter_form {
+            [&['m'], &['s']]
+        } else {
+            [&['\'', '′'], &['"', '″']]
+        };
+        let sixty = Decimal::from(60);
+        let mut parts = [degrees, Decimal::zero(), Decimal::zero()];
+        let mut part_count = 1;
+        for designator in &designators {
+            // only the last component can have a fractional part
+            if has_fraction {
+                break;
+            }
+            let mut j = i;
+            while j < str.len() && str[j] == ' ' {
+                j += 1;
+            }
+            match parse_component(str, &mut j) {
+                Some((num, num_has_fraction))
+                    if num < sixty && is_designator(str, j, designator, letter_form) =>
+                {
+                    parts[part_count] = num;
+                    part_count += 1;
+                    has_fraction = num_has_fraction;
+                    last_designator_index = j;
+                    i = j + 1;
+                }
+                _ => break,
+            }
+        }
+        // a lonely 'd' is the 'day' unit
+        if letter_form && part_count < 2 {
+            return None;
+        }
+
+        let minutes = parts[1].checked_div(&sixty)?;
+        let seconds = parts[2].checked_div(&Decimal::from(3600))?;
+        let value = parts[0].checked_add(&minutes)?.checked_add(&seconds)?;
+        Some((
+            Token {
+                typ: TokenType::NumberLiteral(value),
+                ptr: allocator
+                    .alloc_slice_fill_iter(str.iter().map(|it| *it).take(last_designator_index)),
+                has_error: false,
+            },
+            Token {
+                typ: TokenType::Unit(UnitTokenType::ApplyToPrevToken, UnitOutput::new_deg(units)),
+                ptr: allocator
+                    .alloc_slice_fill_iter(str[last_designator_index..i].iter().map(|it| *it)),
+                has_error: false,
+            },
+        ))
+    }
+
+    #[inline]
+    fn try_extract_as_dms<'text_ptr>(
+        str: &[char],
+        allocator: &'text_ptr Bump,
+    ) -> Option<Token<'text_ptr>> {
+        let expected = &['a', 's', ' ', 'd', 'm', 's'];
+        return if str.starts_with(expected)
+            && str
+                .get(expected.len())
+                .map(|it| !it.is_alphanumeric())
+                .unwrap_or(true)
+        {
+            Some(Token {
+                typ: TokenType::Operator(OperatorTokenType::AsDms),
+                ptr: allocator.alloc_slice_fill_iter(expected.iter().map(|it| *it)),
+                has_error: false,
+            })
+        } else {
+            None
+        };
+    }
+
     #[inline]
     fn try_extract_unit<'text_ptr>(
         str: &[char],
@@ -637,6 +832,40 @@ impl TokenParser {
         };
     }
 
+    // e.g. '.rate' in offers.rate
+    fn try_extract_column_name<'text_ptr>(
+        line: &[char],
+        dst: &[Token<'text_ptr>],
+        allocator: &'text_ptr Bump,
+    ) -> Option<Token<'text_ptr>> {
+        let prev_can_be_table = dst
+            .last()
+            .map(|token| {
+                matches!(
+                    token.typ,
+                    TokenType::Variable { .. }
+                        | TokenType::LineReference { .. }
+                        | TokenType::Operator(OperatorTokenType::BracketClose)
+                )
+            })
+            .unwrap_or(false);
+        if !prev_can_be_table
+            || line[0] != '.'
+            || !line.get(1).map(|it| it.is_alphabetic()).unwrap_or(false)
+        {
+            return None;
+        }
+        let len = 1 + line[1..]
+            .iter()
+            .take_while(|it| it.is_alphanumeric() || **it == '_')
+            .count();
+        Some(Token {
+            typ: TokenType::StringLiteral,
+            ptr: allocator.alloc_slice_fill_iter(line.iter().map(|it| *it).take(len)),
+            has_error: false,
+        })
+    }
+
     #[inline]
     fn try_extract_variable_name<'text_ptr>(
         line: &[char],
@@ -646,6 +875,7 @@ impl TokenParser {
         prev_was_lineref: bool,
         function_param_count: usize,
         func_defs: &FunctionDefinitions<'text_ptr>,
+        scope: Option<&[char]>,
     ) -> Option<Token<'text_ptr>> {
         tracy_span("try_extract_variable_name", file!(), line!());
         if line.starts_with(&['s', 'u', 'm'])
@@ -663,6 +893,23 @@ impl TokenParser {
             });
         }
 
+        if line.starts_with(SECTION_TOTAL_VAR_NAME)
+            && line
+                .get(SECTION_TOTAL_VAR_NAME.len())
+                .map(|it| !it.is_alphanumeric() && *it != '_')
+                .unwrap_or(true)
+        {
+            return Some(Token {
+                typ: TokenType::Variable {
+                    var_index: SECTION_TOTAL_VARIABLE_INDEX,
+                },
+                ptr: allocator.alloc_slice_fill_iter(
+                    line.iter().map(|it| *it).take(SECTION_TOTAL_VAR_NAME.len()),
+                ),
+                has_error: false,
+            });
+        }
+
         let mut longest_match_index = 0;
         let mut longest_match = 0;
         // first try to find the var in 'vars' so parameters can be shadowed
@@ -696,6 +943,7 @@ impl TokenParser {
                                         &mut longest_match,
                                         FIRST_FUNC_PARAM_VAR_INDEX + var_index,
                                         var,
+                                        scope,
                                     )
                                 }
                                 continue 'outer;
@@ -712,10 +960,11 @@ impl TokenParser {
                 &mut longest_match,
                 var_index,
                 var,
+                scope,
             );
         }
 
-        let result = if longest_match > 0 {
+        if longest_match > 0 {
             let is_line_ref = longest_match > 2 && line[0] == '&' && line[1] == '[';
             let typ = if is_line_ref {
                 if prev_was_lineref {
@@ -730,15 +979,38 @@ impl TokenParser {
                     var_index: longest_match_index,
                 }
             };
-            Some(Token {
+            return Some(Token {
                 typ,
                 ptr: allocator.alloc_slice_fill_iter(line.iter().map(|it| *it).take(longest_match)),
                 has_error: false,
-            })
-        } else {
-            None
-        };
-        return result;
+            });
+        }
+
+        // user variables named 'prev' or 'ans' take precedence over the keywords
+        for keyword in &[&['p', 'r', 'e', 'v'][..], &['a', 'n', 's'][..]] {
+            if line.starts_with(keyword)
+                && line
+                    .get(keyword.len())
+                    .map(|it| !it.is_alphanumeric() && *it != '_')
+                    .unwrap_or(true)
+            {
+                // 'prev' and 'ans' are line references to the closest line above which has a result,
+                // (every line with a result has a Variable, see process_variable_assignment_or_line_ref)
+                if prev_was_lineref {
+                    return None;
+                }
+                return (0..parsed_row_index)
+                    .rev()
+                    .find(|i| vars[*i].is_some())
+                    .map(|var_index| Token {
+                        typ: TokenType::LineReference { var_index },
+                        ptr: allocator
+                            .alloc_slice_fill_iter(line.iter().map(|it| *it).take(keyword.len())),
+                        has_error: false,
+                    });
+            }
+        }
+        return None;
     }
 
     fn find_variable_match(
@@ -747,20 +1019,65 @@ impl TokenParser {
         longest_match: &mut usize,
         var_index: usize,
         var: &Option<Variable>,
+        scope: Option<&[char]>,
     ) {
         if var.is_none() {
             return;
         }
         let var = var.as_ref().unwrap();
 
-        for (i, ch) in var.name.iter().enumerate() {
+        TokenParser::find_variable_name_match(
+            line,
+            longest_match_index,
+            longest_match,
+            var_index,
+            &var.name,
+        );
+        // the solved unknowns of a system of equations belong to the variable of its line
+        if let Some(unknowns) = &var.unknowns {
+            for unknown in unknowns.iter() {
+                TokenParser::find_variable_name_match(
+                    line,
+                    longest_match_index,
+                    longest_match,
+                    var_index,
+                    unknown,
+                );
+            }
+        }
+        // the variables of a scoped header region are named as 'Header.name',
+        // inside the region they can be referred to without the qualifier
+        if let Some(scope) = scope {
+            if var.name.len() > scope.len() + 1
+                && var.name.starts_with(scope)
+                && var.name[scope.len()] == '.'
+            {
+                TokenParser::find_variable_name_match(
+                    line,
+                    longest_match_index,
+                    longest_match,
+                    var_index,
+                    &var.name[scope.len() + 1..],
+                );
+            }
+        }
+    }
+
+    fn find_variable_name_match(
+        line: &[char],
+        longest_match_index: &mut usize,
+        longest_match: &mut usize,
+        var_index: usize,
+        var_name: &[char],
+    ) {
+        for (i, ch) in var_name.iter().enumerate() {
             if i >= line.len() || line[i] != *ch {
                 return;
             }
         }
         // if the next char is '(', it can't be a var name
         if line
-            .get(var.name.len())
+            .get(var_name.len())
             .map(|it| *it == '(')
             .unwrap_or(false)
         {
@@ -768,14 +1085,14 @@ impl TokenParser {
         }
         // only full match allowed e.g. if there is variable 'b', it should not match "b0" as 'b' and '0'
         let not_full_match = line
-            .get(var.name.len())
+            .get(var_name.len())
             .map(|it| it.is_alphanumeric())
             .unwrap_or(false);
         if not_full_match {
             return;
         }
-        if var.name.len() > *longest_match {
-            *longest_match = var.name.len();
+        if var_name.len() > *longest_match {
+            *longest_match = var_name.len();
             *longest_match_index = var_index;
         }
     }
@@ -788,7 +1105,7 @@ impl TokenParser {
         tracy_span("try_extract_string_literal", file!(), line!());
         let mut i = 0;
         for ch in str {
-            if "=%/+-*^()[],".chars().any(|it| it == *ch) || ch.is_ascii_whitespace() {
+            if "=%/+-±*^()[],;|".chars().any(|it| it == *ch) || ch.is_ascii_whitespace() {
                 break;
             }
             // it means somwewhere we passed an invalid slice
@@ -802,6 +1119,13 @@ impl TokenParser {
                 ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                 has_error: false,
             }
+        } else if str[0] == '|' {
+            // '|' outside of brackets, e.g. the separator of spreadsheet blocks
+            Token {
+                typ: TokenType::StringLiteral,
+                ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(1)),
+                has_error: false,
+            }
         } else {
             for ch in &str[0..] {
                 if !ch.is_ascii_whitespace() {
@@ -827,6 +1151,7 @@ impl TokenParser {
     #[inline]
     fn try_extract_operator<'text_ptr>(
         str: &[char],
+        dst: &[Token],
         allocator: &'text_ptr Bump,
         can_be_unit_converter: bool,
     ) -> Option<Token<'text_ptr>> {
@@ -853,17 +1178,27 @@ impl TokenParser {
         }
         let result = match str[0] {
             '=' => op(OperatorTokenType::Assign, str, 1, allocator),
+            // '+-' must be followed by a whitespace, '1+-2' is still 1 + (-2)
+            '+' if str.starts_with(&['+', '-', ' ']) => {
+                op(OperatorTokenType::PlusMinus, str, 2, allocator)
+            }
             '+' => op(OperatorTokenType::Add, str, 1, allocator),
             '-' => op(OperatorTokenType::Sub, str, 1, allocator),
             '*' => op(OperatorTokenType::Mult, str, 1, allocator),
             '/' => op(OperatorTokenType::Div, str, 1, allocator),
             '%' => op(OperatorTokenType::Perc, str, 1, allocator),
             '^' => op(OperatorTokenType::Pow, str, 1, allocator),
+            '±' => op(OperatorTokenType::PlusMinus, str, 1, allocator),
+            '.' if str.starts_with(&['.', '.']) => {
+                op(OperatorTokenType::Interval, str, 2, allocator)
+            }
             '(' => op(OperatorTokenType::ParenOpen, str, 1, allocator),
             ')' => op(OperatorTokenType::ParenClose, str, 1, allocator),
             '[' => op(OperatorTokenType::BracketOpen, str, 1, allocator),
             ']' => op(OperatorTokenType::BracketClose, str, 1, allocator),
             ',' => op(OperatorTokenType::Comma, str, 1, allocator),
+            // column separator of tables, e.g. [rate | days; 90 | 20]
+            '|' if is_inside_brackets(dst) => op(OperatorTokenType::Comma, str, 1, allocator),
             ';' => op(OperatorTokenType::Semicolon, str, 1, allocator),
             _ => {
                 if str.starts_with(&['i', 'n', ' ']) && can_be_unit_converter {
@@ -980,6 +1315,19 @@ impl TokenParser {
     }
 }
 
+// whether the parsed tokens have an unclosed '[', e.g. '[rate ' in '[rate | days]'
+fn is_inside_brackets(tokens: &[Token]) -> bool {
+    let mut depth = 0;
+    for token in tokens {
+        match token.typ {
+            TokenType::Operator(OperatorTokenType::BracketOpen) => depth += 1,
+            TokenType::Operator(OperatorTokenType::BracketClose) => depth -= 1,
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
 #[allow(dead_code)]
 #[allow(unused_variables)]
 pub fn debug_print(str: &str) {
@@ -1033,6 +1381,7 @@ pub mod tests {
                 &arena,
                 0,
                 &func_def_tmp,
+                None,
             );
             match vec.get(0) {
                 Some(Token {
@@ -1062,6 +1411,7 @@ pub mod tests {
                 &arena,
                 0,
                 &func_def_tmp,
+                None,
             );
             match vec.get(0) {
                 Some(Token {
@@ -1105,6 +1455,7 @@ pub mod tests {
                     Some(Variable {
                         name: Box::from(*var_name),
                         value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+                        unknowns: None,
                     })
                 } else {
                     None
@@ -1128,6 +1479,7 @@ pub mod tests {
             &arena,
             0,
             &func_def_tmp,
+            None,
         );
 
         let mut differences = Vec::with_capacity(vec.len().max(expected_tokens.len()));
@@ -1794,6 +2146,35 @@ pub mod tests {
         test("2.3e4.0e5", &[num(23000), numf(0e5f64)]);
     }
 
+    #[test]
+    fn test_bar_is_a_column_separator_only_inside_brackets() {
+        test(
+            "[1 | 2]",
+            &[
+                op(OperatorTokenType::BracketOpen),
+                num(1),
+                str(" "),
+                op(OperatorTokenType::Comma),
+                str(" "),
+                num(2),
+                op(OperatorTokenType::BracketClose),
+            ],
+        );
+        test("12 | 3", &[num(12), str(" "), str("|"), str(" "), num(3)]);
+        test(
+            "[1] | 2",
+            &[
+                op(OperatorTokenType::BracketOpen),
+                num(1),
+                op(OperatorTokenType::BracketClose),
+                str(" "),
+                str("|"),
+                str(" "),
+                num(2),
+            ],
+        );
+    }
+
     #[test]
     fn test_dont_count_zeroes() {
         test("1k", &[num(1_000)]);
@@ -2198,6 +2579,82 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_dms_parsing() {
+        test("10°30'", &[numf(10.5), apply_to_prev_token_unit("'")]);
+        test("10° 30'", &[numf(10.5), apply_to_prev_token_unit("'")]);
+        test("10d 30m", &[numf(10.5), apply_to_prev_token_unit("m")]);
+        test("10°", &[num(10), apply_to_prev_token_unit("°")]);
+        // a single 'd' is a 'day'
+        test("10d", &[num(10), apply_to_prev_token_unit("d")]);
+        // minutes must be less than 60
+        test(
+            "10°60'",
+            &[num(10), apply_to_prev_token_unit("°"), num(60), str("'")],
+        );
+        test(
+            "10°30' as dms",
+            &[
+                numf(10.5),
+                apply_to_prev_token_unit("'"),
+                str(" "),
+                op(OperatorTokenType::AsDms),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_plus_minus_parsing() {
+        test(
+            "5 ± 0.2",
+            &[
+                num(5),
+                str(" "),
+                op(OperatorTokenType::PlusMinus),
+                str(" "),
+                numf(0.2),
+            ],
+        );
+        test(
+            "5 +- 0.2",
+            &[
+                num(5),
+                str(" "),
+                op(OperatorTokenType::PlusMinus),
+                str(" "),
+                numf(0.2),
+            ],
+        );
+        test(
+            "1+-2",
+            &[
+                num(1),
+                op(OperatorTokenType::Add),
+                op(OperatorTokenType::Sub),
+                num(2),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_interval_parsing() {
+        test("3..5", &[num(3), op(OperatorTokenType::Interval), num(5)]);
+        test(
+            "1.5..2.5",
+            &[numf(1.5), op(OperatorTokenType::Interval), numf(2.5)],
+        );
+        test(
+            "3..5 days",
+            &[
+                num(3),
+                op(OperatorTokenType::Interval),
+                num(5),
+                str(" "),
+                apply_to_prev_token_unit("days"),
+            ],
+        );
+    }
+
     #[test]
     fn test_unit_after_lineref_is_allowed() {
         test_vars(
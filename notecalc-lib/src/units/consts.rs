@@ -83,6 +83,45 @@ pub(crate) const BASE_UNIT_DIMENSIONS: [[UnitDimensionExponent; BASE_UNIT_DIMENS
     [0, 0, -1, 0, 0, 0, 0, 0, 0, 0],  // Frequency
 ];
 
+// in the order of UnitType, used in the error messages
+const UNIT_TYPE_NAMES: [&str; ALL_UNIT_COUNT] = [
+    "Mass",
+    "Length",
+    "Time",
+    "Current",
+    "Temperature",
+    "Luminous intensity",
+    "Amount of substance",
+    "Angle",
+    "Bit",
+    "Money",
+    "Force",
+    "Surface",
+    "Volume",
+    "Energy",
+    "Power",
+    "Pressure",
+    "Electric charge",
+    "Electric capacitance",
+    "Electric potential",
+    "Electric resistance",
+    "Electric inductance",
+    "Electric conductance",
+    "Magnetic flux",
+    "Magnetic flux density",
+    "Frequency",
+];
+
+/// e.g. 'Length' for [0, 1, 0...], None if the dimensions have no name (e.g. speed)
+pub fn unit_type_name(
+    dimensions: &[UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT],
+) -> Option<&'static str> {
+    BASE_UNIT_DIMENSIONS
+        .iter()
+        .position(|it| it == dimensions)
+        .map(|i| UNIT_TYPE_NAMES[i])
+}
+
 fn create_prefixes() -> UnitPrefixes {
     UnitPrefixes {
         short: Rc::new(Box::new(vec![
@@ -499,6 +499,16 @@ impl UnitOutput {
         return unit;
     }
 
+    pub fn new_deg(units: &Units) -> UnitOutput {
+        let mut unit = UnitOutput::new();
+        let _ = unit.add_unit(UnitInstance::new(
+            Rc::clone(&units.units["deg"]),
+            Rc::clone(&units.no_prefix),
+            1,
+        ));
+        return unit;
+    }
+
     #[must_use]
     pub fn add_unit(&mut self, unit: UnitInstance) -> bool {
         for i in 0..BASE_UNIT_DIMENSION_COUNT {
@@ -0,0 +1,91 @@
+use crate::editor::editor::Pos;
+use crate::editor::editor_content::EditorContent;
+use crate::find::FindMatch;
+use crate::helper::{content_y, AppTokens};
+use crate::rename::{lhs_name_range, symbol_at, symbol_of_token, token_ranges, Symbol};
+use crate::token_parser::TokenType;
+use crate::{local_var_name, FunctionDefinitions, LineData, Variables};
+
+/// The state of the usages panel, opened by Ctrl+Shift+B on a variable, a user function or a line
+#[derive(Debug)]
+pub struct Usages {
+    // e.g. 'price' or 'line 3'
+    pub name: String,
+    pub usages: Vec<FindMatch>,
+    pub selected: Option<usize>,
+}
+
+impl Usages {
+    pub fn select_next(&mut self) -> Option<FindMatch> {
+        if self.usages.is_empty() {
+            return None;
+        }
+        let next = match self.selected {
+            Some(i) => (i + 1) % self.usages.len(),
+            None => 0,
+        };
+        self.selected = Some(next);
+        Some(self.usages[next])
+    }
+
+    pub fn select_prev(&mut self) -> Option<FindMatch> {
+        if self.usages.is_empty() {
+            return None;
+        }
+        let prev = match self.selected {
+            Some(0) | None => self.usages.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.selected = Some(prev);
+        Some(self.usages[prev])
+    }
+}
+
+/// The usages of the variable or user function under the cursor.
+/// If there is none, the line references to the cursor's line are listed.
+pub fn find_usages(
+    pos: Pos,
+    editor_content: &EditorContent<LineData>,
+    apptokens: &AppTokens,
+    vars: &Variables,
+    func_defs: &FunctionDefinitions,
+) -> Option<Usages> {
+    let (symbol, name) = match symbol_at(pos, apptokens, vars, func_defs) {
+        Some(it) => it,
+        None => {
+            let var = vars[pos.row].as_ref()?;
+            let name = if var.name.starts_with(&['&']) {
+                format!("line {}", pos.row + 1)
+            } else {
+                local_var_name(&var.name).iter().collect()
+            };
+            (Symbol::Variable(pos.row), name)
+        }
+    };
+    let mut usages = Vec::new();
+    for row in 0..editor_content.line_count() {
+        let tokens = match &apptokens[content_y(row)] {
+            Some(tokens) => &tokens.tokens,
+            None => continue,
+        };
+        let lhs_end = lhs_name_range(tokens).map(|it| it.end).unwrap_or(0);
+        for (range, token) in token_ranges(tokens) {
+            let is_usage = match token.typ {
+                TokenType::LineReference { var_index } => symbol == Symbol::Variable(var_index),
+                _ => symbol_of_token(token) == Some(symbol),
+            };
+            if range.start >= lhs_end && is_usage {
+                usages.push(FindMatch {
+                    row,
+                    start: range.start,
+                    end: range.end,
+                });
+            }
+        }
+    }
+    Some(Usages {
+        name,
+        usages,
+        selected: None,
+    })
+}
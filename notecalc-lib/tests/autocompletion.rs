@@ -1,5 +1,7 @@
-use notecalc_lib::editor::editor::{EditorInputEvent, InputModifiers};
+use notecalc_lib::completion::CompletionKind;
+use notecalc_lib::editor::editor::{EditorInputEvent, InputModifiers, Pos, Selection};
 use notecalc_lib::test_common::test_common::create_test_app;
+use notecalc_lib::units::units::Units;
 
 #[test]
 fn test_matrix_autocompletion() {
@@ -221,3 +223,156 @@ fn test_that_no_autocompletion_for_multiple_results() {
     test.input(EditorInputEvent::Tab, InputModifiers::none());
     assert_eq!("apple = 12$\nananas = 7$\na   ", test.get_editor_content());
 }
+
+#[test]
+fn test_completion_popup_inserts_builtin_function() {
+    let test = create_test_app(35);
+    test.paste("12 + integ");
+    test.input(EditorInputEvent::Char(' '), InputModifiers::ctrl());
+    let completion = test.app().completion.as_ref().unwrap();
+    assert_eq!(completion.items[0].label, "integrate");
+    assert_eq!(completion.items[0].detail, "integrate(f, from, to)");
+
+    test.input(EditorInputEvent::Enter, InputModifiers::none());
+    assert!(test.app().completion.is_none());
+    assert_eq!("12 + integrate()", test.get_editor_content());
+    assert_eq!(test.get_cursor_pos(), Pos::from_row_column(0, 15));
+
+    test.input(EditorInputEvent::Char('z'), InputModifiers::ctrl());
+    assert_eq!("12 + integ", test.get_editor_content());
+}
+
+#[test]
+fn test_completion_popup_matches_fuzzily() {
+    let test = create_test_app(35);
+    test.paste("price per day = 12\nppd");
+    test.input(EditorInputEvent::Char(' '), InputModifiers::ctrl());
+    assert_eq!(
+        test.app().completion.as_ref().unwrap().items[0].kind,
+        CompletionKind::Variable
+    );
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    assert_eq!(
+        "price per day = 12\nprice per day",
+        test.get_editor_content()
+    );
+    test.assert_results(&["12", "12"][..]);
+}
+
+#[test]
+fn test_completion_popup_prefers_units_after_numbers() {
+    let test = create_test_app(35);
+    test.paste("12 k");
+    test.input(EditorInputEvent::Char(' '), InputModifiers::ctrl());
+    assert_eq!(
+        test.app().completion.as_ref().unwrap().items[0].kind,
+        CompletionKind::Unit
+    );
+}
+
+#[test]
+fn test_completion_popup_prefers_compatible_units_after_in() {
+    let test = create_test_app(35);
+    test.paste("12 km in ");
+    test.input(EditorInputEvent::Char(' '), InputModifiers::ctrl());
+    let units = Units::new();
+    let first = &test.app().completion.as_ref().unwrap().items[0];
+    assert_eq!(first.kind, CompletionKind::Unit);
+    let unit_name = units
+        .aliases
+        .get(first.label.as_str())
+        .copied()
+        .unwrap_or(first.label.as_str());
+    assert_eq!(units.units[unit_name].base, units.units["m"].base);
+}
+
+#[test]
+fn test_completion_popup_follows_typing() {
+    let test = create_test_app(35);
+    test.paste("12 + in");
+    test.input(EditorInputEvent::Char(' '), InputModifiers::ctrl());
+    test.input(EditorInputEvent::Char('t'), InputModifiers::none());
+    assert_eq!(test.app().completion.as_ref().unwrap().prefix, "int");
+
+    test.input(EditorInputEvent::Esc, InputModifiers::none());
+    assert!(test.app().completion.is_none());
+    assert_eq!("12 + int", test.get_editor_content());
+}
+
+#[test]
+fn test_document_snippet() {
+    let test = create_test_app(35);
+    test.paste("// snippet vat: * 27%\n100 .vat");
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    assert_eq!(
+        "// snippet vat: * 27%\n100 * 27%",
+        test.get_editor_content()
+    );
+    assert_eq!(test.get_cursor_pos(), Pos::from_row_column(1, 9));
+    test.assert_results(&["", "27"][..]);
+}
+
+#[test]
+fn test_user_snippet_with_placeholders() {
+    let test = create_test_app(35);
+    test.mut_app()
+        .set_user_snippets("loan: principal = ${1:1000}\\nrate = ${2:5%}\\n$0");
+    test.paste(".loan");
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    assert_eq!("principal = 1000\nrate = 5%\n", test.get_editor_content());
+    assert_eq!(
+        test.get_selection(),
+        Selection::range(Pos::from_row_column(0, 12), Pos::from_row_column(0, 16))
+    );
+
+    test.input(EditorInputEvent::Char('2'), InputModifiers::none());
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    assert_eq!(
+        test.get_selection(),
+        Selection::range(Pos::from_row_column(1, 7), Pos::from_row_column(1, 9))
+    );
+
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    assert_eq!(test.get_cursor_pos(), Pos::from_row_column(2, 0));
+    assert!(test.app().snippet_session.is_none());
+    assert_eq!("principal = 2\nrate = 5%\n", test.get_editor_content());
+}
+
+#[test]
+fn test_snippet_placeholders_in_the_same_line() {
+    let test = create_test_app(35);
+    test.mut_app().set_user_snippets("f: ${1:a} + ${2:b}");
+    test.paste(".f");
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    test.input(EditorInputEvent::Char('x'), InputModifiers::none());
+    test.input(EditorInputEvent::Char('y'), InputModifiers::none());
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    assert_eq!("xy + b", test.get_editor_content());
+    assert_eq!(
+        test.get_selection(),
+        Selection::range(Pos::from_row_column(0, 5), Pos::from_row_column(0, 6))
+    );
+}
+
+#[test]
+fn test_snippet_expansion_is_undone_in_one_step() {
+    let test = create_test_app(35);
+    test.mut_app()
+        .set_user_snippets("loan: principal = ${1:1000}\\nrate = ${2:5%}");
+    test.paste(".loan");
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    test.input(EditorInputEvent::Char('z'), InputModifiers::ctrl());
+    assert_eq!(".loan", test.get_editor_content());
+}
+
+#[test]
+fn test_document_snippet_overrides_user_snippet() {
+    let test = create_test_app(35);
+    test.mut_app().set_user_snippets("vat: * 20%");
+    test.paste("// snippet vat: * 27%\n100 .vat");
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    assert_eq!(
+        "// snippet vat: * 27%\n100 * 27%",
+        test.get_editor_content()
+    );
+}
@@ -59,6 +59,42 @@ fn test_referenced_lineref_of_active_line_are_highlighted() {
     );
 }
 
+#[test]
+fn test_prev_of_active_line_is_highlighted_as_lineref() {
+    let test = create_test_app(35);
+    test.paste("223456\n\nasd prev * 2");
+    test.set_cursor_row_col(1, 0);
+
+    test.render();
+    let render_command_count_before = test.get_all_custom_commands_render_commands().len();
+
+    test.input(EditorInputEvent::Down, InputModifiers::none());
+
+    let left_gutter_w = LEFT_GUTTER_MIN_WIDTH;
+    let render_commands = &test.get_all_custom_commands_render_commands();
+    // (setcolor + underline) + (setcolor + 2*rect)
+    assert_eq!(render_commands.len(), render_command_count_before + 5);
+    assert_contains(
+        render_commands,
+        1,
+        OutputMessage::RenderRectangle {
+            x: 0,
+            y: canvas_y(0),
+            w: left_gutter_w,
+            h: 1,
+        },
+    );
+    assert_contains(
+        render_commands,
+        1,
+        OutputMessage::RenderUnderline {
+            x: left_gutter_w + "asd ".len(),
+            y: canvas_y(2),
+            w: "223 456".len(),
+        },
+    );
+}
+
 #[test]
 fn test_multiple_referenced_linerefs_in_different_rows_of_active_line_are_highlighted() {
     let test = create_test_app(35);
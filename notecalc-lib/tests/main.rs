@@ -1,5 +1,8 @@
+use notecalc_lib::calc::{CalcResult, CalcResultType};
 use notecalc_lib::editor::editor::{EditorInputEvent, InputModifiers, Pos, Selection};
+use notecalc_lib::find::FindMatch;
 use notecalc_lib::helper::{canvas_y, content_y};
+use notecalc_lib::lint::WarningKind;
 use notecalc_lib::test_common::test_common::{
     assert_contains, assert_contains_pulse, create_test_app, create_test_app2, pulsing_ref_rect,
     to_char_slice, TestHelper,
@@ -905,6 +908,516 @@ sum",
     test.assert_results(&["6 m^2", "", "1", "2", "3", "", "4", "5", "9"][..]);
 }
 
+#[test]
+fn test_header_section_totals() {
+    let test = create_test_app(35);
+    test.paste(
+        "# Groceries [sum]
+10
+20
+# Fuel [avg]
+30
+50
+# Trips [count]
+1
+
+2
+# Without total
+3",
+    );
+    test.assert_results(
+        &[
+            "30", "10", "20", "40", "30", "50", "2", "1", "", "2", "", "3",
+        ][..],
+    );
+}
+
+#[test]
+fn test_section_total_variable() {
+    let test = create_test_app(35);
+    test.paste(
+        "# Fuel
+30
+50
+section_total
+# Other
+4
+section_total",
+    );
+    test.assert_results(&["", "30", "50", "80", "", "4", "4"][..]);
+}
+
+#[test]
+fn test_lines_using_totals_are_not_part_of_the_section_total() {
+    let test = create_test_app(35);
+    test.paste(
+        "# Fuel [sum]
+30
+50
+section_total * 2
+sum",
+    );
+    test.assert_results(&["80", "30", "50", "160", "240"][..]);
+}
+
+#[test]
+fn test_scoped_header_variables() {
+    let test = create_test_app(35);
+    test.paste(
+        "# Groceries [scoped]
+price = 3
+price * 2
+# Fuel [scoped] [sum]
+price = 10
+price + Groceries.price
+# Summary
+price
+Groceries.price + Fuel.price",
+    );
+    test.assert_results(&["", "3", "6", "23", "10", "13", "", "", "13"][..]);
+}
+
+#[test]
+fn test_variables_become_local_when_the_header_gets_scoped() {
+    let test = create_test_app(35);
+    test.paste("# Groceries\nprice = 3\n# Summary\nprice * 2");
+    test.assert_results(&["", "3", "", "6"][..]);
+    test.set_cursor_row_col(0, 11);
+    test.paste(" [scoped]");
+    test.assert_results(&["", "3", "", ""][..]);
+}
+
+#[test]
+fn test_random_numbers_do_not_change_when_other_lines_are_edited() {
+    let test = create_test_app(35);
+    test.paste("a = rand()\n\nb = normal(100, 15) + 3d6");
+    let a = test.vars()[0].as_ref().unwrap().value.clone();
+    let b = test.vars()[2].as_ref().unwrap().value.clone();
+    assert!(a.is_ok() && b.is_ok());
+    test.set_cursor_row_col(1, 0);
+    test.input(EditorInputEvent::Char('5'), InputModifiers::none());
+    assert_eq!(test.vars()[0].as_ref().unwrap().value, a);
+    assert_eq!(test.vars()[2].as_ref().unwrap().value, b);
+}
+
+#[test]
+fn test_random_numbers_depend_on_the_seed_of_the_document() {
+    let rand_with_seed = |content: &str| {
+        let test = create_test_app(35);
+        test.paste(content);
+        test.vars()[1].as_ref().unwrap().value.clone()
+    };
+    assert_eq!(
+        rand_with_seed("seed 1\na = rand()"),
+        rand_with_seed("seed 1\na = rand()")
+    );
+    assert_ne!(
+        rand_with_seed("seed 1\na = rand()"),
+        rand_with_seed("seed 2\na = rand()")
+    );
+
+    let test = create_test_app(35);
+    test.paste("seed 1\na = rand()");
+    test.set_cursor_row_col(0, 6);
+    test.input(EditorInputEvent::Char('0'), InputModifiers::none());
+    assert_eq!(
+        test.vars()[1].as_ref().unwrap().value,
+        rand_with_seed("seed 10\na = rand()")
+    );
+}
+
+#[test]
+fn test_random_functions_with_degenerate_distributions() {
+    let test = create_test_app(35);
+    test.paste(
+        "randint(3, 3)\nuniform(2 m, 2 m)\nnormal(7, 0)\n1d1\n4d1\n0d6\nrandint(5, 3)
+triangular(5 m, 5 m, 5 m)\ntriangular(5, 5, 4)\ntriangular(1, 2 m, 3)",
+    );
+    test.assert_results(&["3", "2 m", "7", "1", "4", "Err", "Err", "5 m", "Err", "Err"][..]);
+}
+
+#[test]
+fn test_simulation() {
+    let test = create_test_app(35);
+    test.paste(
+        "simulate 100
+x = 4d1
+y = x * 2
+# Summary
+x.mean
+y.p95",
+    );
+    test.assert_results(&["", "", "", "", "4", "8"][..]);
+}
+
+#[test]
+fn test_simulation_percentiles_of_a_distribution() {
+    let test = create_test_app(35);
+    test.paste(
+        "simulate 1000
+x = uniform(0, 100)
+y = triangular(10, 20, 60)
+# Summary
+x.p5
+x.mean
+x.p95
+y.p5
+y.p95",
+    );
+    let num_at = |y: usize| -> f64 {
+        match &test.results()[content_y(y)] {
+            Ok(Some(CalcResult {
+                typ: CalcResultType::Number(num),
+                ..
+            })) => num.to_string().parse().unwrap(),
+            other => panic!("{:?}", other),
+        }
+    };
+    let (x_p5, x_mean, x_p95) = (num_at(4), num_at(5), num_at(6));
+    assert!(2.0 < x_p5 && x_p5 < 8.0, "{}", x_p5);
+    assert!(45.0 < x_mean && x_mean < 55.0, "{}", x_mean);
+    assert!(92.0 < x_p95 && x_p95 < 98.0, "{}", x_p95);
+    // the 5th percentile is 10 + sqrt(0.05 * 50 * 10) = 15, the 95th is 60 - sqrt(0.05 * 50 * 40) = 50
+    let (y_p5, y_p95) = (num_at(7), num_at(8));
+    assert!(13.0 < y_p5 && y_p5 < 17.0, "{}", y_p5);
+    assert!(47.0 < y_p95 && y_p95 < 53.0, "{}", y_p95);
+}
+
+#[test]
+fn test_invalid_simulation_iteration_count() {
+    let test = create_test_app(35);
+    test.paste("simulate 0\nx = 4d1");
+    test.assert_results(&["Err", "4"][..]);
+}
+
+#[test]
+fn test_scenarios_use_the_first_scenario_by_default() {
+    let test = create_test_app(35);
+    test.paste("rate = 90 | 100 | 120 @ low, mid, high\nrate * 2");
+    test.assert_results(&["90", "180"][..]);
+}
+
+#[test]
+fn test_switching_the_active_scenario() {
+    let test = create_test_app(35);
+    test.paste("rate = 90 | 100 | 120 @ low, mid, high\nrate * 2\n3");
+    test.input(EditorInputEvent::Char('s'), InputModifiers::alt());
+    test.assert_results(&["100", "200", "3"][..]);
+    test.input(EditorInputEvent::Char('s'), InputModifiers::alt());
+    test.assert_results(&["120", "240", "3"][..]);
+    // all the scenarios side by side, only the lines depending on them become tables
+    test.input(EditorInputEvent::Char('s'), InputModifiers::alt());
+    test.assert_results(&["", "", "3"][..]);
+    test.input(EditorInputEvent::Char('s'), InputModifiers::alt());
+    test.assert_results(&["90", "180", "3"][..]);
+}
+
+#[test]
+fn test_compared_scenarios_are_updated_on_change() {
+    let test = create_test_app(35);
+    test.paste("rate = 90 | 100 | 120 @ low, mid, high\nrate * 2\n3");
+    for _ in 0..3 {
+        test.input(EditorInputEvent::Char('s'), InputModifiers::alt());
+    }
+    let scenario_results = |y: usize| -> Vec<String> {
+        match &test.results()[content_y(y)] {
+            Ok(Some(CalcResult {
+                typ: CalcResultType::Matrix(mat),
+                ..
+            })) => mat
+                .cells
+                .iter()
+                .map(|it| match &it.typ {
+                    CalcResultType::Number(num) => num.to_string(),
+                    other => format!("{:?}", other),
+                })
+                .collect(),
+            other => panic!("{:?}", other),
+        }
+    };
+    assert_eq!(scenario_results(1), &["180", "200", "240"]);
+
+    // the lines below keep the results of every scenario
+    test.set_cursor_row_col(2, 1);
+    test.input(EditorInputEvent::Char('0'), InputModifiers::none());
+    test.assert_results(&["", "", "30"][..]);
+    assert_eq!(scenario_results(1), &["180", "200", "240"]);
+
+    // only the 'high' value changes, 'rate * 2' is updated in that scenario
+    test.set_cursor_row_col(0, 20);
+    test.input(EditorInputEvent::Backspace, InputModifiers::none());
+    test.input(EditorInputEvent::Char('3'), InputModifiers::none());
+    assert_eq!(scenario_results(0), &["90", "100", "130"]);
+    assert_eq!(scenario_results(1), &["180", "200", "260"]);
+}
+
+#[test]
+fn test_invalid_scenario_lines() {
+    let test = create_test_app(35);
+    test.paste("a = 1 | 2 @ x, y, z\nb = 1 | 2 @ x, x\nc = 1 | | 2 @ x, y, z");
+    test.assert_results(&["Err", "Err", "Err"][..]);
+}
+
+#[test]
+fn test_scenario_line_without_the_active_scenario() {
+    let test = create_test_app(35);
+    test.paste("a = 1 | 2 @ x, y\nb = 3 | 4 @ z, w\na + b");
+    // the lines without the active scenario use their first alternative
+    test.assert_results(&["1", "3", "4"][..]);
+    test.input(EditorInputEvent::Char('s'), InputModifiers::alt());
+    test.assert_results(&["2", "3", "5"][..]);
+    test.input(EditorInputEvent::Char('s'), InputModifiers::alt());
+    test.assert_results(&["1", "3", "4"][..]);
+    test.input(EditorInputEvent::Char('s'), InputModifiers::alt());
+    test.assert_results(&["1", "4", "5"][..]);
+}
+
+#[test]
+fn test_find_and_navigation_between_the_matches() {
+    let test = create_test_app(35);
+    test.paste("price = 12\nprices = 3\nprice * 2");
+    test.set_cursor_row_col(0, 0);
+    test.input(EditorInputEvent::Char('f'), InputModifiers::ctrl());
+    // the text pasted into the find bar becomes the query
+    test.paste("price");
+    assert_eq!(test.app().find.as_ref().unwrap().matches.len(), 3);
+    assert_eq!(
+        test.get_selection(),
+        Selection::range(Pos::from_row_column(0, 0), Pos::from_row_column(0, 5))
+    );
+    test.input(EditorInputEvent::Enter, InputModifiers::none());
+    assert_eq!(
+        test.get_selection(),
+        Selection::range(Pos::from_row_column(1, 0), Pos::from_row_column(1, 5))
+    );
+    test.input(EditorInputEvent::Enter, InputModifiers::shift());
+    assert_eq!(
+        test.get_selection(),
+        Selection::range(Pos::from_row_column(0, 0), Pos::from_row_column(0, 5))
+    );
+
+    test.input(EditorInputEvent::Char('w'), InputModifiers::alt());
+    assert_eq!(test.app().find.as_ref().unwrap().matches.len(), 2);
+
+    test.input(EditorInputEvent::Esc, InputModifiers::none());
+    assert!(test.app().find.is_none());
+    assert_eq!(
+        test.get_editor_content(),
+        "price = 12\nprices = 3\nprice * 2"
+    );
+}
+
+#[test]
+fn test_replace_all_is_a_single_undo_step() {
+    let test = create_test_app(35);
+    test.paste("rate = 5\ntax_rate = rate * 2");
+    test.input(EditorInputEvent::Char('h'), InputModifiers::ctrl());
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    test.paste("rate");
+    test.input(EditorInputEvent::Char('w'), InputModifiers::alt());
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    test.paste("r");
+    test.input(EditorInputEvent::Enter, InputModifiers::ctrl());
+    assert_eq!(test.get_editor_content(), "r = 5\ntax_rate = r * 2");
+    test.assert_results(&["5", "10"][..]);
+
+    test.input(EditorInputEvent::Char('z'), InputModifiers::ctrl());
+    assert_eq!(test.get_editor_content(), "rate = 5\ntax_rate = rate * 2");
+}
+
+#[test]
+fn test_replace_the_current_match() {
+    let test = create_test_app(35);
+    test.paste("a = 1\nb = 1\nc = 1");
+    test.set_cursor_row_col(0, 0);
+    test.input(EditorInputEvent::Char('h'), InputModifiers::ctrl());
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    test.paste("1");
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    test.paste("2");
+    test.input(EditorInputEvent::Enter, InputModifiers::none());
+    test.input(EditorInputEvent::Enter, InputModifiers::none());
+    assert_eq!(test.get_editor_content(), "a = 2\nb = 2\nc = 1");
+}
+
+#[test]
+fn test_regex_replace_with_groups() {
+    let test = create_test_app(35);
+    test.paste("a1 b22");
+    test.input(EditorInputEvent::Char('h'), InputModifiers::ctrl());
+    test.input(EditorInputEvent::Char('r'), InputModifiers::alt());
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    test.paste("([a-z])(\\d+)");
+    assert_eq!(test.app().find.as_ref().unwrap().matches.len(), 2);
+    test.input(EditorInputEvent::Tab, InputModifiers::none());
+    test.paste("$2$1");
+    test.input(EditorInputEvent::Enter, InputModifiers::ctrl());
+    assert_eq!(test.get_editor_content(), "1a 22b");
+}
+
+#[test]
+fn test_invalid_regex_in_find() {
+    let test = create_test_app(35);
+    test.paste("a(b");
+    test.input(EditorInputEvent::Char('f'), InputModifiers::ctrl());
+    test.input(EditorInputEvent::Char('r'), InputModifiers::alt());
+    test.paste("a(");
+    let find = test.app().find.as_ref().unwrap();
+    assert_eq!(find.error, Some("Invalid regex".to_owned()));
+    assert!(find.matches.is_empty());
+}
+
+#[test]
+fn test_rename_variable() {
+    let test = create_test_app(35);
+    test.paste("price = 12\nf(x):\n  x * price\n[price, 2]\nf(2) + price // price");
+    test.set_cursor_row_col(0, 2);
+    test.input(EditorInputEvent::F2, InputModifiers::none());
+    test.paste("price per day");
+    test.input(EditorInputEvent::Enter, InputModifiers::none());
+    assert!(test.app().rename.is_none());
+    assert_eq!(
+        test.get_editor_content(),
+        "price per day = 12\nf(x):\n  x * price per day\n[price per day, 2]\nf(2) + price per day // price"
+    );
+
+    test.input(EditorInputEvent::Char('z'), InputModifiers::ctrl());
+    assert_eq!(
+        test.get_editor_content(),
+        "price = 12\nf(x):\n  x * price\n[price, 2]\nf(2) + price // price"
+    );
+}
+
+#[test]
+fn test_rename_function_from_its_usage() {
+    let test = create_test_app(35);
+    test.paste("f(x):\n  x * 2\nf(3)\nfx = 1");
+    test.set_cursor_row_col(2, 1);
+    test.input(EditorInputEvent::F2, InputModifiers::none());
+    assert_eq!(test.app().rename.as_ref().unwrap().old_name, "f");
+    test.paste("double");
+    test.input(EditorInputEvent::Enter, InputModifiers::none());
+    assert_eq!(
+        test.get_editor_content(),
+        "double(x):\n  x * 2\ndouble(3)\nfx = 1"
+    );
+    test.assert_results(&["", "", "6", "1"][..]);
+}
+
+#[test]
+fn test_rename_function_with_an_indented_definition() {
+    let test = create_test_app(35);
+    test.paste("  f(x):\n    x * 2\nf(3)");
+    test.assert_results(&["", "", "6"][..]);
+    test.set_cursor_row_col(2, 1);
+    test.input(EditorInputEvent::F2, InputModifiers::none());
+    test.paste("double");
+    test.input(EditorInputEvent::Enter, InputModifiers::none());
+    assert_eq!(
+        test.get_editor_content(),
+        "  double(x):\n    x * 2\ndouble(3)"
+    );
+    test.assert_results(&["", "", "6"][..]);
+}
+
+#[test]
+fn test_rename_with_invalid_name_does_nothing() {
+    let test = create_test_app(35);
+    test.paste("a = 1\na + 1");
+    test.set_cursor_row_col(1, 0);
+    test.input(EditorInputEvent::F2, InputModifiers::none());
+    test.paste("1b");
+    test.input(EditorInputEvent::Enter, InputModifiers::none());
+    assert!(test.app().rename.is_some());
+    test.input(EditorInputEvent::Esc, InputModifiers::none());
+    assert!(test.app().rename.is_none());
+    assert_eq!(test.get_editor_content(), "a = 1\na + 1");
+}
+
+#[test]
+fn test_rename_rejects_names_which_are_already_taken() {
+    let test = create_test_app(35);
+    test.paste("x = 1\ny = 2\nx + y");
+    test.set_cursor_row_col(0, 0);
+    // a variable, units and builtins
+    for new_name in &["y", "m", "kg", "sum", "sin"] {
+        test.input(EditorInputEvent::F2, InputModifiers::none());
+        test.paste(new_name);
+        test.input(EditorInputEvent::Enter, InputModifiers::none());
+        assert!(test.app().rename.is_some());
+        test.input(EditorInputEvent::Esc, InputModifiers::none());
+        assert_eq!(test.get_editor_content(), "x = 1\ny = 2\nx + y");
+    }
+}
+
+#[test]
+fn test_find_usages_of_a_variable() {
+    let test = create_test_app(35);
+    test.paste("price = 12\nf(x):\n  x * price\nprice * 2\n// price");
+    test.set_cursor_row_col(0, 0);
+    test.input(EditorInputEvent::Char('b'), InputModifiers::ctrl_shift());
+    let usages = test.app().usages.as_ref().unwrap();
+    assert_eq!(usages.name, "price");
+    assert_eq!(
+        usages.usages,
+        &[
+            FindMatch {
+                row: 2,
+                start: 6,
+                end: 11
+            },
+            FindMatch {
+                row: 3,
+                start: 0,
+                end: 5
+            },
+        ][..]
+    );
+
+    test.input(EditorInputEvent::Down, InputModifiers::none());
+    test.input(EditorInputEvent::Down, InputModifiers::none());
+    assert_eq!(
+        test.get_selection(),
+        Selection::range(Pos::from_row_column(3, 0), Pos::from_row_column(3, 5))
+    );
+    test.input(EditorInputEvent::Enter, InputModifiers::none());
+    assert!(test.app().usages.is_none());
+    assert_eq!(test.get_cursor_pos(), Pos::from_row_column(3, 5));
+}
+
+#[test]
+fn test_find_usages_of_a_function_and_of_a_line() {
+    let test = create_test_app(35);
+    test.paste("f(x):\n  x * 2\nf(3)\n&[3] + f(1)");
+    test.set_cursor_row_col(0, 0);
+    test.input(EditorInputEvent::Char('b'), InputModifiers::ctrl_shift());
+    let rows: Vec<usize> = test
+        .app()
+        .usages
+        .as_ref()
+        .unwrap()
+        .usages
+        .iter()
+        .map(|it| it.row)
+        .collect();
+    assert_eq!(rows, &[2, 3]);
+
+    test.input(EditorInputEvent::Esc, InputModifiers::none());
+    assert!(test.app().usages.is_none());
+    test.set_cursor_row_col(2, 4);
+    test.input(EditorInputEvent::Char('b'), InputModifiers::ctrl_shift());
+    let usages = test.app().usages.as_ref().unwrap();
+    assert_eq!(usages.name, "line 3");
+    assert_eq!(
+        usages.usages,
+        &[FindMatch {
+            row: 3,
+            start: 0,
+            end: 4
+        }][..]
+    );
+}
+
 #[test]
 fn test_that_header_lengths_are_separate_and_not_add() {
     let test = create_test_app2(79, 32);
@@ -2013,6 +2526,165 @@ fn test_sum_inside_parens() {
     }
 }
 
+#[test]
+fn test_sum_of_intervals() {
+    let test = create_test_app(35);
+    test.paste("3..5 days\n1..2 days\nsum");
+    test.assert_results(&["3 .. 5 day", "1 .. 2 day", "4 .. 7 day"]);
+}
+
+#[test]
+fn test_goalseek() {
+    let test = create_test_app(35);
+    test.paste("price = 100\ntax = 25%\nnet = price - price * tax\ngoalseek(net, price, 600)");
+    test.assert_results(&["100", "25 %", "75", "800"]);
+}
+
+#[test]
+fn test_goalseek_is_unit_aware() {
+    let test = create_test_app(35);
+    test.paste(
+        "speed = 50 km/h\ntime = 2 h\ndistance = speed * time\ngoalseek(distance, speed, 150000 m)",
+    );
+    test.assert_results(&["50 km / h", "2 h", "100 km", "75 km / h"]);
+}
+
+#[test]
+fn test_goalseek_errors() {
+    let test = create_test_app(35);
+    test.paste("x = 2\ny = x^2\ngoalseek(y, x, -1)\ngoalseek(x, y, 4)\ngoalseek(y, x, 4 m)");
+    test.assert_results(&["2", "4", "Err", "Err", "Err"]);
+}
+
+#[test]
+fn test_linear_equation() {
+    let test = create_test_app(35);
+    test.paste("2x + 3 = 11\nx * 2\nk = 3\n2(y - k) = y / 2 + 6\ny");
+    test.assert_results(&["4", "8", "3", "8", "8"]);
+}
+
+#[test]
+fn test_words_with_a_number_are_a_variable_name_not_an_equation() {
+    let test = create_test_app(35);
+    test.paste("2 apples = 5\napples");
+    test.assert_results(&["5", ""]);
+    let name: String = test.vars()[0].as_ref().unwrap().name.iter().collect();
+    assert_eq!(name, "2 apples");
+}
+
+#[test]
+fn test_system_of_linear_equations() {
+    let test = create_test_app(35);
+    test.paste("x + y = 10; x - y = 2\nx\ny * 2\n2z = x + y");
+    test.assert_results(&["", "6", "8", "5"]);
+    // the solution of the system is the row vector of the unknowns
+    assert!(matches!(
+        &test.vars()[0].as_ref().unwrap().value,
+        Ok(CalcResult {
+            typ: CalcResultType::Matrix(..),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_matrix_indexing_with_variables() {
+    let test = create_test_app(35);
+    test.paste(
+        "prices = [10, 20, 30; 40, 50, 60]
+prices[1, 2]
+prices[0, :]
+&[3][-1]
+sum(prices[:, 0])
+row = prices[-1]
+sum(row[0..1])
+prices[2, 0]",
+    );
+    test.assert_results(&["", "60", "", "30", "50", "", "90", "Err"][..]);
+}
+
+#[test]
+fn test_table_with_named_columns() {
+    let test = create_test_app(35);
+    test.paste(
+        "offers = [rate | days; 90$ | 20; 100$ | 18]
+offers.days
+sum(offers.days)
+offers.rate[1]
+offers[0, 1]
+offers.price",
+    );
+    test.assert_results(&["", "", "38", "100 $", "20", "Err"][..]);
+}
+
+#[test]
+fn test_prev_and_ans() {
+    let test = create_test_app(35);
+    test.paste("prev\n12\n\nprev * 2\n// comment\nans + 1\nx = prev");
+    test.assert_results(&["", "12", "", "24", "", "25", "25"][..]);
+}
+
+#[test]
+fn test_user_variables_named_prev_and_ans_are_not_line_references() {
+    {
+        let test = create_test_app(35);
+        test.paste("prev = 3\n12\nprev * 2");
+        test.assert_results(&["3", "12", "6"][..]);
+    }
+    {
+        let test = create_test_app(35);
+        test.paste("ans = 5\n12\nans + 1");
+        test.assert_results(&["5", "12", "6"][..]);
+    }
+}
+
+#[test]
+fn test_prev_is_updated_when_the_line_above_gets_a_result() {
+    let test = create_test_app(35);
+    test.paste("12\n\nprev * 2");
+    test.set_cursor_row_col(1, 0);
+    test.input(EditorInputEvent::Char('3'), InputModifiers::none());
+    test.assert_results(&["12", "3", "6"][..]);
+}
+
+#[test]
+fn test_spreadsheet_block() {
+    let test = create_test_app(35);
+    test.paste(
+        "discount = 1
+```sheet
+item | qty | price | total = qty * price - discount
+apple | 2 | 10 |
+pear | 3 | 5 |
+| | | sum
+| | | avg
+```
+total",
+    );
+    test.assert_results(&["1", "", "", "19", "14", "33", "16.5", "", ""][..]);
+}
+
+#[test]
+fn test_spreadsheet_block_errors() {
+    let test = create_test_app(35);
+    test.paste(
+        "```sheet
+qty | price | total = qty * price
+2 | 10
+apple | 5
+| | sum
+```",
+    );
+    test.assert_results(&["", "", "20", "Err", "Err", ""][..]);
+}
+
+#[test]
+fn test_linear_equation_errors() {
+    let test = create_test_app(35);
+    test.paste("x + 1 = x + 2\n2y = y + y\nz * z = 4\np + q = 1; p + q = 2");
+    test.assert_results(&["Err", "Err", "Err", "Err"]);
+}
+
 #[test]
 fn test_that_result_is_not_changing_if_tokens_change_before_it() {
     let test = create_test_app(35);
@@ -4639,3 +5311,358 @@ fn test_missing_arg_nth_panic() {
     let exp_col = exp_col + 1;
     test.assert_contains_paren(1, |op| op.char == ')' && op.col == exp_col);
 }
+
+#[test]
+fn test_hovering_over_a_variable_shows_its_value_and_definition() {
+    let test = create_test_app(35);
+    test.paste("price = 12\nprice * 2");
+    test.handle_mouse_move(LEFT_GUTTER_MIN_WIDTH + 1, 1);
+    assert_eq!(
+        test.app().tooltip.as_ref().unwrap().lines,
+        vec!["price = 12".to_owned(), "line 1: price = 12".to_owned()]
+    );
+
+    test.handle_mouse_move(LEFT_GUTTER_MIN_WIDTH + 8, 1);
+    assert!(test.app().tooltip.is_none());
+}
+
+#[test]
+fn test_hovering_over_an_unknown_of_a_system_shows_its_own_value() {
+    let test = create_test_app(35);
+    test.paste("x + y = 10; x - y = 2\nx\ny * 2");
+    test.handle_mouse_move(LEFT_GUTTER_MIN_WIDTH, 1);
+    assert_eq!(
+        test.app().tooltip.as_ref().unwrap().lines,
+        vec![
+            "x = 6".to_owned(),
+            "line 1: x + y = 10; x - y = 2".to_owned()
+        ]
+    );
+
+    test.handle_mouse_move(LEFT_GUTTER_MIN_WIDTH, 2);
+    assert_eq!(test.app().tooltip.as_ref().unwrap().lines[0], "y = 4");
+}
+
+#[test]
+fn test_hovering_over_a_unit_shows_its_si_conversion_and_dimensions() {
+    let test = create_test_app(35);
+    test.paste("1 psi");
+    test.handle_mouse_move(LEFT_GUTTER_MIN_WIDTH + 3, 0);
+    assert_eq!(
+        test.app().tooltip.as_ref().unwrap().lines,
+        vec!["psi = 6894.76 Pa, M L⁻¹ T⁻²".to_owned()]
+    );
+}
+
+#[test]
+fn test_hovering_over_a_function_shows_its_signature() {
+    let test = create_test_app(35);
+    test.paste("sin(1)");
+    test.handle_mouse_move(LEFT_GUTTER_MIN_WIDTH + 1, 0);
+    assert_eq!(
+        test.app().tooltip.as_ref().unwrap().lines,
+        vec!["sin(angle)".to_owned(), "The sine of the angle".to_owned()]
+    );
+}
+
+#[test]
+fn test_hovering_over_goalseek_and_constants() {
+    let test = create_test_app(35);
+    test.paste("x = 1\ngoalseek(x * 2, x, 10)\npi()");
+    test.handle_mouse_move(LEFT_GUTTER_MIN_WIDTH + 1, 1);
+    assert_eq!(
+        test.app().tooltip.as_ref().unwrap().lines,
+        vec![
+            "goalseek(expr, var, target)".to_owned(),
+            "The value of var for which expr equals the target".to_owned()
+        ]
+    );
+    test.handle_mouse_move(LEFT_GUTTER_MIN_WIDTH + 1, 2);
+    assert_eq!(
+        test.app().tooltip.as_ref().unwrap().lines,
+        vec![
+            "pi()".to_owned(),
+            "The ratio of a circle's circumference to its diameter".to_owned()
+        ]
+    );
+}
+
+#[test]
+fn test_hovering_over_an_erroneous_token_shows_the_reason() {
+    let test = create_test_app(35);
+    test.paste("12 m + 3 kg");
+    let reason = match &test.results()[content_y(0)] {
+        Err(err) => err.reason.clone(),
+        Ok(_) => panic!("the line must fail"),
+    };
+    let tokens = &test.tokens()[content_y(0)].as_ref().unwrap().tokens;
+    let mut column = 0;
+    for token in tokens.iter() {
+        if token.has_error() {
+            break;
+        }
+        column += token.ptr.len();
+    }
+    test.handle_mouse_move(LEFT_GUTTER_MIN_WIDTH + column, 0);
+    assert_eq!(test.app().tooltip.as_ref().unwrap().lines, vec![reason]);
+}
+
+#[test]
+fn test_error_messages_of_invalid_operations() {
+    let test = create_test_app(35);
+    test.paste("12 m + 3 kg\n1 / 0\nsin(1 kg)\nasin(2)\nabs(1, 2)");
+    let reasons: Vec<String> = (0..5)
+        .map(|i| match &test.results()[content_y(i)] {
+            Err(err) => err.reason.clone(),
+            Ok(_) => panic!("line {} must fail", i + 1),
+        })
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            "Cannot add Length to Mass".to_owned(),
+            "Division by zero".to_owned(),
+            "The argument must be an angle or a number".to_owned(),
+            "The argument must be between -1 and 1".to_owned(),
+            "Expected 1 argument, provided 2".to_owned(),
+        ]
+    );
+}
+
+#[test]
+fn test_error_message_is_rendered_next_to_err() {
+    let test = create_test_app(35);
+    test.paste("12 m + 3 kg");
+    let commands = &test.render_bucket().custom_commands[Layer::AboveText as usize];
+    let rendered_reason = commands.iter().find_map(|it| match it {
+        OutputMessage::RenderString(RenderStringMsg { text, row, .. }) if *row == canvas_y(0) => {
+            Some(text.clone())
+        }
+        _ => None,
+    });
+    // it is cut if it does not fit into the result panel
+    let rendered_reason = rendered_reason.unwrap();
+    let visible_part = rendered_reason.trim_end_matches('…');
+    assert!("Cannot add Length to Mass".starts_with(visible_part));
+}
+
+fn warnings(test: &TestHelper) -> Vec<(usize, String)> {
+    test.app()
+        .warnings
+        .iter()
+        .map(|it| (it.row, it.message.clone()))
+        .collect()
+}
+
+#[test]
+fn test_warnings_for_shadowing_and_unused_variables() {
+    let test = create_test_app(35);
+    test.mut_app()
+        .lint_settings
+        .set_enabled(WarningKind::Unused, true);
+    test.paste("price = 12\nprice = 14\nprice * 2\ntotal = 1\ntotal = total + price");
+    assert_eq!(
+        warnings(&test),
+        vec![
+            (0, "'price' is never used".to_owned()),
+            (1, "'price' is already defined in line 1".to_owned()),
+            (4, "'total' is never used".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_warning_for_unused_functions() {
+    let test = create_test_app(35);
+    test.mut_app()
+        .lint_settings
+        .set_enabled(WarningKind::Unused, true);
+    test.paste("f(x):\n  x * 2\ng(x):\n  x * 3\ng(1)");
+    assert_eq!(
+        warnings(&test),
+        vec![(0, "The function 'f' is never used".to_owned())]
+    );
+}
+
+#[test]
+fn test_warnings_for_units() {
+    let test = create_test_app(35);
+    test.paste("5 kmh\n5 km/h\n1 km/m");
+    assert_eq!(
+        warnings(&test),
+        vec![
+            (
+                0,
+                "'kmh' is not a unit, did you mean 'km/h' or 'km h'?".to_owned()
+            ),
+            (2, "'km' and 'm' are units of the same kind".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_warning_categories_can_be_disabled() {
+    let test = create_test_app(35);
+    test.mut_app()
+        .lint_settings
+        .set_enabled(WarningKind::UnknownUnit, false);
+    test.paste("price = 12\nprice = 14\n5 kmh");
+    assert_eq!(
+        warnings(&test),
+        vec![(1, "'price' is already defined in line 1".to_owned())]
+    );
+}
+
+#[test]
+fn test_unused_warnings_are_disabled_by_default() {
+    let test = create_test_app(35);
+    test.paste("price = 12\n5 kmh");
+    assert_eq!(
+        warnings(&test),
+        vec![(
+            1,
+            "'kmh' is not a unit, did you mean 'km/h' or 'km h'?".to_owned()
+        )]
+    );
+}
+
+#[test]
+fn test_hovering_over_the_line_number_shows_the_warnings() {
+    let test = create_test_app(35);
+    test.mut_app()
+        .lint_settings
+        .set_enabled(WarningKind::Unused, true);
+    test.paste("price = 12\n12");
+    let commands = &test.render_bucket().custom_commands[Layer::Text as usize];
+    assert_contains(
+        commands,
+        1,
+        OutputMessage::SetColor(THEMES[0].line_num_warning),
+    );
+    test.handle_mouse_move(0, 0);
+    assert_eq!(
+        test.app().tooltip.as_ref().unwrap().lines,
+        vec!["'price' is never used".to_owned()]
+    );
+
+    test.handle_mouse_move(0, 1);
+    assert!(test.app().tooltip.is_none());
+}
+
+fn fold_summaries(test: &TestHelper) -> Vec<(String, isize)> {
+    test.render_bucket().custom_commands[Layer::AboveText as usize]
+        .iter()
+        .filter_map(|it| match it {
+            OutputMessage::RenderString(RenderStringMsg { text, row, .. })
+                if text.starts_with('⋯') =>
+            {
+                Some((text.clone(), row.as_isize()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_folding_a_header_region_hides_its_lines_but_evaluates_them() {
+    let test = create_test_app(35);
+    test.paste("# A\na = 1\nb = 2\n# B\na + b");
+    test.set_cursor_row_col(0, 0);
+    test.input(EditorInputEvent::Char('['), InputModifiers::ctrl_shift());
+
+    assert!(test.app().editor_content.get_data(0).folded);
+    assert!(test.get_render_data().is_hidden(content_y(1)));
+    assert!(test.get_render_data().is_hidden(content_y(2)));
+    assert_eq!(
+        test.get_render_data().get_render_y(content_y(3)),
+        Some(canvas_y(1))
+    );
+    assert_eq!(
+        fold_summaries(&test),
+        vec![("⋯ 2 lines, total 3".to_owned(), 0)]
+    );
+    assert!(test.vars()[1].is_some());
+    assert!(test.vars()[2].is_some());
+    test.assert_contains_result(1, |cmd| cmd.text == "3");
+
+    test.input(EditorInputEvent::Char(']'), InputModifiers::ctrl_shift());
+    assert!(!test.app().editor_content.get_data(0).folded);
+    assert!(!test.get_render_data().is_hidden(content_y(1)));
+    assert!(fold_summaries(&test).is_empty());
+}
+
+#[test]
+fn test_header_region_ends_at_the_next_header_of_the_same_level() {
+    let test = create_test_app(35);
+    test.paste("# A\n## A1\n1\n## A2\n2\n\n# B\n3");
+    test.set_cursor_row_col(0, 0);
+    test.input(EditorInputEvent::Char('['), InputModifiers::ctrl_shift());
+    for row in 1..=4 {
+        assert!(test.get_render_data().is_hidden(content_y(row)));
+    }
+    // the empty line before the next header remains visible
+    assert!(!test.get_render_data().is_hidden(content_y(5)));
+    assert!(!test.get_render_data().is_hidden(content_y(6)));
+}
+
+#[test]
+fn test_fold_ranges_of_nested_headers() {
+    let test = create_test_app(35);
+    test.paste("# A\n## A1\n1\n## A2\n2\n\n# B\n3");
+    assert_eq!(
+        test.app().fold_ranges,
+        vec![
+            Some(1..5),
+            Some(2..3),
+            None,
+            Some(4..5),
+            None,
+            None,
+            Some(7..8),
+            None
+        ]
+    );
+}
+
+#[test]
+fn test_function_bodies_can_be_folded_by_clicking_on_the_gutter() {
+    let test = create_test_app(35);
+    test.paste("f(x):\n  y = x * 2\n  y + 1\nf(3)");
+    test.click(0, 0);
+    assert!(test.app().editor_content.get_data(0).folded);
+    assert!(test.get_render_data().is_hidden(content_y(1)));
+    assert!(test.get_render_data().is_hidden(content_y(2)));
+    test.assert_contains_result(1, |cmd| cmd.text == "7");
+
+    test.click(0, 0);
+    assert!(!test.app().editor_content.get_data(0).folded);
+    assert!(!test.get_render_data().is_hidden(content_y(1)));
+}
+
+#[test]
+fn test_cursor_jumps_over_folded_regions() {
+    let test = create_test_app(35);
+    test.paste("# A\n1\n2\n# B\n3");
+    test.set_cursor_row_col(0, 0);
+    test.input(EditorInputEvent::Char('['), InputModifiers::ctrl_shift());
+
+    test.input(EditorInputEvent::Down, InputModifiers::none());
+    assert_eq!(test.get_cursor_pos().row, 3);
+    test.input(EditorInputEvent::Up, InputModifiers::none());
+    assert_eq!(test.get_cursor_pos().row, 0);
+    assert!(test.app().editor_content.get_data(0).folded);
+}
+
+#[test]
+fn test_moving_the_cursor_into_a_folded_region_unfolds_it() {
+    let test = create_test_app(35);
+    test.paste("# A\n1\n2\n# B\n3");
+    test.set_cursor_row_col(0, 0);
+    test.input(EditorInputEvent::Char('['), InputModifiers::ctrl_shift());
+
+    // e.g. find or jumping to a definition
+    test.set_cursor_row_col(2, 0);
+    test.input(EditorInputEvent::Char('4'), InputModifiers::none());
+    assert!(!test.app().editor_content.get_data(0).folded);
+    assert!(!test.get_render_data().is_hidden(content_y(2)));
+}
@@ -960,3 +960,96 @@ func2():
         _ => false,
     });
 }
+
+#[test]
+fn test_deriv_of_user_func() {
+    let test = create_test_app(35);
+    test.paste(
+        "f(x):
+  x^2 + 3 * x
+deriv(f, 2)
+deriv(f, 0)",
+    );
+    test.assert_results(&["", "", "7", "3"][..]);
+}
+
+#[test]
+fn test_integrate_user_func_with_units() {
+    let test = create_test_app(35);
+    test.paste(
+        "speed(t):
+  t * 2 m/s^2
+integrate(speed, 0 s, 10 s)",
+    );
+    test.assert_results(&["", "", "100 m"][..]);
+}
+
+#[test]
+fn test_root_of_user_func() {
+    let test = create_test_app(35);
+    test.paste(
+        "f(x):
+  x^2 - 2
+root(f, 0, 5)
+root(f, 2, 5)
+root(12, 0, 5)",
+    );
+    test.assert_results(&["", "", "1.4142", "Err", "Err"][..]);
+}
+
+#[test]
+fn test_map_filter_reduce() {
+    let test = create_test_app(35);
+    test.paste(
+        "double(x):
+  x * 2
+not_two(x):
+  x - 2
+add(a, b):
+  a + b
+nth(map([1, 2, 3], double), 0)
+nth(map([1, 2, 3], double), 1)
+nth(map([1, 2, 3], double), 2)
+nth(filter([1, 2, 3, 2], not_two), 0)
+nth(filter([1, 2, 3, 2], not_two), 1)
+nth(filter([1, 2, 3, 2], not_two), 2)
+reduce([1, 2, 3, 4], add, 10)
+map([1, 2; 3, 4], double)
+filter([2, 2], not_two)
+reduce([1, 2], double, 0)",
+    );
+    // matrices are not rendered into the result buffer, so the elements are checked one by one
+    test.assert_results(
+        &[
+            "", "", "", "", "", "", "2", "4", "6", "1", "3", "Err", "20", "Err", "Err", "Err",
+        ][..],
+    );
+}
+
+#[test]
+fn test_user_func_precedes_the_builtin_with_the_same_name() {
+    let test = create_test_app(35);
+    test.paste(
+        "sort(x):
+  x * 2
+sort(3)
+cumsum(a, b):
+  a - b
+cumsum(5, 1)",
+    );
+    test.assert_results(&["", "", "6", "", "", "4"][..]);
+}
+
+#[test]
+fn test_user_func_precedes_the_random_builtin_with_the_same_name() {
+    let test = create_test_app(35);
+    test.paste(
+        "rand(x):
+  x + 1
+rand(2)
+normal(a, b):
+  a * b
+normal(2, 3)",
+    );
+    test.assert_results(&["", "", "3", "", "", "6"][..]);
+}